@@ -0,0 +1,64 @@
+-- Index of DATE / DATE_RANGE values of page records, maintained by the backend on record write.
+-- Lets the hourly expiry scan find due items with a single query instead of parsing every record.
+CREATE TABLE IF NOT EXISTS record_due_dates (
+    record_id INT UNSIGNED NOT NULL,
+    page_id INT UNSIGNED NOT NULL,
+    field_id INT UNSIGNED NOT NULL,
+    start_date DATE NOT NULL,
+    end_date DATE NOT NULL,
+    PRIMARY KEY (record_id, field_id),
+    FOREIGN KEY (record_id) REFERENCES page_records (id) ON DELETE CASCADE,
+    FOREIGN KEY (page_id) REFERENCES custom_pages (id) ON DELETE CASCADE,
+    FOREIGN KEY (field_id) REFERENCES page_fields (id) ON DELETE CASCADE,
+    INDEX idx_record_due_dates_start (field_id, start_date),
+    INDEX idx_record_due_dates_end (field_id, end_date)
+);
+
+-- Last successful expiry scan, so runs missed while the server was down are caught up.
+CREATE TABLE IF NOT EXISTS expiry_scan_state (
+    id TINYINT UNSIGNED NOT NULL,
+    last_success_date DATE NOT NULL,
+    last_success_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+-- Backfill DATE fields ("YYYY-MM-DD")
+INSERT IGNORE INTO record_due_dates (record_id, page_id, field_id, start_date, end_date)
+SELECT pr.id, pr.page_id, f.id,
+       STR_TO_DATE(JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '"'))), '%Y-%m-%d'),
+       STR_TO_DATE(JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '"'))), '%Y-%m-%d')
+FROM page_records pr
+JOIN page_fields f ON f.page_id = pr.page_id
+JOIN field_types t ON t.id = f.field_type_id AND t.name = 'DATE'
+WHERE JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '"'))) REGEXP '^[0-9]{4}-[0-9]{2}-[0-9]{2}$';
+
+-- Backfill DATE_RANGE fields ({"start": "YYYY-MM-DD", "end": "YYYY-MM-DD"})
+INSERT IGNORE INTO record_due_dates (record_id, page_id, field_id, start_date, end_date)
+SELECT pr.id, pr.page_id, f.id,
+       STR_TO_DATE(JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '".start'))), '%Y-%m-%d'),
+       STR_TO_DATE(JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '".end'))), '%Y-%m-%d')
+FROM page_records pr
+JOIN page_fields f ON f.page_id = pr.page_id
+JOIN field_types t ON t.id = f.field_type_id AND t.name = 'DATE_RANGE'
+WHERE JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '".start'))) REGEXP '^[0-9]{4}-[0-9]{2}-[0-9]{2}$'
+  AND JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '".end'))) REGEXP '^[0-9]{4}-[0-9]{2}-[0-9]{2}$';
+
+-- Backfill DATE fields saved by older versions ("DD/MM/YYYY")
+INSERT IGNORE INTO record_due_dates (record_id, page_id, field_id, start_date, end_date)
+SELECT pr.id, pr.page_id, f.id,
+       STR_TO_DATE(TRIM(JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '"')))), '%d/%m/%Y'),
+       STR_TO_DATE(TRIM(JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '"')))), '%d/%m/%Y')
+FROM page_records pr
+JOIN page_fields f ON f.page_id = pr.page_id
+JOIN field_types t ON t.id = f.field_type_id AND t.name = 'DATE'
+WHERE TRIM(JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '"')))) REGEXP '^[0-9]{2}/[0-9]{2}/[0-9]{4}$';
+
+-- Backfill DATE_RANGE fields saved by older versions ("DD/MM/YYYY - DD/MM/YYYY")
+INSERT IGNORE INTO record_due_dates (record_id, page_id, field_id, start_date, end_date)
+SELECT pr.id, pr.page_id, f.id,
+       STR_TO_DATE(TRIM(SUBSTRING_INDEX(JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '"'))), ' - ', 1)), '%d/%m/%Y'),
+       STR_TO_DATE(TRIM(SUBSTRING_INDEX(JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '"'))), ' - ', -1)), '%d/%m/%Y')
+FROM page_records pr
+JOIN page_fields f ON f.page_id = pr.page_id
+JOIN field_types t ON t.id = f.field_type_id AND t.name = 'DATE_RANGE'
+WHERE JSON_UNQUOTE(JSON_EXTRACT(pr.data, CONCAT('$."', f.name, '"'))) REGEXP '^ *[0-9]{2}/[0-9]{2}/[0-9]{4} - [0-9]{2}/[0-9]{2}/[0-9]{4} *$';
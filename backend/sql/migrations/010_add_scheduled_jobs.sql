@@ -0,0 +1,29 @@
+-- Background jobs run by the backend scheduler. One row per named job; the lock columns make
+-- sure only one backend instance runs a job at a time.
+CREATE TABLE IF NOT EXISTS scheduled_jobs (
+    name VARCHAR(100) NOT NULL,
+    cron_expression VARCHAR(100) NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT true,
+    next_run_at TIMESTAMP NULL,
+    last_run_at TIMESTAMP NULL,
+    last_status VARCHAR(20) NULL,
+    locked_by VARCHAR(64) NULL,
+    locked_until TIMESTAMP NULL,
+    PRIMARY KEY (name)
+);
+
+-- History of every job run, scheduled or triggered manually by an admin.
+CREATE TABLE IF NOT EXISTS scheduled_job_runs (
+    id INT UNSIGNED AUTO_INCREMENT,
+    job_name VARCHAR(100) NOT NULL,
+    instance_id VARCHAR(64) NOT NULL,
+    triggered_by INT UNSIGNED NULL,
+    status VARCHAR(20) NOT NULL,
+    message TEXT NULL,
+    started_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at TIMESTAMP NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (job_name) REFERENCES scheduled_jobs (name) ON DELETE CASCADE,
+    FOREIGN KEY (triggered_by) REFERENCES users (id) ON DELETE SET NULL,
+    INDEX idx_scheduled_job_runs_job (job_name, started_at)
+);
@@ -0,0 +1,14 @@
+-- Per-role read/write permissions on individual page fields.
+-- A field without any row here follows the page permissions; once a field has rows, only
+-- the listed roles (and admins) can read or write it.
+CREATE TABLE IF NOT EXISTS page_field_permissions (
+    id INT UNSIGNED AUTO_INCREMENT UNIQUE NOT NULL,
+    field_id INT UNSIGNED NOT NULL,
+    role_id INT UNSIGNED NOT NULL,
+    can_read BOOLEAN NOT NULL DEFAULT false,
+    can_write BOOLEAN NOT NULL DEFAULT false,
+    PRIMARY KEY (id),
+    UNIQUE KEY unique_field_role (field_id, role_id),
+    FOREIGN KEY (field_id) REFERENCES page_fields (id) ON DELETE CASCADE,
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE
+);
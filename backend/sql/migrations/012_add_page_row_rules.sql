@@ -0,0 +1,15 @@
+-- Row-level access rules per page, role and action (VIEW, EDIT, DELETE).
+-- A role without rules for an action keeps access to every record it has page permission for;
+-- once it has rules, it only reaches the records matching at least one of them.
+CREATE TABLE IF NOT EXISTS page_row_rules (
+    id INT UNSIGNED AUTO_INCREMENT UNIQUE NOT NULL,
+    page_id INT UNSIGNED NOT NULL,
+    role_id INT UNSIGNED NOT NULL,
+    action VARCHAR(10) NOT NULL,
+    rule_type VARCHAR(30) NOT NULL,
+    field_name VARCHAR(100) NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (page_id) REFERENCES custom_pages (id) ON DELETE CASCADE,
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE,
+    INDEX idx_page_row_rules_page_role (page_id, role_id, action)
+);
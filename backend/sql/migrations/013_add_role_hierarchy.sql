@@ -0,0 +1,44 @@
+-- Roles can have a parent role: a role holds everything granted to its parent and the parent's
+-- ancestors. Permissions granted on a group page also apply to every page under its path,
+-- unless the role has its own row on a page closer to it.
+ALTER TABLE roles
+    ADD COLUMN parent_role_id INT UNSIGNED NULL AFTER is_admin,
+    ADD CONSTRAINT fk_roles_parent FOREIGN KEY (parent_role_id) REFERENCES roles (id) ON DELETE SET NULL;
+
+-- Roles held by each user, directly or through the parents of their roles.
+-- The depth limit only guards against cycles; the API refuses to create them.
+CREATE OR REPLACE VIEW user_effective_roles AS
+WITH RECURSIVE chain (user_id, role_id, depth) AS (
+    SELECT user_id, role_id, 0 FROM user_roles
+    UNION ALL
+    SELECT c.user_id, r.parent_role_id, c.depth + 1
+    FROM chain c
+    JOIN roles r ON r.id = c.role_id
+    WHERE r.parent_role_id IS NOT NULL AND c.depth < 16
+)
+SELECT DISTINCT user_id, role_id FROM chain;
+
+-- Permissions of each role on each page. A page without a row for a role takes the row of the
+-- nearest group above it that has one.
+CREATE OR REPLACE VIEW effective_page_permissions AS
+WITH RECURSIVE ancestors (page_id, ancestor_id, parent_path, depth) AS (
+    SELECT id, id, parent_path, 0 FROM custom_pages
+    UNION ALL
+    SELECT a.page_id, p.id, p.parent_path, a.depth + 1
+    FROM ancestors a
+    JOIN custom_pages p ON p.path = a.parent_path AND p.is_group = true
+    WHERE a.depth < 16
+),
+candidates AS (
+    SELECT a.page_id, pp.role_id, pp.page_id AS source_page_id,
+           pp.can_view, pp.can_create, pp.can_edit, pp.can_delete,
+           pp.can_manage_fields, pp.can_view_acknowledgments, pp.can_add,
+           ROW_NUMBER() OVER (PARTITION BY a.page_id, pp.role_id ORDER BY a.depth) AS nearest
+    FROM ancestors a
+    JOIN page_permissions pp ON pp.page_id = a.ancestor_id
+)
+SELECT page_id, role_id, source_page_id,
+       can_view, can_create, can_edit, can_delete,
+       can_manage_fields, can_view_acknowledgments, can_add
+FROM candidates
+WHERE nearest = 1;
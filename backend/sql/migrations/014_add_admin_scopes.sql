@@ -0,0 +1,38 @@
+-- Scoped administration: a role can administer the pages under some groups and the members of
+-- some roles without being a global admin.
+CREATE TABLE IF NOT EXISTS role_admin_page_groups (
+    role_id INT UNSIGNED NOT NULL,
+    page_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (role_id, page_id),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE,
+    FOREIGN KEY (page_id) REFERENCES custom_pages (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS role_admin_roles (
+    role_id INT UNSIGNED NOT NULL,
+    managed_role_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (role_id, managed_role_id),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE,
+    FOREIGN KEY (managed_role_id) REFERENCES roles (id) ON DELETE CASCADE
+);
+
+-- Pages each user administers: the groups granted to their roles and everything under them.
+CREATE OR REPLACE VIEW user_managed_pages AS
+WITH RECURSIVE managed (user_id, page_id, path, depth) AS (
+    SELECT ur.user_id, cp.id, cp.path, 0
+    FROM user_effective_roles ur
+    JOIN role_admin_page_groups g ON g.role_id = ur.role_id
+    JOIN custom_pages cp ON cp.id = g.page_id
+    UNION ALL
+    SELECT m.user_id, cp.id, cp.path, m.depth + 1
+    FROM managed m
+    JOIN custom_pages cp ON cp.parent_path = m.path
+    WHERE m.depth < 16
+)
+SELECT DISTINCT user_id, page_id FROM managed;
+
+-- Roles whose members each user administers.
+CREATE OR REPLACE VIEW user_managed_roles AS
+SELECT DISTINCT ur.user_id, ar.managed_role_id AS role_id
+FROM user_effective_roles ur
+JOIN role_admin_roles ar ON ar.role_id = ur.role_id;
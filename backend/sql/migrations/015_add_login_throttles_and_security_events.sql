@@ -0,0 +1,25 @@
+-- Failed login counters per account (email) and per client IP. Once a key goes over its limit
+-- it is locked until `locked_until`, for longer after each further failure.
+CREATE TABLE IF NOT EXISTS login_throttles (
+    scope VARCHAR(10) NOT NULL,
+    throttle_key VARCHAR(255) NOT NULL,
+    failed_attempts INT UNSIGNED NOT NULL DEFAULT 0,
+    last_failure_at TIMESTAMP NULL,
+    locked_until TIMESTAMP NULL,
+    PRIMARY KEY (scope, throttle_key)
+);
+
+-- Security relevant events. Users are not foreign keys so the history survives their deletion.
+CREATE TABLE IF NOT EXISTS security_events (
+    id INT UNSIGNED AUTO_INCREMENT,
+    event_type VARCHAR(30) NOT NULL,
+    user_id INT UNSIGNED NULL,
+    actor_user_id INT UNSIGNED NULL,
+    ip_address VARCHAR(45) NULL,
+    details TEXT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_security_events_user (user_id, created_at),
+    INDEX idx_security_events_type (event_type, created_at),
+    INDEX idx_security_events_created (created_at)
+);
@@ -0,0 +1,27 @@
+-- TOTP second factor per user. The row is created when enrollment starts and only counts once
+-- `enabled`; `last_used_step` stops a code from being used twice.
+CREATE TABLE IF NOT EXISTS user_two_factor (
+    user_id INT UNSIGNED NOT NULL,
+    secret VARCHAR(64) NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT false,
+    last_used_step BIGINT NULL,
+    enabled_at TIMESTAMP NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+-- Single-use recovery codes, stored as SHA-256.
+CREATE TABLE IF NOT EXISTS user_recovery_codes (
+    id INT UNSIGNED AUTO_INCREMENT,
+    user_id INT UNSIGNED NOT NULL,
+    code_hash BINARY(32) NOT NULL,
+    used_at TIMESTAMP NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    INDEX idx_user_recovery_codes_user (user_id)
+);
+
+-- Members of these roles must use a second factor to log in.
+ALTER TABLE roles
+    ADD COLUMN requires_two_factor BOOLEAN NOT NULL DEFAULT false AFTER parent_role_id;
@@ -0,0 +1,30 @@
+-- Single-use tokens behind invitation and password reset links. Only the SHA-256 of the token
+-- is stored.
+CREATE TABLE IF NOT EXISTS user_tokens (
+    id INT UNSIGNED AUTO_INCREMENT,
+    user_id INT UNSIGNED NOT NULL,
+    purpose ENUM('INVITATION', 'PASSWORD_RESET') NOT NULL,
+    token_hash BINARY(32) NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP NULL,
+    created_by INT UNSIGNED NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_user_tokens_hash (token_hash),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE SET NULL,
+    INDEX idx_user_tokens_user (user_id, purpose)
+);
+
+-- Settings changed by admins at runtime
+CREATE TABLE IF NOT EXISTS app_settings (
+    setting_key VARCHAR(64) NOT NULL,
+    setting_value VARCHAR(255) NOT NULL,
+    updated_by INT UNSIGNED NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (setting_key),
+    FOREIGN KEY (updated_by) REFERENCES users (id) ON DELETE SET NULL
+);
+
+-- Registration stays open, as before, until an admin closes it
+INSERT IGNORE INTO app_settings (setting_key, setting_value) VALUES ('registration_open', 'true');
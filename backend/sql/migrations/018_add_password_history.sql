@@ -0,0 +1,14 @@
+-- Passwords are now stored as PHC strings, which carry their own hashing parameters. Existing
+-- 48 byte hashes keep working and are replaced on the next login.
+ALTER TABLE users MODIFY password VARBINARY(255) NOT NULL;
+
+-- Previous password hashes, so they are not reused
+CREATE TABLE IF NOT EXISTS password_history (
+    id INT UNSIGNED AUTO_INCREMENT,
+    user_id INT UNSIGNED NOT NULL,
+    password_hash VARBINARY(255) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    INDEX idx_password_history_user (user_id, created_at)
+);
@@ -0,0 +1,21 @@
+-- Where an account authenticates: 'local' checks users.password, any other value names a
+-- directory provider and the password is checked there. external_id is the entry of the user
+-- in that directory.
+ALTER TABLE users
+    ADD COLUMN auth_source VARCHAR(16) NOT NULL DEFAULT 'local',
+    ADD COLUMN external_id VARCHAR(255) NULL,
+    ADD UNIQUE KEY uk_users_external_id (auth_source, external_id);
+
+-- Provider whose group mapping granted a role, NULL when an admin assigned it. Those roles are
+-- synced with the directory groups on every login.
+ALTER TABLE user_roles
+    ADD COLUMN granted_by VARCHAR(16) NULL;
+
+-- Directory groups whose members get a role
+CREATE TABLE IF NOT EXISTS directory_group_roles (
+    provider VARCHAR(16) NOT NULL,
+    group_name VARCHAR(255) NOT NULL,
+    role_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (provider, group_name, role_id),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE
+);
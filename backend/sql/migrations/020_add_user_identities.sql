@@ -0,0 +1,13 @@
+-- Accounts at single sign-on identity providers linked to users. Unlike users.auth_source
+-- these do not say where the password is checked, a user can keep their password and also log
+-- in through the identity provider.
+CREATE TABLE IF NOT EXISTS user_identities (
+    provider VARCHAR(16) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    user_id INT UNSIGNED NOT NULL,
+    linked_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    last_login_at TIMESTAMP NULL,
+    PRIMARY KEY (provider, subject),
+    INDEX idx_user_identities_user (user_id),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
@@ -0,0 +1,60 @@
+-- Holidays and company closures, replacing the constants in the code. Rules describe holidays
+-- that come back every year, fixed on a date or a number of days from Easter, and every year
+-- they are turned into the rows of holidays that the calendar and the working day counts use.
+-- location is a value of the Location enum; NULL means every clinic.
+CREATE TABLE IF NOT EXISTS holiday_rules (
+    id INT UNSIGNED AUTO_INCREMENT,
+    name VARCHAR(255) NOT NULL,
+    scope ENUM('NATIONAL', 'MUNICIPAL', 'COMPANY') NOT NULL,
+    location TINYINT NULL,
+    month TINYINT UNSIGNED NULL,
+    day TINYINT UNSIGNED NULL,
+    easter_offset SMALLINT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS holidays (
+    id INT UNSIGNED AUTO_INCREMENT,
+    rule_id INT UNSIGNED NULL,
+    name VARCHAR(255) NOT NULL,
+    holiday_date DATE NOT NULL,
+    scope ENUM('NATIONAL', 'MUNICIPAL', 'COMPANY') NOT NULL,
+    location TINYINT NULL,
+    created_by INT UNSIGNED NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_holidays_rule_date (rule_id, holiday_date),
+    INDEX idx_holidays_date (holiday_date),
+    FOREIGN KEY (rule_id) REFERENCES holiday_rules (id) ON DELETE SET NULL,
+    FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE SET NULL
+);
+
+-- Years whose holidays were already generated from the rules
+CREATE TABLE IF NOT EXISTS holiday_years (
+    year SMALLINT UNSIGNED NOT NULL,
+    generated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (year)
+);
+
+INSERT INTO holiday_rules (name, scope, location, month, day, easter_offset) VALUES
+    ('Ano Novo', 'NATIONAL', NULL, 1, 1, NULL),
+    ('Dia da Liberdade', 'NATIONAL', NULL, 4, 25, NULL),
+    ('Dia do Trabalhador', 'NATIONAL', NULL, 5, 1, NULL),
+    ('Dia de Portugal', 'NATIONAL', NULL, 6, 10, NULL),
+    ('Assunção de Nossa Senhora', 'NATIONAL', NULL, 8, 15, NULL),
+    ('Implantação da República', 'NATIONAL', NULL, 10, 5, NULL),
+    ('Todos os Santos', 'NATIONAL', NULL, 11, 1, NULL),
+    ('Restauração da Independência', 'NATIONAL', NULL, 12, 1, NULL),
+    ('Imaculada Conceição', 'NATIONAL', NULL, 12, 8, NULL),
+    ('Natal', 'NATIONAL', NULL, 12, 25, NULL),
+    ('Carnaval', 'NATIONAL', NULL, NULL, NULL, -47),
+    ('Sexta-feira Santa', 'NATIONAL', NULL, NULL, NULL, -2),
+    ('Páscoa', 'NATIONAL', NULL, NULL, NULL, 0),
+    ('Corpo de Deus', 'NATIONAL', NULL, NULL, NULL, 60),
+    ('Nossa Senhora da Agonia', 'MUNICIPAL', 0, 8, 20, NULL),
+    ('São João', 'MUNICIPAL', 1, 6, 24, NULL),
+    ('São João', 'MUNICIPAL', 2, 6, 24, NULL),
+    ('Santo António', 'MUNICIPAL', 3, 6, 13, NULL);
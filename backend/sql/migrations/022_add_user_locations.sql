@@ -0,0 +1,5 @@
+-- Clinic a user works at, a value of the Location enum (0 Viana do Castelo, 1 Braga, 2 Porto,
+-- 3 Vila Real). Its municipal holidays are not counted as vacation days. NULL when the user has
+-- no location, only national and company holidays apply then.
+ALTER TABLE users
+    ADD COLUMN location TINYINT NULL;
@@ -0,0 +1,31 @@
+-- Work schedules: how many minutes are worked on each day of a cycle that repeats, a week or a
+-- rotation of shifts (day_minutes is a JSON array, 0 for days off). Users without a schedule
+-- work Monday to Friday. A user's schedules apply from valid_from until the next one, and the
+-- cycle starts again on cycle_start, so teams on the same rotation can be out of step.
+CREATE TABLE IF NOT EXISTS work_schedules (
+    id INT UNSIGNED AUTO_INCREMENT,
+    name VARCHAR(255) NOT NULL,
+    description TEXT NULL,
+    day_minutes JSON NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS user_work_schedules (
+    id INT UNSIGNED AUTO_INCREMENT,
+    user_id INT UNSIGNED NOT NULL,
+    schedule_id INT UNSIGNED NOT NULL,
+    valid_from DATE NOT NULL,
+    cycle_start DATE NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_user_work_schedules_from (user_id, valid_from),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (schedule_id) REFERENCES work_schedules (id)
+);
+
+-- DAYS counts every scheduled day as one vacation day, HOURS counts the scheduled time and
+-- vacation_days_current_year then holds hours.
+ALTER TABLE users
+    ADD COLUMN vacation_balance_unit ENUM('DAYS', 'HOURS') NOT NULL DEFAULT 'DAYS';
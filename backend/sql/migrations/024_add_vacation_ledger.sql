@@ -0,0 +1,30 @@
+-- Vacation balance of each user and year as a ledger: the year's entitlement, what was carried
+-- over from the year before (until expires_on), adjustments made by admins with a reason, and
+-- the carried-over days lost at the expiry. The balance is the sum of the amounts minus the
+-- approved vacation of the year. Amounts are in the user's vacation_balance_unit.
+CREATE TABLE IF NOT EXISTS vacation_ledger_entries (
+    id INT UNSIGNED AUTO_INCREMENT,
+    user_id INT UNSIGNED NOT NULL,
+    year SMALLINT UNSIGNED NOT NULL,
+    entry_type ENUM('ENTITLEMENT', 'CARRY_OVER', 'ADJUSTMENT', 'EXPIRY') NOT NULL,
+    amount INT NOT NULL,
+    reason VARCHAR(255) NULL,
+    expires_on DATE NULL,
+    created_by INT UNSIGNED NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_vacation_ledger_user_year (user_id, year),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE SET NULL
+);
+
+-- vacation_days_current_year is now the yearly entitlement new years start with. The balance
+-- it held becomes this year's entitlement.
+INSERT INTO vacation_ledger_entries (user_id, year, entry_type, amount, reason)
+SELECT id, YEAR(CURDATE()), 'ENTITLEMENT', COALESCE(vacation_days_current_year, 0),
+       'Saldo anterior ao registo de férias'
+FROM users;
+
+-- Carried-over vacation expires on this day (MM-DD) of the next year. With no
+-- vacation_carry_over_max, everything left is carried over.
+INSERT IGNORE INTO app_settings (setting_key, setting_value) VALUES ('vacation_carry_over_expiry', '04-30');
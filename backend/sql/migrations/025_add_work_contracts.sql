@@ -0,0 +1,28 @@
+-- Worker contracts and their addenda (contract_type 1 worker, 0 addendum) with their term (0
+-- permanent, 1 fixed, 2 uncertain). The one that starts last says when the employment ends,
+-- which the statutory vacation rules need for contracts shorter than 6 months.
+CREATE TABLE IF NOT EXISTS work_contracts (
+    id INT UNSIGNED AUTO_INCREMENT,
+    user_id INT UNSIGNED NOT NULL,
+    contract_type TINYINT NOT NULL,
+    term TINYINT NOT NULL,
+    start_date DATE NOT NULL,
+    end_date DATE NULL,
+    notes VARCHAR(255) NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_work_contracts_user (user_id, start_date),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+-- STATUTORY users get their vacation days from the Código do Trabalho rules, from hire_date on:
+-- 22 a year, and 2 per complete month in the hire year (up to 20) after 6 months of contract.
+-- FIXED users keep getting vacation_days_current_year.
+ALTER TABLE users
+    ADD COLUMN hire_date DATE NULL,
+    ADD COLUMN vacation_entitlement_rule ENUM('FIXED', 'STATUTORY') NOT NULL DEFAULT 'FIXED';
+
+-- Entries that cannot be taken before a date, the days of the hire year until the end of the
+-- waiting period
+ALTER TABLE vacation_ledger_entries
+    ADD COLUMN available_from DATE NULL AFTER expires_on;
@@ -0,0 +1,47 @@
+-- Kinds of absence a request can be for. Each one says whether it needs an admin's approval,
+-- whether a supporting document has to be uploaded (types without approval are approved once
+-- it is), whether its days come out of the vacation ledger, and how many working days it can
+-- take per request and per year (NULL for no limit).
+CREATE TABLE IF NOT EXISTS absence_types (
+    id INT UNSIGNED AUTO_INCREMENT,
+    code VARCHAR(32) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    requires_approval BOOLEAN NOT NULL DEFAULT TRUE,
+    requires_document BOOLEAN NOT NULL DEFAULT FALSE,
+    counts_against_vacation BOOLEAN NOT NULL DEFAULT FALSE,
+    max_days_per_request SMALLINT UNSIGNED NULL,
+    max_days_per_year SMALLINT UNSIGNED NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_absence_types_code (code)
+);
+
+INSERT IGNORE INTO absence_types
+    (id, code, name, requires_approval, requires_document, counts_against_vacation,
+     max_days_per_request, max_days_per_year)
+VALUES
+    (1, 'VACATION', 'Férias', TRUE, FALSE, TRUE, NULL, NULL),
+    (2, 'SICK_LEAVE', 'Baixa médica', FALSE, TRUE, FALSE, NULL, NULL),
+    (3, 'TRAINING', 'Formação', TRUE, FALSE, FALSE, NULL, NULL),
+    (4, 'BEREAVEMENT', 'Falecimento de familiar', FALSE, TRUE, FALSE, 20, NULL),
+    (5, 'PARENTAL_LEAVE', 'Licença parental', TRUE, TRUE, FALSE, NULL, NULL),
+    (6, 'COMPENSATORY', 'Folga compensatória', TRUE, FALSE, FALSE, NULL, NULL);
+
+-- Every existing request is a vacation
+ALTER TABLE vacation_requests
+    ADD COLUMN absence_type_id INT UNSIGNED NOT NULL DEFAULT 1 AFTER user_id,
+    ADD CONSTRAINT fk_vacation_requests_absence_type
+        FOREIGN KEY (absence_type_id) REFERENCES absence_types (id);
+
+CREATE TABLE IF NOT EXISTS absence_documents (
+    id INT UNSIGNED AUTO_INCREMENT,
+    vacation_request_id INT UNSIGNED NOT NULL,
+    file_name VARCHAR(255) NOT NULL,
+    file_path VARCHAR(255) NOT NULL,
+    uploaded_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    uploaded_by INT UNSIGNED NOT NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (vacation_request_id) REFERENCES vacation_requests (id) ON DELETE CASCADE,
+    FOREIGN KEY (uploaded_by) REFERENCES users (id)
+);
@@ -0,0 +1,5 @@
+-- Requests for part of a day: the morning or the afternoon, half of the day's scheduled time,
+-- or a number of minutes (HOURS). Partial requests are for a single day.
+ALTER TABLE vacation_requests
+    ADD COLUMN day_part ENUM('FULL', 'MORNING', 'AFTERNOON', 'HOURS') NOT NULL DEFAULT 'FULL' AFTER end_date,
+    ADD COLUMN minutes SMALLINT UNSIGNED NULL AFTER day_part;
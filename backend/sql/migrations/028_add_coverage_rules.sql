@@ -0,0 +1,18 @@
+-- How many members of a role can be absent on the same day (MAX_ABSENT), or how many must be
+-- present (MIN_PRESENT). A rule with a location only counts the members of that clinic; one
+-- counted per location counts each clinic on its own. Requests that break an active rule are
+-- refused when made, and need the admin to override it when approved.
+CREATE TABLE IF NOT EXISTS coverage_rules (
+    id INT UNSIGNED AUTO_INCREMENT,
+    name VARCHAR(255) NOT NULL,
+    role_id INT UNSIGNED NOT NULL,
+    location TINYINT NULL,
+    per_location BOOLEAN NOT NULL DEFAULT FALSE,
+    requirement ENUM('MAX_ABSENT', 'MIN_PRESENT') NOT NULL,
+    threshold SMALLINT UNSIGNED NOT NULL,
+    weekdays_only BOOLEAN NOT NULL DEFAULT FALSE,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE
+);
@@ -0,0 +1,57 @@
+-- Steps a request of a member of the role goes through before it is approved, in order. Each
+-- step is approved by a member of approver_role_id, who is reminded once deadline_days have
+-- passed. Users with several roles follow the chain of the first one that has steps; without
+-- a chain, any admin approves.
+CREATE TABLE IF NOT EXISTS approval_steps (
+    id INT UNSIGNED AUTO_INCREMENT,
+    role_id INT UNSIGNED NOT NULL,
+    step_order TINYINT UNSIGNED NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    approver_role_id INT UNSIGNED NOT NULL,
+    deadline_days SMALLINT UNSIGNED NULL,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_approval_steps_order (role_id, step_order),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE,
+    FOREIGN KEY (approver_role_id) REFERENCES roles (id) ON DELETE CASCADE
+);
+
+-- An approver's steps go to the delegate while the delegation lasts
+CREATE TABLE IF NOT EXISTS approval_delegations (
+    id INT UNSIGNED AUTO_INCREMENT,
+    approver_id INT UNSIGNED NOT NULL,
+    delegate_id INT UNSIGNED NOT NULL,
+    start_date DATE NOT NULL,
+    end_date DATE NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_approval_delegations_approver (approver_id, start_date),
+    FOREIGN KEY (approver_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (delegate_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+-- The approval trail of each request: one row per step it reached, and per approver a step
+-- was taken away from (REASSIGNED). Requests without a chain get one row, step 0, for the
+-- admin's decision.
+CREATE TABLE IF NOT EXISTS vacation_request_approvals (
+    id INT UNSIGNED AUTO_INCREMENT,
+    vacation_request_id INT UNSIGNED NOT NULL,
+    step_order TINYINT UNSIGNED NOT NULL,
+    step_name VARCHAR(255) NULL,
+    approver_role_id INT UNSIGNED NULL,
+    assigned_to INT UNSIGNED NULL,
+    delegated_from INT UNSIGNED NULL,
+    status ENUM('PENDING', 'APPROVED', 'REJECTED', 'REASSIGNED') NOT NULL DEFAULT 'PENDING',
+    acted_by INT UNSIGNED NULL,
+    notes TEXT NULL,
+    due_date DATE NULL,
+    reminded_at TIMESTAMP NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    acted_at TIMESTAMP NULL,
+    PRIMARY KEY (id),
+    INDEX idx_vacation_request_approvals_status (status, assigned_to),
+    FOREIGN KEY (vacation_request_id) REFERENCES vacation_requests (id) ON DELETE CASCADE,
+    FOREIGN KEY (approver_role_id) REFERENCES roles (id) ON DELETE SET NULL,
+    FOREIGN KEY (assigned_to) REFERENCES users (id) ON DELETE SET NULL,
+    FOREIGN KEY (delegated_from) REFERENCES users (id) ON DELETE SET NULL,
+    FOREIGN KEY (acted_by) REFERENCES users (id) ON DELETE SET NULL
+);
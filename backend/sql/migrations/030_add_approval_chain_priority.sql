@@ -0,0 +1,13 @@
+-- Users with several roles that have an approval chain follow the chain with the lowest
+-- priority. Priorities are unique, so the choice never depends on the order of the roles.
+CREATE TABLE IF NOT EXISTS approval_chains (
+    role_id INT UNSIGNED NOT NULL,
+    priority INT UNSIGNED NOT NULL,
+    PRIMARY KEY (role_id),
+    UNIQUE KEY uk_approval_chains_priority (priority),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE
+);
+
+-- Existing chains keep the order they were picked in until now, the lowest role id first
+INSERT IGNORE INTO approval_chains (role_id, priority)
+SELECT DISTINCT role_id, role_id FROM approval_steps;
@@ -0,0 +1,83 @@
+-- The role and page hierarchies flattened into tables, so the views that resolve inherited
+-- roles and permissions are plain joins MySQL can merge into each query instead of recursive
+-- CTEs it materializes in full every time. The backend rebuilds both tables whenever a role's
+-- parent or a page's place in the tree changes.
+
+-- Every role with itself and each role above it.
+CREATE TABLE IF NOT EXISTS role_ancestors (
+    role_id INT UNSIGNED NOT NULL,
+    ancestor_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (role_id, ancestor_id),
+    INDEX idx_role_ancestors_ancestor (ancestor_id),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE,
+    FOREIGN KEY (ancestor_id) REFERENCES roles (id) ON DELETE CASCADE
+);
+
+-- Every page with itself and each group above it, depth 0 being the page.
+CREATE TABLE IF NOT EXISTS page_ancestors (
+    page_id INT UNSIGNED NOT NULL,
+    ancestor_id INT UNSIGNED NOT NULL,
+    depth TINYINT UNSIGNED NOT NULL,
+    PRIMARY KEY (page_id, ancestor_id),
+    INDEX idx_page_ancestors_ancestor (ancestor_id),
+    FOREIGN KEY (page_id) REFERENCES custom_pages (id) ON DELETE CASCADE,
+    FOREIGN KEY (ancestor_id) REFERENCES custom_pages (id) ON DELETE CASCADE
+);
+
+-- The depth limit only guards against cycles; the API refuses to create them.
+INSERT IGNORE INTO role_ancestors (role_id, ancestor_id)
+WITH RECURSIVE chain (role_id, ancestor_id, depth) AS (
+    SELECT id, id, 0 FROM roles
+    UNION ALL
+    SELECT c.role_id, r.parent_role_id, c.depth + 1
+    FROM chain c
+    JOIN roles r ON r.id = c.ancestor_id
+    WHERE r.parent_role_id IS NOT NULL AND c.depth < 16
+)
+SELECT DISTINCT role_id, ancestor_id FROM chain;
+
+INSERT IGNORE INTO page_ancestors (page_id, ancestor_id, depth)
+WITH RECURSIVE chain (page_id, ancestor_id, parent_path, depth) AS (
+    SELECT id, id, parent_path, 0 FROM custom_pages
+    UNION ALL
+    SELECT c.page_id, p.id, p.parent_path, c.depth + 1
+    FROM chain c
+    JOIN custom_pages p ON p.path = c.parent_path AND p.is_group = true
+    WHERE c.depth < 16
+)
+SELECT page_id, ancestor_id, MIN(depth) FROM chain GROUP BY page_id, ancestor_id;
+
+-- Roles held by each user, directly or through the parents of their roles. A role the user
+-- reaches in two ways is listed twice.
+CREATE OR REPLACE VIEW user_effective_roles AS
+SELECT ur.user_id, ra.ancestor_id AS role_id
+FROM user_roles ur
+JOIN role_ancestors ra ON ra.role_id = ur.role_id;
+
+-- Permissions of each role on each page. A page without a row for a role takes the row of the
+-- nearest group above it that has one.
+CREATE OR REPLACE VIEW effective_page_permissions AS
+SELECT pa.page_id, pp.role_id, pp.page_id AS source_page_id,
+       pp.can_view, pp.can_create, pp.can_edit, pp.can_delete,
+       pp.can_manage_fields, pp.can_view_acknowledgments, pp.can_add
+FROM page_ancestors pa
+JOIN page_permissions pp ON pp.page_id = pa.ancestor_id
+WHERE NOT EXISTS (
+    SELECT 1
+    FROM page_ancestors closer
+    JOIN page_permissions cpp ON cpp.page_id = closer.ancestor_id AND cpp.role_id = pp.role_id
+    WHERE closer.page_id = pa.page_id AND closer.depth < pa.depth
+);
+
+-- Pages each user administers: the groups granted to their roles and everything under them.
+CREATE OR REPLACE VIEW user_managed_pages AS
+SELECT ur.user_id, pa.page_id
+FROM user_effective_roles ur
+JOIN role_admin_page_groups g ON g.role_id = ur.role_id
+JOIN page_ancestors pa ON pa.ancestor_id = g.page_id;
+
+-- Roles whose members each user administers.
+CREATE OR REPLACE VIEW user_managed_roles AS
+SELECT ur.user_id, ar.managed_role_id AS role_id
+FROM user_effective_roles ur
+JOIN role_admin_roles ar ON ar.role_id = ur.role_id;
@@ -2,9 +2,16 @@ CREATE TABLE users (
     id INT UNSIGNED AUTO_INCREMENT UNIQUE NOT NULL,
     username VARCHAR(255) NOT NULL,
     email VARCHAR(255) UNIQUE NOT NULL,
-    password BINARY(48) NOT NULL,
-    vacation_days_current_year SMALLINT UNSIGNED DEFAULT 0 COMMENT 'Remaining vacation days for the current year',
-    PRIMARY KEY (id)
+    password VARBINARY(255) NOT NULL COMMENT 'Argon2 PHC string, or 48 raw bytes for old accounts',
+    vacation_days_current_year SMALLINT UNSIGNED DEFAULT 0 COMMENT 'Yearly vacation entitlement, what each new year starts with in the ledger',
+    auth_source VARCHAR(16) NOT NULL DEFAULT 'local' COMMENT 'local, or the directory provider that checks the password',
+    external_id VARCHAR(255) NULL COMMENT 'Entry of the user in the directory',
+    location TINYINT NULL COMMENT 'Clinic the user works at (0 Viana do Castelo, 1 Braga, 2 Porto, 3 Vila Real), whose municipal holidays apply',
+    vacation_balance_unit ENUM('DAYS', 'HOURS') NOT NULL DEFAULT 'DAYS' COMMENT 'HOURS when vacation_days_current_year holds hours of the work schedule',
+    hire_date DATE NULL COMMENT 'Start of the employment, for the statutory vacation rules',
+    vacation_entitlement_rule ENUM('FIXED', 'STATUTORY') NOT NULL DEFAULT 'FIXED' COMMENT 'FIXED gets vacation_days_current_year every year, STATUTORY the Código do Trabalho days from hire_date',
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_users_external_id (auth_source, external_id)
 );
 
 CREATE TABLE   user_page_analytics (
@@ -83,9 +90,12 @@ CREATE TABLE   roles (
     name VARCHAR(100) NOT NULL UNIQUE,
     description TEXT,
     is_admin BOOLEAN NOT NULL DEFAULT false,
+    parent_role_id INT UNSIGNED NULL COMMENT 'The role inherits everything granted to its parent',
+    requires_two_factor BOOLEAN NOT NULL DEFAULT false COMMENT 'Members must log in with a second factor',
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
     updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
-    PRIMARY KEY (id)
+    PRIMARY KEY (id),
+    CONSTRAINT fk_roles_parent FOREIGN KEY (parent_role_id) REFERENCES roles (id) ON DELETE SET NULL
 );
 
 -- Role Holiday Groups Table (for roles that interfere with each other for vacations)
@@ -107,6 +117,7 @@ CREATE INDEX   idx_role_holiday_groups_interferes_with ON role_holiday_groups (i
 CREATE TABLE   user_roles (
     user_id INT UNSIGNED NOT NULL,
     role_id INT UNSIGNED NOT NULL,
+    granted_by VARCHAR(16) NULL COMMENT 'Provider whose group mapping granted the role, NULL when assigned by an admin',
     PRIMARY KEY (user_id, role_id),
     FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
     FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE
@@ -130,6 +141,107 @@ CREATE TABLE   page_permissions (
     FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE
 );
 
+-- Role Ancestors Table (every role with itself and each role above it, rebuilt by the backend when a parent changes)
+CREATE TABLE   role_ancestors (
+    role_id INT UNSIGNED NOT NULL,
+    ancestor_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (role_id, ancestor_id),
+    INDEX idx_role_ancestors_ancestor (ancestor_id),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE,
+    FOREIGN KEY (ancestor_id) REFERENCES roles (id) ON DELETE CASCADE
+);
+
+-- Page Ancestors Table (every page with itself and each group above it, rebuilt by the backend when the tree changes)
+CREATE TABLE   page_ancestors (
+    page_id INT UNSIGNED NOT NULL,
+    ancestor_id INT UNSIGNED NOT NULL,
+    depth TINYINT UNSIGNED NOT NULL COMMENT '0 for the page itself',
+    PRIMARY KEY (page_id, ancestor_id),
+    INDEX idx_page_ancestors_ancestor (ancestor_id),
+    FOREIGN KEY (page_id) REFERENCES custom_pages (id) ON DELETE CASCADE,
+    FOREIGN KEY (ancestor_id) REFERENCES custom_pages (id) ON DELETE CASCADE
+);
+
+-- Roles held by each user, directly or through the parents of their roles. A role the user
+-- reaches in two ways is listed twice.
+CREATE OR REPLACE VIEW user_effective_roles AS
+SELECT ur.user_id, ra.ancestor_id AS role_id
+FROM user_roles ur
+JOIN role_ancestors ra ON ra.role_id = ur.role_id;
+
+-- Permissions of each role on each page. A page without a row for a role takes the row of the
+-- nearest group above it that has one.
+CREATE OR REPLACE VIEW effective_page_permissions AS
+SELECT pa.page_id, pp.role_id, pp.page_id AS source_page_id,
+       pp.can_view, pp.can_create, pp.can_edit, pp.can_delete,
+       pp.can_manage_fields, pp.can_view_acknowledgments, pp.can_add
+FROM page_ancestors pa
+JOIN page_permissions pp ON pp.page_id = pa.ancestor_id
+WHERE NOT EXISTS (
+    SELECT 1
+    FROM page_ancestors closer
+    JOIN page_permissions cpp ON cpp.page_id = closer.ancestor_id AND cpp.role_id = pp.role_id
+    WHERE closer.page_id = pa.page_id AND closer.depth < pa.depth
+);
+
+-- Page Field Permissions Table (per-role read/write on single fields; fields without rows follow page_permissions)
+CREATE TABLE   page_field_permissions (
+    id INT UNSIGNED AUTO_INCREMENT UNIQUE NOT NULL,
+    field_id INT UNSIGNED NOT NULL,
+    role_id INT UNSIGNED NOT NULL,
+    can_read BOOLEAN NOT NULL DEFAULT false,
+    can_write BOOLEAN NOT NULL DEFAULT false,
+    PRIMARY KEY (id),
+    UNIQUE KEY unique_field_role (field_id, role_id),
+    FOREIGN KEY (field_id) REFERENCES page_fields (id) ON DELETE CASCADE,
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE
+);
+
+-- Page Row Rules Table (row-level access per role and action; roles without rules reach every record)
+CREATE TABLE   page_row_rules (
+    id INT UNSIGNED AUTO_INCREMENT UNIQUE NOT NULL,
+    page_id INT UNSIGNED NOT NULL,
+    role_id INT UNSIGNED NOT NULL,
+    action VARCHAR(10) NOT NULL COMMENT 'VIEW, EDIT or DELETE',
+    rule_type VARCHAR(30) NOT NULL COMMENT 'OWN_RECORDS, FIELD_MATCHES_ROLE or FIELD_MATCHES_LOCATION',
+    field_name VARCHAR(100) NULL COMMENT 'Record field compared by the FIELD_MATCHES_* rules',
+    PRIMARY KEY (id),
+    FOREIGN KEY (page_id) REFERENCES custom_pages (id) ON DELETE CASCADE,
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE,
+    INDEX idx_page_row_rules_page_role (page_id, role_id, action)
+);
+
+-- Role Admin Page Groups Table (the role administers these groups and every page under them)
+CREATE TABLE   role_admin_page_groups (
+    role_id INT UNSIGNED NOT NULL,
+    page_id INT UNSIGNED NOT NULL COMMENT 'Group page',
+    PRIMARY KEY (role_id, page_id),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE,
+    FOREIGN KEY (page_id) REFERENCES custom_pages (id) ON DELETE CASCADE
+);
+
+-- Role Admin Roles Table (the role administers the members of these roles)
+CREATE TABLE   role_admin_roles (
+    role_id INT UNSIGNED NOT NULL,
+    managed_role_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (role_id, managed_role_id),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE,
+    FOREIGN KEY (managed_role_id) REFERENCES roles (id) ON DELETE CASCADE
+);
+
+-- Pages each user administers: the groups granted to their roles and everything under them.
+CREATE OR REPLACE VIEW user_managed_pages AS
+SELECT ur.user_id, pa.page_id
+FROM user_effective_roles ur
+JOIN role_admin_page_groups g ON g.role_id = ur.role_id
+JOIN page_ancestors pa ON pa.ancestor_id = g.page_id;
+
+-- Roles whose members each user administers.
+CREATE OR REPLACE VIEW user_managed_roles AS
+SELECT ur.user_id, ar.managed_role_id AS role_id
+FROM user_effective_roles ur
+JOIN role_admin_roles ar ON ar.role_id = ur.role_id;
+
 -- Page Records Table (for storing dynamic data)
 CREATE TABLE   page_records (
     id INT UNSIGNED AUTO_INCREMENT UNIQUE NOT NULL,
@@ -171,11 +283,41 @@ CREATE TABLE   record_acknowledgments (
 );
 
 -- Vacation Requests Table
+-- Absence Types Table (vacation, sick leave, training... and the rules of each)
+CREATE TABLE   absence_types (
+    id INT UNSIGNED AUTO_INCREMENT,
+    code VARCHAR(32) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    requires_approval BOOLEAN NOT NULL DEFAULT TRUE COMMENT 'Requests of types without approval are approved when made, or once their document is uploaded',
+    requires_document BOOLEAN NOT NULL DEFAULT FALSE COMMENT 'A supporting document must be uploaded before the request is approved',
+    counts_against_vacation BOOLEAN NOT NULL DEFAULT FALSE COMMENT 'Working days are taken from the vacation ledger',
+    max_days_per_request SMALLINT UNSIGNED NULL COMMENT 'Most working days of one request, NULL for no limit',
+    max_days_per_year SMALLINT UNSIGNED NULL COMMENT 'Most working days a user can have in a year, NULL for no limit',
+    active BOOLEAN NOT NULL DEFAULT TRUE COMMENT 'Inactive types cannot be requested any more',
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_absence_types_code (code)
+);
+
+INSERT IGNORE INTO absence_types
+    (id, code, name, requires_approval, requires_document, counts_against_vacation,
+     max_days_per_request, max_days_per_year)
+VALUES
+    (1, 'VACATION', 'Férias', TRUE, FALSE, TRUE, NULL, NULL),
+    (2, 'SICK_LEAVE', 'Baixa médica', FALSE, TRUE, FALSE, NULL, NULL),
+    (3, 'TRAINING', 'Formação', TRUE, FALSE, FALSE, NULL, NULL),
+    (4, 'BEREAVEMENT', 'Falecimento de familiar', FALSE, TRUE, FALSE, 20, NULL),
+    (5, 'PARENTAL_LEAVE', 'Licença parental', TRUE, TRUE, FALSE, NULL, NULL),
+    (6, 'COMPENSATORY', 'Folga compensatória', TRUE, FALSE, FALSE, NULL, NULL);
+
 CREATE TABLE   vacation_requests (
     id INT UNSIGNED AUTO_INCREMENT UNIQUE NOT NULL,
     user_id INT UNSIGNED NOT NULL,
+    absence_type_id INT UNSIGNED NOT NULL DEFAULT 1 COMMENT 'What the absence is for, 1 for vacation',
     start_date DATE NOT NULL,
     end_date DATE NOT NULL,
+    day_part ENUM('FULL', 'MORNING', 'AFTERNOON', 'HOURS') NOT NULL DEFAULT 'FULL' COMMENT 'Part of the day taken, partial requests are for a single day',
+    minutes SMALLINT UNSIGNED NULL COMMENT 'Time taken by HOURS requests',
     status ENUM('PENDING', 'APPROVED', 'REJECTED') NOT NULL DEFAULT 'PENDING',
     notes TEXT DEFAULT NULL COMMENT 'User notes on request, or admin notes on action',
     requested_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
@@ -184,6 +326,7 @@ CREATE TABLE   vacation_requests (
     PRIMARY KEY (id),
     FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
     FOREIGN KEY (approved_by) REFERENCES users(id) ON DELETE SET NULL,
+    FOREIGN KEY (absence_type_id) REFERENCES absence_types (id),
     INDEX idx_vacation_user_status (user_id, status),
     INDEX idx_vacation_dates (start_date, end_date)
 );
@@ -210,3 +353,373 @@ CREATE TABLE   notifications (
     INDEX idx_user_unread (user_id, is_read),
     INDEX idx_vacation_request_id (vacation_request_id)
 );
+
+-- Record Due Dates Table (index of DATE / DATE_RANGE values, maintained on record write)
+CREATE TABLE   record_due_dates (
+    record_id INT UNSIGNED NOT NULL,
+    page_id INT UNSIGNED NOT NULL,
+    field_id INT UNSIGNED NOT NULL,
+    start_date DATE NOT NULL,
+    end_date DATE NOT NULL,
+    PRIMARY KEY (record_id, field_id),
+    FOREIGN KEY (record_id) REFERENCES page_records (id) ON DELETE CASCADE,
+    FOREIGN KEY (page_id) REFERENCES custom_pages (id) ON DELETE CASCADE,
+    FOREIGN KEY (field_id) REFERENCES page_fields (id) ON DELETE CASCADE,
+    INDEX idx_record_due_dates_start (field_id, start_date),
+    INDEX idx_record_due_dates_end (field_id, end_date)
+);
+
+-- Expiry Scan State Table (last successful run of the expiry notification scan)
+CREATE TABLE   expiry_scan_state (
+    id TINYINT UNSIGNED NOT NULL,
+    last_success_date DATE NOT NULL,
+    last_success_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+-- Scheduled Jobs Table (background jobs, with the lock used to run each job on a single instance)
+CREATE TABLE   scheduled_jobs (
+    name VARCHAR(100) NOT NULL,
+    cron_expression VARCHAR(100) NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT true,
+    next_run_at TIMESTAMP NULL,
+    last_run_at TIMESTAMP NULL,
+    last_status VARCHAR(20) NULL,
+    locked_by VARCHAR(64) NULL,
+    locked_until TIMESTAMP NULL,
+    PRIMARY KEY (name)
+);
+
+-- Scheduled Job Runs Table (run history)
+CREATE TABLE   scheduled_job_runs (
+    id INT UNSIGNED AUTO_INCREMENT,
+    job_name VARCHAR(100) NOT NULL,
+    instance_id VARCHAR(64) NOT NULL,
+    triggered_by INT UNSIGNED NULL,
+    status VARCHAR(20) NOT NULL,
+    message TEXT NULL,
+    started_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at TIMESTAMP NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (job_name) REFERENCES scheduled_jobs (name) ON DELETE CASCADE,
+    FOREIGN KEY (triggered_by) REFERENCES users (id) ON DELETE SET NULL,
+    INDEX idx_scheduled_job_runs_job (job_name, started_at)
+);
+
+-- Login Throttles Table (failed logins per account and per IP, with progressive lockout)
+CREATE TABLE   login_throttles (
+    scope VARCHAR(10) NOT NULL COMMENT 'ACCOUNT, IP or RESET (password reset emails)',
+    throttle_key VARCHAR(255) NOT NULL COMMENT 'Lowercase email or client IP',
+    failed_attempts INT UNSIGNED NOT NULL DEFAULT 0,
+    last_failure_at TIMESTAMP NULL,
+    locked_until TIMESTAMP NULL,
+    PRIMARY KEY (scope, throttle_key)
+);
+
+-- Security Events Table (logins, password and role changes, user deletions)
+CREATE TABLE   security_events (
+    id INT UNSIGNED AUTO_INCREMENT,
+    event_type VARCHAR(30) NOT NULL,
+    user_id INT UNSIGNED NULL COMMENT 'User the event is about, kept after deletion',
+    actor_user_id INT UNSIGNED NULL COMMENT 'User who caused the event when not the same',
+    ip_address VARCHAR(45) NULL,
+    details TEXT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_security_events_user (user_id, created_at),
+    INDEX idx_security_events_type (event_type, created_at),
+    INDEX idx_security_events_created (created_at)
+);
+
+-- User Two Factor Table (TOTP secret, only used once enabled)
+CREATE TABLE   user_two_factor (
+    user_id INT UNSIGNED NOT NULL,
+    secret VARCHAR(64) NOT NULL COMMENT 'Base32 TOTP secret',
+    enabled BOOLEAN NOT NULL DEFAULT false,
+    last_used_step BIGINT NULL COMMENT 'Last accepted time step, codes cannot be reused',
+    enabled_at TIMESTAMP NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+-- User Recovery Codes Table (single-use, stored as SHA-256)
+CREATE TABLE   user_recovery_codes (
+    id INT UNSIGNED AUTO_INCREMENT,
+    user_id INT UNSIGNED NOT NULL,
+    code_hash BINARY(32) NOT NULL,
+    used_at TIMESTAMP NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    INDEX idx_user_recovery_codes_user (user_id)
+);
+
+-- User Tokens Table (invitation and password reset links, stored as SHA-256)
+CREATE TABLE   user_tokens (
+    id INT UNSIGNED AUTO_INCREMENT,
+    user_id INT UNSIGNED NOT NULL,
+    purpose ENUM('INVITATION', 'PASSWORD_RESET') NOT NULL,
+    token_hash BINARY(32) NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP NULL,
+    created_by INT UNSIGNED NULL COMMENT 'Admin who sent the invitation',
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_user_tokens_hash (token_hash),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE SET NULL,
+    INDEX idx_user_tokens_user (user_id, purpose)
+);
+
+-- App Settings Table (changed by admins at runtime)
+CREATE TABLE   app_settings (
+    setting_key VARCHAR(64) NOT NULL,
+    setting_value VARCHAR(255) NOT NULL,
+    updated_by INT UNSIGNED NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (setting_key),
+    FOREIGN KEY (updated_by) REFERENCES users (id) ON DELETE SET NULL
+);
+
+INSERT IGNORE INTO app_settings (setting_key, setting_value) VALUES ('registration_open', 'true');
+INSERT IGNORE INTO app_settings (setting_key, setting_value) VALUES ('vacation_carry_over_expiry', '04-30');
+
+-- Password History Table (previous hashes, to refuse reused passwords)
+CREATE TABLE   password_history (
+    id INT UNSIGNED AUTO_INCREMENT,
+    user_id INT UNSIGNED NOT NULL,
+    password_hash VARBINARY(255) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    INDEX idx_password_history_user (user_id, created_at)
+);
+
+-- Directory Group Roles Table (directory groups whose members get a role)
+CREATE TABLE   directory_group_roles (
+    provider VARCHAR(16) NOT NULL,
+    group_name VARCHAR(255) NOT NULL COMMENT 'Lowercase group DN or cn',
+    role_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (provider, group_name, role_id),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE
+);
+
+-- User Identities Table (accounts at single sign-on identity providers linked to users)
+CREATE TABLE   user_identities (
+    provider VARCHAR(16) NOT NULL,
+    subject VARCHAR(255) NOT NULL COMMENT 'sub claim of the identity provider',
+    user_id INT UNSIGNED NOT NULL,
+    linked_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    last_login_at TIMESTAMP NULL,
+    PRIMARY KEY (provider, subject),
+    INDEX idx_user_identities_user (user_id),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+-- Holiday Rules Table (holidays that come back every year)
+CREATE TABLE   holiday_rules (
+    id INT UNSIGNED AUTO_INCREMENT,
+    name VARCHAR(255) NOT NULL,
+    scope ENUM('NATIONAL', 'MUNICIPAL', 'COMPANY') NOT NULL,
+    location TINYINT NULL COMMENT 'Location enum value, NULL for every clinic',
+    month TINYINT UNSIGNED NULL,
+    day TINYINT UNSIGNED NULL,
+    easter_offset SMALLINT NULL COMMENT 'Days from Easter Sunday, instead of month and day',
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+-- Holidays Table (holidays and closures of each year, generated from the rules or added by admins)
+CREATE TABLE   holidays (
+    id INT UNSIGNED AUTO_INCREMENT,
+    rule_id INT UNSIGNED NULL,
+    name VARCHAR(255) NOT NULL,
+    holiday_date DATE NOT NULL,
+    scope ENUM('NATIONAL', 'MUNICIPAL', 'COMPANY') NOT NULL,
+    location TINYINT NULL,
+    created_by INT UNSIGNED NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_holidays_rule_date (rule_id, holiday_date),
+    INDEX idx_holidays_date (holiday_date),
+    FOREIGN KEY (rule_id) REFERENCES holiday_rules (id) ON DELETE SET NULL,
+    FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE SET NULL
+);
+
+-- Holiday Years Table (years whose holidays were already generated from the rules)
+CREATE TABLE   holiday_years (
+    year SMALLINT UNSIGNED NOT NULL,
+    generated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (year)
+);
+
+INSERT INTO holiday_rules (name, scope, location, month, day, easter_offset) VALUES
+    ('Ano Novo', 'NATIONAL', NULL, 1, 1, NULL),
+    ('Dia da Liberdade', 'NATIONAL', NULL, 4, 25, NULL),
+    ('Dia do Trabalhador', 'NATIONAL', NULL, 5, 1, NULL),
+    ('Dia de Portugal', 'NATIONAL', NULL, 6, 10, NULL),
+    ('Assunção de Nossa Senhora', 'NATIONAL', NULL, 8, 15, NULL),
+    ('Implantação da República', 'NATIONAL', NULL, 10, 5, NULL),
+    ('Todos os Santos', 'NATIONAL', NULL, 11, 1, NULL),
+    ('Restauração da Independência', 'NATIONAL', NULL, 12, 1, NULL),
+    ('Imaculada Conceição', 'NATIONAL', NULL, 12, 8, NULL),
+    ('Natal', 'NATIONAL', NULL, 12, 25, NULL),
+    ('Carnaval', 'NATIONAL', NULL, NULL, NULL, -47),
+    ('Sexta-feira Santa', 'NATIONAL', NULL, NULL, NULL, -2),
+    ('Páscoa', 'NATIONAL', NULL, NULL, NULL, 0),
+    ('Corpo de Deus', 'NATIONAL', NULL, NULL, NULL, 60),
+    ('Nossa Senhora da Agonia', 'MUNICIPAL', 0, 8, 20, NULL),
+    ('São João', 'MUNICIPAL', 1, 6, 24, NULL),
+    ('São João', 'MUNICIPAL', 2, 6, 24, NULL),
+    ('Santo António', 'MUNICIPAL', 3, 6, 13, NULL);
+
+-- Work Schedules Table (minutes worked on each day of a repeating cycle)
+CREATE TABLE   work_schedules (
+    id INT UNSIGNED AUTO_INCREMENT,
+    name VARCHAR(255) NOT NULL,
+    description TEXT NULL,
+    day_minutes JSON NOT NULL COMMENT 'Minutes per day of the cycle, 0 for days off',
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+-- User Work Schedules Table (schedule of each user over time)
+CREATE TABLE   user_work_schedules (
+    id INT UNSIGNED AUTO_INCREMENT,
+    user_id INT UNSIGNED NOT NULL,
+    schedule_id INT UNSIGNED NOT NULL,
+    valid_from DATE NOT NULL COMMENT 'Applies from this date until the next schedule of the user',
+    cycle_start DATE NOT NULL COMMENT 'A date the first day of the cycle falls on',
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_user_work_schedules_from (user_id, valid_from),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (schedule_id) REFERENCES work_schedules (id)
+);
+
+-- Vacation Ledger Entries Table (entitlement, carry-over, adjustments and expiries of each user and year)
+CREATE TABLE   vacation_ledger_entries (
+    id INT UNSIGNED AUTO_INCREMENT,
+    user_id INT UNSIGNED NOT NULL,
+    year SMALLINT UNSIGNED NOT NULL,
+    entry_type ENUM('ENTITLEMENT', 'CARRY_OVER', 'ADJUSTMENT', 'EXPIRY') NOT NULL,
+    amount INT NOT NULL COMMENT 'In the vacation_balance_unit of the user, negative to take away',
+    reason VARCHAR(255) NULL,
+    expires_on DATE NULL COMMENT 'Last day a carry-over can be taken',
+    available_from DATE NULL COMMENT 'First day the entry can be taken, the end of the waiting period of the hire year',
+    created_by INT UNSIGNED NULL COMMENT 'NULL for entries made by the scheduler, or the statutory rules',
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_vacation_ledger_user_year (user_id, year),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE SET NULL
+);
+
+-- Work Contracts Table (worker contracts and addenda, for the statutory vacation rules)
+CREATE TABLE   work_contracts (
+    id INT UNSIGNED AUTO_INCREMENT,
+    user_id INT UNSIGNED NOT NULL,
+    contract_type TINYINT NOT NULL COMMENT '0 addendum, 1 worker contract',
+    term TINYINT NOT NULL COMMENT '0 permanent, 1 fixed, 2 uncertain',
+    start_date DATE NOT NULL,
+    end_date DATE NULL COMMENT 'NULL for permanent contracts, or uncertain ones with no known end',
+    notes VARCHAR(255) NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_work_contracts_user (user_id, start_date),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+-- Absence Documents Table (supporting documents of absence requests, like a sick note)
+CREATE TABLE   absence_documents (
+    id INT UNSIGNED AUTO_INCREMENT,
+    vacation_request_id INT UNSIGNED NOT NULL,
+    file_name VARCHAR(255) NOT NULL,
+    file_path VARCHAR(255) NOT NULL,
+    uploaded_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    uploaded_by INT UNSIGNED NOT NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (vacation_request_id) REFERENCES vacation_requests (id) ON DELETE CASCADE,
+    FOREIGN KEY (uploaded_by) REFERENCES users (id)
+);
+
+-- Coverage Rules Table (how many members of a role can be absent, or must be present, each day)
+CREATE TABLE   coverage_rules (
+    id INT UNSIGNED AUTO_INCREMENT,
+    name VARCHAR(255) NOT NULL,
+    role_id INT UNSIGNED NOT NULL,
+    location TINYINT NULL COMMENT 'Only the members of this clinic, NULL for every clinic',
+    per_location BOOLEAN NOT NULL DEFAULT FALSE COMMENT 'Counts the members of each clinic on its own',
+    requirement ENUM('MAX_ABSENT', 'MIN_PRESENT') NOT NULL,
+    threshold SMALLINT UNSIGNED NOT NULL COMMENT 'Most members absent, or fewest present, on a day',
+    weekdays_only BOOLEAN NOT NULL DEFAULT FALSE COMMENT 'Only checked Monday to Friday',
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE
+);
+
+-- Approval Steps Table (the approval chain of the requests of each role's members)
+CREATE TABLE   approval_steps (
+    id INT UNSIGNED AUTO_INCREMENT,
+    role_id INT UNSIGNED NOT NULL COMMENT 'Role of the users whose requests go through the step',
+    step_order TINYINT UNSIGNED NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    approver_role_id INT UNSIGNED NOT NULL COMMENT 'Members of this role approve the step',
+    deadline_days SMALLINT UNSIGNED NULL COMMENT 'Days the approver has before being reminded',
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_approval_steps_order (role_id, step_order),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE,
+    FOREIGN KEY (approver_role_id) REFERENCES roles (id) ON DELETE CASCADE
+);
+
+-- Approval Chains Table (which chain users with several roles follow)
+CREATE TABLE   approval_chains (
+    role_id INT UNSIGNED NOT NULL,
+    priority INT UNSIGNED NOT NULL COMMENT 'Users with several chains follow the lowest',
+    PRIMARY KEY (role_id),
+    UNIQUE KEY uk_approval_chains_priority (priority),
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE
+);
+
+-- Approval Delegations Table (approvers away hand their steps to a colleague)
+CREATE TABLE   approval_delegations (
+    id INT UNSIGNED AUTO_INCREMENT,
+    approver_id INT UNSIGNED NOT NULL,
+    delegate_id INT UNSIGNED NOT NULL,
+    start_date DATE NOT NULL,
+    end_date DATE NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    INDEX idx_approval_delegations_approver (approver_id, start_date),
+    FOREIGN KEY (approver_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (delegate_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+-- Vacation Request Approvals Table (the approval trail of each request)
+CREATE TABLE   vacation_request_approvals (
+    id INT UNSIGNED AUTO_INCREMENT,
+    vacation_request_id INT UNSIGNED NOT NULL,
+    step_order TINYINT UNSIGNED NOT NULL COMMENT '0 for the decision of an admin on a request without a chain',
+    step_name VARCHAR(255) NULL,
+    approver_role_id INT UNSIGNED NULL,
+    assigned_to INT UNSIGNED NULL COMMENT 'NULL when no member of the approver role could take the step',
+    delegated_from INT UNSIGNED NULL COMMENT 'Approver who delegated the step to assigned_to',
+    status ENUM('PENDING', 'APPROVED', 'REJECTED', 'REASSIGNED') NOT NULL DEFAULT 'PENDING',
+    acted_by INT UNSIGNED NULL,
+    notes TEXT NULL,
+    due_date DATE NULL,
+    reminded_at TIMESTAMP NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    acted_at TIMESTAMP NULL,
+    PRIMARY KEY (id),
+    INDEX idx_vacation_request_approvals_status (status, assigned_to),
+    FOREIGN KEY (vacation_request_id) REFERENCES vacation_requests (id) ON DELETE CASCADE,
+    FOREIGN KEY (approver_role_id) REFERENCES roles (id) ON DELETE SET NULL,
+    FOREIGN KEY (assigned_to) REFERENCES users (id) ON DELETE SET NULL,
+    FOREIGN KEY (delegated_from) REFERENCES users (id) ON DELETE SET NULL,
+    FOREIGN KEY (acted_by) REFERENCES users (id) ON DELETE SET NULL
+);
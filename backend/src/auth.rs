@@ -1,186 +1,347 @@
-use actix_session::Session;
-use actix_web::HttpResponse;
+//! Session authentication and admin checks.
+//!
+//! Handlers take an [`AuthenticatedUser`] or an [`AdminUser`] argument instead of reading the
+//! session themselves. Admin status is always read from the user's roles, never from the session,
+//! so removing the admin role from someone takes effect on their next request. Scopes that are
+//! admin-only as a whole are wrapped in [`require_admin`] instead. Routes that scoped admins can
+//! also use take an [`AdminScope`] and check the page or role they act on.
+//!
+//! A login that still needs a second factor only stores a pending login in the session, see
+//! [`start_pending_login`]; the session is not authenticated until [`start_session`].
+//!
+//! Every rejection is a JSON body `{"error": "..."}` with 401 when there is no valid session and
+//! 403 when the user is not an admin.
+
+use std::future::{Ready, ready};
+
+use actix_session::{Session, SessionExt};
+use actix_web::{
+    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    error::InternalError,
+    middleware::Next,
+    web,
+};
+use futures_util::future::LocalBoxFuture;
+use sqlx::MySqlPool;
+
+use crate::{State, models::admin_scope::UserAdminScopes};
+
+/// Seconds after which the session id is renewed on the next request.
+const SESSION_RENEWAL_SECS: i64 = 300;
+/// Seconds a user has to enter their second factor after the password.
+const PENDING_LOGIN_SECS: i64 = 300;
+
+pub fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Sessão inválida ou expirada" }))
+}
+
+pub fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden()
+        .json(serde_json::json!({ "error": "Acesso reservado a administradores" }))
+}
+
+fn reject(resp: HttpResponse) -> Error {
+    InternalError::from_response("", resp).into()
+}
 
 pub fn validate_session(session: &Session) -> Result<i32, HttpResponse> {
     let user_id = session.get::<i32>("user_id").unwrap_or(None);
 
-    if let Some(last_renewal) = session.get::<i64>("last_renewal").unwrap_or(None) {
-        let now = chrono::Utc::now().timestamp();
-        if now - last_renewal > 300 {
+    let now = chrono::Utc::now().timestamp();
+    let last_renewal = session.get::<i64>("last_renewal").unwrap_or(None);
+    if last_renewal.is_none_or(|last| now - last > SESSION_RENEWAL_SECS) {
+        if last_renewal.is_some() {
             session.renew();
-            session.insert("last_renewal", now).unwrap();
         }
-    } else {
-        session
-            .insert("last_renewal", chrono::Utc::now().timestamp())
-            .unwrap();
+        if let Err(e) = session.insert("last_renewal", now) {
+            log::error!("Error storing session renewal time: {}", e);
+        }
     }
 
     match user_id {
         Some(id) => Ok(id),
-        None => Err(HttpResponse::Unauthorized().finish()),
+        None => Err(unauthorized()),
     }
 }
 
-pub fn is_admin(session: &Session) -> Result<i32, HttpResponse> {
-    let is_admin = session.get::<bool>("is_admin").unwrap();
+/// Logs the user in on this session. Drops any pending login and renews the session id so a
+/// session id known before the login cannot be reused.
+pub fn start_session(session: &Session, user_id: i32) -> Result<(), HttpResponse> {
+    session.remove("pending_login_user_id");
+    session.remove("pending_login_since");
+    session.remove("pending_login_setup");
+    session.renew();
 
-    match is_admin {
-        Some(true) => Ok(session.get::<i32>("user_id").unwrap().unwrap()),
-        _ => Err(HttpResponse::Forbidden().finish()),
-    }
+    // Admin status is not stored, it is checked against the roles on each request instead.
+    session
+        .insert("user_id", user_id)
+        .and_then(|_| session.insert("last_renewal", chrono::Utc::now().timestamp()))
+        .map_err(|e| {
+            log::error!("Error storing session data during login: {}", e);
+            HttpResponse::InternalServerError().finish()
+        })
 }
 
-pub async fn user_can_manage_page(
-    pool: &sqlx::MySqlPool,
+/// A login whose password was right and that still needs the second factor.
+#[derive(Debug, Clone, Copy)]
+pub struct PendingLogin {
+    pub user_id: i32,
+    /// The user has no second factor yet but one of their roles requires it, so they may only
+    /// enroll
+    pub needs_setup: bool,
+}
+
+pub fn start_pending_login(
+    session: &Session,
     user_id: i32,
-    page_id: u32,
-) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"
-        SELECT COUNT(*) as count
-        FROM user_roles ur
-        LEFT JOIN roles r ON r.id = ur.role_id
-        LEFT JOIN page_permissions pp ON pp.role_id = ur.role_id AND pp.page_id = ?
-        WHERE ur.user_id = ? AND (r.is_admin = 1 OR pp.can_manage_fields = 1)
-        "#,
-        page_id,
-        user_id
-    )
-    .fetch_one(pool)
-    .await?;
+    needs_setup: bool,
+) -> Result<(), HttpResponse> {
+    session.remove("user_id");
 
-    Ok(result.count > 0)
+    session
+        .insert("pending_login_user_id", user_id)
+        .and_then(|_| session.insert("pending_login_since", chrono::Utc::now().timestamp()))
+        .and_then(|_| session.insert("pending_login_setup", needs_setup))
+        .map_err(|e| {
+            log::error!("Error storing pending login: {}", e);
+            HttpResponse::InternalServerError().finish()
+        })
 }
 
-// Function to get user IDs who have view permission for a specific page
-pub async fn get_user_ids_with_view_permission(
-    pool: &sqlx::MySqlPool,
-    page_id: u32,
-) -> Result<Vec<u32>, sqlx::Error> {
-    let user_ids = sqlx::query_scalar!(
-        r#"
-        SELECT DISTINCT ur.user_id
-        FROM user_roles ur
-        LEFT JOIN roles r ON ur.role_id = r.id
-        LEFT JOIN page_permissions pp ON pp.role_id = ur.role_id AND pp.page_id = ?
-        WHERE r.is_admin = 1 OR pp.can_view = 1
-        "#,
-        page_id
-    )
-    .fetch_all(pool)
-    .await?;
+/// The pending login of this session, unless it expired.
+pub fn pending_login(session: &Session) -> Option<PendingLogin> {
+    let user_id = session.get::<i32>("pending_login_user_id").ok()??;
+    let since = session.get::<i64>("pending_login_since").ok()??;
+    if chrono::Utc::now().timestamp() - since > PENDING_LOGIN_SECS {
+        return None;
+    }
 
-    // The query directly returns Vec<u32> as user_id is not nullable
-    Ok(user_ids)
+    Some(PendingLogin {
+        user_id,
+        needs_setup: session
+            .get::<bool>("pending_login_setup")
+            .ok()
+            .flatten()
+            .unwrap_or(false),
+    })
 }
 
-pub async fn user_can_view_page(
-    pool: &sqlx::MySqlPool,
-    user_id: i32,
-    page_id: u32,
-) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query!(
+/// Client address used for login limits and the security log. The socket address, forwarded
+/// headers could be set by the client to dodge the limits.
+pub fn client_ip(req: &HttpRequest) -> String {
+    req.peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default()
+}
+
+/// Whether any of the user's roles is an admin role.
+pub async fn user_is_admin(pool: &MySqlPool, user_id: i32) -> Result<bool, sqlx::Error> {
+    let count = sqlx::query_scalar!(
         r#"
-        SELECT COUNT(*) as count
-        FROM user_roles ur
-        LEFT JOIN roles r ON r.id = ur.role_id
-        LEFT JOIN page_permissions pp ON pp.role_id = ur.role_id AND pp.page_id = ?
-        WHERE ur.user_id = ? AND (r.is_admin = 1 OR pp.can_view = 1)
+        SELECT COUNT(*)
+        FROM user_effective_roles ur
+        JOIN roles r ON ur.role_id = r.id
+        WHERE ur.user_id = ? AND r.is_admin = 1
         "#,
-        page_id,
         user_id
     )
     .fetch_one(pool)
     .await?;
 
-    Ok(result.count > 0)
+    Ok(count > 0)
 }
 
-pub async fn user_can_create_record(
-    pool: &sqlx::MySqlPool,
-    user_id: i32,
-    page_id: u32,
-) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"
-        SELECT COUNT(*) as count
-        FROM user_roles ur
-        LEFT JOIN roles r ON r.id = ur.role_id
-        LEFT JOIN page_permissions pp ON pp.role_id = ur.role_id AND pp.page_id = ?
-        WHERE ur.user_id = ? AND (r.is_admin = 1 OR pp.can_create = 1)
-        "#,
-        page_id,
-        user_id
-    )
-    .fetch_one(pool)
-    .await?;
+fn app_pool(req: &HttpRequest) -> Result<MySqlPool, Error> {
+    match req.app_data::<web::Data<State>>() {
+        Some(state) => Ok(state.db.pool.clone()),
+        None => {
+            log::error!("Application state missing while authenticating request");
+            Err(reject(HttpResponse::InternalServerError().finish()))
+        }
+    }
+}
+
+/// Resolves the admin behind a request, re-checking the roles in the database. Reuses the
+/// result of [`require_admin`] when the request already went through it.
+async fn resolve_admin(req: HttpRequest) -> Result<AdminUser, Error> {
+    if let Some(admin) = req.extensions().get::<AdminUser>() {
+        return Ok(*admin);
+    }
+
+    let id = validate_session(&req.get_session()).map_err(reject)?;
+    let pool = app_pool(&req)?;
 
-    Ok(result.count > 0)
+    match user_is_admin(&pool, id).await {
+        Ok(true) => {
+            let admin = AdminUser { id };
+            req.extensions_mut().insert(admin);
+            Ok(admin)
+        }
+        Ok(false) => Err(reject(forbidden())),
+        Err(e) => {
+            log::error!("Error checking admin status of user {}: {}", id, e);
+            Err(reject(HttpResponse::InternalServerError().finish()))
+        }
+    }
 }
 
-pub async fn user_can_edit_record(
-    pool: &sqlx::MySqlPool,
-    user_id: i32,
-    page_id: u32,
-) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"
-        SELECT COUNT(*) as count
-        FROM user_roles ur
-        LEFT JOIN roles r ON r.id = ur.role_id
-        LEFT JOIN page_permissions pp ON pp.role_id = ur.role_id AND pp.page_id = ?
-        WHERE ur.user_id = ? AND (r.is_admin = 1 OR pp.can_edit = 1)
-        "#,
-        page_id,
-        user_id
-    )
-    .fetch_one(pool)
-    .await?;
+/// The logged in user. Extracting it fails with 401 without a valid session.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthenticatedUser {
+    pub id: i32,
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
 
-    Ok(result.count > 0)
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            validate_session(&req.get_session())
+                .map(|id| AuthenticatedUser { id })
+                .map_err(reject),
+        )
+    }
 }
 
-pub async fn user_can_delete_record(
-    pool: &sqlx::MySqlPool,
-    user_id: i32,
-    page_id: u32,
-) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"
-        SELECT COUNT(*) as count
-        FROM user_roles ur
-        LEFT JOIN roles r ON r.id = ur.role_id
-        LEFT JOIN page_permissions pp ON pp.role_id = ur.role_id AND pp.page_id = ?
-        WHERE ur.user_id = ? AND (r.is_admin = 1 OR pp.can_delete = 1)
-        "#,
-        page_id,
-        user_id
-    )
-    .fetch_one(pool)
-    .await?;
+/// A logged in user holding an admin role. Extracting it fails with 401 without a valid session
+/// and with 403 when none of the user's roles is admin.
+#[derive(Debug, Clone, Copy)]
+pub struct AdminUser {
+    pub id: i32,
+}
+
+impl FromRequest for AdminUser {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        Box::pin(resolve_admin(req.clone()))
+    }
+}
+
+/// Resolves what the user behind a request administers. Global admins administer everything;
+/// anyone else needs at least one scope through their roles.
+async fn resolve_admin_scope(req: HttpRequest) -> Result<AdminScope, Error> {
+    if let Some(scope) = req.extensions().get::<AdminScope>() {
+        return Ok(scope.clone());
+    }
+
+    let id = validate_session(&req.get_session()).map_err(reject)?;
+    let pool = app_pool(&req)?;
+
+    let scope = match user_is_admin(&pool, id).await {
+        Ok(true) => AdminScope {
+            id,
+            is_admin: true,
+            scopes: UserAdminScopes::default(),
+        },
+        Ok(false) => match UserAdminScopes::for_user(&pool, id).await {
+            Ok(scopes) if scopes.is_empty() => return Err(reject(forbidden())),
+            Ok(scopes) => AdminScope {
+                id,
+                is_admin: false,
+                scopes,
+            },
+            Err(e) => {
+                log::error!("Error fetching admin scopes of user {}: {}", id, e);
+                return Err(reject(HttpResponse::InternalServerError().finish()));
+            }
+        },
+        Err(e) => {
+            log::error!("Error checking admin status of user {}: {}", id, e);
+            return Err(reject(HttpResponse::InternalServerError().finish()));
+        }
+    };
+
+    req.extensions_mut().insert(scope.clone());
+    Ok(scope)
+}
 
-    Ok(result.count > 0)
+/// A logged in user who is a global admin or administers some page groups or roles. Extracting
+/// it fails with 401 without a valid session and with 403 when the user administers nothing;
+/// handlers still have to check the page or role they act on.
+#[derive(Debug, Clone)]
+pub struct AdminScope {
+    pub id: i32,
+    pub is_admin: bool,
+    scopes: UserAdminScopes,
 }
 
-pub async fn user_can_add_to_record(
+impl AdminScope {
+    /// Pages inside one of the user's groups, groups included.
+    pub fn can_manage_page(&self, page_id: u32) -> bool {
+        self.is_admin || self.scopes.page_ids.contains(&page_id)
+    }
+
+    /// Whether a page can be created, or moved, under `parent_path`. Only global admins can use
+    /// the top level.
+    pub fn can_manage_parent_path(&self, parent_path: Option<&str>) -> bool {
+        if self.is_admin {
+            return true;
+        }
+
+        parent_path.is_some_and(|path| {
+            let path = if path.len() > 1 {
+                path.trim_end_matches('/')
+            } else {
+                path
+            };
+            self.scopes.group_paths.contains(path)
+        })
+    }
+
+    pub fn can_manage_role(&self, role_id: u32) -> bool {
+        self.is_admin || self.scopes.role_ids.contains(&role_id)
+    }
+
+    pub fn can_manage_roles<'a>(&self, role_ids: impl IntoIterator<Item = &'a u32>) -> bool {
+        self.is_admin
+            || role_ids
+                .into_iter()
+                .all(|id| self.scopes.role_ids.contains(id))
+    }
+}
+
+impl FromRequest for AdminScope {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        Box::pin(resolve_admin_scope(req.clone()))
+    }
+}
+
+/// Middleware for scopes where every route is admin-only, used with
+/// `actix_web::middleware::from_fn`.
+pub async fn require_admin(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    resolve_admin(req.request().clone()).await?;
+    next.call(req).await
+}
+
+// Function to get user IDs who have view permission for a specific page
+pub async fn get_user_ids_with_view_permission(
     pool: &sqlx::MySqlPool,
-    user_id: i32,
     page_id: u32,
-) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query!(
+) -> Result<Vec<u32>, sqlx::Error> {
+    let user_ids = sqlx::query_scalar!(
         r#"
-        SELECT COUNT(*) as count
-        FROM user_roles ur
-        LEFT JOIN roles r ON r.id = ur.role_id
-        LEFT JOIN page_permissions pp ON pp.role_id = ur.role_id AND pp.page_id = ?
-        WHERE ur.user_id = ? AND (r.is_admin = 1 OR pp.can_add = 1)
+        SELECT DISTINCT ur.user_id
+        FROM user_effective_roles ur
+        LEFT JOIN roles r ON ur.role_id = r.id
+        LEFT JOIN effective_page_permissions pp ON pp.role_id = ur.role_id AND pp.page_id = ?
+        WHERE r.is_admin = 1 OR pp.can_view = 1
         "#,
-        page_id,
-        user_id
+        page_id
     )
-    .fetch_one(pool)
+    .fetch_all(pool)
     .await?;
 
-    Ok(result.count > 0)
+    // The query directly returns Vec<u32> as user_id is not nullable
+    Ok(user_ids)
 }
@@ -1,6 +1,6 @@
 use sqlx::mysql::MySqlPool;
 
-use crate::utils::hashing_utils::hash;
+use crate::{models::role::Role, utils::hashing_utils::hash};
 
 const SCHEMA: &str = include_str!("../sql/schema.sql");
 
@@ -38,6 +38,10 @@ impl Db {
         .execute(&pool)
         .await?;
 
+        let mut tx = pool.begin().await?;
+        Role::refresh_ancestors(&mut tx).await?;
+        tx.commit().await?;
+
         let admin_role = sqlx::query!("SELECT id FROM roles WHERE is_admin = true LIMIT 1")
             .fetch_one(&pool)
             .await?;
@@ -49,7 +53,7 @@ impl Db {
             "INSERT IGNORE INTO users (username, email, password) VALUES (?, ?, ?)",
             "admin",
             "admin@jcc.pt",
-            &admin_password[..]
+            admin_password.as_bytes()
         )
         .execute(&pool)
         .await?;
@@ -0,0 +1,139 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+
+use crate::{
+    State,
+    auth::{AdminUser, AuthenticatedUser},
+    models::absence_type::{AbsenceType, AbsenceTypeRequest},
+    utils::json_utils::{Json, json_response_with_etag},
+};
+
+/// The absence types users can request.
+pub async fn get_active_absence_types(
+    state: web::Data<State>,
+    _user: AuthenticatedUser,
+    req: HttpRequest,
+) -> impl Responder {
+    match AbsenceType::get_all(&state.db.pool).await {
+        Ok(absence_types) => {
+            let active: Vec<AbsenceType> = absence_types
+                .into_iter()
+                .filter(|absence_type| absence_type.active)
+                .collect();
+            json_response_with_etag(&active, &req)
+        }
+        Err(e) => {
+            log::error!("Error fetching absence types: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Every absence type, inactive ones included.
+pub async fn get_absence_types(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+) -> impl Responder {
+    match AbsenceType::get_all(&state.db.pool).await {
+        Ok(absence_types) => json_response_with_etag(&absence_types, &req),
+        Err(e) => {
+            log::error!("Error fetching absence types: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn create_absence_type(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<AbsenceTypeRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match AbsenceType::create(&state.db.pool, &req).await {
+        Ok(id) => HttpResponse::Created().json(serde_json::json!({ "id": id })),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            HttpResponse::Conflict().body("Já existe um tipo de ausência com este código.")
+        }
+        Err(e) => {
+            log::error!("Error creating absence type: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Changes the rules of a type. Requests already made keep their status.
+pub async fn update_absence_type(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let absence_type_id = path.into_inner();
+
+    let Json(req): Json<AbsenceTypeRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match AbsenceType::update(&state.db.pool, absence_type_id, &req).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Tipo de ausência não encontrado"),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            HttpResponse::Conflict().body("Já existe um tipo de ausência com este código.")
+        }
+        Err(e) => {
+            log::error!("Error updating absence type {}: {}", absence_type_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Types with requests cannot be deleted, only deactivated.
+pub async fn delete_absence_type(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let absence_type_id = path.into_inner();
+
+    match AbsenceType::is_used(&state.db.pool, absence_type_id).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return HttpResponse::Conflict()
+                .body("Este tipo de ausência já tem pedidos. Desative-o em vez de o apagar.");
+        }
+        Err(e) => {
+            log::error!(
+                "Error checking requests of absence type {}: {}",
+                absence_type_id,
+                e
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    match AbsenceType::delete(&state.db.pool, absence_type_id).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Tipo de ausência não encontrado"),
+        Err(e) => {
+            log::error!("Error deleting absence type {}: {}", absence_type_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,598 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+
+use crate::{
+    State,
+    auth::{AdminUser, client_ip},
+    models::{
+        app_setting::{AppSetting, SETTING_REGISTRATION_OPEN},
+        login_throttle::LoginThrottle,
+        security_event::{
+            NewSecurityEvent, SECURITY_EVENT_PASSWORD_CHANGE,
+            SECURITY_EVENT_PASSWORD_RESET_REQUESTED, SECURITY_EVENT_REGISTRATION_CHANGED,
+            SECURITY_EVENT_USER_INVITED, SecurityEvent,
+        },
+        user_token::{TOKEN_PURPOSE_INVITATION, TOKEN_PURPOSE_PASSWORD_RESET, UserToken},
+    },
+    services::{
+        auth_provider::LOCAL_PROVIDER,
+        password_service::{check_new_password, password_rejected, store_password},
+    },
+    utils::{
+        hashing_utils::hash,
+        json_utils::Json,
+        login_throttle::{PASSWORD_RESET_POLICY, SCOPE_ACCOUNT, SCOPE_PASSWORD_RESET, account_key},
+        secure_token::{generate_token, hash_token},
+    },
+};
+
+const INVITATION_VALID_DAYS: i64 = 7;
+const PASSWORD_RESET_VALID_MINUTES: i64 = 60;
+
+#[derive(Deserialize)]
+pub struct InviteUserRequest {
+    username: String,
+    email: String,
+    /// Roles of the new user, the default role when missing
+    role_ids: Option<Vec<u32>>,
+}
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordRequest {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct SetPasswordWithTokenRequest {
+    token: String,
+    new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct RegistrationSettingRequest {
+    open: bool,
+}
+
+/// Creates a token for the user and emails them the link to set their password. Returns
+/// whether the email went out.
+async fn send_token_email(
+    state: &State,
+    user_id: u32,
+    email: &str,
+    purpose: &str,
+    created_by: Option<u32>,
+) -> Result<bool, sqlx::Error> {
+    let token = generate_token();
+    let (valid_for, subject, intro) = if purpose == TOKEN_PURPOSE_INVITATION {
+        (
+            Duration::days(INVITATION_VALID_DAYS),
+            "Convite para a plataforma de Gestão Documental",
+            "Foi criada uma conta para si na plataforma de Gestão Documental.",
+        )
+    } else {
+        (
+            Duration::minutes(PASSWORD_RESET_VALID_MINUTES),
+            "Recuperação da palavra-passe",
+            "Foi pedida a recuperação da palavra-passe da sua conta. Se não fez este pedido, \
+             ignore este email.",
+        )
+    };
+    let expires_at = Utc::now() + valid_for;
+
+    UserToken::create(
+        &state.db.pool,
+        user_id,
+        purpose,
+        &hash_token(&token),
+        expires_at,
+        created_by,
+    )
+    .await?;
+
+    let link = state
+        .mailer
+        .link(&format!("/definir-palavra-passe?token={}", token));
+    let body = format!(
+        "{}\n\nPara definir a sua palavra-passe abra o endereço abaixo até {}:\n\n{}\n\n\
+         O endereço só pode ser usado uma vez.",
+        intro,
+        expires_at.format("%d/%m/%Y %H:%M UTC"),
+        link
+    );
+
+    match state.mailer.send(email, subject, body).await {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            log::error!("Error sending {} email to user {}: {}", purpose, user_id, e);
+            Ok(false)
+        }
+    }
+}
+
+/// Creates a user without a password and emails them an invitation to set one.
+pub async fn invite_user(
+    state: web::Data<State>,
+    admin: AdminUser,
+    http_req: HttpRequest,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<InviteUserRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+
+    let username = req.username.trim();
+    let email = req.email.trim();
+    if username.is_empty() || !email.contains('@') {
+        return HttpResponse::BadRequest().body("Nome de utilizador ou email inválido.");
+    }
+
+    let mut tx = match state.db.pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            log::error!("Database error starting transaction: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match sqlx::query_scalar!(
+        r#"SELECT id FROM users WHERE username = ? OR email = ?"#,
+        username,
+        email
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            return HttpResponse::Conflict()
+                .body("Já existe um utilizador com este nome ou email.");
+        }
+        Err(e) => {
+            log::error!("Database error checking existing user: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    // Nobody knows this password, the account only becomes usable through the invitation
+    let unusable_password = hash(&generate_token());
+    let user_id = match sqlx::query!(
+        r#"INSERT INTO users (username, email, password) VALUES (?, ?, ?)"#,
+        username,
+        email,
+        unusable_password.as_bytes()
+    )
+    .execute(&mut *tx)
+    .await
+    {
+        Ok(result) => result.last_insert_id() as u32,
+        Err(e) => {
+            log::error!("Database error creating invited user: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let role_ids = match req.role_ids {
+        Some(role_ids) => role_ids,
+        None => {
+            match sqlx::query_scalar!(r#"SELECT id FROM roles WHERE name = 'Colaborador' LIMIT 1"#)
+                .fetch_all(&mut *tx)
+                .await
+            {
+                Ok(role_ids) => role_ids,
+                Err(e) => {
+                    log::error!("Database error fetching default role: {}", e);
+                    return HttpResponse::InternalServerError().finish();
+                }
+            }
+        }
+    };
+
+    for role_id in &role_ids {
+        if let Err(e) = sqlx::query!(
+            r#"INSERT INTO user_roles (user_id, role_id) VALUES (?, ?)"#,
+            user_id,
+            role_id
+        )
+        .execute(&mut *tx)
+        .await
+        {
+            log::error!(
+                "Database error assigning role {} to invited user: {}",
+                role_id,
+                e
+            );
+            return HttpResponse::BadRequest().body("Função inválida.");
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        log::error!("Database error committing invited user: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    SecurityEvent::record(
+        &state.db.pool,
+        NewSecurityEvent {
+            event_type: SECURITY_EVENT_USER_INVITED,
+            user_id: Some(user_id),
+            actor_user_id: Some(admin.id as u32),
+            ip_address: Some(&client_ip(&http_req)),
+            details: Some(email.to_string()),
+        },
+    )
+    .await;
+
+    let email_sent = match send_token_email(
+        &state,
+        user_id,
+        email,
+        TOKEN_PURPOSE_INVITATION,
+        Some(admin.id as u32),
+    )
+    .await
+    {
+        Ok(sent) => sent,
+        Err(e) => {
+            log::error!("Error creating invitation of user {}: {}", user_id, e);
+            false
+        }
+    };
+
+    HttpResponse::Created().json(serde_json::json!({
+        "user_id": user_id,
+        "email_sent": email_sent,
+    }))
+}
+
+/// Sends a new invitation to a user who never set their password, replacing the old link.
+pub async fn resend_invitation(
+    state: web::Data<State>,
+    admin: AdminUser,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+    let pool = &state.db.pool;
+
+    let email = match sqlx::query_scalar!(r#"SELECT email FROM users WHERE id = ?"#, user_id)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(Some(email)) => email,
+        Ok(None) => return HttpResponse::NotFound().body("Utilizador não encontrado."),
+        Err(e) => {
+            log::error!("Error fetching email of user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match UserToken::has_pending_invitation(pool, user_id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::Conflict().body("O utilizador não tem um convite pendente.");
+        }
+        Err(e) => {
+            log::error!("Error checking invitation of user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    match send_token_email(
+        &state,
+        user_id,
+        &email,
+        TOKEN_PURPOSE_INVITATION,
+        Some(admin.id as u32),
+    )
+    .await
+    {
+        Ok(email_sent) => HttpResponse::Ok().json(serde_json::json!({ "email_sent": email_sent })),
+        Err(e) => {
+            log::error!("Error creating invitation of user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Emails a password reset link to a user, on their behalf.
+pub async fn admin_send_password_reset(
+    state: web::Data<State>,
+    admin: AdminUser,
+    http_req: HttpRequest,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+
+    let email = match sqlx::query_scalar!(r#"SELECT email FROM users WHERE id = ?"#, user_id)
+        .fetch_optional(&state.db.pool)
+        .await
+    {
+        Ok(Some(email)) => email,
+        Ok(None) => return HttpResponse::NotFound().body("Utilizador não encontrado."),
+        Err(e) => {
+            log::error!("Error fetching email of user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let email_sent = match send_token_email(
+        &state,
+        user_id,
+        &email,
+        TOKEN_PURPOSE_PASSWORD_RESET,
+        Some(admin.id as u32),
+    )
+    .await
+    {
+        Ok(sent) => sent,
+        Err(e) => {
+            log::error!("Error creating password reset of user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    SecurityEvent::record(
+        &state.db.pool,
+        NewSecurityEvent {
+            event_type: SECURITY_EVENT_PASSWORD_RESET_REQUESTED,
+            user_id: Some(user_id),
+            actor_user_id: Some(admin.id as u32),
+            ip_address: Some(&client_ip(&http_req)),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    HttpResponse::Ok().json(serde_json::json!({ "email_sent": email_sent }))
+}
+
+/// Self-service reset. Always answers the same way, so it cannot be used to find out which
+/// emails have an account.
+pub async fn forgot_password(
+    state: web::Data<State>,
+    http_req: HttpRequest,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<ForgotPasswordRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+
+    let pool = &state.db.pool;
+    let account = account_key(&req.email);
+    let accepted = HttpResponse::Accepted().body(
+        "Se o email estiver registado, receberá em breve um endereço para definir uma nova \
+         palavra-passe.",
+    );
+
+    match LoginThrottle::locked_until(pool, SCOPE_PASSWORD_RESET, &account).await {
+        Ok(None) => {}
+        Ok(Some(_)) => return accepted,
+        Err(e) => {
+            log::error!("Error checking password reset limit of {}: {}", account, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    if let Err(e) =
+        LoginThrottle::record_failure(pool, SCOPE_PASSWORD_RESET, &account, &PASSWORD_RESET_POLICY)
+            .await
+    {
+        log::error!("Error counting password reset of {}: {}", account, e);
+    }
+
+    // Directory accounts change their password in the directory
+    let user = match sqlx::query!(
+        r#"SELECT id, email FROM users WHERE LOWER(email) = ? AND auth_source = ?"#,
+        account,
+        LOCAL_PROVIDER
+    )
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return accepted,
+        Err(e) => {
+            log::error!("Database error during password reset: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Err(e) = send_token_email(
+        &state,
+        user.id,
+        &user.email,
+        TOKEN_PURPOSE_PASSWORD_RESET,
+        None,
+    )
+    .await
+    {
+        log::error!("Error creating password reset of user {}: {}", user.id, e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    SecurityEvent::record(
+        pool,
+        NewSecurityEvent {
+            event_type: SECURITY_EVENT_PASSWORD_RESET_REQUESTED,
+            user_id: Some(user.id),
+            ip_address: Some(&client_ip(&http_req)),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    accepted
+}
+
+/// Who a link belongs to, so the page can greet them before they choose a password.
+pub async fn get_password_token(
+    state: web::Data<State>,
+    path: web::Path<String>,
+) -> impl Responder {
+    match UserToken::find_valid(&state.db.pool, &hash_token(&path.into_inner())).await {
+        Ok(Some(owner)) => HttpResponse::Ok().json(owner),
+        Ok(None) => HttpResponse::NotFound().body("Endereço inválido ou expirado."),
+        Err(e) => {
+            log::error!("Error fetching password token: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Sets a password through an invitation or reset link.
+pub async fn set_password_with_token(
+    state: web::Data<State>,
+    http_req: HttpRequest,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<SetPasswordWithTokenRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+
+    let pool = &state.db.pool;
+    let token_hash = hash_token(&req.token);
+
+    let owner = match UserToken::find_valid(pool, &token_hash).await {
+        Ok(Some(owner)) => owner,
+        Ok(None) => return HttpResponse::NotFound().body("Endereço inválido ou expirado."),
+        Err(e) => {
+            log::error!("Error fetching password token: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match check_new_password(
+        pool,
+        &state.password_policy,
+        Some(owner.user_id),
+        &req.new_password,
+        &[&owner.username, &owner.email],
+    )
+    .await
+    {
+        Ok(violations) if violations.is_empty() => {}
+        Ok(violations) => return password_rejected(violations),
+        Err(e) => {
+            log::error!(
+                "Error checking new password of user {}: {}",
+                owner.user_id,
+                e
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            log::error!("Database error starting transaction: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    // The token is taken again under a lock, it may have been used in the meantime
+    let (user_id, purpose) = match UserToken::consume(&mut tx, &token_hash).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return HttpResponse::NotFound().body("Endereço inválido ou expirado."),
+        Err(e) => {
+            log::error!("Error using password token: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Err(e) =
+        store_password(&mut tx, &state.password_policy, user_id, &req.new_password).await
+    {
+        log::error!("Database error setting password of user {}: {}", user_id, e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(e) = tx.commit().await {
+        log::error!("Database error committing new password: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // Proving access to the mailbox lifts a lock left by failed logins
+    if let Err(e) = LoginThrottle::clear(pool, SCOPE_ACCOUNT, &account_key(&owner.email)).await {
+        log::error!("Error clearing failed logins of user {}: {}", user_id, e);
+    }
+
+    SecurityEvent::record(
+        pool,
+        NewSecurityEvent {
+            event_type: SECURITY_EVENT_PASSWORD_CHANGE,
+            user_id: Some(user_id),
+            ip_address: Some(&client_ip(&http_req)),
+            details: Some(format!("Definida através de {}", purpose)),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    HttpResponse::Ok().body("Palavra-passe definida com sucesso.")
+}
+
+pub async fn get_registration_status(state: web::Data<State>) -> impl Responder {
+    match AppSetting::get_bool(&state.db.pool, SETTING_REGISTRATION_OPEN, true).await {
+        Ok(open) => HttpResponse::Ok().json(serde_json::json!({ "open": open })),
+        Err(e) => {
+            log::error!("Error fetching registration setting: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Opens or closes `/users/register`. With registration closed, accounts are only created by
+/// invitation.
+pub async fn set_registration_status(
+    state: web::Data<State>,
+    admin: AdminUser,
+    http_req: HttpRequest,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<RegistrationSettingRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+
+    let value = if req.open { "true" } else { "false" };
+    if let Err(e) = AppSetting::set(
+        &state.db.pool,
+        SETTING_REGISTRATION_OPEN,
+        value,
+        admin.id as u32,
+    )
+    .await
+    {
+        log::error!("Error storing registration setting: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    SecurityEvent::record(
+        &state.db.pool,
+        NewSecurityEvent {
+            event_type: SECURITY_EVENT_REGISTRATION_CHANGED,
+            actor_user_id: Some(admin.id as u32),
+            ip_address: Some(&client_ip(&http_req)),
+            details: Some(format!("{} = {}", SETTING_REGISTRATION_OPEN, value)),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    HttpResponse::Ok().json(serde_json::json!({ "open": req.open }))
+}
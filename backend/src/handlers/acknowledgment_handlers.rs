@@ -1,15 +1,14 @@
-use actix_session::Session;
 use actix_web::{HttpResponse, Responder, web};
 use serde::Serialize;
 
 use crate::{
     State,
-    auth::{user_can_view_page, validate_session},
+    auth::AuthenticatedUser,
     models::{
-        custom_page::CustomPage, // Added for fetching user-specific page permissions
         page_record::PageRecord, // To get page_id from record_id
         record_acknowledgment::RecordAcknowledgment,
     },
+    services::permission_service::Permissions,
     utils::json_utils::json_response,
 };
 
@@ -17,12 +16,9 @@ use crate::{
 pub async fn acknowledge_record(
     state: web::Data<State>,
     record_id_path: web::Path<u32>,
-    session: Session,
+    permissions: Permissions,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+    let user_id = permissions.user_id() as u32;
     let record_id = record_id_path.into_inner();
 
     // First, check if the user can even view the page this record belongs to
@@ -37,9 +33,9 @@ pub async fn acknowledge_record(
         }
     };
 
-    match user_can_view_page(&state.db.pool, user_id as i32, page_id).await {
-        Ok(can_view) => {
-            if !can_view {
+    match permissions.for_page(page_id).await {
+        Ok(perms) => {
+            if !perms.can_view {
                 log::warn!(
                     "User {} attempted to acknowledge record {} on page {} without view permission.",
                     user_id,
@@ -86,12 +82,9 @@ struct AcknowledgmentStatusResponse {
 pub async fn check_acknowledgment_status(
     state: web::Data<State>,
     record_id_path: web::Path<u32>,
-    session: Session,
+    user: AuthenticatedUser,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id as u32;
     let record_id = record_id_path.into_inner();
 
     // Optional: Could also check if user can view the page here, for consistency.
@@ -117,12 +110,9 @@ pub async fn check_acknowledgment_status(
 pub async fn get_acknowledgments_for_record(
     state: web::Data<State>,
     record_id_path: web::Path<u32>,
-    session: Session,
+    permissions: Permissions,
 ) -> impl Responder {
-    let user_id_from_session = match validate_session(&session) {
-        Ok(id) => id,
-        Err(resp) => return resp,
-    };
+    let user_id_from_session = permissions.user_id();
     let record_id = record_id_path.into_inner();
 
     let page_id = match PageRecord::get_page_id_for_record(&state.db.pool, record_id).await {
@@ -135,11 +125,9 @@ pub async fn get_acknowledgments_for_record(
     };
 
     // Check if the user has permission to view acknowledgments for this page
-    match CustomPage::get_user_permissions_for_page(&state.db.pool, user_id_from_session, page_id)
-        .await
-    {
-        Ok(permissions) => {
-            if !permissions.is_admin && !permissions.can_view_acknowledgments {
+    match permissions.for_page(page_id).await {
+        Ok(perms) => {
+            if !perms.is_admin && !perms.can_view_acknowledgments {
                 log::warn!(
                     "User {} attempted to view acknowledgments for record {} on page {} without can_view_acknowledgments permission.",
                     user_id_from_session,
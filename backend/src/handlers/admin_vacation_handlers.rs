@@ -1,33 +1,69 @@
-use actix_session::Session;
-use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use actix_web::{HttpRequest, HttpResponse, Responder, http::header, web};
 
 use crate::{
     State,
-    auth::is_admin,
-    models::{notification::Notification, role::Role, vacation_request::{VacationRequest, VacationRequestStatus}},
+    auth::AdminScope,
+    handlers::vacation_handlers::absence_document_response,
+    models::{
+        absence_type::{ABSENCE_TYPE_VACATION, AbsenceDocument, AbsenceType},
+        coverage_rule::{CoverageRule, violations_message},
+        notification::Notification,
+        role::Role,
+        vacation_approval::{APPROVAL_APPROVED, APPROVAL_REJECTED, VacationRequestApproval},
+        vacation_overview::{
+            ExportFormat, OverviewUser, VacationOverview, VacationOverviewExportQuery,
+            VacationOverviewQuery,
+        },
+        vacation_request::{ActionVacationRequest, VacationRequest, VacationRequestStatus},
+        work_schedule::WorkingTime,
+    },
+    services::{
+        vacation_approval_service::{next_step, open_step},
+        vacation_report_service::{overview_csv, overview_xlsx},
+    },
     utils::json_utils::json_response_with_etag,
 };
 
 // Use notification constants from the Notification module
 use crate::models::notification::{
+    NOTIFICATION_TYPE_VACATION_APPROVAL_PENDING,
     NOTIFICATION_TYPE_VACATION_APPROVED,
     NOTIFICATION_TYPE_VACATION_CANCELED,
     NOTIFICATION_TYPE_VACATION_CANCELLATION_REJECTED,
     NOTIFICATION_TYPE_VACATION_REJECTED,
 };
 
+/// Scoped admins can only see and action requests of members of the roles they manage, never
+/// their own.
+async fn can_manage_user(
+    state: &State,
+    admin: &AdminScope,
+    user_id: u32,
+) -> Result<bool, sqlx::Error> {
+    if admin.is_admin {
+        return Ok(true);
+    }
+    if user_id == admin.id as u32 {
+        return Ok(false);
+    }
+
+    let role_ids = Role::get_effective_role_ids(&state.db.pool, user_id).await?;
+    Ok(role_ids
+        .into_iter()
+        .any(|role_id| admin.can_manage_role(role_id)))
+}
+
 // Handler to get pending vacation requests for users within a specific role
 pub async fn get_pending_requests_for_role(
     state: web::Data<State>,
-    session: Session,
+    admin: AdminScope,
     req: HttpRequest,
     role_id_path: web::Path<u32>,
 ) -> impl Responder {
-    if let Err(admin_check_response) = is_admin(&session) {
-        return admin_check_response;
-    }
-
     let role_id = role_id_path.into_inner();
+    if !admin.can_manage_role(role_id) {
+        return HttpResponse::Forbidden().finish();
+    }
 
     // 1. Get user IDs for the given role_id
     let user_ids = match Role::get_user_ids_by_role_id(&state.db.pool, role_id).await {
@@ -64,15 +100,10 @@ pub async fn get_pending_requests_for_role(
 // Handler for an admin to action a vacation request (approve or reject)
 pub async fn action_vacation_request_admin(
     state: web::Data<State>,
-    session: Session,
+    admin: AdminScope,
     request_id_path: web::Path<u32>,
-    data: web::Json<crate::models::vacation_request::ActionVacationRequest>,
+    data: web::Json<ActionVacationRequest>,
 ) -> impl Responder {
-    let admin_user_id = match is_admin(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
-
     let request_id = request_id_path.into_inner();
     let action_data = data.into_inner();
 
@@ -91,17 +122,280 @@ pub async fn action_vacation_request_admin(
         }
     };
 
+    // Only global admins decide their own requests
+    if !admin.is_admin && request_details.user_id == admin.id as u32 {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    // Requests in an approval chain are decided one step at a time, by the approver of the
+    // step or by a global admin in their place
+    let current_step = if request_details.status == VacationRequestStatus::Pending {
+        match VacationRequestApproval::get_open(&state.db.pool, request_id).await {
+            Ok(current_step) => current_step,
+            Err(e) => {
+                log::error!(
+                    "Error fetching approval step of vacation request {}: {}",
+                    request_id,
+                    e
+                );
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    } else {
+        None
+    };
+
+    let allowed = match &current_step {
+        Some(_) if admin.is_admin => Ok(true),
+        Some(step) => {
+            step.can_be_decided_by(
+                &state.db.pool,
+                admin.id as u32,
+                request_details.user_id,
+                chrono::Utc::now().date_naive(),
+            )
+            .await
+        }
+        None => can_manage_user(&state, &admin, request_details.user_id).await,
+    };
+    match allowed {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Forbidden().finish(),
+        Err(e) => {
+            log::error!(
+                "Error checking who can action vacation request {}: {}",
+                request_id,
+                e
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    action_request(
+        &state,
+        admin.id as u32,
+        admin.is_admin,
+        request_details,
+        current_step,
+        action_data,
+    )
+    .await
+}
+
+/// Approves or rejects a request, or its cancellation, once the caller checked `actor_id` may.
+/// A step of an approval chain approved before the last one only moves the request on to the
+/// next step. Only global admins, `actor_is_admin`, can approve despite the coverage rules.
+pub(crate) async fn action_request(
+    state: &State,
+    actor_id: u32,
+    actor_is_admin: bool,
+    request_details: VacationRequest,
+    current_step: Option<VacationRequestApproval>,
+    action_data: ActionVacationRequest,
+) -> HttpResponse {
+    let request_id = request_details.id;
+    let today = chrono::Utc::now().date_naive();
+
+    // Names the request in the messages, "férias" for vacation
+    let (absence_label, is_vacation, requires_approval) =
+        match AbsenceType::get_by_id(&state.db.pool, request_details.absence_type_id).await {
+            Ok(Some(absence_type)) => (
+                absence_type.label(),
+                absence_type.id == ABSENCE_TYPE_VACATION,
+                absence_type.requires_approval,
+            ),
+            Ok(None) => ("ausência".to_string(), false, true),
+            Err(e) => {
+                log::error!(
+                    "Error fetching absence type {}: {}",
+                    request_details.absence_type_id,
+                    e
+                );
+                return HttpResponse::InternalServerError().finish();
+            }
+        };
+
+    if let Some(step) = &current_step
+        && action_data.status == VacationRequestStatus::Approved
+    {
+        let next = match next_step(&state.db.pool, request_details.user_id, step.step_order).await {
+            Ok(next) => next,
+            Err(e) => {
+                log::error!(
+                    "Error fetching approval chain of vacation request {}: {}",
+                    request_id,
+                    e
+                );
+                return HttpResponse::InternalServerError().finish();
+            }
+        };
+
+        if let Some(next) = next {
+            match VacationRequestApproval::decide(
+                &state.db.pool,
+                step.id,
+                APPROVAL_APPROVED,
+                actor_id,
+                action_data.admin_notes.as_deref(),
+            )
+            .await
+            {
+                Ok(true) => {}
+                Ok(false) => {
+                    return HttpResponse::Conflict().body("Este passo já foi decidido.");
+                }
+                Err(e) => {
+                    log::error!(
+                        "Error approving step {} of vacation request {}: {}",
+                        step.step_order,
+                        request_id,
+                        e
+                    );
+                    return HttpResponse::InternalServerError().finish();
+                }
+            }
+
+            if let Err(e) = open_step(
+                &state.db.pool,
+                request_id,
+                request_details.user_id,
+                &next,
+                today,
+            )
+            .await
+            {
+                log::error!(
+                    "Error opening step {} of vacation request {}: {}",
+                    next.step_order,
+                    request_id,
+                    e
+                );
+                return HttpResponse::InternalServerError().finish();
+            }
+
+            let user_message = format!(
+                "O seu pedido de {} ({} a {}) foi aprovado em {} e segue para {}.",
+                absence_label,
+                request_details.start_date.format("%d/%m/%Y"),
+                request_details.end_date.format("%d/%m/%Y"),
+                step.step_name.as_deref().unwrap_or("aprovação"),
+                next.name
+            );
+            if let Err(e) = Notification::create(
+                &state.db.pool,
+                request_details.user_id,
+                None,
+                Some(request_id),
+                None,
+                None,
+                NOTIFICATION_TYPE_VACATION_APPROVAL_PENDING,
+                &user_message,
+                Some(request_details.end_date),
+            )
+            .await
+            {
+                log::error!(
+                    "Failed to notify user {} of approval step of vacation request {}: {}",
+                    request_details.user_id,
+                    request_id,
+                    e
+                );
+            }
+
+            return HttpResponse::Ok().body(format!(
+                "Passo aprovado. O pedido segue para {}.",
+                next.name
+            ));
+        }
+    }
+
+    // Absences approved since the request was made can leave too few members, unless a
+    // global admin chooses to approve it anyway
+    if action_data.status == VacationRequestStatus::Approved
+        && request_details.status == VacationRequestStatus::Pending
+        && requires_approval
+        && !(actor_is_admin && action_data.ignore_coverage)
+    {
+        let violations = match WorkingTime::load(
+            &state.db.pool,
+            request_details.user_id,
+            request_details.start_date,
+            request_details.end_date,
+        )
+        .await
+        {
+            Ok(working_time) => {
+                CoverageRule::find_violations(
+                    &state.db.pool,
+                    request_details.user_id,
+                    request_details.start_date,
+                    request_details.end_date,
+                    request_details.part(),
+                    &working_time,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        match violations {
+            Ok(violations) if violations.is_empty() => {}
+            Ok(violations) => {
+                return HttpResponse::Conflict().json(serde_json::json!({
+                    "message": violations_message(&violations),
+                    "violations": violations,
+                }));
+            }
+            Err(e) => {
+                log::error!(
+                    "Error checking coverage rules for vacation request {}: {}",
+                    request_id,
+                    e
+                );
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
     // Action the request
     match crate::models::vacation_request::VacationRequest::action_request_with_days_deduction(
         &state.db.pool,
         request_id,
-        admin_user_id,
+        actor_id,
         action_data.status,
-        action_data.admin_notes,
+        action_data.admin_notes.clone(),
+        current_step.as_ref().map(|step| step.id),
     )
     .await
     {
         Ok(true) => {
+            // The decision on a pending request outside a chain goes in its approval trail;
+            // the step of a chain was decided with the request
+            let decision = match action_data.status {
+                VacationRequestStatus::Approved => Some(APPROVAL_APPROVED),
+                VacationRequestStatus::Rejected => Some(APPROVAL_REJECTED),
+                _ => None,
+            };
+            if request_details.status == VacationRequestStatus::Pending
+                && current_step.is_none()
+                && let Some(decision) = decision
+            {
+                let recorded = VacationRequestApproval::record_direct(
+                    &state.db.pool,
+                    request_id,
+                    decision,
+                    actor_id,
+                    action_data.admin_notes.as_deref(),
+                )
+                .await;
+                if let Err(e) = recorded {
+                    log::error!(
+                        "Error recording approval trail of vacation request {}: {}",
+                        request_id,
+                        e
+                    );
+                }
+            }
+
             // Format dates for the message
             let start_date_fmt = request_details.start_date.format("%d/%m/%Y").to_string();
             let end_date_fmt = request_details.end_date.format("%d/%m/%Y").to_string();
@@ -117,9 +411,9 @@ pub async fn action_vacation_request_admin(
             let (notification_type, user_message) = match action_data.status {
                 VacationRequestStatus::Approved => {
                     let msg = if request_details.status == VacationRequestStatus::CancellationRequested {
-                        format!("O seu pedido de cancelamento das férias ({} a {}) foi recusado. As férias mantêm-se aprovadas.", start_date_fmt, end_date_fmt)
+                        format!("O seu pedido de cancelamento do pedido de {} ({} a {}) foi recusado. O pedido mantém-se aprovado.", absence_label, start_date_fmt, end_date_fmt)
                     } else {
-                        format!("O seu pedido de férias ({} a {}) foi aprovado.", start_date_fmt, end_date_fmt)
+                        format!("O seu pedido de {} ({} a {}) foi aprovado.", absence_label, start_date_fmt, end_date_fmt)
                     };
                     let notif_type = if request_details.status == VacationRequestStatus::CancellationRequested {
                         NOTIFICATION_TYPE_VACATION_CANCELLATION_REJECTED
@@ -130,11 +424,11 @@ pub async fn action_vacation_request_admin(
                 },
                 VacationRequestStatus::Rejected => (
                     NOTIFICATION_TYPE_VACATION_REJECTED,
-                    format!("O seu pedido de férias ({} a {}) foi recusado.", start_date_fmt, end_date_fmt),
+                    format!("O seu pedido de {} ({} a {}) foi recusado.", absence_label, start_date_fmt, end_date_fmt),
                 ),
                 VacationRequestStatus::Cancelled => (
                     NOTIFICATION_TYPE_VACATION_CANCELED,
-                    format!("O seu pedido de cancelamento das férias ({} a {}) foi aprovado. As férias foram canceladas.", start_date_fmt, end_date_fmt),
+                    format!("O seu pedido de cancelamento do pedido de {} ({} a {}) foi aprovado. O pedido foi cancelado.", absence_label, start_date_fmt, end_date_fmt),
                 ),
                 _ => unreachable!(),
             };
@@ -173,7 +467,20 @@ pub async fn action_vacation_request_admin(
             let notify_colleagues = action_data.status == VacationRequestStatus::Approved && request_details.status == VacationRequestStatus::Pending
                 || action_data.status == VacationRequestStatus::Cancelled;
             if notify_colleagues {
-                let colleague_message = if action_data.status == VacationRequestStatus::Cancelled {
+                // Colleagues are not told the reason of other absences
+                let colleague_message = if !is_vacation {
+                    if action_data.status == VacationRequestStatus::Cancelled {
+                        format!(
+                            "A ausência do seu colega {} ({} a {}) foi cancelada.",
+                            user_name, start_date_fmt, end_date_fmt
+                        )
+                    } else {
+                        format!(
+                            "O seu colega {} vai estar ausente ({} a {}).",
+                            user_name, start_date_fmt, end_date_fmt
+                        )
+                    }
+                } else if action_data.status == VacationRequestStatus::Cancelled {
                     format!(
                         "As férias do seu colega {} ({} a {}) foram canceladas.",
                         user_name, start_date_fmt, end_date_fmt
@@ -241,6 +548,13 @@ pub async fn action_vacation_request_admin(
             // Success response
             HttpResponse::Ok().body("Pedido de férias atualizado com sucesso")
         }
+        Err(sqlx::Error::Protocol(message)) if message == "Supporting document missing." => {
+            HttpResponse::BadRequest()
+                .body("Este tipo de ausência precisa de um documento comprovativo.")
+        }
+        Ok(false) if current_step.is_some() => {
+            HttpResponse::Conflict().body("Este passo já foi decidido.")
+        }
         Ok(false) => HttpResponse::BadRequest().body(
             "Pedido de férias já foi atualizado ou não encontrado quando tentando atualizar o status.",
         ),
@@ -250,3 +564,207 @@ pub async fn action_vacation_request_admin(
         }
     }
 }
+
+// Handler for the documents supporting a request, like a sick note
+pub async fn get_request_documents(
+    state: web::Data<State>,
+    admin: AdminScope,
+    req: HttpRequest,
+    request_id_path: web::Path<u32>,
+) -> impl Responder {
+    let request_id = request_id_path.into_inner();
+
+    let request_details = match VacationRequest::get_by_id(&state.db.pool, request_id).await {
+        Ok(Some(request)) => request,
+        Ok(None) => return HttpResponse::NotFound().body("Vacation request not found"),
+        Err(e) => {
+            log::error!("Error fetching vacation request {}: {}", request_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match can_manage_user(&state, &admin, request_details.user_id).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Forbidden().finish(),
+        Err(e) => {
+            log::error!(
+                "Error fetching roles of user {}: {}",
+                request_details.user_id,
+                e
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    match AbsenceDocument::get_for_request(&state.db.pool, request_id).await {
+        Ok(documents) => json_response_with_etag(&documents, &req),
+        Err(e) => {
+            log::error!("Error fetching documents of request {}: {}", request_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+// Handler for an admin to download a document supporting a request
+pub async fn download_request_document(
+    state: web::Data<State>,
+    admin: AdminScope,
+    req: HttpRequest,
+    path: web::Path<(u32, u32)>,
+) -> impl Responder {
+    let (request_id, document_id) = path.into_inner();
+
+    let request_details = match VacationRequest::get_by_id(&state.db.pool, request_id).await {
+        Ok(Some(request)) => request,
+        Ok(None) => return HttpResponse::NotFound().body("Vacation request not found"),
+        Err(e) => {
+            log::error!("Error fetching vacation request {}: {}", request_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match can_manage_user(&state, &admin, request_details.user_id).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Forbidden().finish(),
+        Err(e) => {
+            log::error!(
+                "Error fetching roles of user {}: {}",
+                request_details.user_id,
+                e
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    absence_document_response(&state, &req, request_id, document_id).await
+}
+// Handler for the approval trail of a request: who approved each step, or who it waits on
+pub async fn get_request_approvals(
+    state: web::Data<State>,
+    admin: AdminScope,
+    req: HttpRequest,
+    request_id_path: web::Path<u32>,
+) -> impl Responder {
+    let request_id = request_id_path.into_inner();
+
+    let request_details = match VacationRequest::get_by_id(&state.db.pool, request_id).await {
+        Ok(Some(request)) => request,
+        Ok(None) => return HttpResponse::NotFound().body("Vacation request not found"),
+        Err(e) => {
+            log::error!("Error fetching vacation request {}: {}", request_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match can_manage_user(&state, &admin, request_details.user_id).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Forbidden().finish(),
+        Err(e) => {
+            log::error!(
+                "Error fetching roles of user {}: {}",
+                request_details.user_id,
+                e
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    match VacationRequestApproval::get_for_request(&state.db.pool, request_id).await {
+        Ok(approvals) => json_response_with_etag(&approvals, &req),
+        Err(e) => {
+            log::error!("Error fetching approvals of request {}: {}", request_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Scoped admins see the members of the roles they manage, never themselves.
+fn can_see_user(admin: &AdminScope, user: &OverviewUser) -> bool {
+    admin.is_admin
+        || (user.id != admin.id as u32
+            && user
+                .role_ids
+                .iter()
+                .any(|role_id| admin.can_manage_role(*role_id)))
+}
+
+/// Builds the overview of a query, or the response to send when it cannot be built.
+async fn build_overview(
+    state: &State,
+    admin: &AdminScope,
+    query: &VacationOverviewQuery,
+) -> Result<VacationOverview, HttpResponse> {
+    if let Err(message) = query.validate() {
+        return Err(HttpResponse::BadRequest().body(message));
+    }
+    if let Some(role_id) = query.role_id
+        && !admin.can_manage_role(role_id)
+    {
+        return Err(HttpResponse::Forbidden().finish());
+    }
+
+    VacationOverview::build(&state.db.pool, query, |user| can_see_user(admin, user))
+        .await
+        .map_err(|e| {
+            log::error!("Error building vacation overview: {}", e);
+            HttpResponse::InternalServerError().finish()
+        })
+}
+
+// Handler for the absences of everyone, or of a role or location, day by day, with a summary
+// of each user's vacation
+pub async fn get_vacation_overview(
+    state: web::Data<State>,
+    admin: AdminScope,
+    req: HttpRequest,
+    query: web::Query<VacationOverviewQuery>,
+) -> impl Responder {
+    match build_overview(&state, &admin, &query).await {
+        Ok(overview) => json_response_with_etag(&overview, &req),
+        Err(response) => response,
+    }
+}
+
+// Handler for the overview as a CSV or XLSX file
+pub async fn export_vacation_overview(
+    state: web::Data<State>,
+    admin: AdminScope,
+    query: web::Query<VacationOverviewQuery>,
+    export: web::Query<VacationOverviewExportQuery>,
+) -> impl Responder {
+    let overview = match build_overview(&state, &admin, &query).await {
+        Ok(overview) => overview,
+        Err(response) => return response,
+    };
+
+    let (body, content_type, extension) = match export.format {
+        ExportFormat::Csv => (
+            overview_csv(&overview).into_bytes(),
+            "text/csv; charset=utf-8",
+            "csv",
+        ),
+        ExportFormat::Xlsx => match overview_xlsx(&overview) {
+            Ok(body) => (
+                body,
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                "xlsx",
+            ),
+            Err(e) => {
+                log::error!("Error writing vacation overview spreadsheet: {}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        },
+    };
+
+    let file_name = format!(
+        "ferias_{}_{}.{}",
+        overview.start_date, overview.end_date, extension
+    );
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", file_name),
+        ))
+        .body(body)
+}
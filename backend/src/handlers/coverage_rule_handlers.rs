@@ -0,0 +1,101 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+
+use crate::{
+    State,
+    auth::AdminUser,
+    models::coverage_rule::{CoverageRule, CoverageRuleRequest},
+    utils::json_utils::{Json, json_response_with_etag},
+};
+
+/// Every coverage rule, inactive ones included.
+pub async fn get_coverage_rules(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+) -> impl Responder {
+    match CoverageRule::get_all(&state.db.pool).await {
+        Ok(rules) => json_response_with_etag(&rules, &req),
+        Err(e) => {
+            log::error!("Error fetching coverage rules: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn create_coverage_rule(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<CoverageRuleRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match CoverageRule::create(&state.db.pool, &req).await {
+        Ok(id) => HttpResponse::Created().json(serde_json::json!({ "id": id })),
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            HttpResponse::BadRequest().body("Função não encontrada")
+        }
+        Err(e) => {
+            log::error!("Error creating coverage rule: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Changes a rule. Requests already approved are not checked again.
+pub async fn update_coverage_rule(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let rule_id = path.into_inner();
+
+    let Json(req): Json<CoverageRuleRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match CoverageRule::update(&state.db.pool, rule_id, &req).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Regra de cobertura não encontrada"),
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            HttpResponse::BadRequest().body("Função não encontrada")
+        }
+        Err(e) => {
+            log::error!("Error updating coverage rule {}: {}", rule_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn delete_coverage_rule(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let rule_id = path.into_inner();
+
+    match CoverageRule::delete(&state.db.pool, rule_id).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Regra de cobertura não encontrada"),
+        Err(e) => {
+            log::error!("Error deleting coverage rule {}: {}", rule_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
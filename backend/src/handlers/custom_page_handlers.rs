@@ -1,4 +1,3 @@
-use actix_session::Session;
 use actix_web::{HttpRequest, HttpResponse, Responder, web, FromRequest};
 use actix_multipart::Multipart;
 use futures_util::{StreamExt, TryStreamExt};
@@ -9,26 +8,23 @@ use uuid::Uuid;
 
 use crate::{
     State,
-    auth::{is_admin, user_can_manage_page, validate_session},
+    auth::{AdminScope, AuthenticatedUser},
     models::{
-        auth::calculate_user_page_permissions,
         custom_page::{
             CreateCustomPageRequest, CustomPage, RolePermissionRequest, UpdateCustomPageRequest,
         },
+        page_row_rule::{PageRowRule, RowRuleRequest},
         role::Role,
     },
+    services::permission_service::Permissions,
     utils::json_utils::{Json, json_response_with_etag},
 };
 
 pub async fn get_group_pages(
     state: web::Data<State>,
-    session: Session,
+    _user: AuthenticatedUser,
     req: HttpRequest,
 ) -> impl Responder {
-    if let Err(resp) = validate_session(&session) {
-        return resp;
-    }
-
     // In a more structured approach, this query would be in `CustomPage::get_all_groups(&state.db.pool).await`
     match sqlx::query_as!(
         CustomPage,
@@ -56,13 +52,9 @@ pub async fn get_group_pages(
 
 pub async fn get_custom_pages(
     state: web::Data<State>,
-    session: Session,
+    _user: AuthenticatedUser,
     req: HttpRequest,
 ) -> impl Responder {
-    if let Err(resp) = validate_session(&session) {
-        return resp;
-    }
-
     match CustomPage::get_all(&state.db.pool).await {
         Ok(pages) => json_response_with_etag(&pages, &req),
         Err(e) => {
@@ -75,13 +67,10 @@ pub async fn get_custom_pages(
 pub async fn get_custom_page(
     state: web::Data<State>,
     path: web::Path<u32>,
-    session: Session,
+    user: AuthenticatedUser,
     req: HttpRequest,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id;
     let page_id = path.into_inner();
 
     // Pass user_id to check permissions within get_by_id
@@ -105,13 +94,9 @@ pub async fn get_custom_page(
 
 pub async fn create_custom_page(
     state: web::Data<State>,
-    session: Session,
+    admin: AdminScope,
     mut payload: Multipart,
 ) -> impl Responder {
-    if let Err(resp) = is_admin(&session) {
-        return resp;
-    }
-
     // Check if this is a multipart form (with file) or a JSON request
     let mut fields = std::collections::HashMap::new();
     let mut icon_image: Option<(Vec<u8>, String)> = None;
@@ -179,6 +164,10 @@ pub async fn create_custom_page(
                 custom_page_req.fields = fields;
             }
         }
+
+        if !admin.can_manage_parent_path(custom_page_req.parent_path.as_deref()) {
+            return HttpResponse::Forbidden().finish();
+        }
         
         // Create the page first to get its ID
         let page_id = match CustomPage::create(&state.db.pool, &custom_page_req).await {
@@ -240,12 +229,8 @@ pub async fn create_custom_page(
 pub async fn create_custom_page_json(
     state: web::Data<State>,
     req: HttpRequest,
-    session: Session,
+    admin: AdminScope,
 ) -> impl Responder {
-    if let Err(resp) = is_admin(&session) {
-        return resp;
-    }
-
     // Parse request body
     let body = match web::Json::<CreateCustomPageRequest>::extract(&req).await {
         Ok(json_data) => json_data.into_inner(),
@@ -264,6 +249,10 @@ pub async fn create_custom_page_json(
         return HttpResponse::BadRequest().finish();
     }
 
+    if !admin.can_manage_parent_path(body.parent_path.as_deref()) {
+        return HttpResponse::Forbidden().finish();
+    }
+
     match CustomPage::create(&state.db.pool, &body).await {
         Ok(page_id) => HttpResponse::Created().body(page_id.to_string()),
         Err(e) => {
@@ -273,28 +262,53 @@ pub async fn create_custom_page_json(
     }
 }
 
+/// Managing a page's fields lets a user edit it, but moving it to another group also needs
+/// the right to create pages there, like [`create_custom_page`] checks.
+async fn check_parent_path_change(
+    state: &State,
+    req: &HttpRequest,
+    page_id: u32,
+    parent_path: Option<&str>,
+) -> Result<(), HttpResponse> {
+    let current = match CustomPage::get_parent_path(&state.db.pool, page_id).await {
+        Ok(current) => current,
+        Err(sqlx::Error::RowNotFound) => return Err(HttpResponse::NotFound().finish()),
+        Err(e) => {
+            log::error!("Error fetching parent path of page {}: {}", page_id, e);
+            return Err(HttpResponse::InternalServerError().finish());
+        }
+    };
+
+    let clean = |path: &str| {
+        if path.len() > 1 {
+            path.trim_end_matches('/').to_string()
+        } else {
+            path.to_string()
+        }
+    };
+    if current.as_deref().map(clean) == parent_path.map(clean) {
+        return Ok(());
+    }
+
+    match AdminScope::extract(req).await {
+        Ok(admin) if admin.can_manage_parent_path(parent_path) => Ok(()),
+        _ => Err(HttpResponse::Forbidden().finish()),
+    }
+}
+
 pub async fn update_custom_page(
     state: web::Data<State>,
     path: web::Path<u32>,
+    req: HttpRequest,
     mut payload: Multipart,
-    session: Session,
+    permissions: Permissions,
 ) -> impl Responder {
     let page_id = path.into_inner();
-    let user_id = match validate_session(&session) {
-        Ok(id) => id,
+    match permissions.page(page_id).await {
+        Ok(perms) if perms.can_manage_fields => {}
+        Ok(_) => return HttpResponse::Forbidden().finish(),
         Err(resp) => return resp,
-    };
-    match user_can_manage_page(&state.db.pool, user_id, page_id).await {
-        Ok(can) => {
-            if !can {
-                return HttpResponse::Forbidden().finish();
-            }
-        }
-        Err(e) => {
-            log::error!("Error checking permissions: {}", e);
-            return HttpResponse::InternalServerError().finish();
-        }
-    };
+    }
 
     // Check if this is a multipart form (with file) or a JSON request
     let mut fields = std::collections::HashMap::new();
@@ -343,6 +357,12 @@ pub async fn update_custom_page(
             notify_on_new_record: fields.get("notify_on_new_record").map(|v| Some(v == "true")).unwrap_or(None),
             requires_acknowledgment: fields.get("requires_acknowledgment").map(|v| Some(v == "true")).unwrap_or(None),
         };
+
+        if let Err(resp) =
+            check_parent_path_change(&state, &req, page_id, update_req.parent_path.as_deref()).await
+        {
+            return resp;
+        }
         
         // Update the page
         match CustomPage::update(&state.db.pool, page_id, &update_req).await {
@@ -401,24 +421,14 @@ pub async fn update_custom_page_json(
     state: web::Data<State>,
     path: web::Path<u32>,
     req: HttpRequest,
-    session: Session,
+    permissions: Permissions,
 ) -> impl Responder {
     let page_id = path.into_inner();
-    let user_id = match validate_session(&session) {
-        Ok(id) => id,
+    match permissions.page(page_id).await {
+        Ok(perms) if perms.can_manage_fields => {}
+        Ok(_) => return HttpResponse::Forbidden().finish(),
         Err(resp) => return resp,
-    };
-    match user_can_manage_page(&state.db.pool, user_id, page_id).await {
-        Ok(can) => {
-            if !can {
-                return HttpResponse::Forbidden().finish();
-            }
-        }
-        Err(e) => {
-            log::error!("Error checking permissions: {}", e);
-            return HttpResponse::InternalServerError().finish();
-        }
-    };
+    }
 
     // Parse request body
     let body = match web::Json::<UpdateCustomPageRequest>::extract(&req).await {
@@ -429,6 +439,12 @@ pub async fn update_custom_page_json(
         }
     };
 
+    if let Err(resp) =
+        check_parent_path_change(&state, &req, page_id, body.parent_path.as_deref()).await
+    {
+        return resp;
+    }
+
     match CustomPage::update(&state.db.pool, page_id, &body).await {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(e) => {
@@ -477,12 +493,11 @@ async fn handle_icon_upload(page_id: u32, image_data: Vec<u8>, filename: String)
 pub async fn delete_custom_page(
     state: web::Data<State>,
     path: web::Path<u32>,
-    session: Session,
+    admin: AdminScope,
 ) -> impl Responder {
     let page_id = path.into_inner();
-
-    if let Err(resp) = is_admin(&session) {
-        return resp;
+    if !admin.can_manage_page(page_id) {
+        return HttpResponse::Forbidden().finish();
     }
 
     match CustomPage::delete(&state.db.pool, page_id).await {
@@ -496,13 +511,10 @@ pub async fn delete_custom_page(
 
 pub async fn get_navigation_menu(
     state: web::Data<State>,
-    session: Session,
+    user: AuthenticatedUser,
     req: HttpRequest,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id;
 
     match CustomPage::get_navigation_menu(&state.db.pool, user_id).await {
         Ok(menu) => json_response_with_etag(&menu, &req),
@@ -517,10 +529,10 @@ pub async fn update_page_permissions(
     state: web::Data<State>,
     path: web::Path<u32>,
     data: web::Bytes,
-    session: Session,
+    admin: AdminScope,
 ) -> impl Responder {
-    if let Err(resp) = is_admin(&session) {
-        return resp;
+    if !admin.can_manage_page(*path) {
+        return HttpResponse::Forbidden().finish();
     }
 
     let Json(permissions): Json<Vec<RolePermissionRequest>> = match Json::from_bytes(&data) {
@@ -540,16 +552,65 @@ pub async fn update_page_permissions(
     }
 }
 
+pub async fn get_page_row_rules(
+    state: web::Data<State>,
+    path: web::Path<u32>,
+    admin: AdminScope,
+    req: HttpRequest,
+) -> impl Responder {
+    if !admin.can_manage_page(*path) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    match PageRowRule::get_by_page_id(&state.db.pool, *path).await {
+        Ok(rules) => json_response_with_etag(&rules, &req),
+        Err(e) => {
+            log::error!("Error fetching row rules for page {}: {}", *path, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn update_page_row_rules(
+    state: web::Data<State>,
+    path: web::Path<u32>,
+    data: web::Bytes,
+    admin: AdminScope,
+) -> impl Responder {
+    if !admin.can_manage_page(*path) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let Json(rules): Json<Vec<RowRuleRequest>> = match Json::from_bytes(&data) {
+        Ok(json) => json,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().finish();
+        }
+    };
+
+    for rule in &rules {
+        if let Err(message) = rule.validate() {
+            return HttpResponse::BadRequest().body(message);
+        }
+    }
+
+    match PageRowRule::replace_for_page(&state.db.pool, *path, &rules).await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => {
+            log::error!("Error updating row rules for page {}: {}", *path, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
 pub async fn get_custom_page_by_path(
     state: web::Data<State>,
     path_param: web::Path<String>,
-    session: Session,
+    user: AuthenticatedUser,
     req: HttpRequest, // Keep req for potential ETag later if needed
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id;
 
     let requested_path = path_param.into_inner();
     // Ensure path starts and ends with '/' for consistent DB lookup, if that's how they are stored
@@ -581,65 +642,31 @@ pub async fn get_custom_page_by_path(
 
     let page_id = page_info.id;
 
-    // 2. Get full page details (including all role permissions)
-    let mut page_with_fields = match CustomPage::get_by_id(&state.db.pool, page_id, user_id).await {
+    // 2. Get full page details, including all role permissions and the user's own. Users who
+    // cannot view the page get RowNotFound.
+    let page_with_fields = match CustomPage::get_by_id(&state.db.pool, page_id, user_id).await {
         Ok(page_data) => page_data,
-        Err(sqlx::Error::RowNotFound) => return HttpResponse::NotFound().finish(), // Should not happen if ID was found, but good practice
+        Err(sqlx::Error::RowNotFound) => return HttpResponse::NotFound().finish(),
         Err(e) => {
             log::error!("Error fetching page details for ID {}: {}", page_id, e);
             return HttpResponse::InternalServerError().finish();
         }
     };
 
-    // 3. Calculate current user's specific permissions for this page
-    match calculate_user_page_permissions(&state.db.pool, user_id, &page_with_fields.permissions)
-        .await
-    {
-        Ok(user_perms) => {
-            // 4. Check if user can view this page
-            if !user_perms.can_view {
-                log::warn!(
-                    "User {} attempted to view page {} ({}) without permission.",
-                    user_id,
-                    page_id,
-                    final_path
-                );
-                return HttpResponse::Forbidden().finish(); // Or NotFound() to obscure existence
-            }
-            // Attach calculated permissions to the response
-            page_with_fields.current_user_permissions = Some(user_perms);
-        }
-        Err(e) => {
-            log::error!(
-                "Error calculating user permissions for user {} on page {}: {}",
-                user_id,
-                page_id,
-                e
-            );
-            return HttpResponse::InternalServerError().finish();
-        }
-    }
-
-    // 5. Return the data
+    // 3. Return the data
     json_response_with_etag(&page_with_fields, &req)
 }
 
 pub async fn duplicate_custom_page(
     state: web::Data<State>,
     path: web::Path<u32>,
-    session: Session,
+    admin: AdminScope,
 ) -> impl Responder {
-    if let Err(resp) = is_admin(&session) {
-        return resp;
+    let page_id = path.into_inner();
+    if !admin.can_manage_page(page_id) {
+        return HttpResponse::Forbidden().finish();
     }
 
-    let page_id = path.into_inner();
-    // Get the user_id from the session (unused but needed for validation)
-    let _user_id = match validate_session(&session) {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::Unauthorized().finish(),
-    };
-    
     // Fetch the original page using a direct query instead of the get_by_id method
     // to bypass permission checks that might filter it out
     let original_page_data = match sqlx::query!(
@@ -669,6 +696,11 @@ pub async fn duplicate_custom_page(
         }
     };
 
+    // The copy goes next to the original, which has to be a place the user manages too
+    if !admin.can_manage_parent_path(original_page_data.parent_path.as_deref()) {
+        return HttpResponse::Forbidden().finish();
+    }
+
     // Start transaction for field and permission operations
     let mut tx = match state.db.pool.begin().await {
         Ok(tx) => tx,
@@ -836,14 +868,9 @@ pub async fn duplicate_custom_page(
 // Add new handler for reordering pages
 pub async fn reorder_pages(
     state: web::Data<State>,
-    session: Session,
+    admin: AdminScope,
     data: web::Bytes,
 ) -> impl Responder {
-    // Check if user is admin
-    if let Err(resp) = is_admin(&session) {
-        return resp;
-    }
-
     // Parse the request body
     #[derive(serde::Deserialize)]
     struct PageOrder {
@@ -864,6 +891,10 @@ pub async fn reorder_pages(
         }
     };
     
+    if !req.orders.iter().all(|order| admin.can_manage_page(order.id)) {
+        return HttpResponse::Forbidden().finish();
+    }
+
     // Convert the orders to the format expected by update_multiple_display_orders
     let orders: Vec<(u32, u32)> = req.orders
         .iter()
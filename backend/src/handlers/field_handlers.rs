@@ -1,26 +1,26 @@
-use actix_session::Session;
 use actix_web::{HttpRequest, HttpResponse, Responder, web};
 
 use crate::{
     State,
-    auth::{user_can_manage_page, validate_session},
+    auth::AuthenticatedUser,
     models::{
         custom_page::CreatePageFieldRequest,
-        field::{FieldType, PageField, UpdatePageFieldRequest},
+        field::{
+            FieldPermissionRequest, FieldType, PageField, PageFieldPermission,
+            UpdatePageFieldRequest,
+        },
+        record_due_date::RecordDueDate,
         validation,
     },
+    services::permission_service::Permissions,
     utils::json_utils::{Json, json_response, json_response_with_etag},
 };
 
 pub async fn get_field_types(
     state: web::Data<State>,
-    session: Session,
+    _user: AuthenticatedUser,
     req: HttpRequest,
 ) -> impl Responder {
-    if let Err(resp) = validate_session(&session) {
-        return resp;
-    }
-
     match FieldType::get_all(&state.db.pool).await {
         Ok(field_types) => json_response_with_etag(&field_types, &req),
         Err(e) => {
@@ -32,14 +32,10 @@ pub async fn get_field_types(
 
 pub async fn get_page_fields(
     state: web::Data<State>,
-    session: Session,
+    _user: AuthenticatedUser,
     path: web::Path<u32>,
     req: HttpRequest,
 ) -> impl Responder {
-    if let Err(resp) = validate_session(&session) {
-        return resp;
-    }
-
     match PageField::get_by_page_id(&state.db.pool, path.into_inner()).await {
         Ok(fields) => json_response_with_etag(&fields, &req),
         Err(e) => {
@@ -51,28 +47,17 @@ pub async fn get_page_fields(
 
 pub async fn add_page_field(
     state: web::Data<State>,
-    session: Session,
+    permissions: Permissions,
     path: web::Path<u32>,
     data: web::Bytes,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(user_id) => user_id,
-        Err(resp) => return resp,
-    };
-
     let page_id = path.into_inner();
 
-    match user_can_manage_page(&state.db.pool, user_id, page_id).await {
-        Ok(can) => {
-            if !can {
-                return HttpResponse::Forbidden().finish();
-            }
-        }
-        Err(resp) => {
-            log::error!("Error checking page permissions: {}", resp);
-            return HttpResponse::InternalServerError().finish();
-        }
-    };
+    match permissions.page(page_id).await {
+        Ok(perms) if perms.can_manage_fields => {}
+        Ok(_) => return HttpResponse::Forbidden().finish(),
+        Err(resp) => return resp,
+    }
 
     let Json(field_req): Json<CreatePageFieldRequest> = match Json::from_bytes(&data) {
         Ok(data) => data,
@@ -94,42 +79,14 @@ pub async fn add_page_field(
 
 pub async fn update_field(
     state: web::Data<State>,
-    session: Session,
+    permissions: Permissions,
     path: web::Path<u32>,
     data: web::Bytes,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(user_id) => user_id,
-        Err(resp) => return resp,
-    };
-
     let field_id = path.into_inner();
-    let page_id = match sqlx::query!(
-        r#"
-        SELECT page_id FROM page_fields WHERE id = ?
-        "#,
-        field_id
-    )
-    .fetch_one(&state.db.pool)
-    .await
-    {
-        Ok(record) => record.page_id,
-        Err(e) => {
-            log::error!("Error fetching page ID for field {}: {}", field_id, e);
-            return HttpResponse::InternalServerError().finish();
-        }
-    };
-
-    match user_can_manage_page(&state.db.pool, user_id, page_id).await {
-        Ok(can) => {
-            if !can {
-                return HttpResponse::Forbidden().finish();
-            }
-        }
-        Err(resp) => {
-            log::error!("Error checking page permissions: {}", resp);
-            return HttpResponse::InternalServerError().finish();
-        }
+    let page_id = match get_managed_field_page_id(&state, &permissions, field_id).await {
+        Ok(page_id) => page_id,
+        Err(resp) => return resp,
     };
 
     let Json(field_req): Json<UpdatePageFieldRequest> = match Json::from_bytes(&data) {
@@ -140,8 +97,25 @@ pub async fn update_field(
         }
     };
 
+    let previous_type_id = match PageField::get_field_type_id(&state.db.pool, field_id).await {
+        Ok(type_id) => type_id,
+        Err(e) => {
+            log::error!("Error fetching type of field {}: {}", field_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
     match PageField::update(&state.db.pool, field_id, &field_req).await {
-        Ok(_) => HttpResponse::Ok().finish(),
+        Ok(_) => {
+            // The field may have changed to or from a date type
+            if previous_type_id != field_req.field_type_id
+                && let Err(e) =
+                    RecordDueDate::sync_for_field(&state.db.pool, page_id, field_id).await
+            {
+                log::error!("Error re-indexing dates of field {}: {}", field_id, e);
+            }
+            HttpResponse::Ok().finish()
+        }
         Err(e) => {
             log::error!("Error updating field {}: {}", field_id, e);
             HttpResponse::InternalServerError().finish()
@@ -151,57 +125,102 @@ pub async fn update_field(
 
 pub async fn delete_field(
     state: web::Data<State>,
-    session: Session,
+    permissions: Permissions,
     path: web::Path<u32>,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id,
-        Err(resp) => return resp,
-    };
-
     let field_id = path.into_inner();
+    if let Err(resp) = get_managed_field_page_id(&state, &permissions, field_id).await {
+        return resp;
+    }
+
+    match PageField::delete(&state.db.pool, field_id).await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => {
+            log::error!("Error deleting field {}: {}", field_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+// Fetches the page of a field and checks that the user can manage it
+async fn get_managed_field_page_id(
+    state: &State,
+    permissions: &Permissions,
+    field_id: u32,
+) -> Result<u32, HttpResponse> {
     let page_id = match sqlx::query!(
         r#"
         SELECT page_id FROM page_fields WHERE id = ?
         "#,
         field_id
     )
-    .fetch_one(&state.db.pool)
+    .fetch_optional(&state.db.pool)
     .await
     {
-        Ok(record) => record.page_id,
+        Ok(Some(record)) => record.page_id,
+        Ok(None) => return Err(HttpResponse::NotFound().finish()),
         Err(e) => {
             log::error!("Error fetching page ID for field {}: {}", field_id, e);
-            return HttpResponse::InternalServerError().finish();
+            return Err(HttpResponse::InternalServerError().finish());
         }
     };
 
-    match user_can_manage_page(&state.db.pool, user_id, page_id).await {
-        Ok(can) => {
-            if !can {
-                return HttpResponse::Forbidden().finish();
-            }
+    if permissions.page(page_id).await?.can_manage_fields {
+        Ok(page_id)
+    } else {
+        Err(HttpResponse::Forbidden().finish())
+    }
+}
+
+pub async fn get_field_permissions(
+    state: web::Data<State>,
+    permissions: Permissions,
+    path: web::Path<u32>,
+    req: HttpRequest,
+) -> impl Responder {
+    let field_id = path.into_inner();
+    if let Err(resp) = get_managed_field_page_id(&state, &permissions, field_id).await {
+        return resp;
+    }
+
+    match PageFieldPermission::get_by_field_id(&state.db.pool, field_id).await {
+        Ok(permissions) => json_response_with_etag(&permissions, &req),
+        Err(e) => {
+            log::error!("Error fetching permissions of field {}: {}", field_id, e);
+            HttpResponse::InternalServerError().finish()
         }
-        Err(resp) => {
-            log::error!("Error checking page permissions: {}", resp);
-            return HttpResponse::InternalServerError().finish();
+    }
+}
+
+pub async fn update_field_permissions(
+    state: web::Data<State>,
+    permissions: Permissions,
+    path: web::Path<u32>,
+    data: web::Bytes,
+) -> impl Responder {
+    let field_id = path.into_inner();
+    if let Err(resp) = get_managed_field_page_id(&state, &permissions, field_id).await {
+        return resp;
+    }
+
+    let Json(permissions): Json<Vec<FieldPermissionRequest>> = match Json::from_bytes(&data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().finish();
         }
     };
 
-    match PageField::delete(&state.db.pool, field_id).await {
+    match PageFieldPermission::replace_for_field(&state.db.pool, field_id, &permissions).await {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(e) => {
-            log::error!("Error deleting field {}: {}", field_id, e);
+            log::error!("Error updating permissions of field {}: {}", field_id, e);
             HttpResponse::InternalServerError().finish()
         }
     }
 }
 
-pub async fn get_validations(session: Session, req: HttpRequest) -> impl Responder {
-    if let Err(resp) = validate_session(&session) {
-        return resp;
-    }
-
+pub async fn get_validations(_user: AuthenticatedUser, req: HttpRequest) -> impl Responder {
     let validations = validation::get_available_validations();
     json_response_with_etag(&validations, &req)
 }
@@ -0,0 +1,196 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+
+use crate::{
+    State,
+    auth::AdminUser,
+    models::holiday::{Holiday, HolidayRequest, HolidayRule, HolidayRuleRequest, HolidayYearQuery},
+    utils::json_utils::{Json, json_response_with_etag},
+};
+
+/// Every holiday of a year, of every scope and location.
+pub async fn get_holidays(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+    query: web::Query<HolidayYearQuery>,
+) -> impl Responder {
+    match Holiday::get_for_year(&state.db.pool, query.year).await {
+        Ok(holidays) => json_response_with_etag(&holidays, &req),
+        Err(e) => {
+            log::error!("Error fetching holidays of {}: {}", query.year, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn create_holiday(
+    state: web::Data<State>,
+    admin: AdminUser,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<HolidayRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match Holiday::create(&state.db.pool, &req, admin.id as u32).await {
+        Ok(id) => HttpResponse::Created().json(serde_json::json!({ "id": id })),
+        Err(e) => {
+            log::error!("Error creating holiday: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn update_holiday(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let holiday_id = path.into_inner();
+
+    let Json(req): Json<HolidayRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match Holiday::update(&state.db.pool, holiday_id, &req).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Feriado não encontrado"),
+        Err(e) => {
+            log::error!("Error updating holiday {}: {}", holiday_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn delete_holiday(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let holiday_id = path.into_inner();
+
+    match Holiday::delete(&state.db.pool, holiday_id).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Feriado não encontrado"),
+        Err(e) => {
+            log::error!("Error deleting holiday {}: {}", holiday_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Generates the holidays of a year from the rules again, adding the ones that are missing.
+pub async fn generate_holidays(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    query: web::Query<HolidayYearQuery>,
+) -> impl Responder {
+    match Holiday::generate_year(&state.db.pool, query.year, true).await {
+        Ok(created) => HttpResponse::Ok().json(serde_json::json!({ "created": created })),
+        Err(e) => {
+            log::error!("Error generating holidays of {}: {}", query.year, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn get_holiday_rules(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+) -> impl Responder {
+    match HolidayRule::get_all(&state.db.pool).await {
+        Ok(rules) => json_response_with_etag(&rules, &req),
+        Err(e) => {
+            log::error!("Error fetching holiday rules: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn create_holiday_rule(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<HolidayRuleRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match HolidayRule::create(&state.db.pool, &req).await {
+        Ok(id) => HttpResponse::Created().json(serde_json::json!({ "id": id })),
+        Err(e) => {
+            log::error!("Error creating holiday rule: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Changes a rule. Its holidays from today on follow the change, past ones stay as they were.
+pub async fn update_holiday_rule(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let rule_id = path.into_inner();
+
+    let Json(req): Json<HolidayRuleRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match HolidayRule::update(&state.db.pool, rule_id, &req).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Regra não encontrada"),
+        Err(e) => {
+            log::error!("Error updating holiday rule {}: {}", rule_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn delete_holiday_rule(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let rule_id = path.into_inner();
+
+    match HolidayRule::delete(&state.db.pool, rule_id).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Regra não encontrada"),
+        Err(e) => {
+            log::error!("Error deleting holiday rule {}: {}", rule_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+
+use crate::{
+    State,
+    auth::AdminUser,
+    models::scheduled_job::{ScheduledJob, ScheduledJobEnabledRequest, ScheduledJobRun},
+    services::scheduler,
+    utils::json_utils::{Json, json_response_with_etag},
+};
+
+/// Number of past runs returned per job.
+const JOB_RUN_HISTORY_LIMIT: u32 = 50;
+
+pub async fn get_jobs(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+) -> impl Responder {
+    match ScheduledJob::get_all(&state.db.pool).await {
+        Ok(jobs) => json_response_with_etag(&jobs, &req),
+        Err(e) => {
+            log::error!("Error fetching scheduled jobs: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Pauses or resumes the schedule of a job.
+pub async fn set_job_enabled(
+    state: web::Data<State>,
+    admin: AdminUser,
+    name: web::Path<String>,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let name = name.into_inner();
+
+    let Json(req): Json<ScheduledJobEnabledRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+
+    if scheduler::find_job(&name).is_none() {
+        return HttpResponse::NotFound().body("Tarefa não encontrada");
+    }
+
+    match ScheduledJob::set_enabled(&state.db.pool, &name, req.enabled).await {
+        Ok(()) => {
+            log::info!(
+                "Admin {} {} job {}",
+                admin.id,
+                if req.enabled { "enabled" } else { "disabled" },
+                name
+            );
+            HttpResponse::Ok().finish()
+        }
+        Err(e) => {
+            log::error!("Error updating job {}: {}", name, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn get_job_runs(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> impl Responder {
+    let name = name.into_inner();
+
+    if scheduler::find_job(&name).is_none() {
+        return HttpResponse::NotFound().body("Tarefa não encontrada");
+    }
+
+    match ScheduledJobRun::get_recent_by_job(&state.db.pool, &name, JOB_RUN_HISTORY_LIMIT).await {
+        Ok(runs) => json_response_with_etag(&runs, &req),
+        Err(e) => {
+            log::error!("Error fetching runs of job {}: {}", name, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn trigger_job(
+    state: web::Data<State>,
+    admin: AdminUser,
+    name: web::Path<String>,
+) -> impl Responder {
+    let admin_id = admin.id as u32;
+
+    let name = name.into_inner();
+
+    let Some(job) = scheduler::find_job(&name) else {
+        return HttpResponse::NotFound().body("Tarefa não encontrada");
+    };
+
+    match scheduler::trigger(&state.db.pool, job, admin_id).await {
+        Ok(Some(run_id)) => {
+            log::info!(
+                "Admin {} triggered job {} (run #{})",
+                admin_id,
+                name,
+                run_id
+            );
+            HttpResponse::Accepted().json(serde_json::json!({ "run_id": run_id }))
+        }
+        Ok(None) => HttpResponse::Conflict().body("A tarefa já está em execução"),
+        Err(e) => {
+            log::error!("Error triggering job {}: {}", name, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
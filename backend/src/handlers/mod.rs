@@ -1,10 +1,22 @@
+pub mod absence_type_handlers;
+pub mod account_handlers;
 pub mod acknowledgment_handlers;
 pub mod admin_vacation_handlers;
 pub mod calendar_handlers;
+pub mod coverage_rule_handlers;
 pub mod custom_page_handlers;
 pub mod field_handlers;
+pub mod holiday_handlers;
+pub mod job_handlers;
 pub mod notification_handlers;
+pub mod oidc_handlers;
 pub mod record_handlers;
 pub mod role_handlers;
+pub mod security_handlers;
+pub mod two_factor_handlers;
 pub mod user_handlers;
+pub mod vacation_approval_handlers;
 pub mod vacation_handlers;
+pub mod vacation_ledger_handlers;
+pub mod work_contract_handlers;
+pub mod work_schedule_handlers;
@@ -1,11 +1,10 @@
-use actix_session::Session;
 use actix_web::{HttpResponse, Responder, web};
 use serde::Deserialize;
 use std::collections::HashSet; // For unique user IDs
 
 use crate::{
     State,
-    auth::{is_admin, validate_session}, // Added is_admin
+    auth::{AdminUser, AuthenticatedUser},
     models::{notification::Notification, role::Role}, // Added Role
     utils::json_utils::json_response,
 };
@@ -14,11 +13,11 @@ use crate::{
 use crate::models::notification::NOTIFICATION_TYPE_ADMIN_BROADCAST;
 
 // Handler to get the list of unread notifications for the current user
-pub async fn get_unread_notifications(state: web::Data<State>, session: Session) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+pub async fn get_unread_notifications(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+) -> impl Responder {
+    let user_id = user.id as u32;
 
     match Notification::get_unread_by_user(&state.db.pool, user_id).await {
         Ok(notifications) => json_response(&notifications),
@@ -43,14 +42,9 @@ pub struct BroadcastNotificationRequest {
 // New handler for broadcasting notifications
 pub async fn broadcast_notification_to_roles(
     state: web::Data<State>,
-    session: Session,
+    _admin: AdminUser,
     body: web::Json<BroadcastNotificationRequest>,
 ) -> impl Responder {
-    if let Err(resp) = is_admin(&session) {
-        log::warn!("broadcast_notification_to_roles: Non-admin user attempted to broadcast.");
-        return resp;
-    }
-
     let req_data = body.into_inner();
 
     if req_data.role_ids.is_empty() {
@@ -125,11 +119,8 @@ pub async fn broadcast_notification_to_roles(
 }
 
 // Handler to get the count of unread notifications for the current user
-pub async fn get_unread_count(state: web::Data<State>, session: Session) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+pub async fn get_unread_count(state: web::Data<State>, user: AuthenticatedUser) -> impl Responder {
+    let user_id = user.id as u32;
 
     match Notification::count_unread_by_user(&state.db.pool, user_id).await {
         Ok(count) => json_response(&serde_json::json!({ "count": count })),
@@ -152,13 +143,10 @@ pub struct MarkReadRequest {
 // Handler to mark specific notifications as read
 pub async fn mark_notifications_read(
     state: web::Data<State>,
-    session: Session,
+    user: AuthenticatedUser,
     body: web::Json<MarkReadRequest>,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id as u32;
 
     if body.ids.is_empty() {
         return HttpResponse::BadRequest().body("No notification IDs provided");
@@ -180,12 +168,9 @@ pub async fn mark_notifications_read(
 // Handler to mark ALL notifications as read for the current user
 pub async fn mark_all_notifications_read(
     state: web::Data<State>,
-    session: Session,
+    user: AuthenticatedUser,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id as u32;
 
     match Notification::mark_all_as_read(&state.db.pool, user_id).await {
         Ok(_rows_affected) => HttpResponse::Ok().finish(),
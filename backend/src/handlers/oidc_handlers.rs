@@ -0,0 +1,166 @@
+use actix_session::Session;
+use actix_web::{HttpRequest, HttpResponse, Responder, http::header, web};
+use serde::Deserialize;
+
+use crate::{
+    State,
+    auth::{client_ip, start_pending_login},
+    handlers::user_handlers::{check_login_locks, finish_login, second_factor_step},
+    models::security_event::{NewSecurityEvent, SECURITY_EVENT_LOGIN_FAILURE, SecurityEvent},
+    services::oidc_service::{PendingOidcLogin, provision_oidc_user},
+    utils::login_throttle::account_key,
+};
+
+/// How long the user has to log in at the identity provider.
+const PENDING_OIDC_LOGIN_SECS: i64 = 10 * 60;
+const PENDING_OIDC_LOGIN_KEY: &str = "pending_oidc_login";
+
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+fn redirect(location: String) -> HttpResponse {
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, location))
+        .finish()
+}
+
+/// Back to the frontend login page, which shows what `reason` stands for.
+fn login_page(state: &State, reason: &str) -> HttpResponse {
+    redirect(state.mailer.link(&format!("/login?sso={}", reason)))
+}
+
+/// Ways of logging in besides email and password, for the login page.
+pub async fn get_login_options(state: web::Data<State>) -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({ "oidc": state.oidc.is_some() }))
+}
+
+/// Sends the browser to the identity provider's login page.
+pub async fn oidc_login(state: web::Data<State>, session: Session) -> impl Responder {
+    let Some(oidc) = &state.oidc else {
+        return HttpResponse::NotFound().body("Início de sessão único não configurado");
+    };
+
+    let (url, pending) = match oidc.authorization_url().await {
+        Ok(started) => started,
+        Err(e) => {
+            log::error!("Error starting single sign-on: {}", e);
+            return login_page(&state, "unavailable");
+        }
+    };
+
+    if let Err(e) = session.insert(PENDING_OIDC_LOGIN_KEY, &pending) {
+        log::error!("Error storing pending single sign-on: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    redirect(url)
+}
+
+/// Where the identity provider sends the browser back with a code.
+pub async fn oidc_callback(
+    state: web::Data<State>,
+    session: Session,
+    http_req: HttpRequest,
+    query: web::Query<OidcCallbackQuery>,
+) -> impl Responder {
+    let Some(oidc) = &state.oidc else {
+        return HttpResponse::NotFound().body("Início de sessão único não configurado");
+    };
+
+    let pool = &state.db.pool;
+    let ip = client_ip(&http_req);
+    let pending = session
+        .remove_as::<PendingOidcLogin>(PENDING_OIDC_LOGIN_KEY)
+        .and_then(Result::ok);
+
+    // The user cancelled or was refused at the identity provider
+    if let Some(error) = &query.error {
+        log::info!("Single sign-on refused by the identity provider: {}", error);
+        return login_page(&state, "cancelled");
+    }
+
+    let (Some(pending), Some(code)) = (pending, &query.code) else {
+        return login_page(&state, "expired");
+    };
+    if query.state.as_deref() != Some(pending.state.as_str())
+        || chrono::Utc::now().timestamp() - pending.started_at > PENDING_OIDC_LOGIN_SECS
+    {
+        return login_page(&state, "expired");
+    }
+
+    let identity = match oidc
+        .exchange_code(code, &pending)
+        .await
+        .and_then(|claims| oidc.identity(&claims))
+    {
+        Ok(identity) => identity,
+        Err(e) => {
+            log::error!("Error completing single sign-on: {}", e);
+            SecurityEvent::record(
+                pool,
+                NewSecurityEvent {
+                    event_type: SECURITY_EVENT_LOGIN_FAILURE,
+                    ip_address: Some(ip.as_str()),
+                    details: Some(format!("Início de sessão único: {}", e)),
+                    ..Default::default()
+                },
+            )
+            .await;
+            return login_page(&state, "failed");
+        }
+    };
+
+    let account = account_key(&identity.email);
+    if check_login_locks(pool, &account, &ip).await.is_err() {
+        return login_page(&state, "locked");
+    }
+
+    let user_id = match provision_oidc_user(pool, &identity).await {
+        Ok(user_id) => user_id,
+        Err(e) => {
+            log::error!(
+                "Database error provisioning single sign-on user {}: {}",
+                identity.email,
+                e
+            );
+            return login_page(&state, "failed");
+        }
+    };
+
+    // The local second factor applies here too, the frontend asks for it like after a
+    // password login
+    match second_factor_step(pool, user_id).await {
+        Ok(Some(needs_setup)) => {
+            if let Err(resp) = start_pending_login(&session, user_id as i32, needs_setup) {
+                return resp;
+            }
+            login_page(
+                &state,
+                if needs_setup {
+                    "two_factor_setup_required"
+                } else {
+                    "two_factor_required"
+                },
+            )
+        }
+        Ok(None) => {
+            let resp = finish_login(pool, &session, user_id, &account, &ip).await;
+            if !resp.status().is_success() {
+                return resp;
+            }
+            redirect(state.mailer.link("/"))
+        }
+        Err(e) => {
+            log::error!(
+                "Database error checking second factor of user {}: {}",
+                user_id,
+                e
+            );
+            login_page(&state, "failed")
+        }
+    }
+}
@@ -1,21 +1,24 @@
 use actix_multipart::form::MultipartForm;
-use actix_session::Session;
 use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use chrono::{Duration, NaiveDate, Utc};
 use serde::Deserialize;
 
 use crate::{
     State,
-    auth::{
-        get_user_ids_with_view_permission, user_can_create_record, user_can_delete_record,
-        user_can_edit_record, user_can_view_page, validate_session,
-        user_can_add_to_record,
-    },
+    auth::{AuthenticatedUser, get_user_ids_with_view_permission},
     models::{
         custom_page::CustomPage, // Added for fetching page details
-        field::PageField,
+        field::{FieldAccess, PageField},
         notification::Notification, // Added for creating notifications
-        page_record::{CreatePageRecordRequest, PageRecord, UpdatePageRecordRequest},
+        page_record::{
+            CreatePageRecordRequest, PageRecord, RECORD_FILES_DIR, UpdatePageRecordRequest,
+        },
+        page_row_rule::{
+            ROW_ACTION_DELETE, ROW_ACTION_EDIT, ROW_ACTION_VIEW, RowAccess, RowAccessCache,
+        },
+        record_due_date::RecordDueDate,
     },
+    services::permission_service::Permissions,
     utils::{
         forms::FilesFormRequest,
         json_utils::{Json, json_response_with_etag},
@@ -27,42 +30,137 @@ pub struct RecordSearchQuery {
     search: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct ExpiringRecordsQuery {
+    /// First day of the window, today by default
+    from: Option<NaiveDate>,
+    /// Length of the window in days, counted from `from`
+    days: Option<u32>,
+    /// "asc" (default) or "desc"
+    sort: Option<String>,
+}
+
 const NOTIFICATION_TYPE_NEW_RECORD: &str = "NEW_RECORD";
 
+const DEFAULT_EXPIRING_WINDOW_DAYS: u32 = 30;
+const MAX_EXPIRING_WINDOW_DAYS: u32 = 366;
+
+// Checks the row-level rules of the page for a single record
+async fn check_row_access(
+    pool: &sqlx::MySqlPool,
+    user_id: i32,
+    record: &PageRecord,
+    action: &str,
+) -> Result<bool, HttpResponse> {
+    match RowAccess::for_user(pool, user_id, record.page_id, action).await {
+        Ok(access) => Ok(access.allows(record)),
+        Err(e) => {
+            log::error!("Error resolving row access rules: {}", e);
+            Err(HttpResponse::InternalServerError().finish())
+        }
+    }
+}
+
+// Upcoming DATE / DATE_RANGE deadlines across every page the user can view, for the home page
+pub async fn get_expiring_records(
+    state: web::Data<State>,
+    query: web::Query<ExpiringRecordsQuery>,
+    user: AuthenticatedUser,
+    req: HttpRequest,
+) -> impl Responder {
+    let user_id = user.id as u32;
+
+    let days = query.days.unwrap_or(DEFAULT_EXPIRING_WINDOW_DAYS);
+    if days > MAX_EXPIRING_WINDOW_DAYS {
+        return HttpResponse::BadRequest().body(format!(
+            "O intervalo não pode ser superior a {} dias",
+            MAX_EXPIRING_WINDOW_DAYS
+        ));
+    }
+
+    let descending = match query.sort.as_deref() {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        Some(_) => return HttpResponse::BadRequest().body("Ordenação inválida"),
+    };
+
+    let today = Utc::now().date_naive();
+    let from = query.from.unwrap_or(today);
+    let Some(to) = from.checked_add_signed(Duration::days(days as i64)) else {
+        return HttpResponse::BadRequest().body("Data inválida");
+    };
+
+    let mut row_access = RowAccessCache::default();
+    match RecordDueDate::get_expiring_for_user(
+        &state.db.pool,
+        user_id,
+        from,
+        to,
+        descending,
+        &mut row_access,
+    )
+    .await
+    {
+        Ok(rows) => {
+            let groups = RecordDueDate::group_by_page_and_field(rows, today);
+            json_response_with_etag(&groups, &req)
+        }
+        Err(e) => {
+            log::error!("Error fetching expiring records for user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
 pub async fn get_page_records(
     state: web::Data<State>,
     path: web::Path<u32>,
     query: web::Query<RecordSearchQuery>,
-    session: Session,
+    permissions: Permissions,
     req: HttpRequest,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(user_id) => user_id,
-        Err(resp) => return resp,
-    };
-
+    let user_id = permissions.user_id();
     let page_id = path.into_inner();
 
-    match user_can_view_page(&state.db.pool, user_id, page_id).await {
-        Ok(can_view) => {
-            if !can_view {
-                return HttpResponse::Unauthorized().finish();
-            }
-        }
+    match permissions.page(page_id).await {
+        Ok(perms) if perms.can_view => {}
+        Ok(_) => return HttpResponse::Unauthorized().finish(),
+        Err(resp) => return resp,
+    }
+
+    let field_access = match FieldAccess::for_user_on_page(&state.db.pool, user_id, page_id).await
+    {
+        Ok(access) => access,
         Err(e) => {
-            log::error!("Error checking user permissions: {}", e);
+            log::error!("Error fetching field permissions: {}", e);
             return HttpResponse::InternalServerError().finish();
         }
     };
 
     let records = if let Some(search_term) = &query.search {
-        PageRecord::search_records(&state.db.pool, page_id, search_term).await
+        PageRecord::search_records(&state.db.pool, page_id, search_term, &field_access.hidden)
+            .await
     } else {
         PageRecord::get_by_page_id(&state.db.pool, page_id).await
     };
 
+    let row_access =
+        match RowAccess::for_user(&state.db.pool, user_id, page_id, ROW_ACTION_VIEW).await {
+            Ok(access) => access,
+            Err(e) => {
+                log::error!("Error resolving row access rules: {}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        };
+
     match records {
-        Ok(records) => json_response_with_etag(&records, &req),
+        Ok(mut records) => {
+            records.retain(|record| row_access.allows(record));
+            for record in &mut records {
+                field_access.strip_hidden(&mut record.data);
+            }
+            json_response_with_etag(&records, &req)
+        }
         Err(e) => {
             log::error!("Error fetching page records: {}", e);
             HttpResponse::InternalServerError().finish()
@@ -73,17 +171,13 @@ pub async fn get_page_records(
 pub async fn get_record(
     state: web::Data<State>,
     path: web::Path<u32>,
-    session: Session,
+    permissions: Permissions,
     req: HttpRequest,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(user_id) => user_id,
-        Err(resp) => return resp,
-    };
-
+    let user_id = permissions.user_id();
     let record_id = path.into_inner();
 
-    let record_with_files = match PageRecord::get_by_id(&state.db.pool, record_id).await {
+    let mut record_with_files = match PageRecord::get_by_id(&state.db.pool, record_id).await {
         Ok(record) => record,
         Err(e) => {
             log::error!("Error fetching page record: {}", e);
@@ -91,14 +185,26 @@ pub async fn get_record(
         }
     };
 
-    match user_can_view_page(&state.db.pool, user_id, record_with_files.record.page_id).await {
-        Ok(can_view) => {
-            if !can_view {
-                return HttpResponse::Unauthorized().finish();
-            }
-        }
+    let page_id = record_with_files.record.page_id;
+
+    match permissions.page(page_id).await {
+        Ok(perms) if perms.can_view => {}
+        Ok(_) => return HttpResponse::Unauthorized().finish(),
+        Err(resp) => return resp,
+    }
+
+    match check_row_access(&state.db.pool, user_id, &record_with_files.record, ROW_ACTION_VIEW)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().finish(),
+        Err(resp) => return resp,
+    }
+
+    match FieldAccess::for_user_on_page(&state.db.pool, user_id, page_id).await {
+        Ok(access) => access.strip_hidden(&mut record_with_files.record.data),
         Err(e) => {
-            log::error!("Error checking user permissions: {}", e);
+            log::error!("Error fetching field permissions: {}", e);
             return HttpResponse::InternalServerError().finish();
         }
     }
@@ -110,25 +216,16 @@ pub async fn create_record(
     state: web::Data<State>,
     path: web::Path<u32>,
     data: web::Bytes,
-    session: Session,
+    permissions: Permissions,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(user_id) => user_id,
-        Err(resp) => return resp,
-    };
+    let user_id = permissions.user_id();
     let page_id = path.into_inner();
 
-    match user_can_create_record(&state.db.pool, user_id, page_id).await {
-        Ok(can_create) => {
-            if !can_create {
-                return HttpResponse::Forbidden().finish();
-            }
-        }
-        Err(e) => {
-            log::error!("Error checking user permissions: {}", e);
-            return HttpResponse::InternalServerError().finish();
-        }
-    };
+    match permissions.page(page_id).await {
+        Ok(perms) if perms.can_create => {}
+        Ok(_) => return HttpResponse::Forbidden().finish(),
+        Err(resp) => return resp,
+    }
 
     let Json(create_record_req): Json<CreatePageRecordRequest> = match Json::from_bytes(&data) {
         Ok(data) => data,
@@ -138,8 +235,38 @@ pub async fn create_record(
         }
     };
 
+    let field_access = match FieldAccess::for_user_on_page(&state.db.pool, user_id, page_id).await
+    {
+        Ok(access) => access,
+        Err(e) => {
+            log::error!("Error fetching field permissions: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    // Read-only fields can only be left empty on creation
+    if let serde_json::Value::Object(data_map) = &create_record_req.data {
+        for (field_name, value) in data_map {
+            if !value.is_null() && !field_access.can_write(field_name) {
+                return HttpResponse::Forbidden()
+                    .body(format!("Cannot set read-only field {}", field_name));
+            }
+        }
+    }
+
     match PageRecord::create(&state.db.pool, &create_record_req, page_id, user_id as u32).await {
         Ok(new_record_id) => {
+            if let Err(e) = RecordDueDate::sync_for_record(
+                &state.db.pool,
+                new_record_id,
+                page_id,
+                &create_record_req.data,
+            )
+            .await
+            {
+                log::error!("Error indexing dates for record {}: {}", new_record_id, e);
+            }
+
             // Attempt to send notifications if configured for the page
             // We'll clone necessary data for the async block
             let pool_clone = state.db.pool.clone();
@@ -155,6 +282,23 @@ pub async fn create_record(
                             );
                             match get_user_ids_with_view_permission(&pool_clone, page_id).await {
                                 Ok(user_ids_to_notify) => {
+                                    // The row rules of each user decide whether they see it
+                                    let record = match PageRecord::get_record_by_id(
+                                        &pool_clone,
+                                        new_record_id,
+                                    )
+                                    .await
+                                    {
+                                        Ok(record) => record,
+                                        Err(e) => {
+                                            log::error!(
+                                                "Failed to fetch record {} for new record notifications: {}",
+                                                new_record_id,
+                                                e
+                                            );
+                                            return;
+                                        }
+                                    };
                                     if user_ids_to_notify.is_empty() {
                                         log::info!(
                                             "No users found with view permission for page {} to notify.",
@@ -172,6 +316,34 @@ pub async fn create_record(
                                             continue;
                                         }
 
+                                        match RowAccess::for_user(
+                                            &pool_clone,
+                                            notified_user_id as i32,
+                                            page_id,
+                                            ROW_ACTION_VIEW,
+                                        )
+                                        .await
+                                        {
+                                            Ok(access) if access.allows(&record) => {}
+                                            Ok(_) => {
+                                                log::trace!(
+                                                    "Skipping notification for user {}, who cannot view record {}",
+                                                    notified_user_id,
+                                                    new_record_id
+                                                );
+                                                continue;
+                                            }
+                                            Err(e) => {
+                                                log::error!(
+                                                    "Failed to resolve row access of user {} for record {}: {}",
+                                                    notified_user_id,
+                                                    new_record_id,
+                                                    e
+                                                );
+                                                continue;
+                                            }
+                                        }
+
                                         // It's a new record, so a specific check for existing "NEW_RECORD" notifications
                                         // for this exact record ID is usually not needed unless the process could somehow run twice.
                                         // For simplicity here, we assume it won't run twice for the same creation event.
@@ -239,15 +411,13 @@ pub async fn update_record(
     state: web::Data<State>,
     path: web::Path<u32>,
     data: web::Bytes,
-    session: Session,
+    permissions: Permissions,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(user_id) => user_id,
-        Err(resp) => return resp,
-    };
+    let user_id = permissions.user_id();
     let record_id = path.into_inner();
 
-    let record_with_files = match PageRecord::get_by_id(&state.db.pool, record_id).await {
+    // The files are not needed here, only the current data
+    let existing_record = match PageRecord::get_record_by_id(&state.db.pool, record_id).await {
         Ok(record) => record,
         Err(e) => {
             log::error!("Error fetching page record: {}", e);
@@ -255,32 +425,29 @@ pub async fn update_record(
         }
     };
 
-    let page_id = record_with_files.record.page_id;
+    let page_id = existing_record.page_id;
 
-    // Check if user can edit
-    let can_edit = match user_can_edit_record(&state.db.pool, user_id, page_id).await {
-        Ok(can_edit) => can_edit,
-        Err(e) => {
-            log::error!("Error checking edit permissions: {}", e);
-            return HttpResponse::InternalServerError().finish();
-            }
-    };
-
-    // Check if user can add
-    let can_add = match user_can_add_to_record(&state.db.pool, user_id, page_id).await {
-        Ok(can_add) => can_add,
-        Err(e) => {
-            log::error!("Error checking add permissions: {}", e);
-            return HttpResponse::InternalServerError().finish();
-        }
+    let perms = match permissions.page(page_id).await {
+        Ok(perms) => perms,
+        Err(resp) => return resp,
     };
+    let can_edit = perms.can_edit;
+    let can_add = perms.can_add;
 
     // Require at least one permission
     if !can_edit && !can_add {
         return HttpResponse::Forbidden().finish();
     }
 
-    let Json(update_data): Json<UpdatePageRecordRequest> = match Json::from_bytes(&data) {
+    match check_row_access(&state.db.pool, user_id, &existing_record, ROW_ACTION_EDIT)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Forbidden().finish(),
+        Err(resp) => return resp,
+    }
+
+    let Json(mut update_data): Json<UpdatePageRecordRequest> = match Json::from_bytes(&data) {
         Ok(data) => data,
         Err(e) => {
             log::error!("Error parsing JSON: {}", e);
@@ -288,6 +455,29 @@ pub async fn update_record(
         }
     };
 
+    let field_access = match FieldAccess::for_user_on_page(&state.db.pool, user_id, page_id).await
+    {
+        Ok(access) => access,
+        Err(e) => {
+            log::error!("Error fetching field permissions: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    // Reject changes to read-only fields and keep the values of hidden ones
+    if let Err(field_name) =
+        field_access.apply_read_only(&existing_record.data, &mut update_data.data)
+    {
+        log::warn!(
+            "User {} attempted to modify read-only field {} of record {}",
+            user_id,
+            field_name,
+            record_id
+        );
+        return HttpResponse::Forbidden()
+            .body(format!("Cannot modify read-only field {}", field_name));
+    }
+
     let page_fields = match PageField::get_by_page_id(&state.db.pool, page_id).await {
         Ok(fields) => fields,
         Err(e) => {
@@ -299,7 +489,7 @@ pub async fn update_record(
     // For users with only can_add permission, enforce they can only add to empty fields
     if !can_edit && can_add {
         if let serde_json::Value::Object(data_map) = &update_data.data {
-            if let serde_json::Value::Object(existing_data_map) = &record_with_files.record.data {
+            if let serde_json::Value::Object(existing_data_map) = &existing_record.data {
                 for (field_name, new_value) in data_map.iter() {
                     // If field exists and has a value in the existing record
                     if let Some(existing_value) = existing_data_map.get(field_name) {
@@ -330,7 +520,15 @@ pub async fn update_record(
     }
 
     match PageRecord::update(&state.db.pool, record_id, &update_data, user_id as u32).await {
-        Ok(_) => HttpResponse::Ok().finish(),
+        Ok(_) => {
+            if let Err(e) =
+                RecordDueDate::sync_for_record(&state.db.pool, record_id, page_id, &update_data.data)
+                    .await
+            {
+                log::error!("Error indexing dates for record {}: {}", record_id, e);
+            }
+            HttpResponse::Ok().finish()
+        }
         Err(e) => {
             log::error!("Error updating page record: {}", e);
             HttpResponse::InternalServerError().finish()
@@ -341,13 +539,9 @@ pub async fn update_record(
 pub async fn delete_record(
     state: web::Data<State>,
     path: web::Path<u32>,
-    session: Session,
+    permissions: Permissions,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(user_id) => user_id,
-        Err(resp) => return resp,
-    };
-
+    let user_id = permissions.user_id();
     let record_id = path.into_inner();
 
     let record_with_files = match PageRecord::get_by_id(&state.db.pool, record_id).await {
@@ -358,17 +552,19 @@ pub async fn delete_record(
         }
     };
 
-    match user_can_delete_record(&state.db.pool, user_id, record_with_files.record.page_id).await {
-        Ok(can_delete) => {
-            if !can_delete {
-                return HttpResponse::Forbidden().finish();
-            }
-        }
-        Err(e) => {
-            log::error!("Error checking user permissions: {}", e);
-            return HttpResponse::InternalServerError().finish();
-        }
-    };
+    match permissions.page(record_with_files.record.page_id).await {
+        Ok(perms) if perms.can_delete => {}
+        Ok(_) => return HttpResponse::Forbidden().finish(),
+        Err(resp) => return resp,
+    }
+
+    match check_row_access(&state.db.pool, user_id, &record_with_files.record, ROW_ACTION_DELETE)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Forbidden().finish(),
+        Err(resp) => return resp,
+    }
 
     for file in record_with_files.files {
         tokio::task::spawn_blocking(move || {
@@ -389,13 +585,9 @@ pub async fn upload_record_files(
     state: web::Data<State>,
     path: web::Path<u32>,
     MultipartForm(form): MultipartForm<FilesFormRequest>,
-    session: Session,
+    permissions: Permissions,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(user_id) => user_id,
-        Err(resp) => return resp,
-    };
-
+    let user_id = permissions.user_id();
     let record_id = path.into_inner();
 
     let record_with_files = match PageRecord::get_by_id(&state.db.pool, record_id).await {
@@ -406,19 +598,21 @@ pub async fn upload_record_files(
         }
     };
 
-    match user_can_edit_record(&state.db.pool, user_id, record_with_files.record.page_id).await {
-        Ok(can_edit) => {
-            if !can_edit {
-                return HttpResponse::Forbidden().finish();
-            }
-        }
-        Err(e) => {
-            log::error!("Error checking user permissions: {}", e);
-            return HttpResponse::InternalServerError().finish();
-        }
-    };
+    match permissions.page(record_with_files.record.page_id).await {
+        Ok(perms) if perms.can_edit => {}
+        Ok(_) => return HttpResponse::Forbidden().finish(),
+        Err(resp) => return resp,
+    }
 
-    let base_path = format!("media/page_records/{}/files", record_id);
+    match check_row_access(&state.db.pool, user_id, &record_with_files.record, ROW_ACTION_EDIT)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Forbidden().finish(),
+        Err(resp) => return resp,
+    }
+
+    let base_path = format!("{}/{}/files", RECORD_FILES_DIR, record_id);
     let base_path_clone = base_path.clone();
 
     tokio::task::spawn_blocking(move || {
@@ -472,38 +666,91 @@ pub async fn upload_record_files(
     }
 }
 
-pub async fn delete_record_file(
+/// A file of a record, for users who can view the record.
+pub async fn download_record_file(
     state: web::Data<State>,
     path: web::Path<(u32, u32)>,
-    session: Session,
+    permissions: Permissions,
+    req: HttpRequest,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(user_id) => user_id,
-        Err(resp) => return resp,
-    };
-
+    let user_id = permissions.user_id();
     let (record_id, file_id) = path.into_inner();
 
     let record_with_files = match PageRecord::get_by_id(&state.db.pool, record_id).await {
         Ok(record) => record,
+        Err(sqlx::Error::RowNotFound) => return HttpResponse::NotFound().finish(),
         Err(e) => {
             log::error!("Error fetching page record: {}", e);
             return HttpResponse::InternalServerError().finish();
         }
     };
 
-    match user_can_edit_record(&state.db.pool, user_id, record_with_files.record.page_id).await {
-        Ok(can_edit) => {
-            if !can_edit {
-                return HttpResponse::Forbidden().finish();
-            }
+    match permissions.page(record_with_files.record.page_id).await {
+        Ok(perms) if perms.can_view => {}
+        Ok(_) => return HttpResponse::Forbidden().finish(),
+        Err(resp) => return resp,
+    }
+
+    match check_row_access(
+        &state.db.pool,
+        user_id,
+        &record_with_files.record,
+        ROW_ACTION_VIEW,
+    )
+    .await
+    {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Forbidden().finish(),
+        Err(resp) => return resp,
+    }
+
+    let Some(file) = record_with_files
+        .files
+        .into_iter()
+        .find(|f| f.id == file_id)
+    else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    match actix_files::NamedFile::open_async(&file.file_path).await {
+        Ok(named_file) => named_file.into_response(&req),
+        Err(e) => {
+            log::error!("Error opening record file {}: {}", file.file_path, e);
+            HttpResponse::NotFound().finish()
         }
+    }
+}
+
+pub async fn delete_record_file(
+    state: web::Data<State>,
+    path: web::Path<(u32, u32)>,
+    permissions: Permissions,
+) -> impl Responder {
+    let user_id = permissions.user_id();
+    let (record_id, file_id) = path.into_inner();
+
+    let record_with_files = match PageRecord::get_by_id(&state.db.pool, record_id).await {
+        Ok(record) => record,
         Err(e) => {
-            log::error!("Error checking user permissions: {}", e);
+            log::error!("Error fetching page record: {}", e);
             return HttpResponse::InternalServerError().finish();
         }
     };
 
+    match permissions.page(record_with_files.record.page_id).await {
+        Ok(perms) if perms.can_edit => {}
+        Ok(_) => return HttpResponse::Forbidden().finish(),
+        Err(resp) => return resp,
+    }
+
+    match check_row_access(&state.db.pool, user_id, &record_with_files.record, ROW_ACTION_EDIT)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Forbidden().finish(),
+        Err(resp) => return resp,
+    }
+
     let file_to_delete = record_with_files
         .files
         .into_iter()
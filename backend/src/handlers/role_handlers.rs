@@ -1,22 +1,23 @@
-use actix_session::Session;
 use actix_web::{HttpRequest, HttpResponse, Responder, web};
 
 use crate::{
     State,
-    auth::{is_admin, validate_session},
-    models::role::{CreateRoleRequest, Role, UpdateRoleRequest},
-    utils::json_utils::{Json, json_response, json_response_with_etag},
+    auth::{AdminScope, AdminUser, AuthenticatedUser},
+    models::{
+        admin_scope::RoleAdminScopes,
+        role::{CreateRoleRequest, Role, UpdateRoleRequest},
+    },
+    utils::{
+        json_utils::{Json, json_response, json_response_with_etag},
+        role_hierarchy::creates_cycle,
+    },
 };
 
 pub async fn get_roles(
     state: web::Data<State>,
-    session: Session,
+    _user: AuthenticatedUser,
     req: HttpRequest,
 ) -> impl Responder {
-    if let Err(resp) = validate_session(&session) {
-        return resp;
-    }
-
     match Role::get_all(&state.db.pool).await {
         Ok(roles) => json_response_with_etag(&roles, &req),
         Err(e) => {
@@ -29,13 +30,9 @@ pub async fn get_roles(
 // New handler to get roles with their interfering roles
 pub async fn get_roles_with_interfering_roles(
     state: web::Data<State>,
-    session: Session,
+    _user: AuthenticatedUser,
     req: HttpRequest,
 ) -> impl Responder {
-    if let Err(resp) = validate_session(&session) {
-        return resp;
-    }
-
     match Role::get_all_with_interfering_roles(&state.db.pool).await {
         Ok(roles) => json_response_with_etag(&roles, &req),
         Err(e) => {
@@ -48,12 +45,8 @@ pub async fn get_roles_with_interfering_roles(
 pub async fn get_role(
     state: web::Data<State>,
     path: web::Path<u32>,
-    session: Session,
+    _user: AuthenticatedUser,
 ) -> impl Responder {
-    if let Err(resp) = validate_session(&session) {
-        return resp;
-    }
-
     match Role::get_by_id(&state.db.pool, path.into_inner()).await {
         Ok(role) => json_response(&role),
         Err(e) => {
@@ -67,13 +60,9 @@ pub async fn get_role(
 pub async fn get_role_with_interfering_roles(
     state: web::Data<State>,
     path: web::Path<u32>,
-    session: Session,
+    _user: AuthenticatedUser,
     req: HttpRequest,
 ) -> impl Responder {
-    if let Err(resp) = validate_session(&session) {
-        return resp;
-    }
-
     match Role::get_by_id_with_interfering_roles(&state.db.pool, path.into_inner()).await {
         Ok(role) => json_response_with_etag(&role, &req),
         Err(e) => {
@@ -83,15 +72,138 @@ pub async fn get_role_with_interfering_roles(
     }
 }
 
+/// Checks that the parent role exists and, when updating `role_id`, is not the role itself or
+/// one of the roles below it.
+async fn validate_parent_role(
+    state: &web::Data<State>,
+    role_id: Option<u32>,
+    parent_id: Option<u32>,
+) -> Result<(), HttpResponse> {
+    let Some(parent_id) = parent_id else {
+        return Ok(());
+    };
+
+    let parents = Role::get_parent_map(&state.db.pool).await.map_err(|e| {
+        log::error!("Error fetching role hierarchy: {}", e);
+        HttpResponse::InternalServerError().finish()
+    })?;
+
+    if !parents.contains_key(&parent_id) {
+        return Err(HttpResponse::BadRequest().body("A função pai não existe"));
+    }
+
+    if role_id.is_some_and(|role_id| creates_cycle(&parents, role_id, parent_id)) {
+        return Err(HttpResponse::BadRequest()
+            .body("A função pai não pode ser a própria função nem uma das suas descendentes"));
+    }
+
+    Ok(())
+}
+
+/// Checks that page scopes only name group pages.
+async fn validate_admin_scopes(
+    state: &web::Data<State>,
+    scopes: Option<&RoleAdminScopes>,
+) -> Result<(), HttpResponse> {
+    let Some(scopes) = scopes else {
+        return Ok(());
+    };
+
+    let non_groups = RoleAdminScopes::find_non_groups(&state.db.pool, &scopes.page_group_ids)
+        .await
+        .map_err(|e| {
+            log::error!("Error validating admin page groups: {}", e);
+            HttpResponse::InternalServerError().finish()
+        })?;
+
+    if !non_groups.is_empty() {
+        return Err(HttpResponse::BadRequest()
+            .body("Só é possível delegar a administração de grupos de páginas"));
+    }
+
+    Ok(())
+}
+
+/// Scoped admins can only edit roles they manage, and cannot use the edit to widen anyone's
+/// access: the admin flag and the admin scopes stay as they are, and the parent and interfering
+/// roles can only change to roles they manage. The new parent cannot be an admin role or have
+/// one above it, as the members would become admins through it.
+async fn check_scoped_role_update(
+    state: &web::Data<State>,
+    admin: &AdminScope,
+    role_id: u32,
+    data: &UpdateRoleRequest,
+) -> Result<(), HttpResponse> {
+    if admin.is_admin {
+        return Ok(());
+    }
+
+    if !admin.can_manage_role(role_id) || data.admin_scopes.is_some() {
+        return Err(HttpResponse::Forbidden().finish());
+    }
+
+    let current = match Role::get_by_id_with_interfering_roles(&state.db.pool, role_id).await {
+        Ok(role) => role,
+        Err(sqlx::Error::RowNotFound) => return Err(HttpResponse::NotFound().finish()),
+        Err(e) => {
+            log::error!("Error fetching role {}: {}", role_id, e);
+            return Err(HttpResponse::InternalServerError().finish());
+        }
+    };
+
+    if data.is_admin != current.role.is_admin
+        || data.requires_two_factor != current.role.requires_two_factor
+    {
+        return Err(HttpResponse::Forbidden().finish());
+    }
+
+    if data.parent_role_id != current.role.parent_role_id
+        && !admin.can_manage_roles(
+            data.parent_role_id
+                .iter()
+                .chain(&current.role.parent_role_id),
+        )
+    {
+        return Err(HttpResponse::Forbidden().finish());
+    }
+
+    if data.parent_role_id != current.role.parent_role_id
+        && let Some(parent_id) = data.parent_role_id
+    {
+        let admin_role_ids = Role::get_admin_role_ids(&state.db.pool)
+            .await
+            .map_err(|e| {
+                log::error!("Error fetching admin roles: {}", e);
+                HttpResponse::InternalServerError().finish()
+            })?;
+        if admin_role_ids.contains(&parent_id) {
+            return Err(HttpResponse::Forbidden().finish());
+        }
+    }
+
+    if let Some(interfering_ids) = &data.interfering_role_ids {
+        let changed = interfering_ids
+            .iter()
+            .filter(|id| !current.interfering_role_ids.contains(id))
+            .chain(
+                current
+                    .interfering_role_ids
+                    .iter()
+                    .filter(|id| !interfering_ids.contains(id)),
+            );
+        if !admin.can_manage_roles(changed) {
+            return Err(HttpResponse::Forbidden().finish());
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn create_role(
     state: web::Data<State>,
     data: web::Bytes,
-    session: Session,
+    _admin: AdminUser,
 ) -> impl Responder {
-    if let Err(resp) = is_admin(&session) {
-        return resp;
-    }
-
     let Json(data): Json<CreateRoleRequest> = match Json::from_bytes(&data) {
         Ok(data) => data,
         Err(e) => {
@@ -100,6 +212,14 @@ pub async fn create_role(
         }
     };
 
+    if let Err(resp) = validate_parent_role(&state, None, data.parent_role_id).await {
+        return resp;
+    }
+
+    if let Err(resp) = validate_admin_scopes(&state, data.admin_scopes.as_ref()).await {
+        return resp;
+    }
+
     match Role::create(&state.db.pool, &data).await {
         Ok(role_id) => json_response(&role_id),
         Err(e) => {
@@ -113,12 +233,8 @@ pub async fn update_role(
     state: web::Data<State>,
     data: web::Bytes,
     path: web::Path<u32>,
-    session: Session,
+    admin: AdminScope,
 ) -> impl Responder {
-    if let Err(resp) = is_admin(&session) {
-        return resp;
-    }
-
     let Json(data): Json<UpdateRoleRequest> = match Json::from_bytes(&data) {
         Ok(data) => data,
         Err(e) => {
@@ -127,7 +243,21 @@ pub async fn update_role(
         }
     };
 
-    match Role::update(&state.db.pool, path.into_inner(), &data).await {
+    let role_id = path.into_inner();
+
+    if let Err(resp) = check_scoped_role_update(&state, &admin, role_id, &data).await {
+        return resp;
+    }
+
+    if let Err(resp) = validate_parent_role(&state, Some(role_id), data.parent_role_id).await {
+        return resp;
+    }
+
+    if let Err(resp) = validate_admin_scopes(&state, data.admin_scopes.as_ref()).await {
+        return resp;
+    }
+
+    match Role::update(&state.db.pool, role_id, &data).await {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(e) => {
             log::error!("Error updating role: {}", e);
@@ -139,12 +269,8 @@ pub async fn update_role(
 pub async fn delete_role(
     state: web::Data<State>,
     path: web::Path<u32>,
-    session: Session,
+    _admin: AdminUser,
 ) -> impl Responder {
-    if let Err(resp) = is_admin(&session) {
-        return resp;
-    }
-
     match Role::delete(&state.db.pool, path.into_inner()).await {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(e) => {
@@ -0,0 +1,191 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+
+use crate::{
+    State,
+    auth::AdminUser,
+    models::{
+        directory_group::{DirectoryGroupRole, ReplaceGroupRolesRequest},
+        login_throttle::LoginThrottle,
+        security_event::{
+            NewSecurityEvent, SECURITY_EVENT_ACCOUNT_UNLOCKED, SECURITY_EVENT_ROLE_CHANGE,
+            SecurityEvent, SecurityEventFilter,
+        },
+    },
+    services::auth_provider::LOCAL_PROVIDER,
+    utils::{
+        json_utils::{Json, json_response_with_etag},
+        login_throttle::{SCOPE_ACCOUNT, SCOPE_IP, account_key},
+    },
+};
+
+pub async fn get_security_events(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+    filter: web::Query<SecurityEventFilter>,
+) -> impl Responder {
+    match SecurityEvent::search(&state.db.pool, &filter).await {
+        Ok(events) => json_response_with_etag(&events, &req),
+        Err(e) => {
+            log::error!("Error fetching security events: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Every user who can log in, with their roles, last login and lock state.
+pub async fn get_access_summary(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+) -> impl Responder {
+    match SecurityEvent::get_access_summary(&state.db.pool).await {
+        Ok(users) => json_response_with_etag(&users, &req),
+        Err(e) => {
+            log::error!("Error fetching user access summary: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn unlock_user(
+    state: web::Data<State>,
+    admin: AdminUser,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+
+    let email = match sqlx::query_scalar!(r#"SELECT email FROM users WHERE id = ?"#, user_id)
+        .fetch_optional(&state.db.pool)
+        .await
+    {
+        Ok(Some(email)) => email,
+        Ok(None) => return HttpResponse::NotFound().body("Utilizador não encontrado"),
+        Err(e) => {
+            log::error!("Error fetching email of user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    unlock(
+        &state,
+        admin,
+        SCOPE_ACCOUNT,
+        &account_key(&email),
+        Some(user_id),
+    )
+    .await
+}
+
+pub async fn unlock_ip(
+    state: web::Data<State>,
+    admin: AdminUser,
+    path: web::Path<String>,
+) -> impl Responder {
+    unlock(&state, admin, SCOPE_IP, &path.into_inner(), None).await
+}
+
+async fn unlock(
+    state: &State,
+    admin: AdminUser,
+    scope: &str,
+    key: &str,
+    user_id: Option<u32>,
+) -> HttpResponse {
+    match LoginThrottle::clear(&state.db.pool, scope, key).await {
+        Ok(true) => {
+            SecurityEvent::record(
+                &state.db.pool,
+                NewSecurityEvent {
+                    event_type: SECURITY_EVENT_ACCOUNT_UNLOCKED,
+                    user_id,
+                    actor_user_id: Some(admin.id as u32),
+                    details: Some(format!("{} {}", scope, key)),
+                    ..Default::default()
+                },
+            )
+            .await;
+            HttpResponse::Ok().finish()
+        }
+        Ok(false) => HttpResponse::NotFound().body("Não existem tentativas falhadas registadas"),
+        Err(e) => {
+            log::error!("Error unlocking {} {}: {}", scope, key, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Providers logins are checked against, in order.
+pub async fn get_auth_providers(state: web::Data<State>, _admin: AdminUser) -> impl Responder {
+    HttpResponse::Ok().json(state.auth_providers.names())
+}
+
+pub async fn get_group_mappings(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+) -> impl Responder {
+    match DirectoryGroupRole::get_all(&state.db.pool).await {
+        Ok(mappings) => json_response_with_etag(&mappings, &req),
+        Err(e) => {
+            log::error!("Error fetching directory group mappings: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Replaces the groups mapped to roles for one provider. Members get the new roles on their
+/// next login.
+pub async fn replace_group_mappings(
+    state: web::Data<State>,
+    admin: AdminUser,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<ReplaceGroupRolesRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+
+    if req.provider == LOCAL_PROVIDER || req.provider.is_empty() || req.provider.len() > 16 {
+        return HttpResponse::BadRequest().body("Fornecedor de autenticação inválido");
+    }
+    if req
+        .mappings
+        .iter()
+        .any(|mapping| mapping.group_name.trim().is_empty())
+    {
+        return HttpResponse::BadRequest().body("O nome do grupo é obrigatório");
+    }
+
+    match DirectoryGroupRole::replace_for_provider(&state.db.pool, &req.provider, &req.mappings)
+        .await
+    {
+        Ok(()) => {
+            SecurityEvent::record(
+                &state.db.pool,
+                NewSecurityEvent {
+                    event_type: SECURITY_EVENT_ROLE_CHANGE,
+                    actor_user_id: Some(admin.id as u32),
+                    details: Some(format!(
+                        "Grupos do fornecedor {} mapeados para funções: {}",
+                        req.provider,
+                        req.mappings.len()
+                    )),
+                    ..Default::default()
+                },
+            )
+            .await;
+            HttpResponse::Ok().finish()
+        }
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            HttpResponse::BadRequest().body("Função não encontrada")
+        }
+        Err(e) => {
+            log::error!("Error replacing group mappings of {}: {}", req.provider, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
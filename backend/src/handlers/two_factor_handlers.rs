@@ -0,0 +1,423 @@
+use actix_session::Session;
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use serde::Deserialize;
+
+use crate::{
+    State,
+    auth::{
+        AdminUser, AuthenticatedUser, client_ip, pending_login, unauthorized, validate_session,
+    },
+    handlers::user_handlers::{check_login_locks, failed_login, finish_login},
+    models::{
+        security_event::{
+            NewSecurityEvent, SECURITY_EVENT_TWO_FACTOR_DISABLED,
+            SECURITY_EVENT_TWO_FACTOR_ENABLED, SECURITY_EVENT_TWO_FACTOR_RESET, SecurityEvent,
+        },
+        two_factor::UserTwoFactor,
+    },
+    utils::{
+        hashing_utils::verify,
+        json_utils::Json,
+        login_throttle::account_key,
+        totp::{
+            generate_recovery_codes, generate_secret, hash_recovery_code, provisioning_uri,
+            verify_code,
+        },
+    },
+};
+
+/// Issuer shown by authenticator apps next to the account.
+const TOTP_ISSUER: &str = "Gestão Documental";
+
+#[derive(Deserialize)]
+pub struct TwoFactorLoginRequest {
+    code: Option<String>,
+    recovery_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorCodeRequest {
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct DisableTwoFactorRequest {
+    current_password: String,
+}
+
+/// Second step of a login with a second factor: a code from the app or a recovery code.
+pub async fn login_second_factor(
+    state: web::Data<State>,
+    request_data: web::Bytes,
+    session: Session,
+    http_req: HttpRequest,
+) -> impl Responder {
+    let Json(req): Json<TwoFactorLoginRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+
+    let Some(pending) = pending_login(&session).filter(|pending| !pending.needs_setup) else {
+        return unauthorized();
+    };
+    let user_id = pending.user_id as u32;
+    let pool = &state.db.pool;
+
+    let (email, two_factor) = match (
+        sqlx::query_scalar!(r#"SELECT email FROM users WHERE id = ?"#, user_id)
+            .fetch_optional(pool)
+            .await,
+        UserTwoFactor::get(pool, user_id).await,
+    ) {
+        (Ok(Some(email)), Ok(Some(two_factor))) if two_factor.enabled => (email, two_factor),
+        (Ok(_), Ok(_)) => return unauthorized(),
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("Database error during second factor login: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    let account = account_key(&email);
+    let ip = client_ip(&http_req);
+    if let Err(resp) = check_login_locks(pool, &account, &ip).await {
+        return resp;
+    }
+
+    let accepted = if let Some(code) = &req.code {
+        match verify_code(&two_factor.secret, code, chrono::Utc::now().timestamp()) {
+            Some(step) => UserTwoFactor::mark_step_used(pool, user_id, step).await,
+            None => Ok(false),
+        }
+    } else if let Some(recovery_code) = &req.recovery_code {
+        UserTwoFactor::use_recovery_code(pool, user_id, &hash_recovery_code(recovery_code)).await
+    } else {
+        return HttpResponse::BadRequest().body("Indique o código de autenticação.");
+    };
+
+    match accepted {
+        Ok(true) => finish_login(pool, &session, user_id, &account, &ip).await,
+        // Wrong codes count towards the same limits as wrong passwords
+        Ok(false) => failed_login(pool, Some(user_id), &account, &ip).await,
+        Err(e) => {
+            log::error!("Database error checking second factor: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// User enrolling a second factor: a logged in user, or one whose login waits for an
+/// enrollment a role requires. The flag tells the latter apart.
+fn enrolling_user(session: &Session) -> Result<(u32, bool), HttpResponse> {
+    if let Ok(user_id) = validate_session(session) {
+        return Ok((user_id as u32, false));
+    }
+
+    match pending_login(session) {
+        Some(pending) if pending.needs_setup => Ok((pending.user_id as u32, true)),
+        _ => Err(unauthorized()),
+    }
+}
+
+pub async fn get_two_factor_status(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+) -> impl Responder {
+    match UserTwoFactor::status(&state.db.pool, user.id as u32).await {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(e) => {
+            log::error!("Error fetching second factor of user {}: {}", user.id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Starts enrolling: a new secret, shown once, to add to an authenticator app.
+pub async fn setup_two_factor(state: web::Data<State>, session: Session) -> impl Responder {
+    let (user_id, _) = match enrolling_user(&session) {
+        Ok(user) => user,
+        Err(resp) => return resp,
+    };
+    let pool = &state.db.pool;
+
+    let email = match UserTwoFactor::get(pool, user_id).await {
+        Ok(Some(two_factor)) if two_factor.enabled => {
+            return HttpResponse::Conflict().body("A autenticação de dois fatores já está ativa.");
+        }
+        Ok(_) => match sqlx::query_scalar!(r#"SELECT email FROM users WHERE id = ?"#, user_id)
+            .fetch_one(pool)
+            .await
+        {
+            Ok(email) => email,
+            Err(e) => {
+                log::error!("Error fetching email of user {}: {}", user_id, e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        },
+        Err(e) => {
+            log::error!("Error fetching second factor of user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let secret = generate_secret();
+    if let Err(e) = UserTwoFactor::start_enrollment(pool, user_id, &secret).await {
+        log::error!(
+            "Error starting second factor enrollment of {}: {}",
+            user_id,
+            e
+        );
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "secret": secret,
+        "otpauth_uri": provisioning_uri(TOTP_ISSUER, &email, &secret),
+    }))
+}
+
+/// Finishes enrolling with a first code from the app. Returns the recovery codes, the only
+/// time they are shown, and completes a login that was waiting for the enrollment.
+pub async fn confirm_two_factor(
+    state: web::Data<State>,
+    session: Session,
+    http_req: HttpRequest,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<TwoFactorCodeRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+
+    let (user_id, pending) = match enrolling_user(&session) {
+        Ok(user) => user,
+        Err(resp) => return resp,
+    };
+    let pool = &state.db.pool;
+
+    let two_factor = match UserTwoFactor::get(pool, user_id).await {
+        Ok(Some(two_factor)) if !two_factor.enabled => two_factor,
+        Ok(Some(_)) => {
+            return HttpResponse::Conflict().body("A autenticação de dois fatores já está ativa.");
+        }
+        Ok(None) => return HttpResponse::BadRequest().body("Inicie primeiro a configuração."),
+        Err(e) => {
+            log::error!("Error fetching second factor of user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let Some(step) = verify_code(
+        &two_factor.secret,
+        &req.code,
+        chrono::Utc::now().timestamp(),
+    ) else {
+        return HttpResponse::BadRequest().body("Código inválido.");
+    };
+
+    let recovery_codes = generate_recovery_codes();
+    let hashes: Vec<Vec<u8>> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    if let Err(e) = UserTwoFactor::enable(pool, user_id, step, &hashes).await {
+        log::error!("Error enabling second factor of user {}: {}", user_id, e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let ip = client_ip(&http_req);
+    SecurityEvent::record(
+        pool,
+        NewSecurityEvent {
+            event_type: SECURITY_EVENT_TWO_FACTOR_ENABLED,
+            user_id: Some(user_id),
+            actor_user_id: Some(user_id),
+            ip_address: Some(&ip),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    if pending {
+        let email = match sqlx::query_scalar!(r#"SELECT email FROM users WHERE id = ?"#, user_id)
+            .fetch_one(pool)
+            .await
+        {
+            Ok(email) => email,
+            Err(e) => {
+                log::error!("Error fetching email of user {}: {}", user_id, e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        };
+
+        let resp = finish_login(pool, &session, user_id, &account_key(&email), &ip).await;
+        if !resp.status().is_success() {
+            return resp;
+        }
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({ "recovery_codes": recovery_codes }))
+}
+
+pub async fn disable_two_factor(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+    http_req: HttpRequest,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<DisableTwoFactorRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    let user_id = user.id as u32;
+    let pool = &state.db.pool;
+
+    match sqlx::query_scalar!(r#"SELECT password FROM users WHERE id = ?"#, user_id)
+        .fetch_one(pool)
+        .await
+    {
+        Ok(password) if verify(&req.current_password, &password) => {}
+        Ok(_) => return HttpResponse::Unauthorized().body("Palavra-passe atual incorreta."),
+        Err(e) => {
+            log::error!("Error fetching password of user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    match UserTwoFactor::is_required_for_user(pool, user_id).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return HttpResponse::Forbidden()
+                .body("A autenticação de dois fatores é obrigatória para as suas funções.");
+        }
+        Err(e) => {
+            log::error!("Error checking second factor requirement: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    match UserTwoFactor::disable(pool, user_id).await {
+        Ok(removed) => {
+            if removed {
+                SecurityEvent::record(
+                    pool,
+                    NewSecurityEvent {
+                        event_type: SECURITY_EVENT_TWO_FACTOR_DISABLED,
+                        user_id: Some(user_id),
+                        actor_user_id: Some(user_id),
+                        ip_address: Some(&client_ip(&http_req)),
+                        ..Default::default()
+                    },
+                )
+                .await;
+            }
+            HttpResponse::Ok().finish()
+        }
+        Err(e) => {
+            log::error!("Error disabling second factor of user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// New recovery codes, replacing the old ones. Needs a current code from the app.
+pub async fn regenerate_recovery_codes(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<TwoFactorCodeRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    let user_id = user.id as u32;
+    let pool = &state.db.pool;
+
+    let two_factor = match UserTwoFactor::get(pool, user_id).await {
+        Ok(Some(two_factor)) if two_factor.enabled => two_factor,
+        Ok(_) => {
+            return HttpResponse::BadRequest()
+                .body("A autenticação de dois fatores não está ativa.");
+        }
+        Err(e) => {
+            log::error!("Error fetching second factor of user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let step = verify_code(
+        &two_factor.secret,
+        &req.code,
+        chrono::Utc::now().timestamp(),
+    );
+    match step {
+        Some(step) => match UserTwoFactor::mark_step_used(pool, user_id, step).await {
+            Ok(true) => {}
+            Ok(false) => return HttpResponse::BadRequest().body("Código inválido."),
+            Err(e) => {
+                log::error!("Error marking second factor code as used: {}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        },
+        None => return HttpResponse::BadRequest().body("Código inválido."),
+    }
+
+    let recovery_codes = generate_recovery_codes();
+    let hashes: Vec<Vec<u8>> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    match UserTwoFactor::replace_recovery_codes(pool, user_id, &hashes).await {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({ "recovery_codes": recovery_codes })),
+        Err(e) => {
+            log::error!("Error replacing recovery codes of user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Removes the second factor of a user who lost it. If a role requires one, they enroll again
+/// on their next login.
+pub async fn admin_reset_two_factor(
+    state: web::Data<State>,
+    admin: AdminUser,
+    http_req: HttpRequest,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+    let pool = &state.db.pool;
+
+    match UserTwoFactor::disable(pool, user_id).await {
+        Ok(true) => {
+            SecurityEvent::record(
+                pool,
+                NewSecurityEvent {
+                    event_type: SECURITY_EVENT_TWO_FACTOR_RESET,
+                    user_id: Some(user_id),
+                    actor_user_id: Some(admin.id as u32),
+                    ip_address: Some(&client_ip(&http_req)),
+                    ..Default::default()
+                },
+            )
+            .await;
+            HttpResponse::Ok().finish()
+        }
+        Ok(false) => HttpResponse::NotFound()
+            .body("O utilizador não tem autenticação de dois fatores configurada."),
+        Err(e) => {
+            log::error!("Error resetting second factor of user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -1,19 +1,43 @@
 use actix_session::Session;
 use actix_web::{HttpRequest, HttpResponse, Responder, web};
 use ahash::HashMap;
+use chrono::Datelike;
 use log::error;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::{
     State, // For returning user details
-    auth::{is_admin, user_can_manage_page, validate_session},
+    auth::{
+        AdminScope, AdminUser, AuthenticatedUser, client_ip, start_pending_login, start_session,
+        user_is_admin,
+    },
     models::{
+        app_setting::{AppSetting, SETTING_REGISTRATION_OPEN},
+        location::Location,
+        login_throttle::LoginThrottle,
+        password_history::PasswordHistory,
         role::{Role, UserRoleAssignment},
+        security_event::{
+            NewSecurityEvent, SECURITY_EVENT_LOGIN_FAILURE, SECURITY_EVENT_LOGIN_LOCKED,
+            SECURITY_EVENT_LOGIN_SUCCESS, SECURITY_EVENT_PASSWORD_CHANGE,
+            SECURITY_EVENT_ROLE_CHANGE, SECURITY_EVENT_USER_DELETED, SecurityEvent,
+        },
+        two_factor::UserTwoFactor,
         user::{User, UserRoleRow, UserWithRoles},
+        vacation_ledger::VacationLedgerEntry,
+        work_contract::{ENTITLEMENT_RULE_FIXED, ENTITLEMENT_RULE_STATUTORY},
+        work_schedule::{BALANCE_UNIT_DAYS, BALANCE_UNIT_HOURS},
+    },
+    services::{
+        auth_provider::{AuthError, LOCAL_PROVIDER},
+        password_service::{check_new_password, password_rejected, store_password},
+        permission_service::Permissions,
+        vacation_ledger_service,
     },
     utils::{
         hashing_utils::{hash, verify},
         json_utils::{Json, json_response, json_response_with_etag}, // Removed custom Json, will use web::Json
+        login_throttle::{ACCOUNT_POLICY, IP_POLICY, SCOPE_ACCOUNT, SCOPE_IP, account_key},
     },
 };
 
@@ -43,6 +67,25 @@ pub struct AdminUpdateUserRequest {
     pub username: Option<String>,
     pub email: Option<String>,
     pub vacation_days_current_year: Option<u16>,
+    /// Left out to keep the location, `null` to remove it
+    #[serde(default, deserialize_with = "present")]
+    pub location: Option<Option<i8>>,
+    /// DAYS or HOURS, what vacation_days_current_year is counted in
+    pub vacation_balance_unit: Option<String>,
+    /// Left out to keep the hire date, `null` to remove it
+    #[serde(default, deserialize_with = "present")]
+    pub hire_date: Option<Option<chrono::NaiveDate>>,
+    /// FIXED or STATUTORY, where the yearly vacation days come from
+    pub vacation_entitlement_rule: Option<String>,
+}
+
+/// Tells a field sent as `null` (`Some(None)`) apart from one left out (`None`).
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
 }
 
 #[derive(Deserialize, Debug)]
@@ -59,6 +102,36 @@ pub async fn register(state: web::Data<State>, request_data: web::Bytes) -> impl
         }
     };
 
+    // Once an admin closes registration, accounts are only created by invitation
+    match AppSetting::get_bool(&state.db.pool, SETTING_REGISTRATION_OPEN, true).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::Forbidden()
+                .body("O registo está fechado. Peça um convite a um administrador.");
+        }
+        Err(e) => {
+            error!("Database error fetching registration setting: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    }
+
+    match check_new_password(
+        &state.db.pool,
+        &state.password_policy,
+        None,
+        &user.password,
+        &[&user.username, &user.email],
+    )
+    .await
+    {
+        Ok(violations) if violations.is_empty() => {}
+        Ok(violations) => return password_rejected(violations),
+        Err(e) => {
+            error!("Database error checking new password: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    }
+
     // --- Transaction Start ---
     let mut tx = match state.db.pool.begin().await {
         Ok(tx) => tx,
@@ -91,14 +164,14 @@ pub async fn register(state: web::Data<State>, request_data: web::Bytes) -> impl
     }
 
     // Hash password
-    let password_bytes = hash(&user.password);
+    let password_hash = hash(&user.password);
 
     // Insert user within the transaction
     let insert_result = sqlx::query!(
         r#"INSERT INTO users (username, email, password) VALUES (?, ?, ?)"#,
         user.username,
         user.email,
-        &password_bytes[..]
+        password_hash.as_bytes()
     )
     .execute(&mut *tx) // Use transaction
     .await;
@@ -112,6 +185,18 @@ pub async fn register(state: web::Data<State>, request_data: web::Bytes) -> impl
         }
     };
 
+    if let Err(e) = PasswordHistory::record(
+        &mut tx,
+        user_id,
+        password_hash.as_bytes(),
+        state.password_policy.history_size,
+    )
+    .await
+    {
+        error!("Database error storing password history: {}", e);
+        return HttpResponse::InternalServerError().body("Error registering user");
+    }
+
     // Fetch the default "Colaborador" role ID within the transaction
     let default_role = sqlx::query!(r#"SELECT id FROM roles WHERE name = 'Colaborador' LIMIT 1"#)
         .fetch_one(&mut *tx) // Use transaction
@@ -160,6 +245,7 @@ pub async fn login(
     state: web::Data<State>,
     request_data: web::Bytes,
     session: Session,
+    http_req: HttpRequest,
 ) -> impl Responder {
     let Json(req): Json<LoginRequest> = match Json::from_bytes(&request_data) {
         Ok(data) => data,
@@ -169,58 +255,214 @@ pub async fn login(
         }
     };
 
-    // Find user by email
-    let user = sqlx::query!(
-        r#"SELECT id, password FROM users WHERE email = ?"#,
-        req.email
-    )
-    .fetch_optional(&state.db.pool)
-    .await;
+    let pool = &state.db.pool;
+    let account = account_key(&req.email);
+    let ip = client_ip(&http_req);
 
-    let user = match user {
-        Ok(Some(user)) => user,
-        Ok(None) => return HttpResponse::Unauthorized().finish(),
-        Err(e) => {
+    // Locked keys are refused before looking at the password, so guessing gets nowhere
+    if let Err(resp) = check_login_locks(pool, &account, &ip).await {
+        return resp;
+    }
+
+    let user_id = match state
+        .auth_providers
+        .authenticate(pool, &req.email, &req.password)
+        .await
+    {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => {
+            let user_id =
+                match sqlx::query_scalar!(r#"SELECT id FROM users WHERE email = ?"#, req.email)
+                    .fetch_optional(pool)
+                    .await
+                {
+                    Ok(user_id) => user_id,
+                    Err(e) => {
+                        error!("Database error during login: {}", e);
+                        return HttpResponse::InternalServerError().body("Database error");
+                    }
+                };
+            return failed_login(pool, user_id, &account, &ip).await;
+        }
+        // Not the user's fault, so it is not counted as a failed login
+        Err(AuthError::Database(e)) => {
             error!("Database error during login: {}", e);
             return HttpResponse::InternalServerError().body("Database error");
         }
+        Err(AuthError::Directory(_)) => {
+            return HttpResponse::ServiceUnavailable()
+                .body("Serviço de autenticação indisponível, tente mais tarde");
+        }
     };
 
-    // Verify password
-    if !verify(&req.password, &&user.password[..]) {
-        return HttpResponse::Unauthorized().body("Invalid credentials");
+    // With a second factor, or one required by a role, the session only holds a pending login
+    // until the code is checked
+    match second_factor_step(pool, user_id).await {
+        Ok(Some(needs_setup)) => {
+            if let Err(resp) = start_pending_login(&session, user_id as i32, needs_setup) {
+                return resp;
+            }
+            if needs_setup {
+                HttpResponse::Accepted()
+                    .json(serde_json::json!({ "two_factor_setup_required": true }))
+            } else {
+                HttpResponse::Accepted().json(serde_json::json!({ "two_factor_required": true }))
+            }
+        }
+        Ok(None) => finish_login(pool, &session, user_id, &account, &ip).await,
+        Err(e) => {
+            error!(
+                "Database error checking second factor of user {}: {}",
+                user_id, e
+            );
+            HttpResponse::InternalServerError().body("Database error")
+        }
     }
+}
 
-    // Check if user is admin (has admin role)
-    let is_admin = match sqlx::query!(
-        r#"
-        SELECT COUNT(*) as count
-        FROM user_roles ur
-        JOIN roles r ON ur.role_id = r.id
-        WHERE ur.user_id = ? AND r.is_admin = 1
-        "#,
-        user.id
-    )
-    .fetch_one(&state.db.pool)
-    .await
+/// Whether a user whose first factor was checked still has to give a second one: `None` if
+/// not, `Some(false)` for a code and `Some(true)` when one of their roles requires a second
+/// factor they have not set up yet.
+pub(crate) async fn second_factor_step(
+    pool: &sqlx::MySqlPool,
+    user_id: u32,
+) -> Result<Option<bool>, sqlx::Error> {
+    if UserTwoFactor::get(pool, user_id)
+        .await?
+        .is_some_and(|two_factor| two_factor.enabled)
     {
-        Ok(result) => result.count > 0,
-        Err(e) => {
-            error!("Database error checking admin status: {}", e);
-            return HttpResponse::InternalServerError().body("Error checking permissions");
+        return Ok(Some(false));
+    }
+
+    Ok(UserTwoFactor::is_required_for_user(pool, user_id)
+        .await?
+        .then_some(true))
+}
+
+/// Refuses a login while the account or the IP is locked.
+pub(crate) async fn check_login_locks(
+    pool: &sqlx::MySqlPool,
+    account: &str,
+    ip: &str,
+) -> Result<(), HttpResponse> {
+    for (scope, key) in [(SCOPE_ACCOUNT, account), (SCOPE_IP, ip)] {
+        match LoginThrottle::locked_until(pool, scope, key).await {
+            Ok(Some(locked_until)) => return Err(too_many_attempts(locked_until)),
+            Ok(None) => {}
+            Err(e) => {
+                error!(
+                    "Database error checking login lock of {} {}: {}",
+                    scope, key, e
+                );
+                return Err(HttpResponse::InternalServerError().body("Database error"));
+            }
         }
-    };
+    }
+
+    Ok(())
+}
+
+/// Logs in a user whose credentials were all checked.
+pub(crate) async fn finish_login(
+    pool: &sqlx::MySqlPool,
+    session: &Session,
+    user_id: u32,
+    account: &str,
+    ip: &str,
+) -> HttpResponse {
+    if let Err(resp) = start_session(session, user_id as i32) {
+        return resp;
+    }
+
+    if let Err(e) = LoginThrottle::clear(pool, SCOPE_ACCOUNT, account).await {
+        error!("Error clearing failed logins of {}: {}", account, e);
+    }
 
-    // Set session data
-    session.insert("user_id", user.id as i32).unwrap();
-    session.insert("is_admin", is_admin).unwrap();
-    session
-        .insert("last_renewal", chrono::Utc::now().timestamp())
-        .unwrap();
+    SecurityEvent::record(
+        pool,
+        NewSecurityEvent {
+            event_type: SECURITY_EVENT_LOGIN_SUCCESS,
+            user_id: Some(user_id),
+            ip_address: Some(ip),
+            ..Default::default()
+        },
+    )
+    .await;
 
     HttpResponse::Ok().finish()
 }
 
+/// Counts a failed login against the account and the IP and records it. `user_id` is the
+/// account behind the email, if there is one.
+pub(crate) async fn failed_login(
+    pool: &sqlx::MySqlPool,
+    user_id: Option<u32>,
+    account: &str,
+    ip: &str,
+) -> HttpResponse {
+    SecurityEvent::record(
+        pool,
+        NewSecurityEvent {
+            event_type: SECURITY_EVENT_LOGIN_FAILURE,
+            user_id,
+            ip_address: Some(ip),
+            details: user_id
+                .is_none()
+                .then(|| format!("Email desconhecido: {}", account)),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    for (scope, key, policy) in [
+        (SCOPE_ACCOUNT, account, &ACCOUNT_POLICY),
+        (SCOPE_IP, ip, &IP_POLICY),
+    ] {
+        match LoginThrottle::record_failure(pool, scope, key, policy).await {
+            Ok(Some(locked_until)) => {
+                SecurityEvent::record(
+                    pool,
+                    NewSecurityEvent {
+                        event_type: SECURITY_EVENT_LOGIN_LOCKED,
+                        user_id: if scope == SCOPE_ACCOUNT {
+                            user_id
+                        } else {
+                            None
+                        },
+                        ip_address: Some(ip),
+                        details: Some(format!(
+                            "{} {} bloqueado até {}",
+                            scope,
+                            key,
+                            locked_until.to_rfc3339()
+                        )),
+                        ..Default::default()
+                    },
+                )
+                .await;
+            }
+            Ok(None) => {}
+            Err(e) => error!(
+                "Database error counting failed login of {} {}: {}",
+                scope, key, e
+            ),
+        }
+    }
+
+    HttpResponse::Unauthorized().body("Invalid credentials")
+}
+
+fn too_many_attempts(locked_until: chrono::DateTime<chrono::Utc>) -> HttpResponse {
+    let retry_after = (locked_until - chrono::Utc::now()).num_seconds().max(1);
+
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", retry_after.to_string()))
+        .json(serde_json::json!({
+            "error": "Demasiadas tentativas de início de sessão falhadas. Tente novamente mais tarde.",
+            "retry_after": retry_after,
+        }))
+}
+
 #[derive(Serialize)]
 pub struct CheckResponse {
     #[serde(rename = "isAdmin")]
@@ -230,20 +472,24 @@ pub struct CheckResponse {
     can_manage_this_page: Option<bool>,
 }
 
-pub async fn check(state: web::Data<State>, session: Session, data: web::Bytes) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id,
-        Err(response) => return response,
-    };
+pub async fn check(
+    state: web::Data<State>,
+    permissions: Permissions,
+    data: web::Bytes,
+) -> impl Responder {
+    let user_id = permissions.user_id();
 
     let Ok(page_path) = String::from_utf8(data.to_vec()) else {
         return HttpResponse::BadRequest().finish();
     };
 
-    let is_admin_session = session
-        .get::<bool>("is_admin")
-        .unwrap_or(Some(false))
-        .unwrap_or(false);
+    let is_admin = match user_is_admin(&state.db.pool, user_id).await {
+        Ok(is_admin) => is_admin,
+        Err(e) => {
+            error!("Error checking admin status of user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
 
     let mut can_manage_this_page_result: Option<bool> = None;
 
@@ -255,9 +501,9 @@ pub async fn check(state: web::Data<State>, session: Session, data: web::Bytes)
             let id_str_cleaned = id_str.trim_end_matches('/');
             if let Ok(page_id) = id_str_cleaned.parse::<u32>() {
                 // Check specific permission for this page
-                match user_can_manage_page(&state.db.pool, user_id, page_id).await {
-                    Ok(can_manage) => {
-                        can_manage_this_page_result = Some(can_manage);
+                match permissions.for_page(page_id).await {
+                    Ok(perms) => {
+                        can_manage_this_page_result = Some(perms.can_manage_fields);
                     }
                     Err(e) => {
                         error!(
@@ -315,7 +561,7 @@ pub async fn check(state: web::Data<State>, session: Session, data: web::Bytes)
 
     // Return the potentially richer response
     json_response(&CheckResponse {
-        is_admin: is_admin_session,
+        is_admin,
         can_manage_this_page: can_manage_this_page_result,
     })
 }
@@ -325,28 +571,15 @@ pub async fn logout(session: Session) -> impl Responder {
     HttpResponse::Ok().finish()
 }
 
-pub async fn protected(session: Session) -> impl Responder {
-    if let Err(resp) = validate_session(&session) {
-        return resp;
-    }
-
-    let is_admin = session
-        .get::<bool>("is_admin")
-        .unwrap_or(Some(false))
-        .unwrap_or(false);
-
-    if !is_admin {
-        return HttpResponse::Forbidden().body("Admin privileges required");
-    }
-
+pub async fn protected(_admin: AdminUser) -> impl Responder {
     HttpResponse::Ok().body("Protected Route")
 }
 
-pub async fn get_user_analytics(state: web::Data<State>, session: Session) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(response) => return response,
-    };
+pub async fn get_user_analytics(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+) -> impl Responder {
+    let user_id = user.id as u32;
 
     // Get analytics directly from database
     let analytics = sqlx::query!(
@@ -386,18 +619,8 @@ struct AssignRoleRequest {
 pub async fn assign_roles(
     state: web::Data<State>,
     request_data: web::Bytes,
-    session: Session,
+    admin: AdminScope,
 ) -> impl Responder {
-    // Only admins can assign roles
-    let is_admin = session
-        .get::<bool>("is_admin")
-        .unwrap_or(Some(false))
-        .unwrap_or(false);
-
-    if !is_admin {
-        return HttpResponse::Forbidden().body("Admin privileges required");
-    }
-
     let Json(req): Json<AssignRoleRequest> = match Json::from_bytes(&request_data) {
         Ok(data) => data,
         Err(e) => {
@@ -406,13 +629,73 @@ pub async fn assign_roles(
         }
     };
 
+    // Scoped admins can only add or remove the roles they manage, never their own, and never
+    // roles that would make someone an admin
+    if !admin.is_admin {
+        if req.user_id == admin.id as u32 {
+            return HttpResponse::Forbidden().body("Não pode alterar as suas próprias funções");
+        }
+
+        let current_role_ids: Vec<u32> =
+            match Role::get_roles_by_user_id(&state.db.pool, req.user_id).await {
+                Ok(roles) => roles.into_iter().map(|role| role.id).collect(),
+                Err(e) => {
+                    error!(
+                        "Database error fetching roles of user {}: {}",
+                        req.user_id, e
+                    );
+                    return HttpResponse::InternalServerError().body("Erro ao atribuir funções");
+                }
+            };
+
+        let changed = req
+            .role_ids
+            .iter()
+            .filter(|id| !current_role_ids.contains(id))
+            .chain(
+                current_role_ids
+                    .iter()
+                    .filter(|id| !req.role_ids.contains(id)),
+            );
+        let changed: Vec<u32> = changed.copied().collect();
+        if !admin.can_manage_roles(&changed) {
+            return HttpResponse::Forbidden()
+                .body("Não pode atribuir ou remover funções que não administra");
+        }
+
+        let admin_role_ids = match Role::get_admin_role_ids(&state.db.pool).await {
+            Ok(ids) => ids,
+            Err(e) => {
+                error!("Database error fetching admin roles: {}", e);
+                return HttpResponse::InternalServerError().body("Erro ao atribuir funções");
+            }
+        };
+        if changed.iter().any(|id| admin_role_ids.contains(id)) {
+            return HttpResponse::Forbidden()
+                .body("Só um administrador pode atribuir ou remover funções de administração");
+        }
+    }
+
     let assignment = UserRoleAssignment {
         user_id: req.user_id,
         role_ids: req.role_ids,
     };
 
     match crate::models::role::Role::assign_roles_to_user(&state.db.pool, &assignment).await {
-        Ok(_) => HttpResponse::Ok().body("Roles assigned successfully"),
+        Ok(_) => {
+            SecurityEvent::record(
+                &state.db.pool,
+                NewSecurityEvent {
+                    event_type: SECURITY_EVENT_ROLE_CHANGE,
+                    user_id: Some(assignment.user_id),
+                    actor_user_id: Some(admin.id as u32),
+                    details: Some(format!("Funções: {:?}", assignment.role_ids)),
+                    ..Default::default()
+                },
+            )
+            .await;
+            HttpResponse::Ok().body("Roles assigned successfully")
+        }
         Err(e) => {
             error!("Database error assigning roles: {}", e);
             HttpResponse::InternalServerError().body("Erro ao atribuir funções") // Translated
@@ -421,15 +704,15 @@ pub async fn assign_roles(
 }
 
 // Handler to get current user's details (username and email)
-pub async fn get_current_user_details(state: web::Data<State>, session: Session) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id,
-        Err(response) => return response,
-    };
+pub async fn get_current_user_details(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+) -> impl Responder {
+    let user_id = user.id;
 
     match sqlx::query_as!(
         User, // Using the User model from models/user.rs
-        r#"SELECT id, username, email, vacation_days_current_year as "vacation_days_current_year: _" FROM users WHERE id = ?"#,
+        r#"SELECT id, username, email, vacation_days_current_year as "vacation_days_current_year: _", location FROM users WHERE id = ?"#,
         user_id
     )
     .fetch_one(&state.db.pool)
@@ -449,13 +732,10 @@ pub async fn get_current_user_details(state: web::Data<State>, session: Session)
 // Handler to update current user's username and/or email
 pub async fn update_user_details(
     state: web::Data<State>,
-    session: Session,
+    user: AuthenticatedUser,
     req_data: web::Json<UpdateUserDetailsRequest>, // Changed to use actix_web::web::Json
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id,
-        Err(response) => return response,
-    };
+    let user_id = user.id;
 
     // req_data is now web::Json<UpdateUserDetailsRequest>, access inner value with .into_inner() or by destructuring
     let update_payload = req_data.into_inner();
@@ -553,48 +833,77 @@ pub async fn update_user_details(
 }
 
 // Handler to change current user's password
+/// Stores a password that passed [`check_new_password`].
+async fn set_password(state: &State, user_id: u32, password: &str) -> Result<(), sqlx::Error> {
+    let mut tx = state.db.pool.begin().await?;
+    store_password(&mut tx, &state.password_policy, user_id, password).await?;
+    tx.commit().await
+}
+
 pub async fn change_user_password(
     state: web::Data<State>,
-    session: Session,
+    user: AuthenticatedUser,
     req_data: web::Json<ChangePasswordRequest>, // Changed to use actix_web::web::Json
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id,
-        Err(response) => return response,
-    };
+    let user_id = user.id;
 
     let change_payload = req_data.into_inner();
 
     // Fetch current user's password
-    let user_password_hash =
-        match sqlx::query_scalar!(r#"SELECT password FROM users WHERE id = ?"#, user_id)
-            .fetch_one(&state.db.pool)
-            .await
-        {
-            Ok(hash) => hash,
-            Err(_) => {
-                return HttpResponse::InternalServerError().body("Erro ao buscar utilizador.");
-            }
-        };
+    let current_user = match sqlx::query!(
+        r#"SELECT username, email, password, auth_source FROM users WHERE id = ?"#,
+        user_id
+    )
+    .fetch_one(&state.db.pool)
+    .await
+    {
+        Ok(user) => user,
+        Err(_) => {
+            return HttpResponse::InternalServerError().body("Erro ao buscar utilizador.");
+        }
+    };
+
+    if current_user.auth_source != LOCAL_PROVIDER {
+        return HttpResponse::BadRequest()
+            .body("A palavra-passe desta conta é gerida pelo diretório da organização.");
+    }
 
     // Verify current password
-    if !verify(&change_payload.current_password, &user_password_hash) {
+    if !verify(&change_payload.current_password, &current_user.password) {
         return HttpResponse::Unauthorized().body("Palavra-passe atual incorreta.");
     }
 
-    // Hash new password
-    let new_password_hashed = hash(&change_payload.new_password);
-
-    // Update password
-    match sqlx::query!(
-        r#"UPDATE users SET password = ? WHERE id = ?"#,
-        &new_password_hashed[..],
-        user_id
+    match check_new_password(
+        &state.db.pool,
+        &state.password_policy,
+        Some(user_id as u32),
+        &change_payload.new_password,
+        &[&current_user.username, &current_user.email],
     )
-    .execute(&state.db.pool)
     .await
     {
-        Ok(_) => HttpResponse::Ok().body("Palavra-passe alterada com sucesso."),
+        Ok(violations) if violations.is_empty() => {}
+        Ok(violations) => return password_rejected(violations),
+        Err(e) => {
+            log::error!("Database error checking new password: {}", e);
+            return HttpResponse::InternalServerError().body("Erro ao alterar palavra-passe.");
+        }
+    }
+
+    // Update password
+    match set_password(&state, user_id as u32, &change_payload.new_password).await {
+        Ok(()) => {
+            SecurityEvent::record(
+                &state.db.pool,
+                NewSecurityEvent {
+                    event_type: SECURITY_EVENT_PASSWORD_CHANGE,
+                    user_id: Some(user_id as u32),
+                    ..Default::default()
+                },
+            )
+            .await;
+            HttpResponse::Ok().body("Palavra-passe alterada com sucesso.")
+        }
         Err(e) => {
             log::error!(
                 "Database error changing password for user_id {}: {}",
@@ -608,14 +917,9 @@ pub async fn change_user_password(
 
 pub async fn get_users_with_roles(
     state: web::Data<State>,
-    session: Session,
+    _admin: AdminScope,
     req: HttpRequest, // Add HttpRequest for ETag
 ) -> impl Responder {
-    // Ensure only admins can access this
-    if is_admin(&session).is_err() {
-        return HttpResponse::Forbidden().finish();
-    }
-
     // Fetch all users and their roles using a LEFT JOIN
     let rows = sqlx::query_as!(
         UserRoleRow,
@@ -624,10 +928,13 @@ pub async fn get_users_with_roles(
         u.id as user_id,
         u.username,
         u.email,
+        u.location,
         r.id as role_id,
         r.name as role_name,
         r.description as role_description,
         r.is_admin as "role_is_admin: bool",
+        r.parent_role_id as role_parent_role_id,
+        r.requires_two_factor as "role_requires_two_factor: bool",
         r.created_at as "role_created_at?: chrono::DateTime<chrono::Utc>",
         r.updated_at as "role_updated_at?: chrono::DateTime<chrono::Utc>"
     FROM users u
@@ -651,6 +958,7 @@ pub async fn get_users_with_roles(
                         id: row.user_id,
                         username: row.username.clone(),
                         email: row.email.clone(),
+                        location: row.location,
                         roles: Vec::new(),
                     });
 
@@ -673,6 +981,8 @@ pub async fn get_users_with_roles(
                         name: role_name,
                         description: row.role_description,
                         is_admin: role_is_admin,
+                        parent_role_id: row.role_parent_role_id,
+                        requires_two_factor: row.role_requires_two_factor.unwrap_or(false),
                         created_at: role_created_at,
                         updated_at: role_updated_at,
                     });
@@ -693,20 +1003,16 @@ pub async fn get_users_with_roles(
 // Admin handler to update a specific user's details (username, email)
 pub async fn admin_update_user_details(
     state: web::Data<State>,
-    session: Session,
+    admin: AdminUser,
     path: web::Path<u32>,                        // User ID from path
     req_data: web::Json<AdminUpdateUserRequest>, // New request struct
 ) -> impl Responder {
-    if let Err(admin_resp) = is_admin(&session) {
-        return admin_resp; // Ensure requester is admin
-    }
-
     let target_user_id = path.into_inner();
     let update_payload = req_data.into_inner();
 
     // Fetch current details of the user being updated
     let target_user = match sqlx::query!(
-        r#"SELECT username, email, vacation_days_current_year FROM users WHERE id = ?"#,
+        r#"SELECT username, email, vacation_days_current_year, location, vacation_balance_unit, hire_date, vacation_entitlement_rule FROM users WHERE id = ?"#,
         target_user_id
     )
     .fetch_optional(&state.db.pool)
@@ -739,6 +1045,33 @@ pub async fn admin_update_user_details(
         .vacation_days_current_year
         .unwrap_or(original_target_vacation_days);
 
+    let new_location = update_payload.location.unwrap_or(target_user.location);
+    if new_location.is_some_and(|location| Location::from_value(location).is_none()) {
+        return HttpResponse::BadRequest().body("Localização inválida.");
+    }
+
+    let new_balance_unit = update_payload
+        .vacation_balance_unit
+        .unwrap_or(target_user.vacation_balance_unit);
+    if new_balance_unit != BALANCE_UNIT_DAYS && new_balance_unit != BALANCE_UNIT_HOURS {
+        return HttpResponse::BadRequest().body("Unidade de férias inválida.");
+    }
+
+    let new_hire_date = update_payload.hire_date.unwrap_or(target_user.hire_date);
+    let new_entitlement_rule = update_payload
+        .vacation_entitlement_rule
+        .unwrap_or_else(|| target_user.vacation_entitlement_rule.clone());
+    if new_entitlement_rule != ENTITLEMENT_RULE_FIXED
+        && new_entitlement_rule != ENTITLEMENT_RULE_STATUTORY
+    {
+        return HttpResponse::BadRequest().body("Regra de férias inválida.");
+    }
+    let statutory = new_entitlement_rule == ENTITLEMENT_RULE_STATUTORY;
+    if statutory && new_hire_date.is_none() {
+        return HttpResponse::BadRequest()
+            .body("As regras legais de férias precisam da data de admissão.");
+    }
+
     // Check for username uniqueness if it's being changed
     if new_username != original_target_username {
         match sqlx::query(r#"SELECT id FROM users WHERE username = ? AND id != ?"#)
@@ -782,70 +1115,131 @@ pub async fn admin_update_user_details(
 
     // Update user details
     match sqlx::query!(
-        r#"UPDATE users SET username = ?, email = ?, vacation_days_current_year = ? WHERE id = ?"#,
+        r#"UPDATE users SET username = ?, email = ?, vacation_days_current_year = ?, location = ?, vacation_balance_unit = ?, hire_date = ?, vacation_entitlement_rule = ? WHERE id = ?"#,
         new_username,
         new_email,
         new_vacation_days,
+        new_location,
+        new_balance_unit,
+        new_hire_date,
+        new_entitlement_rule,
         target_user_id
     )
     .execute(&state.db.pool)
     .await
     {
-        Ok(_) => HttpResponse::Ok().body("Detalhes do utilizador atualizados com sucesso."),
+        Ok(_) => {}
         Err(e) => {
             error!("Database error admin updating user details: {}", e);
-            HttpResponse::InternalServerError().body("Erro ao atualizar detalhes do utilizador.")
+            return HttpResponse::InternalServerError()
+                .body("Erro ao atualizar detalhes do utilizador.");
+        }
+    }
+
+    // Under the statutory rules, this year's entitlement follows the hire date
+    if statutory
+        && (new_hire_date != target_user.hire_date
+            || new_entitlement_rule != target_user.vacation_entitlement_rule)
+    {
+        if let Err(e) = vacation_ledger_service::apply_statutory_entitlement(
+            &state.db.pool,
+            target_user_id,
+            chrono::Utc::now().year(),
+            false,
+        )
+        .await
+        {
+            error!(
+                "Database error applying statutory vacation entitlement of user {}: {}",
+                target_user_id, e
+            );
+            return HttpResponse::InternalServerError()
+                .body("Erro ao atualizar detalhes do utilizador.");
         }
     }
+
+    // Otherwise the yearly entitlement also becomes this year's entitlement in the ledger
+    if !statutory && new_vacation_days != original_target_vacation_days {
+        if let Err(e) = VacationLedgerEntry::set_entitlement(
+            &state.db.pool,
+            target_user_id,
+            chrono::Utc::now().year(),
+            new_vacation_days as i32,
+            Some(admin.id as u32),
+        )
+        .await
+        {
+            error!(
+                "Database error setting vacation entitlement of user {}: {}",
+                target_user_id, e
+            );
+            return HttpResponse::InternalServerError()
+                .body("Erro ao atualizar detalhes do utilizador.");
+        }
+    }
+
+    HttpResponse::Ok().body("Detalhes do utilizador atualizados com sucesso.")
 }
 
 // Admin handler to set/change a specific user's password
 pub async fn admin_set_user_password(
     state: web::Data<State>,
-    session: Session,
+    admin: AdminUser,
     path: web::Path<u32>,                         // User ID from path
     req_data: web::Json<AdminSetPasswordRequest>, // New request struct
 ) -> impl Responder {
-    if let Err(admin_resp) = is_admin(&session) {
-        return admin_resp; // Ensure requester is admin
-    }
-
     let target_user_id = path.into_inner();
     let set_password_payload = req_data.into_inner();
 
     // Check if target user exists
-    match sqlx::query_scalar!(
-        r#"SELECT EXISTS(SELECT 1 FROM users WHERE id = ?)"#,
+    let target_user = match sqlx::query!(
+        r#"SELECT username, email FROM users WHERE id = ?"#,
         target_user_id
     )
-    .fetch_one(&state.db.pool)
+    .fetch_optional(&state.db.pool)
     .await
     {
-        Ok(exists) => {
-            if exists != 1 {
-                // MySQL returns 1 for true, 0 for false for EXISTS
-                return HttpResponse::NotFound().body("Utilizador alvo não encontrado.");
-            }
-        }
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().body("Utilizador alvo não encontrado."),
         Err(e) => {
             error!("Database error checking if target user exists: {}", e);
             return HttpResponse::InternalServerError().body("Erro ao verificar utilizador alvo.");
         }
-    }
-
-    // Hash new password
-    let new_password_hashed = hash(&set_password_payload.new_password);
+    };
 
-    // Update password
-    match sqlx::query!(
-        r#"UPDATE users SET password = ? WHERE id = ?"#,
-        &new_password_hashed[..],
-        target_user_id
+    match check_new_password(
+        &state.db.pool,
+        &state.password_policy,
+        Some(target_user_id),
+        &set_password_payload.new_password,
+        &[&target_user.username, &target_user.email],
     )
-    .execute(&state.db.pool)
     .await
     {
-        Ok(_) => HttpResponse::Ok().body("Palavra-passe do utilizador definida com sucesso."),
+        Ok(violations) if violations.is_empty() => {}
+        Ok(violations) => return password_rejected(violations),
+        Err(e) => {
+            error!("Database error checking new password: {}", e);
+            return HttpResponse::InternalServerError()
+                .body("Erro ao definir palavra-passe do utilizador.");
+        }
+    }
+
+    // Update password
+    match set_password(&state, target_user_id, &set_password_payload.new_password).await {
+        Ok(()) => {
+            SecurityEvent::record(
+                &state.db.pool,
+                NewSecurityEvent {
+                    event_type: SECURITY_EVENT_PASSWORD_CHANGE,
+                    user_id: Some(target_user_id),
+                    actor_user_id: Some(admin.id as u32),
+                    ..Default::default()
+                },
+            )
+            .await;
+            HttpResponse::Ok().body("Palavra-passe do utilizador definida com sucesso.")
+        }
         Err(e) => {
             error!("Database error admin setting user password: {}", e);
             HttpResponse::InternalServerError().body("Erro ao definir palavra-passe do utilizador.")
@@ -856,14 +1250,10 @@ pub async fn admin_set_user_password(
 // Handler for deleting a user (admin only)
 pub async fn admin_delete_user(
     state: web::Data<State>,
-    session: Session,
+    admin: AdminUser,
     path: web::Path<u32>, // User ID from path
 ) -> impl Responder {
-    // Ensure requester is admin and get their user ID
-    let current_user_id = match is_admin(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+    let current_user_id = admin.id as u32;
 
     let target_user_id = path.into_inner();
 
@@ -945,6 +1335,16 @@ pub async fn admin_delete_user(
                 error!("Failed to commit transaction: {}", e);
                 return HttpResponse::InternalServerError().body("Erro ao finalizar eliminação do utilizador.");
             }
+            SecurityEvent::record(
+                &state.db.pool,
+                NewSecurityEvent {
+                    event_type: SECURITY_EVENT_USER_DELETED,
+                    user_id: Some(target_user_id),
+                    actor_user_id: Some(current_user_id),
+                    ..Default::default()
+                },
+            )
+            .await;
             HttpResponse::Ok().body("Utilizador eliminado com sucesso.")
         }
         Err(e) => {
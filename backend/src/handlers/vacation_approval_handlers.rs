@@ -0,0 +1,268 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+
+use crate::{
+    State,
+    auth::{AdminUser, AuthenticatedUser},
+    handlers::admin_vacation_handlers::action_request,
+    models::{
+        vacation_approval::{
+            ApprovalChain, ApprovalChainRequest, ApprovalDelegation, ApprovalDelegationRequest,
+            ApprovalStep, VacationRequestApproval,
+        },
+        vacation_request::{ActionVacationRequest, VacationRequest, VacationRequestStatus},
+    },
+    utils::json_utils::{Json, json_response_with_etag},
+};
+
+/// Steps waiting on the user, as approver or as delegate of one.
+pub async fn get_my_pending_approvals(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+    req: HttpRequest,
+) -> impl Responder {
+    let user_id = user.id as u32;
+    let today = chrono::Utc::now().date_naive();
+
+    match VacationRequestApproval::get_pending_for_approver(&state.db.pool, user_id, today).await {
+        Ok(approvals) => json_response_with_etag(&approvals, &req),
+        Err(e) => {
+            log::error!(
+                "Error fetching pending approvals of user {}: {}",
+                user_id,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Approves or rejects the open step of a request. Approving the last step approves the
+/// request, and rejecting any step rejects it.
+pub async fn action_approval(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+    request_id_path: web::Path<u32>,
+    data: web::Json<ActionVacationRequest>,
+) -> impl Responder {
+    let user_id = user.id as u32;
+    let request_id = request_id_path.into_inner();
+    let action_data = data.into_inner();
+
+    if !matches!(
+        action_data.status,
+        VacationRequestStatus::Approved | VacationRequestStatus::Rejected
+    ) {
+        return HttpResponse::BadRequest().body("O estado tem de ser APPROVED ou REJECTED.");
+    }
+
+    let request_details = match VacationRequest::get_by_id(&state.db.pool, request_id).await {
+        Ok(Some(request)) => request,
+        Ok(None) => {
+            return HttpResponse::NotFound()
+                .body(format!("Pedido #{} não encontrado.", request_id));
+        }
+        Err(e) => {
+            log::error!("Error fetching vacation request {}: {}", request_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    if request_details.user_id == user_id {
+        return HttpResponse::Forbidden().body("Não pode decidir os seus próprios pedidos.");
+    }
+    if request_details.status != VacationRequestStatus::Pending {
+        return HttpResponse::Conflict().body("O pedido já foi decidido.");
+    }
+
+    let current_step = match VacationRequestApproval::get_open(&state.db.pool, request_id).await {
+        Ok(Some(current_step)) => current_step,
+        Ok(None) => return HttpResponse::Forbidden().finish(),
+        Err(e) => {
+            log::error!(
+                "Error fetching approval step of vacation request {}: {}",
+                request_id,
+                e
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let today = chrono::Utc::now().date_naive();
+    match current_step
+        .can_be_decided_by(&state.db.pool, user_id, request_details.user_id, today)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Forbidden().finish(),
+        Err(e) => {
+            log::error!(
+                "Error checking who can action vacation request {}: {}",
+                request_id,
+                e
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    action_request(
+        &state,
+        user_id,
+        false,
+        request_details,
+        Some(current_step),
+        action_data,
+    )
+    .await
+}
+
+/// The approval trail of one of the user's own requests.
+pub async fn get_request_approvals(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+    req: HttpRequest,
+    request_id_path: web::Path<u32>,
+) -> impl Responder {
+    let user_id = user.id as u32;
+    let request_id = request_id_path.into_inner();
+
+    match VacationRequest::get_by_id(&state.db.pool, request_id).await {
+        Ok(Some(request)) if request.user_id == user_id => {}
+        Ok(Some(_)) => return HttpResponse::Forbidden().finish(),
+        Ok(None) => {
+            return HttpResponse::NotFound()
+                .body(format!("Pedido #{} não encontrado.", request_id));
+        }
+        Err(e) => {
+            log::error!("Error fetching vacation request {}: {}", request_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    match VacationRequestApproval::get_for_request(&state.db.pool, request_id).await {
+        Ok(approvals) => json_response_with_etag(&approvals, &req),
+        Err(e) => {
+            log::error!("Error fetching approvals of request {}: {}", request_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn get_my_delegations(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+    req: HttpRequest,
+) -> impl Responder {
+    let user_id = user.id as u32;
+
+    match ApprovalDelegation::get_for_approver(&state.db.pool, user_id).await {
+        Ok(delegations) => json_response_with_etag(&delegations, &req),
+        Err(e) => {
+            log::error!("Error fetching delegations of user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Hands the user's steps to a delegate while they are away. Steps already open move to the
+/// delegate when the scheduler next runs.
+pub async fn create_delegation(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let user_id = user.id as u32;
+
+    let Json(req): Json<ApprovalDelegationRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate(user_id) {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match ApprovalDelegation::create(&state.db.pool, user_id, &req).await {
+        Ok(id) => HttpResponse::Created().json(serde_json::json!({ "id": id })),
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            HttpResponse::BadRequest().body("Utilizador não encontrado")
+        }
+        Err(e) => {
+            log::error!("Error creating delegation of user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn delete_delegation(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let user_id = user.id as u32;
+    let delegation_id = path.into_inner();
+
+    match ApprovalDelegation::delete(&state.db.pool, user_id, delegation_id).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Delegação não encontrada"),
+        Err(e) => {
+            log::error!("Error deleting delegation {}: {}", delegation_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// The approval chain of a role's members and its priority, without steps when any admin
+/// approves their requests.
+pub async fn get_approval_chain(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let role_id = path.into_inner();
+
+    match ApprovalChain::get_for_role(&state.db.pool, role_id).await {
+        Ok(chain) => json_response_with_etag(&chain, &req),
+        Err(e) => {
+            log::error!("Error fetching approval chain of role {}: {}", role_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Replaces the chain of a role. Requests already in the old chain finish the steps they are
+/// on and then follow the new one.
+pub async fn update_approval_chain(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let role_id = path.into_inner();
+
+    let Json(req): Json<ApprovalChainRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match ApprovalStep::replace_for_role(&state.db.pool, role_id, &req).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            HttpResponse::BadRequest().body("Função não encontrada")
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            HttpResponse::Conflict().body("Já existe outra cadeia com essa prioridade.")
+        }
+        Err(e) => {
+            log::error!("Error updating approval chain of role {}: {}", role_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
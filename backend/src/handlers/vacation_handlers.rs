@@ -1,4 +1,3 @@
-use actix_session::Session;
 use actix_web::{HttpResponse, Responder, web};
 use chrono::Datelike; // For year extraction
 use serde::Serialize;
@@ -6,15 +5,30 @@ use sqlx::Row; // Added for Row::get method
 
 use crate::{
     State,
-    auth::validate_session,
+    auth::AuthenticatedUser,
     models::{
+        absence_type::{
+            ABSENCE_DOCUMENTS_DIR, ABSENCE_TYPE_VACATION, AbsenceDocument, AbsenceType,
+        },
+        coverage_rule::{CoverageRule, violations_message},
         role::Role, // Added for shared calendar logic
-        user::User, // Assuming User model exists to fetch vacation_days_current_year
-        vacation_request::{CreateVacationRequest, VacationRequest, VacationRequestStatus},
+        vacation_request::{
+            CreateVacationRequest, VacationDayPart, VacationRequest, VacationRequestStatus,
+        },
         notification::Notification,
+        vacation_ledger::{
+            LEDGER_YEARS, VacationBalance, VacationLedgerEntry, VacationLedgerQuery,
+        },
+        work_schedule::{WorkingTime, balance_unit_str},
+    },
+    services::vacation_approval_service::start_chain,
+    utils::{
+        forms::FilesFormRequest,
+        json_utils::{json_response, json_response_with_etag},
+        working_days::DayPart,
     },
-    utils::json_utils::{json_response, json_response_with_etag},
 };
+use actix_multipart::form::MultipartForm;
 use actix_web::HttpRequest;
 use serde::Deserialize;
 use std::collections::HashMap;
@@ -34,13 +48,10 @@ pub struct SharedCalendarQuery {
 // Handler for a user to submit a new vacation request
 pub async fn submit_vacation_request(
     state: web::Data<State>,
-    session: Session,
+    user: AuthenticatedUser,
     data: web::Json<CreateVacationRequest>,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id as u32;
 
     let request_data = data.into_inner();
 
@@ -49,70 +60,135 @@ pub async fn submit_vacation_request(
         return HttpResponse::BadRequest()
             .body("A data de início não pode ser posterior à data de fim.");
     }
+    if let Err(message) = request_data.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+    let part = request_data.part();
     // Optional: Check if dates are in the past (allow for some flexibility or be strict)
     let today = chrono::Utc::now().date_naive();
     if request_data.start_date < today {
         // return HttpResponse::BadRequest().body("Não pode solicitar férias para datas passadas.");
     }
 
-    let requested_days_count =
-        crate::utils::working_days::count_working_days(
-            request_data.start_date,
-            request_data.end_date,
-            request_data.start_date.year(),
-        );
-    if requested_days_count <= 0 {
-        return HttpResponse::BadRequest().body("Número de dias de férias inválido.");
-    }
+    // Only active types can be requested
+    let absence_type =
+        match AbsenceType::get_by_id(&state.db.pool, request_data.absence_type_id).await {
+            Ok(Some(absence_type)) if absence_type.active => absence_type,
+            Ok(_) => return HttpResponse::BadRequest().body("Tipo de ausência inválido."),
+            Err(e) => {
+                log::error!(
+                    "Error fetching absence type {}: {}",
+                    request_data.absence_type_id,
+                    e
+                );
+                return HttpResponse::InternalServerError().finish();
+            }
+        };
 
     // --- Check Remaining Vacation Days ---
-    let user_details = match sqlx::query_as!(
-        User, // Assuming User model has vacation_days_current_year or a similar field
-        "SELECT id, username, email, vacation_days_current_year FROM users WHERE id = ?", // Adjust query as needed
-        user_id
+    // Counted in the user's scheduled working time, without the holidays of their clinic
+    let working_time = match WorkingTime::load(
+        &state.db.pool,
+        user_id,
+        request_data.start_date,
+        request_data.end_date,
     )
-    .fetch_one(&state.db.pool)
     .await
     {
-        Ok(user) => user,
+        Ok(working_time) => working_time,
         Err(sqlx::Error::RowNotFound) => {
             return HttpResponse::NotFound().body("Utilizador não encontrado.");
         }
         Err(e) => {
-            log::error!("Error fetching user details for vacation check: {}", e);
+            log::error!("Error loading working time for vacation request: {}", e);
             return HttpResponse::InternalServerError().finish();
         }
     };
 
-    let available_days = user_details.vacation_days_current_year.unwrap_or(0); // Default to 0 if null
+    let requested_days_count =
+        working_time.count_part(request_data.start_date, request_data.end_date, part);
+    if requested_days_count <= 0.0 {
+        return HttpResponse::BadRequest().body("Número de dias de férias inválido.");
+    }
 
-    // Calculate already approved days for the current year
-    let current_year = today.year();
-    let approved_days_count = match VacationRequest::count_approved_vacation_days_for_year(
-        &state.db.pool,
-        user_id,
-        current_year,
-    )
-    .await
+    if let Some(max_days) = absence_type.max_days_per_request
+        && requested_days_count > max_days as f64
     {
-        Ok(count) => count,
-        Err(e) => {
-            log::error!(
-                "Error counting approved vacation days for user {}: {}",
-                user_id,
-                e
-            );
-            return HttpResponse::InternalServerError().finish();
-        }
-    };
-
-    if approved_days_count + requested_days_count > available_days as i64 {
         return HttpResponse::BadRequest().body(format!(
-            "Não tem dias de férias suficientes. Disponíveis: {}, Solicitados: {}, Já aprovados: {}.",
-            available_days, requested_days_count, approved_days_count
+            "Um pedido de {} não pode ter mais de {} dias.",
+            absence_type.label(),
+            max_days
         ));
     }
 
+    if absence_type.counts_against_vacation {
+        // Each year the request falls in must have enough balance in the ledger, on top of
+        // what was already approved in it
+        match VacationLedgerEntry::find_shortfall(
+            &state.db.pool,
+            user_id,
+            request_data.start_date,
+            request_data.end_date,
+            part,
+            &working_time,
+        )
+        .await
+        {
+            Ok(None) => {}
+            Ok(Some(shortfall)) => {
+                let mut message = format!(
+                    "Não tem dias de férias suficientes em {}. Disponíveis: {}, Solicitados: {}, Já aprovados: {}.",
+                    shortfall.year, shortfall.available, shortfall.requested, shortfall.approved
+                );
+                if let Some(available_from) = shortfall.available_from {
+                    message.push_str(&format!(
+                        " Os restantes dias só podem ser gozados a partir de {}.",
+                        available_from.format("%d/%m/%Y")
+                    ));
+                }
+                return HttpResponse::BadRequest().body(message);
+            }
+            Err(e) => {
+                log::error!("Error checking vacation balance of user {}: {}", user_id, e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    } else {
+        // Other absences have their own yearly limit, if any
+        match absence_type
+            .find_yearly_limit_excess(
+                &state.db.pool,
+                user_id,
+                request_data.start_date,
+                request_data.end_date,
+                part,
+                &working_time,
+            )
+            .await
+        {
+            Ok(None) => {}
+            Ok(Some(excess)) => {
+                return HttpResponse::BadRequest().body(format!(
+                    "O limite anual de {} em {} é de {} dias. Já marcados: {}, Solicitados: {}.",
+                    absence_type.label(),
+                    excess.year,
+                    excess.limit,
+                    excess.booked,
+                    excess.requested
+                ));
+            }
+            Err(e) => {
+                log::error!(
+                    "Error checking yearly limit of absence type {} for user {}: {}",
+                    absence_type.id,
+                    user_id,
+                    e
+                );
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
     // --- Initial Conflict Check (Same User) ---
     // Check for overlapping requests for the *same user*.
     let existing_requests = match VacationRequest::get_by_user_id(&state.db.pool, user_id).await {
@@ -132,8 +208,11 @@ pub async fn submit_vacation_request(
         if req.status == VacationRequestStatus::Rejected {
             continue;
         }
-        // Check for overlap: (StartA <= EndB) and (EndA >= StartB)
-        if request_data.start_date <= req.end_date && request_data.end_date >= req.start_date {
+        // Check for overlap: (StartA <= EndB) and (EndA >= StartB), on the same part of the day
+        if request_data.start_date <= req.end_date
+            && request_data.end_date >= req.start_date
+            && req.part().overlaps(part)
+        {
             return HttpResponse::Conflict().body(format!(
                 "Já tem um pedido de férias ({:?}) que entra em conflito com as datas solicitadas.",
                 req.status
@@ -142,6 +221,7 @@ pub async fn submit_vacation_request(
     }
     
     // --- Advanced Conflict Check (Colleagues in Shared Holiday Role) ---
+    // Absences that need no approval, like sick leave, cannot be moved and are not checked
     // Get all users who share holiday roles with the current user
     let colleague_user_ids = match Role::get_colleague_user_ids_in_shared_holiday_roles(&state.db.pool, user_id).await {
         Ok(ids) => ids,
@@ -154,11 +234,11 @@ pub async fn submit_vacation_request(
         }
     };
     
-    if !colleague_user_ids.is_empty() {
+    if absence_type.requires_approval && !colleague_user_ids.is_empty() {
         // Get both pending and approved vacation requests from colleagues
         let requests_query = format!(
             r#"
-            SELECT vr.start_date, vr.end_date, vr.status, u.username
+            SELECT vr.start_date, vr.end_date, vr.day_part, vr.minutes, vr.status, u.username
             FROM vacation_requests vr
             JOIN users u ON vr.user_id = u.id
             WHERE vr.user_id IN ({})
@@ -200,8 +280,23 @@ pub async fn submit_vacation_request(
             }
         };
         
-        if !colleague_requests.is_empty() {
-            let first_conflict = colleague_requests.first().unwrap();
+        // A colleague's morning leaves the afternoon free
+        let mut first_conflict = None;
+        for row in &colleague_requests {
+            let day_part: VacationDayPart = match row.try_get("day_part") {
+                Ok(day_part) => day_part,
+                Err(e) => {
+                    log::error!("Error reading colleague vacation request: {}", e);
+                    return HttpResponse::InternalServerError().finish();
+                }
+            };
+            let minutes: Option<u16> = row.try_get("minutes").unwrap_or_default();
+            if day_part.part(minutes).overlaps(part) {
+                first_conflict = Some(row);
+                break;
+            }
+        }
+        if let Some(first_conflict) = first_conflict {
             let status: String = first_conflict.try_get("status").unwrap_or_default();
             let username: String = first_conflict.try_get("username").unwrap_or_default();
             
@@ -219,8 +314,54 @@ pub async fn submit_vacation_request(
     }
     // --- End Conflict Check ---
 
+    // Coverage rules of the user's roles, on top of the approved absences of the other members
+    if absence_type.requires_approval {
+        match CoverageRule::find_violations(
+            &state.db.pool,
+            user_id,
+            request_data.start_date,
+            request_data.end_date,
+            part,
+            &working_time,
+        )
+        .await
+        {
+            Ok(violations) if violations.is_empty() => {}
+            Ok(violations) => {
+                return HttpResponse::Conflict().json(serde_json::json!({
+                    "message": violations_message(&violations),
+                    "violations": violations,
+                }));
+            }
+            Err(e) => {
+                log::error!("Error checking coverage rules for user {}: {}", user_id, e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
     match VacationRequest::create(&state.db.pool, user_id, &request_data).await {
         Ok(request_id) => {
+            // Types that need neither approval nor a document are approved right away
+            if absence_type.is_approved_when_made()
+                && let Err(e) =
+                    VacationRequest::approve_automatically(&state.db.pool, request_id).await
+            {
+                log::error!("Error approving absence request {}: {}", request_id, e);
+                return HttpResponse::InternalServerError().finish();
+            }
+
+            // Requests of users whose role has an approval chain go to its first approver
+            if absence_type.requires_approval
+                && let Err(e) = start_chain(&state.db.pool, request_id, user_id, today).await
+            {
+                log::error!(
+                    "Error starting approval chain of vacation request {}: {}",
+                    request_id,
+                    e
+                );
+            }
+
             // Get user name for the notification
             let user_name = match sqlx::query!("SELECT username FROM users WHERE id = ?", user_id)
                 .fetch_optional(&state.db.pool)
@@ -238,8 +379,8 @@ pub async fn submit_vacation_request(
                 Ok(admin_ids) => {
                     if !admin_ids.is_empty() {
                         let admin_message = format!(
-                            "{} solicitou férias ({} a {}).",
-                            user_name, start_date_fmt, end_date_fmt
+                            "{} solicitou {} ({} a {}).",
+                            user_name, absence_type.label(), start_date_fmt, end_date_fmt
                         );
 
                         // Send notification to each admin
@@ -284,10 +425,18 @@ pub async fn submit_vacation_request(
             match Role::get_colleague_user_ids_in_shared_holiday_roles(&state.db.pool, user_id).await {
                 Ok(colleague_ids) => {
                     if !colleague_ids.is_empty() {
-                        let colleague_message = format!(
-                            "O seu colega {} solicitou férias ({} a {}).",
-                            user_name, start_date_fmt, end_date_fmt
-                        );
+                        // Colleagues are not told the reason of other absences
+                        let colleague_message = if absence_type.id == ABSENCE_TYPE_VACATION {
+                            format!(
+                                "O seu colega {} solicitou férias ({} a {}).",
+                                user_name, start_date_fmt, end_date_fmt
+                            )
+                        } else {
+                            format!(
+                                "O seu colega {} vai estar ausente ({} a {}).",
+                                user_name, start_date_fmt, end_date_fmt
+                            )
+                        };
                         
                         // Send notification to each colleague (except the requesting user)
                         for colleague_id in colleague_ids {
@@ -344,13 +493,10 @@ pub async fn submit_vacation_request(
 // Handler for a user to fetch their own vacation requests
 pub async fn get_my_vacation_requests(
     state: web::Data<State>, 
-    session: Session,
+    user: AuthenticatedUser,
     query: web::Query<HashMap<String, String>>,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id as u32;
 
     // Get year from query parameters or use current year as default
     let year_filter = match query.get("year") {
@@ -391,22 +537,23 @@ pub async fn get_my_vacation_requests(
 
 #[derive(Serialize)]
 struct RemainingVacationDaysResponse {
-    total_allocated_days: u16,
-    approved_days_taken: i64,
-    pending_days_requested: i64, // Also good to show pending
-    remaining_days: i64,
+    total_allocated_days: i64,
+    approved_days_taken: f64,
+    pending_days_requested: f64, // Also good to show pending
+    remaining_days: f64,
+    /// DAYS, or HOURS when the numbers above are hours
+    balance_unit: &'static str,
+    /// What total_allocated_days is made of
+    ledger: VacationBalance,
 }
 
 // Handler for a user to fetch their remaining vacation days
 pub async fn get_my_remaining_vacation_days(
     state: web::Data<State>,
-    session: Session,
+    user: AuthenticatedUser,
     query: web::Query<HashMap<String, String>>,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id as u32;
 
     // Get year from query parameters or use current year as default
     let year = match query.get("year") {
@@ -419,26 +566,23 @@ pub async fn get_my_remaining_vacation_days(
         },
         None => chrono::Utc::now().year(),
     };
+    if !LEDGER_YEARS.contains(&year) {
+        return HttpResponse::BadRequest().body("Ano inválido");
+    }
 
-    let user_details = match sqlx::query!(
-        "SELECT vacation_days_current_year FROM users WHERE id = ?",
-        user_id
-    )
-    .fetch_one(&state.db.pool)
-    .await
-    {
-        Ok(user) => user,
-        Err(sqlx::Error::RowNotFound) => {
-            return HttpResponse::NotFound().body("Utilizador não encontrado.");
-        }
+    let ledger = match VacationLedgerEntry::balance(&state.db.pool, user_id, year).await {
+        Ok(balance) => balance,
         Err(e) => {
-            log::error!("Error fetching user's allocated vacation days: {}", e);
+            log::error!(
+                "Error fetching vacation balance of user {} for {}: {}",
+                user_id,
+                year,
+                e
+            );
             return HttpResponse::InternalServerError().finish();
         }
     };
 
-    let total_allocated_days = user_details.vacation_days_current_year.unwrap_or(0);
-
     let approved_days_taken = match VacationRequest::count_approved_vacation_days_for_year(
         &state.db.pool,
         user_id,
@@ -470,48 +614,91 @@ pub async fn get_my_remaining_vacation_days(
         }
     };
 
-    let mut pending_days_requested: i64 = 0;
+    // Only absences taken from the vacation ledger are pending on it
+    let vacation_type_ids: Vec<u32> = match AbsenceType::get_all(&state.db.pool).await {
+        Ok(absence_types) => absence_types
+            .into_iter()
+            .filter(|absence_type| absence_type.counts_against_vacation)
+            .map(|absence_type| absence_type.id)
+            .collect(),
+        Err(e) => {
+            log::error!("Error fetching absence types: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let year_start = chrono::NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+    let year_end = chrono::NaiveDate::from_ymd_opt(year, 12, 31).unwrap();
+    let working_time = match WorkingTime::load(&state.db.pool, user_id, year_start, year_end).await
+    {
+        Ok(working_time) => working_time,
+        Err(e) => {
+            log::error!("Error loading working time of user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut pending_days_requested: f64 = 0.0;
     for req in all_user_requests {
-        if req.status == VacationRequestStatus::Pending {
+        if req.status == VacationRequestStatus::Pending
+            && vacation_type_ids.contains(&req.absence_type_id)
+        {
             if req.start_date.year() == year || req.end_date.year() == year {
-                let start_date = std::cmp::max(
-                    req.start_date,
-                    chrono::NaiveDate::from_ymd_opt(year, 1, 1).unwrap(),
-                );
-                let end_date = std::cmp::min(
-                    req.end_date,
-                    chrono::NaiveDate::from_ymd_opt(year, 12, 31).unwrap(),
-                );
+                let start_date = std::cmp::max(req.start_date, year_start);
+                let end_date = std::cmp::min(req.end_date, year_end);
                 if start_date <= end_date {
                     pending_days_requested +=
-                        crate::utils::working_days::count_working_days(start_date, end_date, year);
+                        working_time.count_part(start_date, end_date, req.part());
                 }
             }
         }
     }
 
-    let remaining_days = total_allocated_days as i64 - approved_days_taken - pending_days_requested;
+    let remaining_days = ledger.total as f64 - approved_days_taken - pending_days_requested;
 
     json_response(&RemainingVacationDaysResponse {
-        total_allocated_days: total_allocated_days as u16,
+        total_allocated_days: ledger.total,
         approved_days_taken,
         pending_days_requested,
         remaining_days,
+        balance_unit: balance_unit_str(working_time.unit),
+        ledger,
     })
 }
 
+// Handler for the entries of the user's own vacation ledger in a year
+pub async fn get_my_vacation_ledger(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+    req: HttpRequest,
+    query: web::Query<VacationLedgerQuery>,
+) -> impl Responder {
+    let user_id = user.id as u32;
+    let year = query.year.unwrap_or_else(|| chrono::Utc::now().year());
+
+    match VacationLedgerEntry::ledger(&state.db.pool, user_id, year).await {
+        Ok(ledger) => json_response_with_etag(&ledger, &req),
+        Err(e) => {
+            log::error!(
+                "Error fetching vacation ledger of user {} for {}: {}",
+                user_id,
+                year,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
 // Add handlers to the end of src/handlers/vacation_handlers.rs
 
 // Handler for canceling a pending vacation request
 pub async fn cancel_vacation_request(
     state: web::Data<State>,
-    session: Session,
+    user: AuthenticatedUser,
     request_id_path: web::Path<u32>
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id as u32;
 
     let request_id = request_id_path.into_inner();
 
@@ -694,13 +881,10 @@ pub async fn cancel_vacation_request(
 /// Changes status from APPROVED to CANCELLATION_REQUESTED. Admin must approve.
 pub async fn request_vacation_cancellation(
     state: web::Data<State>,
-    session: Session,
+    user: AuthenticatedUser,
     request_id_path: web::Path<u32>,
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id as u32;
 
     let request_id = request_id_path.into_inner();
 
@@ -797,20 +981,22 @@ struct SharedCalendarResponse {
     start_date: chrono::NaiveDate,
     end_date: chrono::NaiveDate,
     status: String,
+    day_part: VacationDayPart,
+    minutes: Option<u16>,
 }
 
 pub async fn get_shared_calendar_vacations(
     state: web::Data<State>,
-    session: Session,
+    user: AuthenticatedUser,
     query: web::Query<SharedCalendarQuery>,
     req: HttpRequest, // Added for ETag
 ) -> impl Responder {
-    let user_id = match validate_session(&session) {
-        Ok(id) => id as u32,
-        Err(resp) => return resp,
-    };
+    let user_id = user.id as u32;
 
     let year = query.year;
+    if !LEDGER_YEARS.contains(&year) {
+        return HttpResponse::BadRequest().body("Ano inválido");
+    }
 
     match Role::get_colleague_user_ids_in_shared_holiday_roles(&state.db.pool, user_id).await {
         Ok(colleague_user_ids) => {
@@ -831,13 +1017,20 @@ pub async fn get_shared_calendar_vacations(
             {
                 Ok(dates) => {
                     // Transform the dates into the response format
-                    let response = dates.into_iter()
-                        .map(|(start_date, end_date, status)| 
-                            SharedCalendarResponse {
+                    let response = dates
+                        .into_iter()
+                        .map(
+                            |(start_date, end_date, status, part)| SharedCalendarResponse {
                                 start_date,
                                 end_date,
                                 status,
-                            })
+                                day_part: VacationDayPart::from(part),
+                                minutes: match part {
+                                    DayPart::Minutes(minutes) => Some(minutes),
+                                    _ => None,
+                                },
+                            },
+                        )
                         .collect::<Vec<_>>();
                     
                     json_response_with_etag(&response, &req)
@@ -854,3 +1047,235 @@ pub async fn get_shared_calendar_vacations(
         }
     }
 }
+
+/// Writes each file to `dir`, failing rather than overwriting one that exists. When one fails,
+/// the ones written before it are removed.
+fn write_new_files(dir: &str, files: &[(String, Vec<u8>)]) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for (index, (path, data)) in files.iter().enumerate() {
+        let result = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .and_then(|mut file| std::io::Write::write_all(&mut file, data));
+        if let Err(e) = result {
+            if e.kind() != std::io::ErrorKind::AlreadyExists {
+                let _ = std::fs::remove_file(path);
+            }
+            for (written, _) in &files[..index] {
+                let _ = std::fs::remove_file(written);
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+// Handler for a user to upload the documents supporting one of their absences, like a sick note
+pub async fn upload_absence_documents(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+    request_id_path: web::Path<u32>,
+    MultipartForm(form): MultipartForm<FilesFormRequest>,
+) -> impl Responder {
+    let user_id = user.id as u32;
+    let request_id = request_id_path.into_inner();
+
+    let request = match VacationRequest::get_by_id(&state.db.pool, request_id).await {
+        Ok(Some(request)) => request,
+        Ok(None) => {
+            return HttpResponse::NotFound()
+                .body(format!("Pedido #{} não encontrado.", request_id));
+        }
+        Err(e) => {
+            log::error!("Error fetching vacation request {}: {}", request_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if request.user_id != user_id {
+        return HttpResponse::Forbidden()
+            .body("Não tem permissão para juntar documentos a este pedido.");
+    }
+    if matches!(
+        request.status,
+        VacationRequestStatus::Rejected | VacationRequestStatus::Cancelled
+    ) {
+        return HttpResponse::Conflict()
+            .body("Não pode juntar documentos a um pedido rejeitado ou cancelado.");
+    }
+    if form.files.is_empty() {
+        return HttpResponse::BadRequest().body("Nenhum documento enviado.");
+    }
+
+    let existing = match AbsenceDocument::get_for_request(&state.db.pool, request_id).await {
+        Ok(documents) => documents,
+        Err(e) => {
+            log::error!("Error fetching documents of request {}: {}", request_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let base_path = format!("{}/{}", ABSENCE_DOCUMENTS_DIR, request_id);
+    let mut documents: Vec<(String, String)> = Vec::with_capacity(form.files.len());
+    let mut writes = Vec::with_capacity(form.files.len());
+    for file in form.files {
+        // Only the name, so that it cannot point outside the request's directory
+        let Some(file_name) = std::path::Path::new(&file.file_name)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_string)
+        else {
+            return HttpResponse::BadRequest().body("Nome de ficheiro inválido.");
+        };
+        let taken = existing
+            .iter()
+            .map(|document| &document.file_name)
+            .chain(documents.iter().map(|(name, _)| name))
+            .any(|name| *name == file_name);
+        if taken {
+            return HttpResponse::Conflict()
+                .body(format!("Já existe um documento com o nome {}.", file_name));
+        }
+
+        let file_path = format!("{}/{}", base_path, file_name);
+        writes.push((file_path.clone(), file.data));
+        documents.push((file_name, file_path));
+    }
+
+    // Every file is on disk before any is recorded
+    match tokio::task::spawn_blocking(move || write_new_files(&base_path, &writes)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return HttpResponse::Conflict().body("Já existe um documento com esse nome.");
+        }
+        Ok(Err(e)) => {
+            log::error!("Error writing documents of request {}: {}", request_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+        Err(e) => {
+            log::error!("Error writing documents of request {}: {}", request_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let mut document_ids = Vec::new();
+    for (file_name, file_path) in &documents {
+        match AbsenceDocument::add(&state.db.pool, request_id, file_name, file_path, user_id).await
+        {
+            Ok(id) => document_ids.push(id),
+            Err(e) => {
+                log::error!("Error adding document to request {}: {}", request_id, e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
+    // Absences that only wait for their document, like sick leave, are approved with it
+    if request.status == VacationRequestStatus::Pending {
+        match AbsenceType::get_by_id(&state.db.pool, request.absence_type_id).await {
+            Ok(Some(absence_type)) if !absence_type.requires_approval => {
+                if let Err(e) =
+                    VacationRequest::approve_automatically(&state.db.pool, request_id).await
+                {
+                    log::error!("Error approving absence request {}: {}", request_id, e);
+                    return HttpResponse::InternalServerError().finish();
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log::error!(
+                    "Error fetching absence type {}: {}",
+                    request.absence_type_id,
+                    e
+                );
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
+    HttpResponse::Created().json(serde_json::json!({ "ids": document_ids }))
+}
+
+// Handler for a user to list the documents of one of their absences
+pub async fn get_absence_documents(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+    req: HttpRequest,
+    request_id_path: web::Path<u32>,
+) -> impl Responder {
+    let user_id = user.id as u32;
+    let request_id = request_id_path.into_inner();
+
+    match VacationRequest::get_by_id(&state.db.pool, request_id).await {
+        Ok(Some(request)) if request.user_id == user_id => {}
+        Ok(Some(_)) => return HttpResponse::Forbidden().finish(),
+        Ok(None) => {
+            return HttpResponse::NotFound()
+                .body(format!("Pedido #{} não encontrado.", request_id));
+        }
+        Err(e) => {
+            log::error!("Error fetching vacation request {}: {}", request_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    match AbsenceDocument::get_for_request(&state.db.pool, request_id).await {
+        Ok(documents) => json_response_with_etag(&documents, &req),
+        Err(e) => {
+            log::error!("Error fetching documents of request {}: {}", request_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+// Handler for a user to download a document of one of their absences
+pub async fn download_absence_document(
+    state: web::Data<State>,
+    user: AuthenticatedUser,
+    req: HttpRequest,
+    path: web::Path<(u32, u32)>,
+) -> impl Responder {
+    let user_id = user.id as u32;
+    let (request_id, document_id) = path.into_inner();
+
+    match VacationRequest::get_by_id(&state.db.pool, request_id).await {
+        Ok(Some(request)) if request.user_id == user_id => {}
+        Ok(Some(_)) => return HttpResponse::Forbidden().finish(),
+        Ok(None) => {
+            return HttpResponse::NotFound()
+                .body(format!("Pedido #{} não encontrado.", request_id));
+        }
+        Err(e) => {
+            log::error!("Error fetching vacation request {}: {}", request_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    absence_document_response(&state, &req, request_id, document_id).await
+}
+
+/// The file of a document of the request, once the caller checked who may see it.
+pub(crate) async fn absence_document_response(
+    state: &State,
+    req: &HttpRequest,
+    request_id: u32,
+    document_id: u32,
+) -> HttpResponse {
+    let document = match AbsenceDocument::get_by_id(&state.db.pool, request_id, document_id).await {
+        Ok(Some(document)) => document,
+        Ok(None) => return HttpResponse::NotFound().body("Documento não encontrado."),
+        Err(e) => {
+            log::error!("Error fetching document {}: {}", document_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match actix_files::NamedFile::open_async(&document.file_path).await {
+        Ok(file) => file.into_response(req),
+        Err(e) => {
+            log::error!("Error opening document {}: {}", document.file_path, e);
+            HttpResponse::NotFound().body("Documento não encontrado.")
+        }
+    }
+}
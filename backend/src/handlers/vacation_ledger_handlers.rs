@@ -0,0 +1,236 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use chrono::{Datelike, Utc};
+
+use crate::{
+    State,
+    auth::AdminUser,
+    models::{
+        app_setting::{AppSetting, SETTING_CARRY_OVER_EXPIRY, SETTING_CARRY_OVER_MAX},
+        vacation_ledger::{
+            CarryOverSettings, VacationAdjustmentRequest, VacationEntitlementRequest,
+            VacationLedgerEntry, VacationLedgerQuery,
+        },
+    },
+    services::vacation_ledger_service,
+    utils::{
+        json_utils::{Json, json_response_with_etag},
+        vacation_balance::parse_expiry,
+    },
+};
+
+/// The ledger of a user in a year, the current one by default.
+pub async fn get_user_vacation_ledger(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+    path: web::Path<u32>,
+    query: web::Query<VacationLedgerQuery>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+    let year = query.year.unwrap_or_else(|| Utc::now().year());
+
+    match VacationLedgerEntry::ledger(&state.db.pool, user_id, year).await {
+        Ok(ledger) => json_response_with_etag(&ledger, &req),
+        Err(e) => {
+            log::error!(
+                "Error fetching vacation ledger of user {} for {}: {}",
+                user_id,
+                year,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Sets what a user is entitled to in a year, replacing the yearly default for that year.
+pub async fn set_user_vacation_entitlement(
+    state: web::Data<State>,
+    admin: AdminUser,
+    path: web::Path<u32>,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let user_id = path.into_inner();
+
+    let Json(req): Json<VacationEntitlementRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match VacationLedgerEntry::set_entitlement(
+        &state.db.pool,
+        user_id,
+        req.year,
+        req.amount,
+        Some(admin.id as u32),
+    )
+    .await
+    {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            HttpResponse::NotFound().body("Utilizador não encontrado")
+        }
+        Err(e) => {
+            log::error!(
+                "Error setting vacation entitlement of user {} for {}: {}",
+                user_id,
+                req.year,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Adds days (or hours) to a user's balance, or takes them away with a negative amount.
+pub async fn add_user_vacation_adjustment(
+    state: web::Data<State>,
+    admin: AdminUser,
+    path: web::Path<u32>,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let user_id = path.into_inner();
+
+    let Json(req): Json<VacationAdjustmentRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match VacationLedgerEntry::add_adjustment(&state.db.pool, user_id, &req, admin.id as u32).await
+    {
+        Ok(id) => HttpResponse::Created().json(serde_json::json!({ "id": id })),
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            HttpResponse::NotFound().body("Utilizador não encontrado")
+        }
+        Err(e) => {
+            log::error!(
+                "Error adding vacation adjustment for user {}: {}",
+                user_id,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// What the Código do Trabalho gives a user in a year, from their hire date and contracts.
+pub async fn get_statutory_entitlement(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+    query: web::Query<VacationLedgerQuery>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+    let year = query.year.unwrap_or_else(|| Utc::now().year());
+
+    match vacation_ledger_service::user_statutory_entitlement(&state.db.pool, user_id, year).await {
+        Ok(Some(entitlement)) => HttpResponse::Ok().json(entitlement),
+        Ok(None) => HttpResponse::BadRequest()
+            .body("O utilizador não tem regras legais de férias ou data de admissão."),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().body("Utilizador não encontrado"),
+        Err(e) => {
+            log::error!(
+                "Error computing statutory vacation entitlement of user {} for {}: {}",
+                user_id,
+                year,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Books the statutory entitlement of a year in a user's ledger, replacing one set by hand.
+pub async fn apply_statutory_entitlement(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+    query: web::Query<VacationLedgerQuery>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+    let year = query.year.unwrap_or_else(|| Utc::now().year());
+
+    match vacation_ledger_service::apply_statutory_entitlement(&state.db.pool, user_id, year, true)
+        .await
+    {
+        Ok(Some(entitlement)) => HttpResponse::Ok().json(entitlement),
+        Ok(None) => HttpResponse::BadRequest()
+            .body("O utilizador não tem regras legais de férias ou data de admissão."),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().body("Utilizador não encontrado"),
+        Err(e) => {
+            log::error!(
+                "Error applying statutory vacation entitlement of user {} for {}: {}",
+                user_id,
+                year,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn get_carry_over_settings(state: web::Data<State>, _admin: AdminUser) -> impl Responder {
+    match vacation_ledger_service::carry_over_settings(&state.db.pool).await {
+        Ok(((month, day), max)) => HttpResponse::Ok().json(CarryOverSettings {
+            expiry: format!("{:02}-{:02}", month, day),
+            max: max.map(|max| max.clamp(0, u16::MAX as i64) as u16),
+        }),
+        Err(e) => {
+            log::error!("Error fetching carry-over settings: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Changes when carried-over vacation expires and how much can be carried over. Applies from
+/// the next carry-over on.
+pub async fn set_carry_over_settings(
+    state: web::Data<State>,
+    admin: AdminUser,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<CarryOverSettings> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    let Some((month, day)) = parse_expiry(&req.expiry) else {
+        return HttpResponse::BadRequest().body(format!(
+            "Data de expiração inválida: {} (use MM-DD)",
+            req.expiry
+        ));
+    };
+
+    let expiry = format!("{:02}-{:02}", month, day);
+    // Empty for no limit
+    let max = req.max.map(|max| max.to_string()).unwrap_or_default();
+
+    for (key, value) in [
+        (SETTING_CARRY_OVER_EXPIRY, &expiry),
+        (SETTING_CARRY_OVER_MAX, &max),
+    ] {
+        if let Err(e) = AppSetting::set(&state.db.pool, key, value, admin.id as u32).await {
+            log::error!("Error storing setting {}: {}", key, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    HttpResponse::Ok().json(CarryOverSettings {
+        expiry,
+        max: req.max,
+    })
+}
@@ -0,0 +1,150 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use chrono::{Datelike, Utc};
+
+use crate::{
+    State,
+    auth::AdminUser,
+    models::work_contract::{WorkContract, WorkContractRequest},
+    services::vacation_ledger_service,
+    utils::json_utils::{Json, json_response_with_etag},
+};
+
+/// Books this year's statutory entitlement again after the user's contracts changed. Admin
+/// overrides are kept.
+async fn reapply_entitlement(state: &State, user_id: u32) -> Result<(), sqlx::Error> {
+    vacation_ledger_service::apply_statutory_entitlement(
+        &state.db.pool,
+        user_id,
+        Utc::now().year(),
+        false,
+    )
+    .await
+    .map(|_| ())
+}
+
+pub async fn get_user_work_contracts(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+
+    match WorkContract::get_for_user(&state.db.pool, user_id).await {
+        Ok(contracts) => json_response_with_etag(&contracts, &req),
+        Err(e) => {
+            log::error!("Error fetching work contracts of user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn create_work_contract(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let user_id = path.into_inner();
+
+    let Json(req): Json<WorkContractRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    let id = match WorkContract::create(&state.db.pool, user_id, &req).await {
+        Ok(id) => id,
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            return HttpResponse::NotFound().body("Utilizador não encontrado");
+        }
+        Err(e) => {
+            log::error!("Error creating work contract for user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Err(e) = reapply_entitlement(&state, user_id).await {
+        log::error!(
+            "Error applying statutory vacation entitlement of user {}: {}",
+            user_id,
+            e
+        );
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Created().json(serde_json::json!({ "id": id }))
+}
+
+pub async fn update_work_contract(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let contract_id = path.into_inner();
+
+    let Json(req): Json<WorkContractRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    let user_id = match WorkContract::update(&state.db.pool, contract_id, &req).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return HttpResponse::NotFound().body("Contrato não encontrado"),
+        Err(e) => {
+            log::error!("Error updating work contract {}: {}", contract_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Err(e) = reapply_entitlement(&state, user_id).await {
+        log::error!(
+            "Error applying statutory vacation entitlement of user {}: {}",
+            user_id,
+            e
+        );
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+pub async fn delete_work_contract(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let contract_id = path.into_inner();
+
+    let user_id = match WorkContract::delete(&state.db.pool, contract_id).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return HttpResponse::NotFound().body("Contrato não encontrado"),
+        Err(e) => {
+            log::error!("Error deleting work contract {}: {}", contract_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Err(e) = reapply_entitlement(&state, user_id).await {
+        log::error!(
+            "Error applying statutory vacation entitlement of user {}: {}",
+            user_id,
+            e
+        );
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().finish()
+}
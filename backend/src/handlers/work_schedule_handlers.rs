@@ -0,0 +1,176 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+
+use crate::{
+    State,
+    auth::AdminUser,
+    models::work_schedule::{
+        UserWorkSchedule, UserWorkScheduleRequest, WorkSchedule, WorkScheduleRequest,
+    },
+    utils::json_utils::{Json, json_response_with_etag},
+};
+
+pub async fn get_work_schedules(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+) -> impl Responder {
+    match WorkSchedule::get_all(&state.db.pool).await {
+        Ok(schedules) => json_response_with_etag(&schedules, &req),
+        Err(e) => {
+            log::error!("Error fetching work schedules: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn create_work_schedule(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let Json(req): Json<WorkScheduleRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match WorkSchedule::create(&state.db.pool, &req).await {
+        Ok(id) => HttpResponse::Created().json(serde_json::json!({ "id": id })),
+        Err(e) => {
+            log::error!("Error creating work schedule: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn update_work_schedule(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let schedule_id = path.into_inner();
+
+    let Json(req): Json<WorkScheduleRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+    if let Err(message) = req.validate() {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    match WorkSchedule::update(&state.db.pool, schedule_id, &req).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Horário não encontrado"),
+        Err(e) => {
+            log::error!("Error updating work schedule {}: {}", schedule_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn delete_work_schedule(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let schedule_id = path.into_inner();
+
+    // Past vacations were counted with it
+    match WorkSchedule::is_assigned(&state.db.pool, schedule_id).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return HttpResponse::Conflict()
+                .body("O horário está atribuído a utilizadores e não pode ser eliminado");
+        }
+        Err(e) => {
+            log::error!("Error checking work schedule {}: {}", schedule_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    match WorkSchedule::delete(&state.db.pool, schedule_id).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Horário não encontrado"),
+        Err(e) => {
+            log::error!("Error deleting work schedule {}: {}", schedule_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn get_user_work_schedules(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    req: HttpRequest,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+
+    match UserWorkSchedule::get_for_user(&state.db.pool, user_id).await {
+        Ok(schedules) => json_response_with_etag(&schedules, &req),
+        Err(e) => {
+            log::error!("Error fetching work schedules of user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Gives a user a schedule from a date on. Vacations from that date are counted with it.
+pub async fn assign_user_work_schedule(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<u32>,
+    request_data: web::Bytes,
+) -> impl Responder {
+    let user_id = path.into_inner();
+
+    let Json(req): Json<UserWorkScheduleRequest> = match Json::from_bytes(&request_data) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Error parsing JSON: {}", e);
+            return HttpResponse::BadRequest().body("Invalid JSON");
+        }
+    };
+
+    match UserWorkSchedule::assign(&state.db.pool, user_id, &req).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            HttpResponse::NotFound().body("Utilizador ou horário não encontrado")
+        }
+        Err(e) => {
+            log::error!("Error assigning work schedule to user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn delete_user_work_schedule(
+    state: web::Data<State>,
+    _admin: AdminUser,
+    path: web::Path<(u32, u32)>,
+) -> impl Responder {
+    let (user_id, assignment_id) = path.into_inner();
+
+    match UserWorkSchedule::delete(&state.db.pool, user_id, assignment_id).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Horário não encontrado"),
+        Err(e) => {
+            log::error!(
+                "Error deleting work schedule {} of user {}: {}",
+                assignment_id,
+                user_id,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -7,14 +7,10 @@ use actix_web::{
     App,
     HttpServer,
     cookie::{Key, time::Duration as ActixDuration},
-    rt::spawn,
     web,
 };
 use actix_files::Files;
 use mimalloc::MiMalloc;
-use tokio::time::{Duration as TokioDuration, interval};
-
-use crate::services::notification_service::check_expiring_date_ranges;
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
@@ -31,6 +27,8 @@ mod services;
 mod utils;
 
 use db::Db;
+use services::{auth_provider::AuthProviders, email_service::Mailer, oidc_service::OidcClient};
+use utils::password_policy::PasswordPolicy;
 
 #[cfg(feature = "https")]
 use rustls::{ServerConfig, pki_types::PrivateKeyDer};
@@ -39,6 +37,11 @@ use rustls_pemfile::{certs, pkcs8_private_keys};
 
 struct State {
     db: Db,
+    mailer: Mailer,
+    password_policy: PasswordPolicy,
+    auth_providers: AuthProviders,
+    /// Single sign-on, when configured
+    oidc: Option<OidcClient>,
 }
 
 #[derive(argh::FromArgs)]
@@ -65,17 +68,52 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
-    let state = web::Data::new(State { db });
+    let mailer = match Mailer::from_env() {
+        Ok(mailer) => mailer,
+        Err(e) => {
+            eprintln!("Invalid email configuration: {e}");
+            return Ok(());
+        }
+    };
 
-    let state_clone = state.clone();
-    spawn(async move {
-        let mut timer = interval(TokioDuration::from_secs(3600));
-        loop {
-            timer.tick().await;
-            check_expiring_date_ranges(&state_clone.db.pool).await;
+    let password_policy = match PasswordPolicy::from_env() {
+        Ok(policy) => policy,
+        Err(e) => {
+            eprintln!("Invalid password policy: {e}");
+            return Ok(());
         }
+    };
+
+    let auth_providers = match AuthProviders::from_env() {
+        Ok(providers) => providers,
+        Err(e) => {
+            eprintln!("Invalid authentication providers: {e}");
+            return Ok(());
+        }
+    };
+
+    let oidc = match OidcClient::from_env() {
+        Ok(oidc) => oidc,
+        Err(e) => {
+            eprintln!("Invalid single sign-on configuration: {e}");
+            return Ok(());
+        }
+    };
+
+    let state = web::Data::new(State {
+        db,
+        mailer,
+        password_policy,
+        auth_providers,
+        oidc,
     });
 
+    if let Err(e) = models::page_record::PageRecord::move_files_out_of_media(&state.db.pool).await {
+        log::error!("Error moving record files out of media: {}", e);
+    }
+
+    services::scheduler::start(state.db.pool.clone()).await;
+
     let key = Key::generate();
 
     HttpServer::new(move || {
@@ -95,7 +133,12 @@ async fn main() -> std::io::Result<()> {
             .wrap(actix_web::middleware::Compress::default())
             .wrap(actix_web::middleware::Logger::default())
             .app_data(state.clone())
-            .service(Files::new("/media", "media").show_files_listing())
+            // Page icons are the only public media, anything else is served by the handlers
+            // that check who can see it
+            .service(
+                Files::new("/media", "media")
+                    .path_filter(|path, _| path.starts_with(routes::PUBLIC_MEDIA_DIR)),
+            )
     })
     .bind((args.address, args.port))?
     .run()
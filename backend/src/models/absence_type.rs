@@ -0,0 +1,381 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+
+use crate::{
+    models::{vacation_request::VacationDayPart, work_schedule::WorkingTime},
+    utils::working_days::DayPart,
+};
+
+/// The type of requests made before there were other types, and of those that name none.
+pub const ABSENCE_TYPE_VACATION: u32 = 1;
+
+/// Where supporting documents are kept, a directory per request. It is outside `media` so that
+/// only the handlers that check who asks serve them.
+pub const ABSENCE_DOCUMENTS_DIR: &str = "documents/absences";
+
+/// A kind of absence and the rules its requests follow.
+#[derive(Debug, Serialize)]
+pub struct AbsenceType {
+    pub id: u32,
+    pub code: String,
+    pub name: String,
+    /// Without approval, requests are approved when made, or once their document is uploaded
+    pub requires_approval: bool,
+    pub requires_document: bool,
+    /// Working days are taken from the vacation ledger
+    pub counts_against_vacation: bool,
+    pub max_days_per_request: Option<u16>,
+    pub max_days_per_year: Option<u16>,
+    pub active: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AbsenceTypeRequest {
+    pub code: String,
+    pub name: String,
+    pub requires_approval: bool,
+    pub requires_document: bool,
+    pub counts_against_vacation: bool,
+    pub max_days_per_request: Option<u16>,
+    pub max_days_per_year: Option<u16>,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+/// A document supporting an absence, like a sick note.
+#[derive(Debug, Serialize)]
+pub struct AbsenceDocument {
+    pub id: u32,
+    pub vacation_request_id: u32,
+    pub file_name: String,
+    pub file_path: String,
+    pub uploaded_at: Option<DateTime<Utc>>,
+    pub uploaded_by: u32,
+}
+
+/// A year in which a request would go over the yearly limit of its type.
+#[derive(Debug)]
+pub struct YearlyLimitExcess {
+    pub year: i32,
+    pub limit: i64,
+    pub booked: f64,
+    pub requested: f64,
+}
+
+impl AbsenceTypeRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        let code = self.code.trim();
+        if code.is_empty()
+            || code.len() > 32
+            || !code
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+        {
+            return Err(
+                "O código tem de ter até 32 letras maiúsculas, algarismos ou _.".to_string(),
+            );
+        }
+        if self.name.trim().is_empty() || self.name.chars().count() > 255 {
+            return Err("O nome é obrigatório (até 255 caracteres).".to_string());
+        }
+        if self.max_days_per_request == Some(0) || self.max_days_per_year == Some(0) {
+            return Err("Os limites de dias têm de ser positivos.".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl AbsenceType {
+    /// How messages name requests of the type, as in "pedido de férias".
+    pub fn label(&self) -> String {
+        if self.id == ABSENCE_TYPE_VACATION {
+            "férias".to_string()
+        } else {
+            self.name.to_lowercase()
+        }
+    }
+
+    /// Whether requests are approved as soon as they are made, needing neither an admin nor
+    /// a document.
+    pub fn is_approved_when_made(&self) -> bool {
+        !self.requires_approval && !self.requires_document
+    }
+
+    pub async fn get_all(pool: &MySqlPool) -> Result<Vec<AbsenceType>, sqlx::Error> {
+        sqlx::query_as!(
+            AbsenceType,
+            r#"
+            SELECT id, code, name,
+                   requires_approval as "requires_approval: bool",
+                   requires_document as "requires_document: bool",
+                   counts_against_vacation as "counts_against_vacation: bool",
+                   max_days_per_request, max_days_per_year,
+                   active as "active: bool"
+            FROM absence_types
+            ORDER BY id
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn get_by_id(
+        pool: &MySqlPool,
+        absence_type_id: u32,
+    ) -> Result<Option<AbsenceType>, sqlx::Error> {
+        sqlx::query_as!(
+            AbsenceType,
+            r#"
+            SELECT id, code, name,
+                   requires_approval as "requires_approval: bool",
+                   requires_document as "requires_document: bool",
+                   counts_against_vacation as "counts_against_vacation: bool",
+                   max_days_per_request, max_days_per_year,
+                   active as "active: bool"
+            FROM absence_types
+            WHERE id = ?
+            "#,
+            absence_type_id
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn create(
+        pool: &MySqlPool,
+        absence_type: &AbsenceTypeRequest,
+    ) -> Result<u32, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO absence_types
+                (code, name, requires_approval, requires_document, counts_against_vacation,
+                 max_days_per_request, max_days_per_year, active)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            absence_type.code.trim(),
+            absence_type.name.trim(),
+            absence_type.requires_approval,
+            absence_type.requires_document,
+            absence_type.counts_against_vacation,
+            absence_type.max_days_per_request,
+            absence_type.max_days_per_year,
+            absence_type.active
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_id() as u32)
+    }
+
+    /// Returns false when the type does not exist. Requests already made keep their status.
+    pub async fn update(
+        pool: &MySqlPool,
+        absence_type_id: u32,
+        absence_type: &AbsenceTypeRequest,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE absence_types
+            SET code = ?, name = ?, requires_approval = ?, requires_document = ?,
+                counts_against_vacation = ?, max_days_per_request = ?, max_days_per_year = ?,
+                active = ?
+            WHERE id = ?
+            "#,
+            absence_type.code.trim(),
+            absence_type.name.trim(),
+            absence_type.requires_approval,
+            absence_type.requires_document,
+            absence_type.counts_against_vacation,
+            absence_type.max_days_per_request,
+            absence_type.max_days_per_year,
+            absence_type.active,
+            absence_type_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Whether any request is of the type, which keeps it from being deleted.
+    pub async fn is_used(pool: &MySqlPool, absence_type_id: u32) -> Result<bool, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) FROM vacation_requests WHERE absence_type_id = ?"#,
+            absence_type_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count > 0)
+    }
+
+    pub async fn delete(pool: &MySqlPool, absence_type_id: u32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(r#"DELETE FROM absence_types WHERE id = ?"#, absence_type_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The first year [start, end] falls in where the user's approved and pending absences of
+    /// this type, with the part of the new one in that year, go over the yearly limit.
+    pub async fn find_yearly_limit_excess(
+        &self,
+        pool: &MySqlPool,
+        user_id: u32,
+        start: NaiveDate,
+        end: NaiveDate,
+        part: DayPart,
+        working_time: &WorkingTime,
+    ) -> Result<Option<YearlyLimitExcess>, sqlx::Error> {
+        let Some(limit) = self.max_days_per_year else {
+            return Ok(None);
+        };
+
+        for year in start.year()..=end.year() {
+            let (Some(year_start), Some(year_end)) = (
+                NaiveDate::from_ymd_opt(year, 1, 1),
+                NaiveDate::from_ymd_opt(year, 12, 31),
+            ) else {
+                continue;
+            };
+
+            let requested = working_time.count_part(start.max(year_start), end.min(year_end), part);
+            if requested <= 0.0 {
+                continue;
+            }
+
+            let booked = self
+                .count_booked_days_between(pool, user_id, year_start, year_end)
+                .await?;
+            if booked + requested > limit as f64 {
+                return Ok(Some(YearlyLimitExcess {
+                    year,
+                    limit: limit as i64,
+                    booked,
+                    requested,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Working days of the user's approved and pending absences of this type in [start, end].
+    async fn count_booked_days_between(
+        &self,
+        pool: &MySqlPool,
+        user_id: u32,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<f64, sqlx::Error> {
+        let requests = sqlx::query!(
+            r#"
+            SELECT start_date, end_date, day_part as "day_part: VacationDayPart", minutes
+            FROM vacation_requests
+            WHERE user_id = ? AND absence_type_id = ?
+              AND status IN ('PENDING', 'APPROVED', 'CANCELLATION_REQUESTED')
+              AND start_date <= ? AND end_date >= ?
+            "#,
+            user_id,
+            self.id,
+            end,
+            start
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let working_time = WorkingTime::load(pool, user_id, start, end).await?;
+        Ok(requests
+            .into_iter()
+            .map(|request| {
+                working_time.count_part(
+                    request.start_date.max(start),
+                    request.end_date.min(end),
+                    request.day_part.part(request.minutes),
+                )
+            })
+            .sum())
+    }
+}
+
+impl AbsenceDocument {
+    pub async fn get_by_id(
+        pool: &MySqlPool,
+        vacation_request_id: u32,
+        document_id: u32,
+    ) -> Result<Option<AbsenceDocument>, sqlx::Error> {
+        sqlx::query_as!(
+            AbsenceDocument,
+            r#"
+            SELECT id, vacation_request_id, file_name, file_path, uploaded_at, uploaded_by
+            FROM absence_documents
+            WHERE id = ? AND vacation_request_id = ?
+            "#,
+            document_id,
+            vacation_request_id
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn get_for_request(
+        pool: &MySqlPool,
+        vacation_request_id: u32,
+    ) -> Result<Vec<AbsenceDocument>, sqlx::Error> {
+        sqlx::query_as!(
+            AbsenceDocument,
+            r#"
+            SELECT id, vacation_request_id, file_name, file_path, uploaded_at, uploaded_by
+            FROM absence_documents
+            WHERE vacation_request_id = ?
+            ORDER BY id
+            "#,
+            vacation_request_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn exists_for_request(
+        pool: &MySqlPool,
+        vacation_request_id: u32,
+    ) -> Result<bool, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) FROM absence_documents WHERE vacation_request_id = ?"#,
+            vacation_request_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count > 0)
+    }
+
+    pub async fn add(
+        pool: &MySqlPool,
+        vacation_request_id: u32,
+        file_name: &str,
+        file_path: &str,
+        uploaded_by: u32,
+    ) -> Result<u32, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO absence_documents (vacation_request_id, file_name, file_path, uploaded_by)
+            VALUES (?, ?, ?, ?)
+            "#,
+            vacation_request_id,
+            file_name,
+            file_path,
+            uploaded_by
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_id() as u32)
+    }
+}
@@ -0,0 +1,154 @@
+use ahash::HashSet;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, MySql, MySqlPool, Transaction};
+
+/// What the members of a role administer without being global admins: the pages under some
+/// groups and the members of some roles.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RoleAdminScopes {
+    /// Groups whose pages, and the pages of their subgroups, the role administers
+    #[serde(default)]
+    pub page_group_ids: Vec<u32>,
+    /// Roles whose assignments and vacation requests the role administers
+    #[serde(default)]
+    pub role_ids: Vec<u32>,
+}
+
+#[derive(FromRow)]
+struct ManagedPage {
+    id: u32,
+    path: String,
+    is_group: bool,
+}
+
+/// Everything a user administers through the scopes of their roles.
+#[derive(Debug, Default, Clone)]
+pub struct UserAdminScopes {
+    /// Managed pages and groups
+    pub page_ids: HashSet<u32>,
+    /// Paths of the managed groups, where new pages can be created
+    pub group_paths: HashSet<String>,
+    pub role_ids: HashSet<u32>,
+}
+
+impl RoleAdminScopes {
+    pub async fn get_for_role(pool: &MySqlPool, role_id: u32) -> Result<Self, sqlx::Error> {
+        let page_group_ids = sqlx::query_scalar!(
+            r#"SELECT page_id FROM role_admin_page_groups WHERE role_id = ?"#,
+            role_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let role_ids = sqlx::query_scalar!(
+            r#"SELECT managed_role_id FROM role_admin_roles WHERE role_id = ?"#,
+            role_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(RoleAdminScopes {
+            page_group_ids,
+            role_ids,
+        })
+    }
+
+    pub async fn replace_for_role(
+        tx: &mut Transaction<'_, MySql>,
+        role_id: u32,
+        scopes: &RoleAdminScopes,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"DELETE FROM role_admin_page_groups WHERE role_id = ?"#,
+            role_id
+        )
+        .execute(&mut **tx)
+        .await?;
+        sqlx::query!(r#"DELETE FROM role_admin_roles WHERE role_id = ?"#, role_id)
+            .execute(&mut **tx)
+            .await?;
+
+        for page_id in &scopes.page_group_ids {
+            sqlx::query!(
+                r#"INSERT INTO role_admin_page_groups (role_id, page_id) VALUES (?, ?)"#,
+                role_id,
+                page_id
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        for managed_role_id in &scopes.role_ids {
+            sqlx::query!(
+                r#"INSERT INTO role_admin_roles (role_id, managed_role_id) VALUES (?, ?)"#,
+                role_id,
+                managed_role_id
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Ids among `page_ids` that are not group pages, to reject them as page scopes.
+    pub async fn find_non_groups(
+        pool: &MySqlPool,
+        page_ids: &[u32],
+    ) -> Result<Vec<u32>, sqlx::Error> {
+        if page_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let groups: HashSet<u32> =
+            sqlx::query_scalar!(r#"SELECT id FROM custom_pages WHERE is_group = true"#)
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .collect();
+
+        Ok(page_ids
+            .iter()
+            .copied()
+            .filter(|id| !groups.contains(id))
+            .collect())
+    }
+}
+
+impl UserAdminScopes {
+    pub fn is_empty(&self) -> bool {
+        self.page_ids.is_empty() && self.role_ids.is_empty()
+    }
+
+    pub async fn for_user(pool: &MySqlPool, user_id: i32) -> Result<Self, sqlx::Error> {
+        let pages = sqlx::query_as!(
+            ManagedPage,
+            r#"
+            SELECT cp.id, cp.path, cp.is_group as "is_group: bool"
+            FROM user_managed_pages mp
+            JOIN custom_pages cp ON cp.id = mp.page_id
+            WHERE mp.user_id = ?
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let role_ids = sqlx::query_scalar!(
+            r#"SELECT role_id FROM user_managed_roles WHERE user_id = ?"#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(UserAdminScopes {
+            page_ids: pages.iter().map(|page| page.id).collect(),
+            group_paths: pages
+                .into_iter()
+                .filter(|page| page.is_group)
+                .map(|page| page.path)
+                .collect(),
+            role_ids: role_ids.into_iter().collect(),
+        })
+    }
+}
@@ -0,0 +1,53 @@
+use sqlx::MySqlPool;
+
+/// Whether anyone can create an account through `/users/register`.
+pub const SETTING_REGISTRATION_OPEN: &str = "registration_open";
+/// Day of the next year (`MM-DD`) carried-over vacation expires on.
+pub const SETTING_CARRY_OVER_EXPIRY: &str = "vacation_carry_over_expiry";
+/// Most vacation carried into the next year, unlimited when missing or empty.
+pub const SETTING_CARRY_OVER_MAX: &str = "vacation_carry_over_max";
+
+/// Settings admins change at runtime, stored as text.
+pub struct AppSetting;
+
+impl AppSetting {
+    pub async fn get(pool: &MySqlPool, key: &str) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT setting_value FROM app_settings WHERE setting_key = ?"#,
+            key
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// A boolean setting, `default` when it was never stored.
+    pub async fn get_bool(pool: &MySqlPool, key: &str, default: bool) -> Result<bool, sqlx::Error> {
+        Ok(Self::get(pool, key)
+            .await?
+            .map_or(default, |value| value == "true"))
+    }
+
+    pub async fn set(
+        pool: &MySqlPool,
+        key: &str,
+        value: &str,
+        updated_by: u32,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO app_settings (setting_key, setting_value, updated_by)
+            VALUES (?, ?, ?)
+            ON DUPLICATE KEY UPDATE
+                setting_value = VALUES(setting_value),
+                updated_by = VALUES(updated_by)
+            "#,
+            key,
+            value,
+            updated_by
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+
+use crate::{
+    models::{location::Location, vacation_request::VacationDayPart, work_schedule::WorkingTime},
+    utils::{
+        coverage::{Absence, DayCoverage, Requirement, uncovered_days},
+        working_days::DayPart,
+    },
+};
+
+/// At most `threshold` members absent on the same day
+pub const COVERAGE_MAX_ABSENT: &str = "MAX_ABSENT";
+/// At least `threshold` members present on each day
+pub const COVERAGE_MIN_PRESENT: &str = "MIN_PRESENT";
+
+/// How many members of a role can be away at once, see [`crate::utils::coverage`].
+#[derive(Debug, Serialize)]
+pub struct CoverageRule {
+    pub id: u32,
+    pub name: String,
+    pub role_id: u32,
+    /// [`Location`] value, only the members of that clinic are counted. `None` for every clinic
+    pub location: Option<i8>,
+    /// The members of each clinic are counted on their own
+    pub per_location: bool,
+    pub requirement: String,
+    pub threshold: u16,
+    /// Weekends are not checked
+    pub weekdays_only: bool,
+    pub active: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CoverageRuleRequest {
+    pub name: String,
+    pub role_id: u32,
+    pub location: Option<i8>,
+    #[serde(default)]
+    pub per_location: bool,
+    pub requirement: String,
+    pub threshold: u16,
+    #[serde(default)]
+    pub weekdays_only: bool,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+/// A rule a request breaks, and the days it breaks it on.
+#[derive(Debug, Serialize)]
+pub struct CoverageViolation {
+    pub rule_id: u32,
+    pub rule_name: String,
+    pub requirement: String,
+    pub threshold: u16,
+    /// Clinic whose members were counted, `None` when counted across clinics
+    pub location: Option<i8>,
+    pub days: Vec<DayCoverage>,
+}
+
+impl CoverageRuleRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() || self.name.chars().count() > 255 {
+            return Err("O nome é obrigatório (até 255 caracteres).".to_string());
+        }
+        if let Some(location) = self.location {
+            if Location::from_value(location).is_none() {
+                return Err(format!("Localização inválida: {}", location));
+            }
+            if self.per_location {
+                return Err(
+                    "Uma regra com localização só conta os membros dessa clínica.".to_string(),
+                );
+            }
+        }
+
+        match self.requirement.as_str() {
+            COVERAGE_MAX_ABSENT => Ok(()),
+            COVERAGE_MIN_PRESENT if self.threshold > 0 => Ok(()),
+            COVERAGE_MIN_PRESENT => {
+                Err("O número mínimo de presentes tem de ser positivo.".to_string())
+            }
+            _ => Err(format!("Requisito inválido: {}", self.requirement)),
+        }
+    }
+}
+
+impl CoverageRule {
+    fn requirement(&self) -> Requirement {
+        if self.requirement == COVERAGE_MIN_PRESENT {
+            Requirement::MinPresent(self.threshold)
+        } else {
+            Requirement::MaxAbsent(self.threshold)
+        }
+    }
+
+    pub async fn get_all(pool: &MySqlPool) -> Result<Vec<CoverageRule>, sqlx::Error> {
+        sqlx::query_as!(
+            CoverageRule,
+            r#"
+            SELECT id, name, role_id, location, per_location as "per_location: bool",
+                   requirement, threshold, weekdays_only as "weekdays_only: bool",
+                   active as "active: bool"
+            FROM coverage_rules
+            ORDER BY role_id, name
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn create(pool: &MySqlPool, rule: &CoverageRuleRequest) -> Result<u32, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO coverage_rules
+                (name, role_id, location, per_location, requirement, threshold, weekdays_only,
+                 active)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            rule.name.trim(),
+            rule.role_id,
+            rule.location,
+            rule.per_location,
+            rule.requirement,
+            rule.threshold,
+            rule.weekdays_only,
+            rule.active
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_id() as u32)
+    }
+
+    /// Returns false when the rule does not exist.
+    pub async fn update(
+        pool: &MySqlPool,
+        rule_id: u32,
+        rule: &CoverageRuleRequest,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE coverage_rules
+            SET name = ?, role_id = ?, location = ?, per_location = ?, requirement = ?,
+                threshold = ?, weekdays_only = ?, active = ?
+            WHERE id = ?
+            "#,
+            rule.name.trim(),
+            rule.role_id,
+            rule.location,
+            rule.per_location,
+            rule.requirement,
+            rule.threshold,
+            rule.weekdays_only,
+            rule.active,
+            rule_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn delete(pool: &MySqlPool, rule_id: u32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(r#"DELETE FROM coverage_rules WHERE id = ?"#, rule_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The active rules of the user's roles that a request for [start, end] breaks, counting
+    /// the approved absences of the other members. Only the days the user works are checked.
+    pub async fn find_violations(
+        pool: &MySqlPool,
+        user_id: u32,
+        start: NaiveDate,
+        end: NaiveDate,
+        part: DayPart,
+        working_time: &WorkingTime,
+    ) -> Result<Vec<CoverageViolation>, sqlx::Error> {
+        let user_location =
+            sqlx::query_scalar!(r#"SELECT location FROM users WHERE id = ?"#, user_id)
+                .fetch_one(pool)
+                .await?;
+
+        // Rules of another clinic do not apply to the user
+        let rules = sqlx::query_as!(
+            CoverageRule,
+            r#"
+            SELECT cr.id, cr.name, cr.role_id, cr.location,
+                   cr.per_location as "per_location: bool", cr.requirement, cr.threshold,
+                   cr.weekdays_only as "weekdays_only: bool", cr.active as "active: bool"
+            FROM coverage_rules cr
+            WHERE cr.role_id IN (SELECT role_id FROM user_effective_roles WHERE user_id = ?)
+              AND cr.active = TRUE
+              AND (cr.location IS NULL OR cr.location = ?)
+            ORDER BY cr.id
+            "#,
+            user_id,
+            user_location
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let request = Absence {
+            user_id,
+            start_date: start,
+            end_date: end,
+            part,
+        };
+
+        let mut violations = Vec::new();
+        for rule in rules {
+            // Some(location) when only the members of one clinic are counted
+            let counted_location = match (rule.location, rule.per_location) {
+                (Some(location), _) => Some(Some(location)),
+                (None, true) => Some(user_location),
+                (None, false) => None,
+            };
+
+            let members = sqlx::query_scalar!(
+                r#"
+                SELECT COUNT(DISTINCT ur.user_id)
+                FROM user_effective_roles ur
+                JOIN users u ON u.id = ur.user_id
+                WHERE ur.role_id = ? AND (? = FALSE OR u.location <=> ?)
+                "#,
+                rule.role_id,
+                counted_location.is_some(),
+                counted_location.flatten()
+            )
+            .fetch_one(pool)
+            .await?;
+
+            let others = sqlx::query!(
+                r#"
+                SELECT vr.user_id, vr.start_date, vr.end_date,
+                       vr.day_part as "day_part: VacationDayPart", vr.minutes
+                FROM vacation_requests vr
+                JOIN users u ON u.id = vr.user_id
+                WHERE EXISTS (
+                      SELECT 1 FROM user_effective_roles ur
+                      WHERE ur.user_id = vr.user_id AND ur.role_id = ?
+                  )
+                  AND vr.user_id <> ?
+                  AND vr.status IN ('APPROVED', 'CANCELLATION_REQUESTED')
+                  AND vr.start_date <= ? AND vr.end_date >= ?
+                  AND (? = FALSE OR u.location <=> ?)
+                "#,
+                rule.role_id,
+                user_id,
+                end,
+                start,
+                counted_location.is_some(),
+                counted_location.flatten()
+            )
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| Absence {
+                user_id: row.user_id,
+                start_date: row.start_date,
+                end_date: row.end_date,
+                part: row.day_part.part(row.minutes),
+            })
+            .collect::<Vec<_>>();
+
+            let dates = start
+                .iter_days()
+                .take_while(|date| *date <= end)
+                .filter(|date| working_time.works_on(*date))
+                .filter(|date| !rule.weekdays_only || date.weekday().number_from_monday() <= 5);
+
+            let days = uncovered_days(
+                rule.requirement(),
+                members as usize,
+                &request,
+                &others,
+                dates,
+            );
+            if !days.is_empty() {
+                violations.push(CoverageViolation {
+                    rule_id: rule.id,
+                    rule_name: rule.name,
+                    requirement: rule.requirement,
+                    threshold: rule.threshold,
+                    location: counted_location.flatten(),
+                    days,
+                });
+            }
+        }
+
+        Ok(violations)
+    }
+}
+
+impl CoverageViolation {
+    /// The rule, the clinic counted and the days the rule is broken on, as in
+    /// "Enfermagem em Braga (no máximo 2 ausentes): 05/06/2025 (3 de 5 ausentes)".
+    pub fn describe(&self) -> String {
+        let limit = if self.requirement == COVERAGE_MIN_PRESENT {
+            format!("no mínimo {} presentes", self.threshold)
+        } else {
+            format!("no máximo {} ausentes", self.threshold)
+        };
+        let days = self
+            .days
+            .iter()
+            .map(|day| {
+                format!(
+                    "{} ({} de {} ausentes)",
+                    day.date.format("%d/%m/%Y"),
+                    day.absent,
+                    day.members
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        match self.location.and_then(Location::from_value) {
+            Some(location) => format!(
+                "{} em {} ({}): {}",
+                self.rule_name,
+                location.name(),
+                limit,
+                days
+            ),
+            None => format!("{} ({}): {}", self.rule_name, limit, days),
+        }
+    }
+}
+
+/// The message of a response refusing a request over `violations`.
+pub fn violations_message(violations: &[CoverageViolation]) -> String {
+    format!(
+        "O pedido não cumpre as regras de cobertura. {}.",
+        violations
+            .iter()
+            .map(CoverageViolation::describe)
+            .collect::<Vec<_>>()
+            .join("; ")
+    )
+}
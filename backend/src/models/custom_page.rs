@@ -4,9 +4,8 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Transaction, MySql}; // Added Transaction and MySql
 
-use crate::auth;
 
-use super::field::PageField;
+use super::field::{FieldAccess, PageField};
 
 // Helper struct for recursive deletion
 #[derive(FromRow, Debug)]
@@ -111,6 +110,9 @@ pub struct CustomPageWithFields {
     pub permissions: Vec<PagePermission>,
     #[serde(rename = "currentUserPermissions")]
     pub current_user_permissions: Option<UserPagePermissions>,
+    /// Names of the fields the current user can see but not change
+    #[serde(rename = "currentUserReadOnlyFields", default)]
+    pub current_user_read_only_fields: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
@@ -217,11 +219,54 @@ impl CustomPage {
             }
         }
 
+        Self::refresh_ancestors(&mut tx).await?;
+
         tx.commit().await?;
 
         Ok(page_id)
     }
 
+    /// Rebuilds `page_ancestors`, which the effective permissions and the managed pages read,
+    /// from the paths of every page. Called whenever a page is added or moved; deleting pages
+    /// cascades to their rows.
+    async fn refresh_ancestors(tx: &mut Transaction<'_, MySql>) -> Result<(), sqlx::Error> {
+        sqlx::query!(r#"DELETE FROM page_ancestors"#)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO page_ancestors (page_id, ancestor_id, depth)
+            WITH RECURSIVE chain (page_id, ancestor_id, parent_path, depth) AS (
+                SELECT id, id, parent_path, 0 FROM custom_pages
+                UNION ALL
+                SELECT c.page_id, p.id, p.parent_path, c.depth + 1
+                FROM chain c
+                JOIN custom_pages p ON p.path = c.parent_path AND p.is_group = true
+                WHERE c.depth < 16
+            )
+            SELECT page_id, ancestor_id, MIN(depth) FROM chain GROUP BY page_id, ancestor_id
+            "#
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The group a page is in, `None` at the top level.
+    pub async fn get_parent_path(
+        pool: &sqlx::MySqlPool,
+        page_id: u32,
+    ) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT parent_path FROM custom_pages WHERE id = ?"#,
+            page_id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
     pub async fn get_all(pool: &sqlx::MySqlPool) -> Result<Vec<CustomPage>, sqlx::Error> {
         sqlx::query_as!(
             CustomPage,
@@ -262,10 +307,9 @@ impl CustomPage {
 
         let mut fields = Vec::new();
         let mut permissions = Vec::new();
-        // current_user_permissions will be calculated later
 
-        let can_view_this = auth::user_can_view_page(pool, user_id, page_id).await?;
-        if !can_view_this {
+        let user_permissions = Self::get_user_permissions_for_page(pool, user_id, page_id).await?;
+        if !user_permissions.can_view {
             return Err(sqlx::Error::RowNotFound);
         }
 
@@ -295,25 +339,40 @@ impl CustomPage {
         }
         // No need to calculate permissions within the `if` block anymore
 
-        // Calculate current_user_permissions directly based on whether it's a group
+        // Groups have no records, only the admin flag is relevant for them
         let final_user_permissions = if !page.is_group {
-            Some(Self::get_user_permissions_for_page(pool, user_id, page_id).await?)
+            Some(user_permissions)
         } else {
-            let is_admin = sqlx::query_scalar!(
-                  "SELECT EXISTS(SELECT 1 FROM user_roles ur JOIN roles r ON ur.role_id = r.id WHERE ur.user_id = ? AND r.is_admin = 1)",
-                  user_id
-              ).fetch_one(pool).await? == 1;
             Some(UserPagePermissions {
-                is_admin,
+                is_admin: user_permissions.is_admin,
                 ..Default::default()
             })
         };
 
+        // Field managers need every field to configure the page, everyone else only gets the
+        // fields they are allowed to see
+        let mut read_only_fields = Vec::new();
+        if !page.is_group {
+            let access = FieldAccess::for_user_on_page(pool, user_id, page_id).await?;
+            let can_manage_fields = final_user_permissions
+                .as_ref()
+                .is_some_and(|p| p.can_manage_fields);
+            if !can_manage_fields {
+                fields.retain(|f| access.can_read(&f.name));
+            }
+            read_only_fields = fields
+                .iter()
+                .filter(|f| !access.can_write(&f.name))
+                .map(|f| f.name.clone())
+                .collect();
+        }
+
         Ok(CustomPageWithFields {
             page,
             fields,
             permissions,
             current_user_permissions: final_user_permissions,
+            current_user_read_only_fields: read_only_fields,
         })
     }
 
@@ -332,13 +391,16 @@ impl CustomPage {
                 MAX(CASE WHEN pp.can_delete = 1 THEN 1 ELSE 0 END) as can_delete,
                 MAX(CASE WHEN pp.can_manage_fields = 1 THEN 1 ELSE 0 END) as can_manage_fields,
                 MAX(CASE WHEN pp.can_view_acknowledgments = 1 THEN 1 ELSE 0 END) as can_view_acknowledgments,
-                MAX(CASE WHEN pp.can_add = 1 THEN 1 ELSE 0 END) as can_add
-            FROM user_roles ur
+                MAX(CASE WHEN pp.can_add = 1 THEN 1 ELSE 0 END) as can_add,
+                MAX(CASE WHEN mp.page_id IS NOT NULL THEN 1 ELSE 0 END) as manages_page
+            FROM user_effective_roles ur
             LEFT JOIN roles r ON r.id = ur.role_id
-            LEFT JOIN page_permissions pp ON pp.role_id = ur.role_id AND pp.page_id = ?
+            LEFT JOIN effective_page_permissions pp ON pp.role_id = ur.role_id AND pp.page_id = ?
+            LEFT JOIN user_managed_pages mp ON mp.user_id = ur.user_id AND mp.page_id = ?
             WHERE ur.user_id = ?
             "#,
             page_id,
+            page_id,
             user_id
         )
         .fetch_one(pool)
@@ -346,14 +408,17 @@ impl CustomPage {
 
         // Combine role-based permission with admin override
         let is_admin = perms.is_admin.unwrap_or(0) == 1;
+        // Scoped admins of the page's group can open and configure it, but only reach its
+        // records through the permissions of their roles: RowAccess gives them none otherwise
+        let manages_page = perms.manages_page.unwrap_or(0) == 1;
 
         Ok(UserPagePermissions {
             is_admin,
-            can_view: is_admin || perms.can_view.unwrap_or(0) == 1,
+            can_view: is_admin || manages_page || perms.can_view.unwrap_or(0) == 1,
             can_create: is_admin || perms.can_create.unwrap_or(0) == 1,
             can_edit: is_admin || perms.can_edit.unwrap_or(0) == 1,
             can_delete: is_admin || perms.can_delete.unwrap_or(0) == 1,
-            can_manage_fields: is_admin || perms.can_manage_fields.unwrap_or(0) == 1,
+            can_manage_fields: is_admin || manages_page || perms.can_manage_fields.unwrap_or(0) == 1,
             can_view_acknowledgments: is_admin || perms.can_view_acknowledgments.unwrap_or(0) == 1,
             can_add: is_admin || perms.can_add.unwrap_or(0) == 1,
         })
@@ -374,6 +439,8 @@ impl CustomPage {
         });
         // --- End Path Cleaning ---
 
+        let mut tx = pool.begin().await?;
+
         sqlx::query!(
             r#"
                 UPDATE custom_pages
@@ -389,10 +456,12 @@ impl CustomPage {
             request.requires_acknowledgment,
             page_id
         )
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
 
-        Ok(())
+        Self::refresh_ancestors(&mut tx).await?;
+
+        tx.commit().await
     }
 
     pub async fn delete(pool: &sqlx::MySqlPool, page_id_to_delete: u32) -> Result<(), sqlx::Error> {
@@ -469,28 +538,33 @@ impl CustomPage {
         .fetch_all(pool)
         .await?;
 
-        // 2. Fetch IDs of pages the user CAN view (non-groups only)
+        // 2. Fetch IDs of pages the user CAN view (non-groups only), including what their roles
+        // inherit from parent roles and from groups, and the pages they administer. Admins are
+        // handled by `is_admin` below.
         let viewable_page_ids: HashSet<u32> = sqlx::query_scalar!(
             r#"
-            SELECT DISTINCT cp.id
+            SELECT cp.id
+            FROM custom_pages cp
+            JOIN effective_page_permissions pp ON cp.id = pp.page_id
+            JOIN user_effective_roles ur ON pp.role_id = ur.role_id
+            WHERE ur.user_id = ? AND cp.is_group = 0 AND pp.can_view = 1
+            UNION
+            SELECT cp.id
             FROM custom_pages cp
-            LEFT JOIN page_permissions pp ON cp.id = pp.page_id
-            LEFT JOIN user_roles ur ON pp.role_id = ur.role_id AND ur.user_id = ?
-            LEFT JOIN roles ro ON ur.role_id = ro.id
-            WHERE
-                cp.is_group = 0 AND (ro.is_admin = 1 OR pp.can_view = 1)
+            JOIN user_managed_pages mp ON mp.page_id = cp.id
+            WHERE mp.user_id = ? AND cp.is_group = 0
             "#,
+            user_id,
             user_id
         )
         .fetch_all(pool)
         .await?
         .into_iter()
-        .filter_map(|id_opt| Some(id_opt)) // Filter out potential NULLs from LEFT JOIN if any (though DISTINCT cp.id should handle)
         .collect();
 
         // 3. Check if user is admin
         let is_admin = sqlx::query_scalar!(
-            "SELECT EXISTS(SELECT 1 FROM user_roles ur JOIN roles r ON ur.role_id = r.id WHERE ur.user_id = ? AND r.is_admin = 1)",
+            "SELECT EXISTS(SELECT 1 FROM user_effective_roles ur JOIN roles r ON ur.role_id = r.id WHERE ur.user_id = ? AND r.is_admin = 1)",
             user_id
         )
         .fetch_one(pool)
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+
+/// A directory group whose members get a role when they log in through `provider`. For single
+/// sign-on the group is a value of the role claim.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DirectoryGroupRole {
+    pub provider: String,
+    pub group_name: String,
+    pub role_id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GroupRoleMapping {
+    pub group_name: String,
+    pub role_id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplaceGroupRolesRequest {
+    pub provider: String,
+    pub mappings: Vec<GroupRoleMapping>,
+}
+
+impl DirectoryGroupRole {
+    pub async fn get_all(pool: &MySqlPool) -> Result<Vec<DirectoryGroupRole>, sqlx::Error> {
+        sqlx::query_as!(
+            DirectoryGroupRole,
+            r#"
+            SELECT provider, group_name, role_id
+            FROM directory_group_roles
+            ORDER BY provider, group_name, role_id
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Replaces every mapping of a provider. Group names are stored lowercase, as
+    /// [`crate::utils::directory::group_names`] gives them.
+    pub async fn replace_for_provider(
+        pool: &MySqlPool,
+        provider: &str,
+        mappings: &[GroupRoleMapping],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            r#"DELETE FROM directory_group_roles WHERE provider = ?"#,
+            provider
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for mapping in mappings {
+            sqlx::query!(
+                r#"
+                INSERT IGNORE INTO directory_group_roles (provider, group_name, role_id)
+                VALUES (?, ?, ?)
+                "#,
+                provider,
+                mapping.group_name.trim().to_lowercase(),
+                mapping.role_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await
+    }
+
+    /// Roles mapped to any of the groups.
+    pub async fn role_ids_for_groups(
+        pool: &MySqlPool,
+        provider: &str,
+        group_names: &[String],
+    ) -> Result<Vec<u32>, sqlx::Error> {
+        if group_names.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = group_names
+            .iter()
+            .map(|_| "?")
+            .collect::<Vec<_>>()
+            .join(",");
+        let query_str = format!(
+            r#"
+            SELECT DISTINCT role_id
+            FROM directory_group_roles
+            WHERE provider = ? AND group_name IN ({})
+            "#,
+            placeholders
+        );
+
+        let mut query_builder = sqlx::query_scalar::<_, u32>(&query_str).bind(provider);
+        for group_name in group_names {
+            query_builder = query_builder.bind(group_name);
+        }
+
+        query_builder.fetch_all(pool).await
+    }
+}
@@ -1,3 +1,4 @@
+use ahash::{HashSet, HashSetExt};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
@@ -41,6 +42,34 @@ pub struct UpdatePageFieldRequest {
     pub notification_target_date_part: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct PageFieldPermission {
+    pub id: u32,
+    pub field_id: u32,
+    pub role_id: u32,
+    pub role_name: String,
+    pub can_read: bool,
+    pub can_write: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldPermissionRequest {
+    pub role_id: u32,
+    pub can_read: bool,
+    pub can_write: bool,
+}
+
+/// Fields of a page a given user cannot read or write.
+///
+/// Fields without per-role permissions are not listed and follow the page permissions.
+#[derive(Debug, Default)]
+pub struct FieldAccess {
+    /// Names of the fields the user cannot read
+    pub hidden: HashSet<String>,
+    /// Names of the fields the user cannot write, hidden fields included
+    pub read_only: HashSet<String>,
+}
+
 impl FieldType {
     pub async fn get_all(pool: &sqlx::MySqlPool) -> Result<Vec<FieldType>, sqlx::Error> {
         sqlx::query_as!(
@@ -57,33 +86,6 @@ impl FieldType {
 }
 
 impl PageField {
-    pub async fn get_notification_enabled_date_range_fields(
-        pool: &sqlx::MySqlPool,
-    ) -> Result<Vec<PageField>, sqlx::Error> {
-        sqlx::query_as!(
-            PageField,
-            r#"
-            SELECT
-                f.id, f.page_id, f.name, f.display_name,
-                f.field_type_id, t.name as field_type_name,
-                f.required as "required: bool",
-                f.options as "options: _", f.validation_name,
-                f.is_searchable as "is_searchable: bool", f.is_displayed_in_table as "is_displayed_in_table: bool", f.order_index,
-                f.notification_enabled as "notification_enabled: bool",
-                f.notification_days_before,
-                f.notification_target_date_part
-            FROM page_fields f
-            JOIN field_types t ON f.field_type_id = t.id
-            WHERE f.notification_enabled = true
-              AND t.name = 'DATE_RANGE'
-              AND f.notification_days_before IS NOT NULL
-              AND f.notification_target_date_part IS NOT NULL
-            "#
-        )
-        .fetch_all(pool)
-        .await
-    }
-
     pub async fn get_by_page_id(
         pool: &sqlx::MySqlPool,
         page_id: u32,
@@ -177,6 +179,18 @@ impl PageField {
         Ok(result.last_insert_id() as u32)
     }
 
+    pub async fn get_field_type_id(
+        pool: &sqlx::MySqlPool,
+        field_id: u32,
+    ) -> Result<u32, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT field_type_id FROM page_fields WHERE id = ?"#,
+            field_id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
     pub async fn update(
         pool: &sqlx::MySqlPool,
         field_id: u32,
@@ -226,3 +240,159 @@ impl PageField {
         Ok(())
     }
 }
+
+impl PageFieldPermission {
+    pub async fn get_by_field_id(
+        pool: &sqlx::MySqlPool,
+        field_id: u32,
+    ) -> Result<Vec<PageFieldPermission>, sqlx::Error> {
+        sqlx::query_as!(
+            PageFieldPermission,
+            r#"
+            SELECT
+                fp.id, fp.field_id, fp.role_id, r.name as role_name,
+                fp.can_read as "can_read: bool", fp.can_write as "can_write: bool"
+            FROM page_field_permissions fp
+            JOIN roles r ON fp.role_id = r.id
+            WHERE fp.field_id = ?
+            "#,
+            field_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Replaces the permissions of a field. An empty list removes the restriction.
+    pub async fn replace_for_field(
+        pool: &sqlx::MySqlPool,
+        field_id: u32,
+        permissions: &[FieldPermissionRequest],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            r#"DELETE FROM page_field_permissions WHERE field_id = ?"#,
+            field_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for permission in permissions {
+            sqlx::query!(
+                r#"
+                INSERT INTO page_field_permissions (field_id, role_id, can_read, can_write)
+                VALUES (?, ?, ?, ?)
+                "#,
+                field_id,
+                permission.role_id,
+                // Writing a field implies being able to see it
+                permission.can_read || permission.can_write,
+                permission.can_write
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+impl FieldAccess {
+    pub async fn for_user_on_page(
+        pool: &sqlx::MySqlPool,
+        user_id: i32,
+        page_id: u32,
+    ) -> Result<FieldAccess, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                f.name,
+                MAX(CASE WHEN r.is_admin = 1 OR fp.can_read = 1 THEN 1 ELSE 0 END) as can_read,
+                MAX(CASE WHEN r.is_admin = 1 OR fp.can_write = 1 THEN 1 ELSE 0 END) as can_write
+            FROM page_fields f
+            JOIN page_field_permissions restricted ON restricted.field_id = f.id
+            LEFT JOIN user_effective_roles ur ON ur.user_id = ?
+            LEFT JOIN roles r ON r.id = ur.role_id
+            LEFT JOIN page_field_permissions fp ON fp.field_id = f.id AND fp.role_id = ur.role_id
+            WHERE f.page_id = ?
+            GROUP BY f.id, f.name
+            "#,
+            user_id,
+            page_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut access = FieldAccess {
+            hidden: HashSet::new(),
+            read_only: HashSet::new(),
+        };
+
+        for row in rows {
+            if row.can_read.unwrap_or(0) != 1 {
+                access.hidden.insert(row.name.clone());
+            }
+            if row.can_write.unwrap_or(0) != 1 {
+                access.read_only.insert(row.name);
+            }
+        }
+
+        Ok(access)
+    }
+
+    pub fn can_read(&self, field_name: &str) -> bool {
+        !self.hidden.contains(field_name)
+    }
+
+    pub fn can_write(&self, field_name: &str) -> bool {
+        !self.read_only.contains(field_name)
+    }
+
+    /// Removes the values of hidden fields from a record's data.
+    pub fn strip_hidden(&self, data: &mut serde_json::Value) {
+        if self.hidden.is_empty() {
+            return;
+        }
+        if let serde_json::Value::Object(map) = data {
+            map.retain(|name, _| self.can_read(name));
+        }
+    }
+
+    /// Checks an incoming record against the existing one and carries over the values of the
+    /// fields the user cannot write, so hidden values are not lost on save.
+    ///
+    /// Returns the name of the first read-only field whose value the user tried to change.
+    pub fn apply_read_only(
+        &self,
+        existing: &serde_json::Value,
+        incoming: &mut serde_json::Value,
+    ) -> Result<(), String> {
+        let serde_json::Value::Object(incoming_map) = incoming else {
+            return Ok(());
+        };
+
+        for name in &self.read_only {
+            let existing_value = existing.get(name);
+            match incoming_map.get(name) {
+                Some(new_value) => {
+                    let unchanged = match existing_value {
+                        Some(old_value) => old_value == new_value,
+                        None => new_value.is_null(),
+                    };
+                    if !unchanged {
+                        return Err(name.clone());
+                    }
+                }
+                None => {
+                    if let Some(old_value) = existing_value {
+                        incoming_map.insert(name.clone(), old_value.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::HashSet;
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+
+use crate::{models::location::Location, utils::working_days::easter_sunday};
+
+pub const HOLIDAY_SCOPE_NATIONAL: &str = "NATIONAL";
+/// Holiday of one municipality, only for the clinic in it
+pub const HOLIDAY_SCOPE_MUNICIPAL: &str = "MUNICIPAL";
+/// Day the company closes, in every clinic or in one
+pub const HOLIDAY_SCOPE_COMPANY: &str = "COMPANY";
+
+/// A holiday that comes back every year, either on a fixed date or a number of days from
+/// Easter Sunday.
+#[derive(Debug, Serialize)]
+pub struct HolidayRule {
+    pub id: u32,
+    pub name: String,
+    pub scope: String,
+    /// [`Location`] value, `None` for every clinic
+    pub location: Option<i8>,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+    pub easter_offset: Option<i16>,
+    pub active: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HolidayRuleRequest {
+    pub name: String,
+    pub scope: String,
+    pub location: Option<i8>,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+    pub easter_offset: Option<i16>,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+/// A holiday or closure on a given date.
+#[derive(Debug, Serialize)]
+pub struct Holiday {
+    /// `None` for holidays worked out from the rules of a year not generated yet
+    pub id: Option<u32>,
+    /// The rule it was generated from, `None` for holidays added by hand
+    pub rule_id: Option<u32>,
+    pub name: String,
+    pub holiday_date: NaiveDate,
+    pub scope: String,
+    pub location: Option<i8>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HolidayRequest {
+    pub name: String,
+    pub holiday_date: NaiveDate,
+    pub scope: String,
+    pub location: Option<i8>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HolidayYearQuery {
+    pub year: i32,
+}
+
+/// Checks the scope and location of a holiday or rule: national holidays apply everywhere,
+/// municipal ones to one clinic.
+fn validate_scope(scope: &str, location: Option<i8>) -> Result<(), String> {
+    if let Some(location) = location {
+        if Location::from_value(location).is_none() {
+            return Err(format!("Localização inválida: {}", location));
+        }
+    }
+
+    match (scope, location) {
+        (HOLIDAY_SCOPE_NATIONAL, None) => Ok(()),
+        (HOLIDAY_SCOPE_NATIONAL, Some(_)) => {
+            Err("Um feriado nacional não pode ter localização.".to_string())
+        }
+        (HOLIDAY_SCOPE_MUNICIPAL, None) => {
+            Err("Um feriado municipal tem de ter localização.".to_string())
+        }
+        (HOLIDAY_SCOPE_MUNICIPAL, Some(_)) | (HOLIDAY_SCOPE_COMPANY, _) => Ok(()),
+        _ => Err(format!("Âmbito inválido: {}", scope)),
+    }
+}
+
+impl HolidayRuleRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("O nome é obrigatório.".to_string());
+        }
+        validate_scope(&self.scope, self.location)?;
+
+        match (self.month, self.day, self.easter_offset) {
+            // A leap year, so 29 February is accepted
+            (Some(month), Some(day), None) => {
+                NaiveDate::from_ymd_opt(2024, month as u32, day as u32)
+                    .map(|_| ())
+                    .ok_or_else(|| format!("Data inválida: {}/{}", day, month))
+            }
+            (None, None, Some(offset)) if offset.abs() <= 300 => Ok(()),
+            _ => Err("Indique o dia e o mês, ou os dias a partir da Páscoa.".to_string()),
+        }
+    }
+}
+
+impl HolidayRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("O nome é obrigatório.".to_string());
+        }
+        validate_scope(&self.scope, self.location)
+    }
+}
+
+impl HolidayRule {
+    /// When the rule falls in `year`. A fixed 29 February is skipped in common years.
+    pub fn date_in_year(&self, year: i32) -> Option<NaiveDate> {
+        match (self.month, self.day, self.easter_offset) {
+            (_, _, Some(offset)) => {
+                easter_sunday(year).map(|easter| easter + chrono::Duration::days(offset as i64))
+            }
+            (Some(month), Some(day), None) => {
+                NaiveDate::from_ymd_opt(year, month as u32, day as u32)
+            }
+            _ => None,
+        }
+    }
+
+    pub async fn get_all(pool: &MySqlPool) -> Result<Vec<HolidayRule>, sqlx::Error> {
+        sqlx::query_as!(
+            HolidayRule,
+            r#"
+            SELECT id, name, scope, location, month, day, easter_offset, active as "active: bool"
+            FROM holiday_rules
+            ORDER BY scope, location, month, day, easter_offset
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn create(pool: &MySqlPool, rule: &HolidayRuleRequest) -> Result<u32, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO holiday_rules (name, scope, location, month, day, easter_offset, active)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+            rule.name.trim(),
+            rule.scope,
+            rule.location,
+            rule.month,
+            rule.day,
+            rule.easter_offset,
+            rule.active
+        )
+        .execute(pool)
+        .await?;
+
+        let rule_id = result.last_insert_id() as u32;
+        Self::regenerate_upcoming(pool, rule_id).await?;
+
+        Ok(rule_id)
+    }
+
+    /// Returns false when the rule does not exist.
+    pub async fn update(
+        pool: &MySqlPool,
+        rule_id: u32,
+        rule: &HolidayRuleRequest,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE holiday_rules
+            SET name = ?, scope = ?, location = ?, month = ?, day = ?, easter_offset = ?,
+                active = ?
+            WHERE id = ?
+            "#,
+            rule.name.trim(),
+            rule.scope,
+            rule.location,
+            rule.month,
+            rule.day,
+            rule.easter_offset,
+            rule.active,
+            rule_id
+        )
+        .execute(pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        Self::regenerate_upcoming(pool, rule_id).await?;
+
+        Ok(true)
+    }
+
+    /// Deletes the rule and the holidays it generated from today on. Past ones stay, they
+    /// were counted in vacation requests already.
+    pub async fn delete(pool: &MySqlPool, rule_id: u32) -> Result<bool, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            r#"DELETE FROM holidays WHERE rule_id = ? AND holiday_date >= CURDATE()"#,
+            rule_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let result = sqlx::query!(r#"DELETE FROM holiday_rules WHERE id = ?"#, rule_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Replaces the holidays a rule generated from today on, so a changed rule applies to the
+    /// years already generated.
+    async fn regenerate_upcoming(pool: &MySqlPool, rule_id: u32) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            r#"DELETE FROM holidays WHERE rule_id = ? AND holiday_date >= CURDATE()"#,
+            rule_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let rule = sqlx::query_as!(
+            HolidayRule,
+            r#"
+            SELECT id, name, scope, location, month, day, easter_offset, active as "active: bool"
+            FROM holiday_rules
+            WHERE id = ? AND active = TRUE
+            "#,
+            rule_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(rule) = rule {
+            let today = chrono::Utc::now().date_naive();
+            let years = sqlx::query_scalar!(
+                r#"SELECT year FROM holiday_years WHERE year >= ?"#,
+                today.year()
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+
+            for year in years {
+                let Some(date) = rule.date_in_year(year as i32).filter(|date| *date >= today)
+                else {
+                    continue;
+                };
+                sqlx::query!(
+                    r#"
+                    INSERT IGNORE INTO holidays (rule_id, name, holiday_date, scope, location)
+                    VALUES (?, ?, ?, ?, ?)
+                    "#,
+                    rule.id,
+                    rule.name,
+                    date,
+                    rule.scope,
+                    rule.location
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await
+    }
+}
+
+impl Holiday {
+    /// Creates the holidays of `year` from the active rules, once per year unless `force` is
+    /// set. Forcing adds back what is missing, such as a generated holiday deleted by mistake.
+    /// Returns how many were created.
+    pub async fn generate_year(
+        pool: &MySqlPool,
+        year: i32,
+        force: bool,
+    ) -> Result<u64, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        if !force {
+            let generated =
+                sqlx::query_scalar!(r#"SELECT COUNT(*) FROM holiday_years WHERE year = ?"#, year)
+                    .fetch_one(&mut *tx)
+                    .await?;
+            if generated > 0 {
+                return Ok(0);
+            }
+        }
+
+        let rules = sqlx::query_as!(
+            HolidayRule,
+            r#"
+            SELECT id, name, scope, location, month, day, easter_offset, active as "active: bool"
+            FROM holiday_rules
+            WHERE active = TRUE
+            "#
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut created = 0;
+        for rule in &rules {
+            let Some(date) = rule.date_in_year(year) else {
+                continue;
+            };
+            created += sqlx::query!(
+                r#"
+                INSERT IGNORE INTO holidays (rule_id, name, holiday_date, scope, location)
+                VALUES (?, ?, ?, ?, ?)
+                "#,
+                rule.id,
+                rule.name,
+                date,
+                rule.scope,
+                rule.location
+            )
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+
+        sqlx::query!(
+            r#"INSERT IGNORE INTO holiday_years (year) VALUES (?)"#,
+            year
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        if created > 0 {
+            log::info!("Generated {} holidays for {}", created, year);
+        }
+
+        Ok(created)
+    }
+
+    /// Every stored holiday of a year, of every scope and location. Until the year is generated
+    /// that is only the ones added by hand.
+    pub async fn get_for_year(pool: &MySqlPool, year: i32) -> Result<Vec<Holiday>, sqlx::Error> {
+        sqlx::query_as!(
+            Holiday,
+            r#"
+            SELECT id as "id?", rule_id, name, holiday_date, scope, location
+            FROM holidays
+            WHERE YEAR(holiday_date) = ?
+            ORDER BY holiday_date, scope, location
+            "#,
+            year
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Holidays in [start, end] that apply at `location`: national ones, closures of every
+    /// clinic and the ones of that location. Without a location only the first two.
+    ///
+    /// Years not generated yet get the holidays of the active rules worked out on the fly.
+    /// Nothing is stored for them, that is left to the scheduler and to admins.
+    pub async fn get_between(
+        pool: &MySqlPool,
+        start: NaiveDate,
+        end: NaiveDate,
+        location: Option<i8>,
+    ) -> Result<Vec<Holiday>, sqlx::Error> {
+        let mut holidays = sqlx::query_as!(
+            Holiday,
+            r#"
+            SELECT id as "id?", rule_id, name, holiday_date, scope, location
+            FROM holidays
+            WHERE holiday_date BETWEEN ? AND ?
+              AND (location IS NULL OR location = ?)
+            ORDER BY holiday_date
+            "#,
+            start,
+            end,
+            location
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let generated: HashSet<i32> = sqlx::query_scalar!(
+            r#"SELECT year FROM holiday_years WHERE year BETWEEN ? AND ?"#,
+            start.year(),
+            end.year()
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(i32::from)
+        .collect();
+        let missing: Vec<i32> = (start.year()..=end.year())
+            .filter(|year| !generated.contains(year))
+            .collect();
+        if missing.is_empty() {
+            return Ok(holidays);
+        }
+
+        let rules = sqlx::query_as!(
+            HolidayRule,
+            r#"
+            SELECT id, name, scope, location, month, day, easter_offset, active as "active: bool"
+            FROM holiday_rules
+            WHERE active = TRUE AND (location IS NULL OR location = ?)
+            "#,
+            location
+        )
+        .fetch_all(pool)
+        .await?;
+
+        for rule in rules {
+            for &year in &missing {
+                let Some(date) = rule
+                    .date_in_year(year)
+                    .filter(|date| (start..=end).contains(date))
+                else {
+                    continue;
+                };
+                holidays.push(Holiday {
+                    id: None,
+                    rule_id: Some(rule.id),
+                    name: rule.name.clone(),
+                    holiday_date: date,
+                    scope: rule.scope.clone(),
+                    location: rule.location,
+                });
+            }
+        }
+        holidays.sort_by_key(|holiday| holiday.holiday_date);
+
+        Ok(holidays)
+    }
+
+    /// Dates of [`Holiday::get_between`], for counting working days.
+    pub async fn dates_between(
+        pool: &MySqlPool,
+        start: NaiveDate,
+        end: NaiveDate,
+        location: Option<i8>,
+    ) -> Result<HashSet<NaiveDate>, sqlx::Error> {
+        Ok(Self::get_between(pool, start, end, location)
+            .await?
+            .into_iter()
+            .map(|holiday| holiday.holiday_date)
+            .collect())
+    }
+
+    pub async fn create(
+        pool: &MySqlPool,
+        holiday: &HolidayRequest,
+        created_by: u32,
+    ) -> Result<u32, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO holidays (name, holiday_date, scope, location, created_by)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+            holiday.name.trim(),
+            holiday.holiday_date,
+            holiday.scope,
+            holiday.location,
+            created_by
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_id() as u32)
+    }
+
+    pub async fn update(
+        pool: &MySqlPool,
+        holiday_id: u32,
+        holiday: &HolidayRequest,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE holidays SET name = ?, holiday_date = ?, scope = ?, location = ?
+            WHERE id = ?
+            "#,
+            holiday.name.trim(),
+            holiday.holiday_date,
+            holiday.scope,
+            holiday.location,
+            holiday_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn delete(pool: &MySqlPool, holiday_id: u32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(r#"DELETE FROM holidays WHERE id = ?"#, holiday_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
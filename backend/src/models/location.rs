@@ -1,5 +1,5 @@
 use crate::impl_enum_conversions;
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Location {
     VianaDoCastelo,
     Braga,
@@ -8,3 +8,29 @@ pub enum Location {
 }
 
 impl_enum_conversions!(Location, VianaDoCastelo => 0, Braga => 1, Porto => 2, VilaReal => 3);
+
+impl Location {
+    pub const ALL: [Location; 4] = [
+        Location::VianaDoCastelo,
+        Location::Braga,
+        Location::Porto,
+        Location::VilaReal,
+    ];
+
+    /// The location stored as `value`, `None` for values that are not a location (unlike
+    /// `From<i8>`, which falls back to the first one).
+    pub fn from_value(value: i8) -> Option<Location> {
+        Location::ALL
+            .into_iter()
+            .find(|location| *location as i8 == value)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Location::VianaDoCastelo => "Viana do Castelo",
+            Location::Braga => "Braga",
+            Location::Porto => "Porto",
+            Location::VilaReal => "Vila Real",
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use chrono::{DateTime, Utc};
+use sqlx::MySqlPool;
+
+use crate::utils::login_throttle::ThrottlePolicy;
+
+pub struct LoginThrottle;
+
+impl LoginThrottle {
+    /// End of the current lock of a key, if it is locked.
+    pub async fn locked_until(
+        pool: &MySqlPool,
+        scope: &str,
+        key: &str,
+    ) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let locked_until = sqlx::query_scalar!(
+            r#"
+            SELECT locked_until
+            FROM login_throttles
+            WHERE scope = ? AND throttle_key = ? AND locked_until > ?
+            "#,
+            scope,
+            key,
+            Utc::now()
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(locked_until.flatten())
+    }
+
+    /// Counts a failed login for a key and locks it when the policy says so. Returns the end of
+    /// the new lock.
+    pub async fn record_failure(
+        pool: &MySqlPool,
+        scope: &str,
+        key: &str,
+        policy: &ThrottlePolicy,
+    ) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let now = Utc::now();
+        let stale_before = now - policy.reset_after;
+
+        // MySQL applies the assignments in order, so the counter still sees the previous failure
+        sqlx::query!(
+            r#"
+            INSERT INTO login_throttles (scope, throttle_key, failed_attempts, last_failure_at)
+            VALUES (?, ?, 1, ?)
+            ON DUPLICATE KEY UPDATE
+                failed_attempts = IF(last_failure_at < ?, 1, failed_attempts + 1),
+                last_failure_at = VALUES(last_failure_at)
+            "#,
+            scope,
+            key,
+            now,
+            stale_before
+        )
+        .execute(pool)
+        .await?;
+
+        let failed_attempts = sqlx::query_scalar!(
+            r#"SELECT failed_attempts FROM login_throttles WHERE scope = ? AND throttle_key = ?"#,
+            scope,
+            key
+        )
+        .fetch_one(pool)
+        .await?;
+
+        let Some(lock) = policy.lock_duration(failed_attempts) else {
+            return Ok(None);
+        };
+
+        let locked_until = now + lock;
+        sqlx::query!(
+            r#"UPDATE login_throttles SET locked_until = ? WHERE scope = ? AND throttle_key = ?"#,
+            locked_until,
+            scope,
+            key
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(Some(locked_until))
+    }
+
+    /// Forgets the failures of a key, lifting its lock.
+    pub async fn clear(pool: &MySqlPool, scope: &str, key: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"DELETE FROM login_throttles WHERE scope = ? AND throttle_key = ?"#,
+            scope,
+            key
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -1,15 +1,32 @@
 // pub mod contract;
-// pub mod location;
-// pub mod work_contract;
+pub mod work_contract;
 
+pub mod absence_type;
+pub mod admin_scope;
+pub mod app_setting;
+pub mod coverage_rule;
 pub mod custom_page;
+pub mod directory_group;
 pub mod field;
+pub mod holiday;
+pub mod location;
+pub mod login_throttle;
 pub mod notification;
 pub mod page_record;
+pub mod page_row_rule;
+pub mod password_history;
 pub mod record_acknowledgment;
+pub mod record_due_date;
 pub mod role;
+pub mod scheduled_job;
+pub mod security_event;
+pub mod two_factor;
 pub mod user;
+pub mod user_token;
+pub mod vacation_approval;
+pub mod vacation_ledger;
+pub mod vacation_overview;
 pub mod vacation_request;
 pub mod validation;
+pub mod work_schedule;
 
-pub mod auth;
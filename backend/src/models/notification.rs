@@ -10,6 +10,7 @@ pub const NOTIFICATION_TYPE_VACATION_REJECTED: &str = "VACATION_REJECTED";
 pub const NOTIFICATION_TYPE_VACATION_CANCELED: &str = "VACATION_CANCELED";
 pub const NOTIFICATION_TYPE_VACATION_CANCELLATION_REJECTED: &str = "VACATION_CANCELLATION_REJECTED";
 pub const NOTIFICATION_TYPE_VACATION_REQUESTED: &str = "VACATION_REQUESTED";
+pub const NOTIFICATION_TYPE_VACATION_APPROVAL_PENDING: &str = "VACATION_APPROVAL_PENDING";
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Notification {
@@ -35,6 +36,19 @@ pub struct Notification {
     // pub page_path: Option<String>, // Example: Might join page path later
 }
 
+/// Owned notification data used for multi-row inserts.
+#[derive(Debug)]
+pub struct NewNotification {
+    pub user_id: u32,
+    pub record_id: Option<u32>,
+    pub vacation_request_id: Option<u32>,
+    pub page_id: Option<u32>,
+    pub field_id: Option<u32>,
+    pub notification_type: &'static str,
+    pub message: String,
+    pub due_date: Option<NaiveDate>,
+}
+
 // Optional: Struct for API response that might include joined data
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NotificationResponse {
@@ -169,35 +183,6 @@ impl Notification {
         Ok(result.rows_affected())
     }
 
-    /// Checks if any notification (read or unread) for a specific user/record/type/due_date already exists.
-    /// This helps prevent re-notifying for the exact same expiring event if it has already been created.
-    pub async fn check_if_event_notification_exists(
-        pool: &sqlx::MySqlPool,
-        user_id: u32,
-        record_id: u32,
-        notification_type: &str,
-        due_date: Option<NaiveDate>, // Add due_date to make the check event-specific
-    ) -> Result<bool, sqlx::Error> {
-        let count = sqlx::query_scalar!(
-            r#"
-            SELECT COUNT(*) 
-            FROM notifications 
-            WHERE user_id = ? 
-              AND record_id = ? 
-              AND notification_type = ? 
-              AND due_date <=> ?  -- Use NULL-safe equality for due_date
-            "#,
-            user_id,
-            record_id,
-            notification_type,
-            due_date
-        )
-        .fetch_one(pool)
-        .await?;
-
-        Ok(count > 0)
-    }
-
     /// Creates a new notification.
     pub async fn create(
         pool: &sqlx::MySqlPool,
@@ -230,4 +215,32 @@ impl Notification {
         .await?;
         Ok(())
     }
+
+    /// Inserts many notifications with a single multi-row INSERT.
+    pub async fn create_many(
+        pool: &sqlx::MySqlPool,
+        notifications: &[NewNotification],
+    ) -> Result<u64, sqlx::Error> {
+        if notifications.is_empty() {
+            return Ok(0);
+        }
+
+        let mut query_builder = sqlx::QueryBuilder::<sqlx::MySql>::new(
+            "INSERT INTO notifications (user_id, record_id, vacation_request_id, page_id, field_id, notification_type, message, due_date, is_read) ",
+        );
+        query_builder.push_values(notifications, |mut row, n| {
+            row.push_bind(n.user_id)
+                .push_bind(n.record_id)
+                .push_bind(n.vacation_request_id)
+                .push_bind(n.page_id)
+                .push_bind(n.field_id)
+                .push_bind(n.notification_type)
+                .push_bind(&n.message)
+                .push_bind(n.due_date)
+                .push_bind(false);
+        });
+
+        let result = query_builder.build().execute(pool).await?;
+        Ok(result.rows_affected())
+    }
 }
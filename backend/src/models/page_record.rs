@@ -1,6 +1,12 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, MySql, QueryBuilder};
+
+/// Where the files of each record are kept, under the record's id. Outside `media`, so only
+/// the record handlers serve them.
+pub const RECORD_FILES_DIR: &str = "documents/page_records";
+/// Where they were kept before, served to anyone
+const LEGACY_RECORD_FILES_DIR: &str = "media/page_records";
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct PageRecord {
@@ -92,7 +98,28 @@ impl PageRecord {
         pool: &sqlx::MySqlPool,
         record_id: u32,
     ) -> Result<PageRecordWithFiles, sqlx::Error> {
-        let record = sqlx::query_as!(
+        let record = Self::get_record_by_id(pool, record_id).await?;
+
+        let files = sqlx::query_as!(
+            PageRecordFile,
+            r#"
+            SELECT id, record_id, file_name, file_path, uploaded_at as "uploaded_at!", uploaded_by
+            FROM page_record_files
+            WHERE record_id = ?
+            "#,
+            record_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(PageRecordWithFiles { record, files })
+    }
+
+    pub async fn get_record_by_id(
+        pool: &sqlx::MySqlPool,
+        record_id: u32,
+    ) -> Result<PageRecord, sqlx::Error> {
+        sqlx::query_as!(
             PageRecord,
             r#"
             SELECT
@@ -109,21 +136,32 @@ impl PageRecord {
             record_id
         )
         .fetch_one(pool)
-        .await?;
+        .await
+    }
 
-        let files = sqlx::query_as!(
-            PageRecordFile,
-            r#"
-            SELECT id, record_id, file_name, file_path, uploaded_at as "uploaded_at!", uploaded_by
-            FROM page_record_files
-            WHERE record_id = ?
-            "#,
-            record_id
-        )
-        .fetch_all(pool)
-        .await?;
+    /// The records among `record_ids` that still exist, in no particular order.
+    pub async fn get_by_ids(
+        pool: &sqlx::MySqlPool,
+        record_ids: &[u32],
+    ) -> Result<Vec<PageRecord>, sqlx::Error> {
+        if record_ids.is_empty() {
+            return Ok(Vec::new());
+        }
 
-        Ok(PageRecordWithFiles { record, files })
+        let mut query_builder = QueryBuilder::<MySql>::new(
+            "SELECT id, page_id, data, created_at, updated_at, created_by, updated_by \
+             FROM page_records WHERE id IN (",
+        );
+        let mut ids = query_builder.separated(", ");
+        for record_id in record_ids {
+            ids.push_bind(*record_id);
+        }
+        ids.push_unseparated(")");
+
+        query_builder
+            .build_query_as::<PageRecord>()
+            .fetch_all(pool)
+            .await
     }
 
     pub async fn update(
@@ -187,10 +225,12 @@ impl PageRecord {
         Ok(())
     }
 
+    /// `hidden_fields` are never matched against, so the search cannot reveal their values.
     pub async fn search_records(
         pool: &sqlx::MySqlPool,
         page_id: u32,
         search_term: &str,
+        hidden_fields: &ahash::HashSet<String>,
     ) -> Result<Vec<PageRecord>, sqlx::Error> {
         // Get all records for the page
         let records = Self::get_by_page_id(pool, page_id).await?;
@@ -203,7 +243,10 @@ impl PageRecord {
         // Get searchable fields for the page
         let fields = crate::models::field::PageField::get_by_page_id(pool, page_id).await?;
         let searchable_fields: Vec<&crate::models::field::PageField> =
-            fields.iter().filter(|f| f.is_searchable).collect();
+            fields
+                .iter()
+                .filter(|f| f.is_searchable && !hidden_fields.contains(&f.name))
+                .collect();
 
         // Filter records by search term
         let search_term = search_term.to_lowercase();
@@ -227,6 +270,48 @@ impl PageRecord {
         Ok(filtered_records)
     }
 
+    /// Moves the files uploaded under `media` to [`RECORD_FILES_DIR`], run at startup. Records
+    /// whose directory cannot be moved keep their old paths.
+    pub async fn move_files_out_of_media(pool: &sqlx::MySqlPool) -> Result<(), sqlx::Error> {
+        let Ok(entries) = std::fs::read_dir(LEGACY_RECORD_FILES_DIR) else {
+            return Ok(());
+        };
+        if let Err(e) = std::fs::create_dir_all(RECORD_FILES_DIR) {
+            log::error!("Error creating {}: {}", RECORD_FILES_DIR, e);
+            return Ok(());
+        }
+
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let Some(record_dir) = name.to_str() else {
+                continue;
+            };
+            let target = format!("{}/{}", RECORD_FILES_DIR, record_dir);
+            if let Err(e) = std::fs::rename(entry.path(), &target) {
+                log::error!("Error moving record files to {}: {}", target, e);
+                continue;
+            }
+
+            sqlx::query!(
+                r#"
+                UPDATE page_record_files
+                SET file_path = CONCAT(?, SUBSTRING(file_path, CHAR_LENGTH(?) + 1))
+                WHERE file_path LIKE CONCAT(?, '/%')
+                "#,
+                target,
+                format!("{}/{}", LEGACY_RECORD_FILES_DIR, record_dir),
+                format!("{}/{}", LEGACY_RECORD_FILES_DIR, record_dir)
+            )
+            .execute(pool)
+            .await?;
+        }
+
+        if let Err(e) = std::fs::remove_dir(LEGACY_RECORD_FILES_DIR) {
+            log::warn!("Could not remove {}: {}", LEGACY_RECORD_FILES_DIR, e);
+        }
+        Ok(())
+    }
+
     pub async fn get_page_id_for_record(pool: &sqlx::MySqlPool, record_id: u32) -> Result<u32, sqlx::Error> {
         let result = sqlx::query_scalar!(
             r#"SELECT page_id FROM page_records WHERE id = ?"#,
@@ -0,0 +1,377 @@
+use ahash::HashMap;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, MySqlPool};
+
+use super::{location::Location, page_record::PageRecord, role::Role, user::User};
+
+/// Maximum number of records loaded by a single query when preloading a list.
+const PRELOAD_BATCH_SIZE: usize = 500;
+
+pub const ROW_ACTION_VIEW: &str = "VIEW";
+pub const ROW_ACTION_EDIT: &str = "EDIT";
+pub const ROW_ACTION_DELETE: &str = "DELETE";
+
+/// Only records created by the user
+pub const ROW_RULE_OWN_RECORDS: &str = "OWN_RECORDS";
+/// Only records where `field_name` holds one of the user's roles, inherited ones included (by
+/// name or id)
+pub const ROW_RULE_FIELD_MATCHES_ROLE: &str = "FIELD_MATCHES_ROLE";
+/// Only records where `field_name` holds the user's location (by name or number)
+pub const ROW_RULE_FIELD_MATCHES_LOCATION: &str = "FIELD_MATCHES_LOCATION";
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct PageRowRule {
+    pub id: u32,
+    pub page_id: u32,
+    pub role_id: u32,
+    pub role_name: String,
+    pub action: String,
+    pub rule_type: String,
+    pub field_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RowRuleRequest {
+    pub role_id: u32,
+    pub action: String,
+    pub rule_type: String,
+    pub field_name: Option<String>,
+}
+
+impl RowRuleRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if ![ROW_ACTION_VIEW, ROW_ACTION_EDIT, ROW_ACTION_DELETE].contains(&self.action.as_str()) {
+            return Err(format!("Ação inválida: {}", self.action));
+        }
+
+        match self.rule_type.as_str() {
+            ROW_RULE_OWN_RECORDS => Ok(()),
+            ROW_RULE_FIELD_MATCHES_ROLE | ROW_RULE_FIELD_MATCHES_LOCATION => {
+                match &self.field_name {
+                    Some(name) if !name.trim().is_empty() => Ok(()),
+                    _ => Err(format!("A regra {} requer um campo", self.rule_type)),
+                }
+            }
+            other => Err(format!("Tipo de regra inválido: {}", other)),
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct GrantingRoleRule {
+    is_admin: bool,
+    rule_type: Option<String>,
+    field_name: Option<String>,
+}
+
+/// Which records of a page a user reaches for a given action.
+#[derive(Debug)]
+pub enum RowAccess {
+    /// No row-level restriction applies
+    All,
+    /// None of the user's roles grants the action on the page, as for scoped admins who can
+    /// only configure it
+    None,
+    /// Only records matching at least one of the rules
+    Rules {
+        user_id: u32,
+        own_records: bool,
+        role_fields: Vec<String>,
+        role_ids: Vec<u32>,
+        role_names: Vec<String>,
+        location_fields: Vec<String>,
+        /// `None` when the user has no location, and no location field matches
+        location: Option<Location>,
+    },
+}
+
+/// Row access for lists that span users and pages, such as the expiring dates. Access is
+/// resolved once per user and page, and records are only loaded where rules have to be checked,
+/// in batches when the list is preloaded.
+#[derive(Default)]
+pub struct RowAccessCache {
+    access: HashMap<(u32, u32), RowAccess>,
+    records: HashMap<u32, Option<PageRecord>>,
+}
+
+impl PageRowRule {
+    pub async fn get_by_page_id(
+        pool: &MySqlPool,
+        page_id: u32,
+    ) -> Result<Vec<PageRowRule>, sqlx::Error> {
+        sqlx::query_as!(
+            PageRowRule,
+            r#"
+            SELECT rr.id, rr.page_id, rr.role_id, r.name as role_name,
+                   rr.action, rr.rule_type, rr.field_name
+            FROM page_row_rules rr
+            JOIN roles r ON rr.role_id = r.id
+            WHERE rr.page_id = ?
+            ORDER BY rr.role_id, rr.action, rr.id
+            "#,
+            page_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn replace_for_page(
+        pool: &MySqlPool,
+        page_id: u32,
+        rules: &[RowRuleRequest],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(r#"DELETE FROM page_row_rules WHERE page_id = ?"#, page_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for rule in rules {
+            sqlx::query!(
+                r#"
+                INSERT INTO page_row_rules (page_id, role_id, action, rule_type, field_name)
+                VALUES (?, ?, ?, ?, ?)
+                "#,
+                page_id,
+                rule.role_id,
+                rule.action,
+                rule.rule_type,
+                rule.field_name
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+impl RowAccess {
+    /// Resolves the row access of a user for an action on a page.
+    ///
+    /// Only the roles that grant the action at page level count. If any of them is admin or has
+    /// no rules for the action, every record is reachable; if there are none, no record is;
+    /// otherwise the rules of all those roles are combined.
+    pub async fn for_user(
+        pool: &MySqlPool,
+        user_id: i32,
+        page_id: u32,
+        action: &str,
+    ) -> Result<RowAccess, sqlx::Error> {
+        let grant = match action {
+            ROW_ACTION_VIEW => "pp.can_view = 1",
+            // Users who can only fill empty fields are still editing the record
+            ROW_ACTION_EDIT => "(pp.can_edit = 1 OR pp.can_add = 1)",
+            _ => "pp.can_delete = 1",
+        };
+
+        let rows = sqlx::query_as::<_, GrantingRoleRule>(&format!(
+            r#"
+            SELECT r.is_admin, rr.rule_type, rr.field_name
+            FROM user_effective_roles ur
+            JOIN roles r ON r.id = ur.role_id
+            LEFT JOIN effective_page_permissions pp ON pp.role_id = ur.role_id AND pp.page_id = ?
+            LEFT JOIN page_row_rules rr
+                ON rr.role_id = ur.role_id AND rr.page_id = ? AND rr.action = ?
+            WHERE ur.user_id = ? AND (r.is_admin = 1 OR {grant})
+            "#
+        ))
+        .bind(page_id)
+        .bind(page_id)
+        .bind(action)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+        if rows.is_empty() {
+            return Ok(RowAccess::None);
+        }
+        if rows.iter().any(|row| row.is_admin || row.rule_type.is_none()) {
+            return Ok(RowAccess::All);
+        }
+
+        let own_records = rows
+            .iter()
+            .any(|row| row.rule_type.as_deref() == Some(ROW_RULE_OWN_RECORDS));
+        let fields_of = |rule_type: &str| -> Vec<String> {
+            rows.iter()
+                .filter(|row| row.rule_type.as_deref() == Some(rule_type))
+                .filter_map(|row| row.field_name.clone())
+                .collect()
+        };
+        let role_fields = fields_of(ROW_RULE_FIELD_MATCHES_ROLE);
+        let location_fields = fields_of(ROW_RULE_FIELD_MATCHES_LOCATION);
+
+        let (role_ids, role_names) = if role_fields.is_empty() {
+            (Vec::new(), Vec::new())
+        } else {
+            Role::get_effective_roles_by_user_id(pool, user_id as u32)
+                .await?
+                .into_iter()
+                .map(|role| (role.id, role.name))
+                .unzip()
+        };
+
+        let location = if location_fields.is_empty() {
+            None
+        } else {
+            User::location(pool, user_id as u32)
+                .await?
+                .and_then(Location::from_value)
+        };
+
+        Ok(RowAccess::Rules {
+            user_id: user_id as u32,
+            own_records,
+            role_fields,
+            role_ids,
+            role_names,
+            location_fields,
+            location,
+        })
+    }
+
+    pub fn allows(&self, record: &PageRecord) -> bool {
+        match self {
+            RowAccess::All => true,
+            RowAccess::None => false,
+            RowAccess::Rules {
+                user_id,
+                own_records,
+                role_fields,
+                role_ids,
+                role_names,
+                location_fields,
+                location,
+            } => {
+                if *own_records && record.created_by == *user_id {
+                    return true;
+                }
+
+                let matches_role = |value: &serde_json::Value| match value {
+                    serde_json::Value::String(s) => {
+                        let s = s.trim();
+                        role_names.iter().any(|name| name == s)
+                            || s.parse::<u32>().is_ok_and(|id| role_ids.contains(&id))
+                    }
+                    serde_json::Value::Number(n) => n
+                        .as_u64()
+                        .is_some_and(|id| role_ids.iter().any(|&role_id| role_id as u64 == id)),
+                    _ => false,
+                };
+
+                let matches_location = |value: &serde_json::Value| {
+                    let Some(location) = location else {
+                        return false;
+                    };
+                    match value {
+                        serde_json::Value::String(s) => {
+                            let s = s.trim();
+                            s.eq_ignore_ascii_case(location.name())
+                                || s.parse::<i8>().is_ok_and(|n| n == *location as i8)
+                        }
+                        serde_json::Value::Number(n) => {
+                            n.as_i64().is_some_and(|n| n == *location as i64)
+                        }
+                        _ => false,
+                    }
+                };
+
+                role_fields
+                    .iter()
+                    .any(|field| field_matches(record, field, &matches_role))
+                    || location_fields
+                        .iter()
+                        .any(|field| field_matches(record, field, &matches_location))
+            }
+        }
+    }
+}
+
+impl RowAccessCache {
+    /// Resolves the access of every (user, page, record) of a list and loads the records that
+    /// rules have to be checked against, so [`Self::can_view`] does not fetch them one by one.
+    pub async fn preload(
+        &mut self,
+        pool: &MySqlPool,
+        items: impl IntoIterator<Item = (u32, u32, u32)>,
+    ) -> Result<(), sqlx::Error> {
+        let mut record_ids = Vec::new();
+        for (user_id, page_id, record_id) in items {
+            self.resolve(pool, user_id, page_id).await?;
+            if matches!(self.access[&(user_id, page_id)], RowAccess::Rules { .. })
+                && !self.records.contains_key(&record_id)
+            {
+                // Stays None if the record was deleted since it was listed
+                self.records.insert(record_id, None);
+                record_ids.push(record_id);
+            }
+        }
+
+        for batch in record_ids.chunks(PRELOAD_BATCH_SIZE) {
+            for record in PageRecord::get_by_ids(pool, batch).await? {
+                self.records.insert(record.id, Some(record));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the user can view the record of the page. Records deleted since they were
+    /// listed are not.
+    pub async fn can_view(
+        &mut self,
+        pool: &MySqlPool,
+        user_id: u32,
+        page_id: u32,
+        record_id: u32,
+    ) -> Result<bool, sqlx::Error> {
+        self.resolve(pool, user_id, page_id).await?;
+
+        match &self.access[&(user_id, page_id)] {
+            RowAccess::All => Ok(true),
+            RowAccess::None => Ok(false),
+            rules => {
+                if !self.records.contains_key(&record_id) {
+                    let record = match PageRecord::get_record_by_id(pool, record_id).await {
+                        Ok(record) => Some(record),
+                        Err(sqlx::Error::RowNotFound) => None,
+                        Err(e) => return Err(e),
+                    };
+                    self.records.insert(record_id, record);
+                }
+                Ok(self.records[&record_id]
+                    .as_ref()
+                    .is_some_and(|record| rules.allows(record)))
+            }
+        }
+    }
+
+    async fn resolve(
+        &mut self,
+        pool: &MySqlPool,
+        user_id: u32,
+        page_id: u32,
+    ) -> Result<(), sqlx::Error> {
+        if !self.access.contains_key(&(user_id, page_id)) {
+            let access =
+                RowAccess::for_user(pool, user_id as i32, page_id, ROW_ACTION_VIEW).await?;
+            self.access.insert((user_id, page_id), access);
+        }
+        Ok(())
+    }
+}
+
+fn field_matches(
+    record: &PageRecord,
+    field: &str,
+    matches: impl Fn(&serde_json::Value) -> bool,
+) -> bool {
+    match record.data.get(field) {
+        // Multi-select fields match if any of the selected values does
+        Some(serde_json::Value::Array(values)) => values.iter().any(&matches),
+        Some(value) => matches(value),
+        None => false,
+    }
+}
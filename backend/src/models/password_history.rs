@@ -0,0 +1,72 @@
+use sqlx::{MySql, MySqlPool, Transaction};
+
+pub struct PasswordHistory;
+
+impl PasswordHistory {
+    /// Current password hash of the user followed by up to `limit` previous ones, newest first.
+    pub async fn recent_hashes(
+        pool: &MySqlPool,
+        user_id: u32,
+        limit: u32,
+    ) -> Result<Vec<Vec<u8>>, sqlx::Error> {
+        let mut hashes = sqlx::query_scalar!(r#"SELECT password FROM users WHERE id = ?"#, user_id)
+            .fetch_all(pool)
+            .await?;
+
+        hashes.extend(
+            sqlx::query_scalar!(
+                r#"
+                SELECT password_hash
+                FROM password_history
+                WHERE user_id = ?
+                ORDER BY id DESC
+                LIMIT ?
+                "#,
+                user_id,
+                limit
+            )
+            .fetch_all(pool)
+            .await?,
+        );
+
+        Ok(hashes)
+    }
+
+    /// Adds a hash to the history, keeping only the latest `keep` entries of the user.
+    pub async fn record(
+        tx: &mut Transaction<'_, MySql>,
+        user_id: u32,
+        password_hash: &[u8],
+        keep: u32,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO password_history (user_id, password_hash) VALUES (?, ?)"#,
+            user_id,
+            password_hash
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        // MySQL does not allow LIMIT directly inside IN, hence the derived table
+        sqlx::query!(
+            r#"
+            DELETE FROM password_history
+            WHERE user_id = ? AND id NOT IN (
+                SELECT id FROM (
+                    SELECT id FROM password_history
+                    WHERE user_id = ?
+                    ORDER BY id DESC
+                    LIMIT ?
+                ) latest
+            )
+            "#,
+            user_id,
+            user_id,
+            keep
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,434 @@
+use ahash::{HashMap, HashMapExt};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, MySql, MySqlPool, QueryBuilder};
+
+use crate::utils::record_dates::extract_date_bounds;
+
+use super::{notification::NOTIFICATION_TYPE_DATE_EXPIRY, page_row_rule::RowAccessCache};
+
+/// Maximum number of rows sent in a single multi-row INSERT when re-indexing a field.
+const SYNC_INSERT_BATCH_SIZE: usize = 500;
+
+/// Denormalized index of the DATE / DATE_RANGE values of every record, kept in sync on
+/// record write so the expiry scan never has to load and parse `page_records.data`.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct RecordDueDate {
+    pub record_id: u32,
+    pub page_id: u32,
+    pub field_id: u32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+/// A notification that is due for a given user, as returned by the expiry scan.
+#[derive(Debug, FromRow)]
+pub struct DueDateNotification {
+    pub user_id: u32,
+    pub record_id: u32,
+    pub page_id: u32,
+    pub field_id: u32,
+    pub field_display_name: String,
+    pub due_date: NaiveDate,
+}
+
+/// A DATE / DATE_RANGE value falling inside the window requested by the dashboard.
+#[derive(Debug, FromRow)]
+pub struct ExpiringRecordRow {
+    pub page_id: u32,
+    pub page_name: String,
+    pub page_path: String,
+    pub field_id: u32,
+    pub field_name: String,
+    pub field_display_name: String,
+    pub record_id: u32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub due_date: NaiveDate,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExpiringRecord {
+    pub record_id: u32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub due_date: NaiveDate,
+    pub days_remaining: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExpiringFieldGroup {
+    pub field_id: u32,
+    pub field_name: String,
+    pub field_display_name: String,
+    pub records: Vec<ExpiringRecord>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExpiringPageGroup {
+    pub page_id: u32,
+    pub page_name: String,
+    pub page_path: String,
+    pub fields: Vec<ExpiringFieldGroup>,
+}
+
+#[derive(FromRow)]
+struct DateFieldRow {
+    id: u32,
+    name: String,
+}
+
+impl RecordDueDate {
+    /// Rebuilds the indexed dates of a single record from its JSON data.
+    pub async fn sync_for_record(
+        pool: &MySqlPool,
+        record_id: u32,
+        page_id: u32,
+        data: &serde_json::Value,
+    ) -> Result<(), sqlx::Error> {
+        let date_fields = Self::get_date_fields(pool, page_id).await?;
+
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!("DELETE FROM record_due_dates WHERE record_id = ?", record_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for field in date_fields {
+            let Some((start_date, end_date)) = data.get(&field.name).and_then(extract_date_bounds)
+            else {
+                continue;
+            };
+
+            sqlx::query!(
+                r#"
+                INSERT INTO record_due_dates (record_id, page_id, field_id, start_date, end_date)
+                VALUES (?, ?, ?, ?, ?)
+                "#,
+                record_id,
+                page_id,
+                field.id,
+                start_date,
+                end_date
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await
+    }
+
+    /// Rebuilds the indexed dates of one field in every record of its page, after it changed
+    /// to or from a date type. Runs in one transaction, inserting rows in batches.
+    pub async fn sync_for_field(
+        pool: &MySqlPool,
+        page_id: u32,
+        field_id: u32,
+    ) -> Result<(), sqlx::Error> {
+        let date_field = Self::get_date_fields(pool, page_id)
+            .await?
+            .into_iter()
+            .find(|field| field.id == field_id);
+
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!("DELETE FROM record_due_dates WHERE field_id = ?", field_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if let Some(field) = date_field {
+            let records = sqlx::query!(
+                r#"SELECT id, data as `data: serde_json::Value` FROM page_records WHERE page_id = ?"#,
+                page_id
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+
+            let dates: Vec<(u32, NaiveDate, NaiveDate)> = records
+                .iter()
+                .filter_map(|record| {
+                    let (start_date, end_date) =
+                        record.data.get(&field.name).and_then(extract_date_bounds)?;
+                    Some((record.id, start_date, end_date))
+                })
+                .collect();
+
+            for batch in dates.chunks(SYNC_INSERT_BATCH_SIZE) {
+                let mut query_builder = QueryBuilder::<MySql>::new(
+                    "INSERT INTO record_due_dates (record_id, page_id, field_id, start_date, end_date) ",
+                );
+                query_builder.push_values(batch, |mut row, (record_id, start_date, end_date)| {
+                    row.push_bind(*record_id)
+                        .push_bind(page_id)
+                        .push_bind(field_id)
+                        .push_bind(*start_date)
+                        .push_bind(*end_date);
+                });
+                query_builder.build().execute(&mut *tx).await?;
+            }
+        }
+
+        tx.commit().await
+    }
+
+    async fn get_date_fields(
+        pool: &MySqlPool,
+        page_id: u32,
+    ) -> Result<Vec<DateFieldRow>, sqlx::Error> {
+        sqlx::query_as!(
+            DateFieldRow,
+            r#"
+            SELECT f.id, f.name
+            FROM page_fields f
+            JOIN field_types t ON f.field_type_id = t.id
+            WHERE f.page_id = ? AND t.name IN ('DATE', 'DATE_RANGE')
+            "#,
+            page_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Returns every (user, record, field) expiry notification that should exist today but
+    /// has not been created yet. Users who cannot read the date field, or whose row rules
+    /// hide the record, are skipped.
+    ///
+    /// `last_success` is the date of the last completed scan: anything whose due date fell
+    /// after it is still picked up, so days missed while the server was down are caught up.
+    pub async fn get_due_notifications(
+        pool: &MySqlPool,
+        today: NaiveDate,
+        last_success: NaiveDate,
+        row_access: &mut RowAccessCache,
+    ) -> Result<Vec<DueDateNotification>, sqlx::Error> {
+        let due = sqlx::query_as::<_, DueDateNotification>(
+            r#"
+            SELECT viewers.user_id, due.record_id, due.page_id, due.field_id,
+                   due.field_display_name, due.due_date
+            FROM (
+                SELECT d.record_id, d.page_id, d.field_id,
+                       f.display_name AS field_display_name,
+                       f.notification_days_before,
+                       CASE WHEN f.notification_target_date_part = 'start_date'
+                            THEN d.start_date ELSE d.end_date END AS due_date
+                FROM record_due_dates d
+                JOIN page_fields f ON f.id = d.field_id
+                WHERE f.notification_enabled = true
+                  AND f.notification_days_before IS NOT NULL
+            ) due
+            JOIN (
+                SELECT ur.user_id, pp.page_id
+                FROM user_effective_roles ur
+                JOIN effective_page_permissions pp ON pp.role_id = ur.role_id
+                WHERE pp.can_view = 1
+                UNION
+                SELECT ur.user_id, cp.id AS page_id
+                FROM user_effective_roles ur
+                JOIN roles r ON r.id = ur.role_id AND r.is_admin = 1
+                CROSS JOIN custom_pages cp
+            ) viewers ON viewers.page_id = due.page_id
+            WHERE due.due_date - INTERVAL due.notification_days_before DAY <= ?
+              AND due.due_date > ?
+              AND NOT EXISTS (
+                  SELECT 1 FROM notifications n
+                  WHERE n.user_id = viewers.user_id
+                    AND n.record_id = due.record_id
+                    AND n.notification_type = ?
+                    AND n.due_date <=> due.due_date
+              )
+              AND (
+                  NOT EXISTS (
+                      SELECT 1 FROM page_field_permissions rfp WHERE rfp.field_id = due.field_id
+                  )
+                  OR EXISTS (
+                      SELECT 1
+                      FROM user_effective_roles fur
+                      LEFT JOIN roles fr ON fr.id = fur.role_id
+                      LEFT JOIN page_field_permissions fp
+                          ON fp.role_id = fur.role_id AND fp.field_id = due.field_id
+                      WHERE fur.user_id = viewers.user_id AND (fr.is_admin = 1 OR fp.can_read = 1)
+                  )
+              )
+            ORDER BY due.due_date
+            "#,
+        )
+        .bind(today)
+        .bind(last_success)
+        .bind(NOTIFICATION_TYPE_DATE_EXPIRY)
+        .fetch_all(pool)
+        .await?;
+
+        row_access
+            .preload(
+                pool,
+                due.iter()
+                    .map(|item| (item.user_id, item.page_id, item.record_id)),
+            )
+            .await?;
+
+        let mut reachable = Vec::with_capacity(due.len());
+        for item in due {
+            if row_access
+                .can_view(pool, item.user_id, item.page_id, item.record_id)
+                .await?
+            {
+                reachable.push(item);
+            }
+        }
+        Ok(reachable)
+    }
+
+    /// Returns the dates due between `from` and `to` (inclusive) on every page the user can
+    /// view, leaving out fields hidden from the user and records their row rules hide, ordered
+    /// by due date. For DATE_RANGE fields the due date is the part the field's notifications
+    /// target, the end date by default.
+    pub async fn get_expiring_for_user(
+        pool: &MySqlPool,
+        user_id: u32,
+        from: NaiveDate,
+        to: NaiveDate,
+        descending: bool,
+        row_access: &mut RowAccessCache,
+    ) -> Result<Vec<ExpiringRecordRow>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT due.*
+            FROM (
+                SELECT d.page_id, cp.name AS page_name, cp.path AS page_path,
+                       d.field_id, f.name AS field_name, f.display_name AS field_display_name,
+                       d.record_id, d.start_date, d.end_date,
+                       CASE WHEN f.notification_target_date_part = 'start_date'
+                            THEN d.start_date ELSE d.end_date END AS due_date
+                FROM record_due_dates d
+                JOIN page_fields f ON f.id = d.field_id
+                JOIN custom_pages cp ON cp.id = d.page_id
+                WHERE EXISTS (
+                    SELECT 1
+                    FROM user_effective_roles ur
+                    LEFT JOIN roles r ON r.id = ur.role_id
+                    LEFT JOIN effective_page_permissions pp
+                        ON pp.role_id = ur.role_id AND pp.page_id = d.page_id
+                    WHERE ur.user_id = ? AND (r.is_admin = 1 OR pp.can_view = 1)
+                )
+                AND (
+                    NOT EXISTS (
+                        SELECT 1 FROM page_field_permissions rfp WHERE rfp.field_id = d.field_id
+                    )
+                    OR EXISTS (
+                        SELECT 1
+                        FROM user_effective_roles ur
+                        LEFT JOIN roles r ON r.id = ur.role_id
+                        LEFT JOIN page_field_permissions fp
+                            ON fp.role_id = ur.role_id AND fp.field_id = d.field_id
+                        WHERE ur.user_id = ? AND (r.is_admin = 1 OR fp.can_read = 1)
+                    )
+                )
+            ) due
+            WHERE due.due_date BETWEEN ? AND ?
+            ORDER BY due.due_date {order}, due.page_id, due.field_id, due.record_id
+            "#,
+            order = if descending { "DESC" } else { "ASC" }
+        );
+
+        let rows = sqlx::query_as::<_, ExpiringRecordRow>(&query)
+            .bind(user_id)
+            .bind(user_id)
+            .bind(from)
+            .bind(to)
+            .fetch_all(pool)
+            .await?;
+
+        row_access
+            .preload(
+                pool,
+                rows.iter().map(|row| (user_id, row.page_id, row.record_id)),
+            )
+            .await?;
+
+        let mut reachable = Vec::with_capacity(rows.len());
+        for row in rows {
+            if row_access
+                .can_view(pool, user_id, row.page_id, row.record_id)
+                .await?
+            {
+                reachable.push(row);
+            }
+        }
+        Ok(reachable)
+    }
+
+    /// Groups rows by page and then by field, keeping the order of the rows: groups come in
+    /// the order of their first (i.e. most relevant) due date.
+    pub fn group_by_page_and_field(
+        rows: Vec<ExpiringRecordRow>,
+        today: NaiveDate,
+    ) -> Vec<ExpiringPageGroup> {
+        let mut pages: Vec<ExpiringPageGroup> = Vec::new();
+        let mut page_index: HashMap<u32, usize> = HashMap::new();
+        let mut field_index: HashMap<u32, usize> = HashMap::new();
+
+        for row in rows {
+            let page_pos = *page_index.entry(row.page_id).or_insert_with(|| {
+                pages.push(ExpiringPageGroup {
+                    page_id: row.page_id,
+                    page_name: row.page_name,
+                    page_path: row.page_path,
+                    fields: Vec::new(),
+                });
+                pages.len() - 1
+            });
+            let page = &mut pages[page_pos];
+
+            // A field belongs to a single page, so its id is enough to find its group
+            let field_pos = *field_index.entry(row.field_id).or_insert_with(|| {
+                page.fields.push(ExpiringFieldGroup {
+                    field_id: row.field_id,
+                    field_name: row.field_name,
+                    field_display_name: row.field_display_name,
+                    records: Vec::new(),
+                });
+                page.fields.len() - 1
+            });
+
+            page.fields[field_pos].records.push(ExpiringRecord {
+                record_id: row.record_id,
+                start_date: row.start_date,
+                end_date: row.end_date,
+                due_date: row.due_date,
+                days_remaining: (row.due_date - today).num_days(),
+            });
+        }
+
+        pages
+    }
+}
+
+/// Bookkeeping for the expiry scan so a restart after downtime knows which days it missed.
+pub struct ExpiryScanState;
+
+impl ExpiryScanState {
+    pub async fn get_last_success_date(
+        pool: &MySqlPool,
+    ) -> Result<Option<NaiveDate>, sqlx::Error> {
+        sqlx::query_scalar!("SELECT last_success_date FROM expiry_scan_state WHERE id = 1")
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn record_success(pool: &MySqlPool, date: NaiveDate) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO expiry_scan_state (id, last_success_date, last_success_at)
+            VALUES (1, ?, CURRENT_TIMESTAMP)
+            ON DUPLICATE KEY UPDATE
+                last_success_date = VALUES(last_success_date),
+                last_success_at = CURRENT_TIMESTAMP
+            "#,
+            date
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, MySql, Transaction};
+
+use super::admin_scope::RoleAdminScopes;
+use crate::utils::role_hierarchy::has_ancestor_in;
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Role {
@@ -8,6 +11,10 @@ pub struct Role {
     pub name: String,
     pub description: Option<String>,
     pub is_admin: bool,
+    /// Role whose permissions this role inherits
+    pub parent_role_id: Option<u32>,
+    /// Members must log in with a second factor
+    pub requires_two_factor: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -17,7 +24,14 @@ pub struct CreateRoleRequest {
     pub name: String,
     pub description: Option<String>,
     pub is_admin: bool,
+    #[serde(default)]
+    pub parent_role_id: Option<u32>,
+    #[serde(default)]
+    pub requires_two_factor: bool,
     pub interfering_role_ids: Option<Vec<u32>>,
+    /// Page groups and roles the members administer, left unchanged when missing
+    #[serde(default)]
+    pub admin_scopes: Option<RoleAdminScopes>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,7 +39,14 @@ pub struct UpdateRoleRequest {
     pub name: String,
     pub description: Option<String>,
     pub is_admin: bool,
+    #[serde(default)]
+    pub parent_role_id: Option<u32>,
+    #[serde(default)]
+    pub requires_two_factor: bool,
     pub interfering_role_ids: Option<Vec<u32>>,
+    /// Page groups and roles the members administer, left unchanged when missing
+    #[serde(default)]
+    pub admin_scopes: Option<RoleAdminScopes>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,6 +60,7 @@ pub struct RoleWithInterferingRoles {
     #[serde(flatten)]
     pub role: Role,
     pub interfering_role_ids: Vec<u32>,
+    pub admin_scopes: RoleAdminScopes,
 }
 
 impl Role {
@@ -50,12 +72,14 @@ impl Role {
         
         let result = sqlx::query!(
             r#"
-            INSERT INTO roles (name, description, is_admin)
-            VALUES (?, ?, ?)
+            INSERT INTO roles (name, description, is_admin, parent_role_id, requires_two_factor)
+            VALUES (?, ?, ?, ?, ?)
             "#,
             request.name,
             request.description,
-            request.is_admin
+            request.is_admin,
+            request.parent_role_id,
+            request.requires_two_factor
         )
         .execute(&mut *tx)
         .await?;
@@ -87,7 +111,13 @@ impl Role {
                 .await?;
             }
         }
-        
+
+        if let Some(scopes) = &request.admin_scopes {
+            RoleAdminScopes::replace_for_role(&mut tx, role_id, scopes).await?;
+        }
+
+        Self::refresh_ancestors(&mut tx).await?;
+
         tx.commit().await?;
         Ok(role_id)
     }
@@ -96,7 +126,7 @@ impl Role {
         sqlx::query_as!(
             Role,
             r#"
-            SELECT id, name, description, is_admin as "is_admin: bool", created_at as "created_at!", updated_at as "updated_at!"
+            SELECT id, name, description, is_admin as "is_admin: bool", parent_role_id, requires_two_factor as "requires_two_factor: bool", created_at as "created_at!", updated_at as "updated_at!"
             FROM roles
             ORDER BY name
             "#
@@ -109,7 +139,7 @@ impl Role {
         sqlx::query_as!(
             Role,
             r#"
-            SELECT id, name, description, is_admin as "is_admin: bool", created_at as "created_at!", updated_at as "updated_at!"
+            SELECT id, name, description, is_admin as "is_admin: bool", parent_role_id, requires_two_factor as "requires_two_factor: bool", created_at as "created_at!", updated_at as "updated_at!"
             FROM roles
             WHERE id = ?
             "#,
@@ -132,10 +162,13 @@ impl Role {
         )
         .fetch_all(pool)
         .await?;
+
+        let admin_scopes = RoleAdminScopes::get_for_role(pool, role_id).await?;
         
         Ok(RoleWithInterferingRoles {
             role,
             interfering_role_ids,
+            admin_scopes,
         })
     }
 
@@ -149,12 +182,14 @@ impl Role {
         sqlx::query!(
             r#"
             UPDATE roles
-            SET name = ?, description = ?, is_admin = ?
+            SET name = ?, description = ?, is_admin = ?, parent_role_id = ?, requires_two_factor = ?
             WHERE id = ?
             "#,
             request.name,
             request.description,
             request.is_admin,
+            request.parent_role_id,
+            request.requires_two_factor,
             role_id
         )
         .execute(&mut *tx)
@@ -195,19 +230,98 @@ impl Role {
                 }
             }
         }
-        
+
+        if let Some(scopes) = &request.admin_scopes {
+            RoleAdminScopes::replace_for_role(&mut tx, role_id, scopes).await?;
+        }
+
+        Self::refresh_ancestors(&mut tx).await?;
+
         tx.commit().await?;
         Ok(())
     }
 
     pub async fn delete(pool: &sqlx::MySqlPool, role_id: u32) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
         sqlx::query!(r#"DELETE FROM roles WHERE id = ?"#, role_id)
-            .execute(pool)
+            .execute(&mut *tx)
+            .await?;
+
+        // The roles below it lose their parent
+        Self::refresh_ancestors(&mut tx).await?;
+
+        tx.commit().await
+    }
+
+    /// Rebuilds `role_ancestors`, which `user_effective_roles` reads, from the parent of every
+    /// role. Called whenever roles or their parents change.
+    pub async fn refresh_ancestors(tx: &mut Transaction<'_, MySql>) -> Result<(), sqlx::Error> {
+        sqlx::query!(r#"DELETE FROM role_ancestors"#)
+            .execute(&mut **tx)
             .await?;
 
+        // The depth limit only guards against cycles; the API refuses to create them
+        sqlx::query!(
+            r#"
+            INSERT INTO role_ancestors (role_id, ancestor_id)
+            WITH RECURSIVE chain (role_id, ancestor_id, depth) AS (
+                SELECT id, id, 0 FROM roles
+                UNION ALL
+                SELECT c.role_id, r.parent_role_id, c.depth + 1
+                FROM chain c
+                JOIN roles r ON r.id = c.ancestor_id
+                WHERE r.parent_role_id IS NOT NULL AND c.depth < 16
+            )
+            SELECT DISTINCT role_id, ancestor_id FROM chain
+            "#
+        )
+        .execute(&mut **tx)
+        .await?;
+
         Ok(())
     }
 
+    /// Roles whose members become admins of some kind: the admin roles, the roles with admin
+    /// scopes and every role below them, as members hold the roles above theirs too. Only global
+    /// admins hand them out.
+    pub async fn get_admin_role_ids(
+        pool: &sqlx::MySqlPool,
+    ) -> Result<ahash::HashSet<u32>, sqlx::Error> {
+        let admin_ids: ahash::HashSet<u32> = sqlx::query_scalar!(
+            r#"
+            SELECT id FROM roles WHERE is_admin = true
+            UNION SELECT role_id FROM role_admin_page_groups
+            UNION SELECT role_id FROM role_admin_roles
+            "#
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .collect();
+
+        let parents = Self::get_parent_map(pool).await?;
+        Ok(parents
+            .keys()
+            .copied()
+            .filter(|&id| has_ancestor_in(&parents, id, &admin_ids))
+            .collect())
+    }
+
+    /// Parent of every role, to validate changes to the hierarchy.
+    pub async fn get_parent_map(
+        pool: &sqlx::MySqlPool,
+    ) -> Result<ahash::HashMap<u32, Option<u32>>, sqlx::Error> {
+        let rows = sqlx::query!(r#"SELECT id, parent_role_id FROM roles"#)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.id, row.parent_role_id))
+            .collect())
+    }
+
     pub async fn get_roles_by_user_id(
         pool: &sqlx::MySqlPool,
         user_id: u32,
@@ -215,7 +329,7 @@ impl Role {
         sqlx::query_as!(
             Role,
             r#"
-            SELECT r.id, r.name, r.description, r.is_admin as "is_admin: bool", r.created_at as "created_at!", r.updated_at as "updated_at!"
+            SELECT r.id, r.name, r.description, r.is_admin as "is_admin: bool", r.parent_role_id, r.requires_two_factor as "requires_two_factor: bool", r.created_at as "created_at!", r.updated_at as "updated_at!"
             FROM roles r
             JOIN user_roles ur ON r.id = ur.role_id
             WHERE ur.user_id = ?
@@ -226,6 +340,38 @@ impl Role {
         .await
     }
 
+    /// Roles a user holds, directly or through the parents of their roles.
+    pub async fn get_effective_roles_by_user_id(
+        pool: &sqlx::MySqlPool,
+        user_id: u32,
+    ) -> Result<Vec<Role>, sqlx::Error> {
+        sqlx::query_as!(
+            Role,
+            r#"
+            SELECT DISTINCT r.id, r.name, r.description, r.is_admin as "is_admin: bool", r.parent_role_id, r.requires_two_factor as "requires_two_factor: bool", r.created_at as "created_at!", r.updated_at as "updated_at!"
+            FROM roles r
+            JOIN user_effective_roles ur ON r.id = ur.role_id
+            WHERE ur.user_id = ?
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Ids of the roles a user holds, directly or through the parents of their roles.
+    pub async fn get_effective_role_ids(
+        pool: &sqlx::MySqlPool,
+        user_id: u32,
+    ) -> Result<Vec<u32>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT DISTINCT role_id FROM user_effective_roles WHERE user_id = ?"#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
     pub async fn assign_roles_to_user(
         pool: &sqlx::MySqlPool,
         assignment: &UserRoleAssignment,
@@ -302,7 +448,7 @@ impl Role {
     ) -> Result<Vec<u32>, sqlx::Error> {
         let user_ids = sqlx::query_scalar!(
             r#"
-            SELECT user_id FROM user_roles WHERE role_id = ?
+            SELECT DISTINCT user_id FROM user_effective_roles WHERE role_id = ?
             "#,
             role_id
         )
@@ -317,8 +463,8 @@ impl Role {
     ) -> Result<Vec<u32>, sqlx::Error> {
         let user_role_ids = sqlx::query_scalar!(
             r#"
-            SELECT role_id 
-            FROM user_roles 
+            SELECT DISTINCT role_id 
+            FROM user_effective_roles 
             WHERE user_id = ?
             "#,
             user_id
@@ -365,7 +511,7 @@ impl Role {
         let query_str = format!(
             r#"
             SELECT DISTINCT user_id
-            FROM user_roles
+            FROM user_effective_roles
             WHERE role_id IN ({}) AND user_id != ?
             "#,
             interfering_role_placeholders
@@ -405,10 +551,13 @@ impl Role {
             )
             .fetch_all(pool)
             .await?;
+
+            let admin_scopes = RoleAdminScopes::get_for_role(pool, role.id).await?;
             
             result.push(RoleWithInterferingRoles {
                 role,
                 interfering_role_ids,
+                admin_scopes,
             });
         }
         
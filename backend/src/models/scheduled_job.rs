@@ -0,0 +1,260 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, MySqlPool};
+
+pub const JOB_STATUS_RUNNING: &str = "RUNNING";
+pub const JOB_STATUS_SUCCESS: &str = "SUCCESS";
+pub const JOB_STATUS_FAILED: &str = "FAILED";
+
+/// Persisted state of a background job. The job itself is defined in code, see
+/// `services::scheduler`.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ScheduledJob {
+    pub name: String,
+    pub cron_expression: String,
+    pub enabled: bool,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_status: Option<String>,
+    pub locked_by: Option<String>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduledJobEnabledRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ScheduledJobRun {
+    pub id: u32,
+    pub job_name: String,
+    pub instance_id: String,
+    pub triggered_by: Option<u32>,
+    pub status: String,
+    pub message: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl ScheduledJob {
+    /// Makes sure the job has a row and that it follows the cron expression defined in code.
+    /// The next run is only recomputed when the expression changed.
+    pub async fn register(
+        pool: &MySqlPool,
+        name: &str,
+        cron_expression: &str,
+        next_run_at: Option<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT IGNORE INTO scheduled_jobs (name, cron_expression, next_run_at)
+            VALUES (?, ?, ?)
+            "#,
+            name,
+            cron_expression,
+            next_run_at
+        )
+        .execute(pool)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE scheduled_jobs
+            SET cron_expression = ?, next_run_at = ?
+            WHERE name = ? AND (cron_expression <> ? OR next_run_at IS NULL)
+            "#,
+            cron_expression,
+            next_run_at,
+            name,
+            cron_expression
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_all(pool: &MySqlPool) -> Result<Vec<ScheduledJob>, sqlx::Error> {
+        sqlx::query_as!(
+            ScheduledJob,
+            r#"
+            SELECT name, cron_expression, enabled as "enabled: bool",
+                   next_run_at, last_run_at, last_status, locked_by, locked_until
+            FROM scheduled_jobs
+            ORDER BY name
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Pauses or resumes the schedule of a job. Admins can still run a disabled job by hand.
+    pub async fn set_enabled(
+        pool: &MySqlPool,
+        name: &str,
+        enabled: bool,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"UPDATE scheduled_jobs SET enabled = ? WHERE name = ?"#,
+            enabled,
+            name
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Takes the job lock for `instance_id` if nobody else holds it.
+    ///
+    /// Unless `force` is set the job must also be enabled and due, so between replicas only the
+    /// first one to reach a due job runs it. Locks expire after `lock_secs` in case the instance
+    /// holding it dies mid-run.
+    pub async fn try_lock(
+        pool: &MySqlPool,
+        name: &str,
+        instance_id: &str,
+        lock_secs: u32,
+        force: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE scheduled_jobs
+            SET locked_by = ?, locked_until = NOW() + INTERVAL ? SECOND
+            WHERE name = ?
+              AND (locked_until IS NULL OR locked_until < NOW())
+              AND (? OR (enabled = true AND next_run_at <= NOW()))
+            "#,
+            instance_id,
+            lock_secs,
+            name,
+            force
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Stores the outcome of a run and releases the lock. `next_run_at` is left untouched when
+    /// `None`, e.g. after a manual run.
+    pub async fn finish(
+        pool: &MySqlPool,
+        name: &str,
+        instance_id: &str,
+        status: &str,
+        next_run_at: Option<DateTime<Utc>>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE scheduled_jobs
+            SET last_run_at = CURRENT_TIMESTAMP, last_status = ?,
+                next_run_at = COALESCE(?, next_run_at),
+                locked_by = NULL, locked_until = NULL
+            WHERE name = ? AND locked_by = ?
+            "#,
+            status,
+            next_run_at,
+            name,
+            instance_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl ScheduledJobRun {
+    pub async fn start(
+        pool: &MySqlPool,
+        job_name: &str,
+        instance_id: &str,
+        triggered_by: Option<u32>,
+    ) -> Result<u32, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO scheduled_job_runs (job_name, instance_id, triggered_by, status)
+            VALUES (?, ?, ?, ?)
+            "#,
+            job_name,
+            instance_id,
+            triggered_by,
+            JOB_STATUS_RUNNING
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_id() as u32)
+    }
+
+    /// Marks as failed the runs of a job still recorded as running after `lock_secs`, left
+    /// behind by an instance that died mid-run. Returns how many there were.
+    pub async fn fail_stale(
+        pool: &MySqlPool,
+        job_name: &str,
+        lock_secs: u32,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE scheduled_job_runs
+            SET status = ?, message = ?, finished_at = CURRENT_TIMESTAMP
+            WHERE job_name = ? AND status = ? AND started_at < NOW() - INTERVAL ? SECOND
+            "#,
+            JOB_STATUS_FAILED,
+            "Interrompida: a instância terminou durante a execução",
+            job_name,
+            JOB_STATUS_RUNNING,
+            lock_secs
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn finish(
+        pool: &MySqlPool,
+        id: u32,
+        status: &str,
+        message: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE scheduled_job_runs
+            SET status = ?, message = ?, finished_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+            status,
+            message,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_recent_by_job(
+        pool: &MySqlPool,
+        job_name: &str,
+        limit: u32,
+    ) -> Result<Vec<ScheduledJobRun>, sqlx::Error> {
+        sqlx::query_as!(
+            ScheduledJobRun,
+            r#"
+            SELECT id, job_name, instance_id, triggered_by, status, message,
+                   started_at as "started_at!", finished_at
+            FROM scheduled_job_runs
+            WHERE job_name = ?
+            ORDER BY started_at DESC, id DESC
+            LIMIT ?
+            "#,
+            job_name,
+            limit
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
@@ -0,0 +1,177 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, MySqlPool};
+
+pub const SECURITY_EVENT_LOGIN_SUCCESS: &str = "LOGIN_SUCCESS";
+pub const SECURITY_EVENT_LOGIN_FAILURE: &str = "LOGIN_FAILURE";
+/// An account or an IP went over its failed login limit
+pub const SECURITY_EVENT_LOGIN_LOCKED: &str = "LOGIN_LOCKED";
+pub const SECURITY_EVENT_ACCOUNT_UNLOCKED: &str = "ACCOUNT_UNLOCKED";
+pub const SECURITY_EVENT_PASSWORD_CHANGE: &str = "PASSWORD_CHANGE";
+pub const SECURITY_EVENT_ROLE_CHANGE: &str = "ROLE_CHANGE";
+pub const SECURITY_EVENT_USER_DELETED: &str = "USER_DELETED";
+pub const SECURITY_EVENT_USER_INVITED: &str = "USER_INVITED";
+pub const SECURITY_EVENT_PASSWORD_RESET_REQUESTED: &str = "PASSWORD_RESET_REQUESTED";
+pub const SECURITY_EVENT_REGISTRATION_CHANGED: &str = "REGISTRATION_CHANGED";
+pub const SECURITY_EVENT_TWO_FACTOR_ENABLED: &str = "TWO_FACTOR_ENABLED";
+pub const SECURITY_EVENT_TWO_FACTOR_DISABLED: &str = "TWO_FACTOR_DISABLED";
+/// An admin removed the second factor of a user who lost it
+pub const SECURITY_EVENT_TWO_FACTOR_RESET: &str = "TWO_FACTOR_RESET";
+
+/// Largest page of events returned at once.
+const MAX_EVENTS_PER_PAGE: u32 = 500;
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct SecurityEvent {
+    pub id: u32,
+    pub event_type: String,
+    pub user_id: Option<u32>,
+    pub username: Option<String>,
+    pub actor_user_id: Option<u32>,
+    pub actor_username: Option<String>,
+    pub ip_address: Option<String>,
+    pub details: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+pub struct NewSecurityEvent<'a> {
+    pub event_type: &'a str,
+    pub user_id: Option<u32>,
+    pub actor_user_id: Option<u32>,
+    pub ip_address: Option<&'a str>,
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SecurityEventFilter {
+    pub user_id: Option<u32>,
+    pub event_type: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+/// Who can log into the server: every user with their roles, last successful login and
+/// whether their account is currently locked.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct UserAccessSummary {
+    pub user_id: u32,
+    pub username: String,
+    pub email: String,
+    pub roles: Option<String>,
+    pub is_admin: bool,
+    pub last_login_at: Option<DateTime<Utc>>,
+    pub last_login_ip: Option<String>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+impl SecurityEvent {
+    /// Stores an event. Failures are only logged, an event that could not be written never
+    /// makes the action itself fail.
+    pub async fn record(pool: &MySqlPool, event: NewSecurityEvent<'_>) {
+        if let Err(e) = sqlx::query!(
+            r#"
+            INSERT INTO security_events (event_type, user_id, actor_user_id, ip_address, details)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+            event.event_type,
+            event.user_id,
+            event.actor_user_id,
+            event.ip_address,
+            event.details
+        )
+        .execute(pool)
+        .await
+        {
+            log::error!(
+                "Error recording security event {} for user {:?}: {}",
+                event.event_type,
+                event.user_id,
+                e
+            );
+        }
+    }
+
+    /// Most recent events first.
+    pub async fn search(
+        pool: &MySqlPool,
+        filter: &SecurityEventFilter,
+    ) -> Result<Vec<SecurityEvent>, sqlx::Error> {
+        let limit = filter.limit.unwrap_or(100).min(MAX_EVENTS_PER_PAGE);
+        let offset = filter.offset.unwrap_or(0);
+
+        sqlx::query_as!(
+            SecurityEvent,
+            r#"
+            SELECT
+                e.id, e.event_type, e.user_id, u.username as "username?",
+                e.actor_user_id, a.username as "actor_username?",
+                e.ip_address, e.details, e.created_at as "created_at!"
+            FROM security_events e
+            LEFT JOIN users u ON u.id = e.user_id
+            LEFT JOIN users a ON a.id = e.actor_user_id
+            WHERE (? IS NULL OR e.user_id = ?)
+              AND (? IS NULL OR e.event_type = ?)
+              AND (? IS NULL OR e.created_at >= ?)
+              AND (? IS NULL OR e.created_at < DATE_ADD(?, INTERVAL 1 DAY))
+            ORDER BY e.created_at DESC, e.id DESC
+            LIMIT ? OFFSET ?
+            "#,
+            filter.user_id,
+            filter.user_id,
+            filter.event_type,
+            filter.event_type,
+            filter.from,
+            filter.from,
+            filter.to,
+            filter.to,
+            limit,
+            offset
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn get_access_summary(
+        pool: &MySqlPool,
+    ) -> Result<Vec<UserAccessSummary>, sqlx::Error> {
+        sqlx::query_as!(
+            UserAccessSummary,
+            r#"
+            SELECT
+                u.id as user_id, u.username, u.email,
+                (
+                    SELECT GROUP_CONCAT(r.name ORDER BY r.name SEPARATOR ', ')
+                    FROM user_roles ur
+                    JOIN roles r ON r.id = ur.role_id
+                    WHERE ur.user_id = u.id
+                ) as roles,
+                EXISTS(
+                    SELECT 1
+                    FROM user_effective_roles ur
+                    JOIN roles r ON r.id = ur.role_id
+                    WHERE ur.user_id = u.id AND r.is_admin = 1
+                ) as "is_admin: bool",
+                last_login.created_at as "last_login_at?",
+                last_login.ip_address as "last_login_ip?",
+                (
+                    SELECT t.locked_until
+                    FROM login_throttles t
+                    WHERE t.scope = 'ACCOUNT' AND t.throttle_key = LOWER(u.email)
+                      AND t.locked_until > NOW()
+                ) as "locked_until?"
+            FROM users u
+            LEFT JOIN security_events last_login ON last_login.id = (
+                SELECT MAX(e.id)
+                FROM security_events e
+                WHERE e.user_id = u.id AND e.event_type = 'LOGIN_SUCCESS'
+            )
+            ORDER BY u.username
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
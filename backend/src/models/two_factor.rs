@@ -0,0 +1,229 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, MySqlPool};
+
+#[derive(Debug, FromRow)]
+pub struct UserTwoFactor {
+    pub user_id: u32,
+    pub secret: String,
+    pub enabled: bool,
+    pub last_used_step: Option<i64>,
+    pub enabled_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    /// One of the user's roles requires a second factor, so it cannot be disabled
+    pub required: bool,
+    pub enabled_at: Option<DateTime<Utc>>,
+    pub recovery_codes_left: i64,
+}
+
+impl UserTwoFactor {
+    pub async fn get(pool: &MySqlPool, user_id: u32) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            UserTwoFactor,
+            r#"
+            SELECT user_id, secret, enabled as "enabled: bool", last_used_step, enabled_at
+            FROM user_two_factor
+            WHERE user_id = ?
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Stores a new secret waiting for its first code. An enabled second factor is left as it
+    /// is.
+    pub async fn start_enrollment(
+        pool: &MySqlPool,
+        user_id: u32,
+        secret: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO user_two_factor (user_id, secret, enabled)
+            VALUES (?, ?, false)
+            ON DUPLICATE KEY UPDATE
+                secret = IF(enabled, secret, VALUES(secret)),
+                last_used_step = IF(enabled, last_used_step, NULL)
+            "#,
+            user_id,
+            secret
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Turns on the pending second factor after its first code, replacing the recovery codes.
+    pub async fn enable(
+        pool: &MySqlPool,
+        user_id: u32,
+        step: i64,
+        recovery_code_hashes: &[Vec<u8>],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE user_two_factor
+            SET enabled = true, last_used_step = ?, enabled_at = ?
+            WHERE user_id = ?
+            "#,
+            step,
+            Utc::now(),
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Self::insert_recovery_codes(&mut tx, user_id, recovery_code_hashes).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Marks a time step as used. False when it, or a later one, already was, so the same code
+    /// cannot log in twice.
+    pub async fn mark_step_used(
+        pool: &MySqlPool,
+        user_id: u32,
+        step: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE user_two_factor
+            SET last_used_step = ?
+            WHERE user_id = ? AND (last_used_step IS NULL OR last_used_step < ?)
+            "#,
+            step,
+            user_id,
+            step
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Spends an unused recovery code. False when no unused code has this hash.
+    pub async fn use_recovery_code(
+        pool: &MySqlPool,
+        user_id: u32,
+        code_hash: &[u8],
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE user_recovery_codes
+            SET used_at = ?
+            WHERE user_id = ? AND code_hash = ? AND used_at IS NULL
+            LIMIT 1
+            "#,
+            Utc::now(),
+            user_id,
+            code_hash
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn replace_recovery_codes(
+        pool: &MySqlPool,
+        user_id: u32,
+        recovery_code_hashes: &[Vec<u8>],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        Self::insert_recovery_codes(&mut tx, user_id, recovery_code_hashes).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn insert_recovery_codes(
+        tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+        user_id: u32,
+        recovery_code_hashes: &[Vec<u8>],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"DELETE FROM user_recovery_codes WHERE user_id = ?"#,
+            user_id
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        for code_hash in recovery_code_hashes {
+            sqlx::query!(
+                r#"INSERT INTO user_recovery_codes (user_id, code_hash) VALUES (?, ?)"#,
+                user_id,
+                code_hash
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the second factor and its recovery codes. Returns whether there was one.
+    pub async fn disable(pool: &MySqlPool, user_id: u32) -> Result<bool, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            r#"DELETE FROM user_recovery_codes WHERE user_id = ?"#,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        let result = sqlx::query!(r#"DELETE FROM user_two_factor WHERE user_id = ?"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Whether any role of the user, inherited ones included, requires a second factor.
+    pub async fn is_required_for_user(pool: &MySqlPool, user_id: u32) -> Result<bool, sqlx::Error> {
+        let required = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS(
+                SELECT 1
+                FROM user_effective_roles ur
+                JOIN roles r ON r.id = ur.role_id
+                WHERE ur.user_id = ? AND r.requires_two_factor = 1
+            ) as "required: bool"
+            "#,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(required)
+    }
+
+    pub async fn status(pool: &MySqlPool, user_id: u32) -> Result<TwoFactorStatus, sqlx::Error> {
+        let two_factor = Self::get(pool, user_id).await?.filter(|tf| tf.enabled);
+        let required = Self::is_required_for_user(pool, user_id).await?;
+
+        let recovery_codes_left = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) FROM user_recovery_codes WHERE user_id = ? AND used_at IS NULL
+            "#,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(TwoFactorStatus {
+            enabled: two_factor.is_some(),
+            required,
+            enabled_at: two_factor.and_then(|tf| tf.enabled_at),
+            recovery_codes_left,
+        })
+    }
+}
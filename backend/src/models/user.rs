@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, MySqlPool};
 
 use super::role::Role; // Import Role if needed for embedding
 
@@ -10,6 +10,20 @@ pub struct User {
     pub username: String,
     pub email: String,
     pub vacation_days_current_year: Option<u16>, // Added field
+    /// Clinic the user works at, see [`super::location::Location`]
+    pub location: Option<i8>,
+}
+
+impl User {
+    /// The location whose municipal holidays apply to the user, `None` when the user has none.
+    pub async fn location(pool: &MySqlPool, user_id: u32) -> Result<Option<i8>, sqlx::Error> {
+        Ok(
+            sqlx::query_scalar!(r#"SELECT location FROM users WHERE id = ?"#, user_id)
+                .fetch_optional(pool)
+                .await?
+                .flatten(),
+        )
+    }
 }
 
 // Structure to hold user data along with their assigned roles
@@ -18,6 +32,7 @@ pub struct UserWithRoles {
     pub id: u32,
     pub username: String,
     pub email: String,
+    pub location: Option<i8>,
     pub roles: Vec<Role>, // Embed the full Role struct or just IDs/names
 }
 
@@ -27,10 +42,13 @@ pub struct UserRoleRow {
     pub user_id: u32,
     pub username: String,
     pub email: String,
+    pub location: Option<i8>,
     pub role_id: Option<u32>, // Use Option for LEFT JOIN
     pub role_name: Option<String>,
     pub role_description: Option<String>,
     pub role_is_admin: Option<bool>, // MySQL boolean can be tinyint
+    pub role_parent_role_id: Option<u32>,
+    pub role_requires_two_factor: Option<bool>,
     pub role_created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub role_updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
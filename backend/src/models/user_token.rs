@@ -0,0 +1,135 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, MySql, MySqlPool, Transaction};
+
+pub const TOKEN_PURPOSE_INVITATION: &str = "INVITATION";
+pub const TOKEN_PURPOSE_PASSWORD_RESET: &str = "PASSWORD_RESET";
+
+/// The user behind a valid token, shown on the page where the password is set.
+#[derive(Debug, Serialize, FromRow)]
+pub struct TokenOwner {
+    #[serde(skip)]
+    pub token_id: u32,
+    pub user_id: u32,
+    pub username: String,
+    pub email: String,
+    pub purpose: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub struct UserToken;
+
+impl UserToken {
+    /// Stores a new token, dropping the unused tokens the user had for the same purpose so only
+    /// the latest link works.
+    pub async fn create(
+        pool: &MySqlPool,
+        user_id: u32,
+        purpose: &str,
+        token_hash: &[u8],
+        expires_at: DateTime<Utc>,
+        created_by: Option<u32>,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            r#"DELETE FROM user_tokens WHERE user_id = ? AND purpose = ? AND used_at IS NULL"#,
+            user_id,
+            purpose
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO user_tokens (user_id, purpose, token_hash, expires_at, created_by)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+            user_id,
+            purpose,
+            token_hash,
+            expires_at,
+            created_by
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Owner of a token that is neither used nor expired.
+    pub async fn find_valid(
+        pool: &MySqlPool,
+        token_hash: &[u8],
+    ) -> Result<Option<TokenOwner>, sqlx::Error> {
+        sqlx::query_as!(
+            TokenOwner,
+            r#"
+            SELECT
+                t.id as token_id, t.user_id, u.username, u.email,
+                t.purpose as "purpose!: String", t.expires_at as "expires_at!"
+            FROM user_tokens t
+            JOIN users u ON u.id = t.user_id
+            WHERE t.token_hash = ? AND t.used_at IS NULL AND t.expires_at > ?
+            "#,
+            token_hash,
+            Utc::now()
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Takes a valid token inside a transaction, locking it so it can only be used once. Every
+    /// other open token of the user is spent along with it.
+    pub async fn consume(
+        tx: &mut Transaction<'_, MySql>,
+        token_hash: &[u8],
+    ) -> Result<Option<(u32, String)>, sqlx::Error> {
+        let Some(token) = sqlx::query!(
+            r#"
+            SELECT user_id, purpose as "purpose!: String"
+            FROM user_tokens
+            WHERE token_hash = ? AND used_at IS NULL AND expires_at > ?
+            FOR UPDATE
+            "#,
+            token_hash,
+            Utc::now()
+        )
+        .fetch_optional(&mut **tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        sqlx::query!(
+            r#"UPDATE user_tokens SET used_at = ? WHERE user_id = ? AND used_at IS NULL"#,
+            Utc::now(),
+            token.user_id
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(Some((token.user_id, token.purpose)))
+    }
+
+    /// Whether the user was invited and never set a password.
+    pub async fn has_pending_invitation(
+        pool: &MySqlPool,
+        user_id: u32,
+    ) -> Result<bool, sqlx::Error> {
+        let pending = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS(
+                SELECT 1 FROM user_tokens
+                WHERE user_id = ? AND purpose = 'INVITATION' AND used_at IS NULL
+            ) as "pending: bool"
+            "#,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(pending)
+    }
+}
@@ -0,0 +1,693 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{MySqlExecutor, MySqlPool};
+
+use crate::models::vacation_request::VacationDayPart;
+
+pub const APPROVAL_PENDING: &str = "PENDING";
+pub const APPROVAL_APPROVED: &str = "APPROVED";
+pub const APPROVAL_REJECTED: &str = "REJECTED";
+/// The step was taken away from the approver, and a new row opened for someone else
+pub const APPROVAL_REASSIGNED: &str = "REASSIGNED";
+
+/// Longest chain a role can have.
+const MAX_STEPS: usize = 10;
+
+/// A step of the approval chain of a role's members.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApprovalStep {
+    pub id: u32,
+    pub role_id: u32,
+    pub step_order: u8,
+    pub name: String,
+    /// Members of this role approve the step
+    pub approver_role_id: u32,
+    /// Days the approver has before being reminded, `None` for no deadline
+    pub deadline_days: Option<u16>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApprovalStepRequest {
+    pub name: String,
+    pub approver_role_id: u32,
+    pub deadline_days: Option<u16>,
+}
+
+/// The chain of a role, with the priority that picks it over the chains of the user's other
+/// roles.
+#[derive(Debug, Serialize)]
+pub struct ApprovalChain {
+    pub role_id: u32,
+    /// `None` when the role has no chain
+    pub priority: Option<u32>,
+    pub steps: Vec<ApprovalStep>,
+}
+
+/// Steps of [`ApprovalStepRequest`], in the order they are approved.
+#[derive(Debug, Deserialize)]
+pub struct ApprovalChainRequest {
+    /// Users with several roles follow the chain with the lowest priority. Required with steps,
+    /// and unique among chains
+    pub priority: Option<u32>,
+    pub steps: Vec<ApprovalStepRequest>,
+}
+
+/// An approver away hands their steps to the delegate from `start_date` to `end_date`.
+#[derive(Debug, Serialize)]
+pub struct ApprovalDelegation {
+    pub id: u32,
+    pub approver_id: u32,
+    pub delegate_id: u32,
+    pub delegate_name: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApprovalDelegationRequest {
+    pub delegate_id: u32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+/// A row of the approval trail of a request.
+#[derive(Debug, Clone, Serialize)]
+pub struct VacationRequestApproval {
+    pub id: u32,
+    pub vacation_request_id: u32,
+    /// 0 for the decision of an admin on a request without a chain
+    pub step_order: u8,
+    pub step_name: Option<String>,
+    pub approver_role_id: Option<u32>,
+    /// `None` when no member of the approver role could take the step, any admin can
+    pub assigned_to: Option<u32>,
+    pub assigned_to_name: Option<String>,
+    /// Approver who delegated the step to `assigned_to`
+    pub delegated_from: Option<u32>,
+    pub status: String,
+    pub acted_by: Option<u32>,
+    pub acted_by_name: Option<String>,
+    pub notes: Option<String>,
+    pub due_date: Option<NaiveDate>,
+    pub reminded_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub acted_at: Option<DateTime<Utc>>,
+}
+
+/// A step waiting for an approver, with the request it belongs to.
+#[derive(Debug, Serialize)]
+pub struct PendingApproval {
+    pub approval_id: u32,
+    pub vacation_request_id: u32,
+    pub step_order: u8,
+    pub step_name: Option<String>,
+    pub delegated_from: Option<u32>,
+    pub due_date: Option<NaiveDate>,
+    pub user_id: u32,
+    pub username: String,
+    pub absence_type_id: u32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub day_part: VacationDayPart,
+    pub minutes: Option<u16>,
+    pub notes: Option<String>,
+    pub requested_at: DateTime<Utc>,
+}
+
+/// Who takes a step: the approver, or their delegate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Assignee {
+    pub user_id: u32,
+    pub delegated_from: Option<u32>,
+}
+
+impl ApprovalChainRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.steps.is_empty() && self.priority.is_none() {
+            return Err("A prioridade da cadeia é obrigatória.".to_string());
+        }
+        if self.steps.len() > MAX_STEPS {
+            return Err(format!(
+                "Uma cadeia não pode ter mais de {} passos.",
+                MAX_STEPS
+            ));
+        }
+        for step in &self.steps {
+            if step.name.trim().is_empty() || step.name.chars().count() > 255 {
+                return Err("O nome de cada passo é obrigatório (até 255 caracteres).".to_string());
+            }
+            if step.deadline_days == Some(0) {
+                return Err("O prazo de cada passo tem de ser positivo.".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ApprovalDelegationRequest {
+    pub fn validate(&self, approver_id: u32) -> Result<(), String> {
+        if self.delegate_id == approver_id {
+            return Err("Não pode delegar em si próprio.".to_string());
+        }
+        if self.start_date > self.end_date {
+            return Err("A data de início não pode ser posterior à data de fim.".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl ApprovalChain {
+    pub async fn get_for_role(
+        pool: &MySqlPool,
+        role_id: u32,
+    ) -> Result<ApprovalChain, sqlx::Error> {
+        let priority = sqlx::query_scalar!(
+            r#"SELECT priority FROM approval_chains WHERE role_id = ?"#,
+            role_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        let steps = sqlx::query_as!(
+            ApprovalStep,
+            r#"
+            SELECT id, role_id, step_order, name, approver_role_id, deadline_days
+            FROM approval_steps
+            WHERE role_id = ?
+            ORDER BY step_order
+            "#,
+            role_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(ApprovalChain {
+            role_id,
+            priority,
+            steps,
+        })
+    }
+}
+
+impl ApprovalStep {
+    /// Replaces the chain of a role and its priority. Requests already in it keep the steps
+    /// they reached. Fails with a unique violation when another chain has the priority.
+    pub async fn replace_for_role(
+        pool: &MySqlPool,
+        role_id: u32,
+        chain: &ApprovalChainRequest,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(r#"DELETE FROM approval_steps WHERE role_id = ?"#, role_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(r#"DELETE FROM approval_chains WHERE role_id = ?"#, role_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if let Some(priority) = chain.priority.filter(|_| !chain.steps.is_empty()) {
+            sqlx::query!(
+                r#"INSERT INTO approval_chains (role_id, priority) VALUES (?, ?)"#,
+                role_id,
+                priority
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for (index, step) in chain.steps.iter().enumerate() {
+            sqlx::query!(
+                r#"
+                INSERT INTO approval_steps
+                    (role_id, step_order, name, approver_role_id, deadline_days)
+                VALUES (?, ?, ?, ?, ?)
+                "#,
+                role_id,
+                (index + 1) as u8,
+                step.name.trim(),
+                step.approver_role_id,
+                step.deadline_days
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await
+    }
+
+    /// The chain the user's requests go through: of their roles with a chain, the one with the
+    /// lowest priority. Empty when none has a chain.
+    pub async fn get_chain_for_user(
+        pool: &MySqlPool,
+        user_id: u32,
+    ) -> Result<Vec<ApprovalStep>, sqlx::Error> {
+        sqlx::query_as!(
+            ApprovalStep,
+            r#"
+            SELECT id, role_id, step_order, name, approver_role_id, deadline_days
+            FROM approval_steps
+            WHERE role_id = (
+                SELECT c.role_id
+                FROM approval_chains c
+                JOIN user_effective_roles ur ON ur.role_id = c.role_id
+                WHERE ur.user_id = ?
+                ORDER BY c.priority
+                LIMIT 1
+            )
+            ORDER BY step_order
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// The first member of the approver role who can take a step on `date`, in the place of
+    /// a member who delegated. The requester never approves their own request, and neither do
+    /// approvers away on a full-day absence.
+    pub async fn find_assignee(
+        pool: &MySqlPool,
+        approver_role_id: u32,
+        requester_id: u32,
+        date: NaiveDate,
+    ) -> Result<Option<Assignee>, sqlx::Error> {
+        let members = sqlx::query_scalar!(
+            r#"SELECT DISTINCT user_id FROM user_effective_roles WHERE role_id = ? ORDER BY user_id"#,
+            approver_role_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        for member in members {
+            let assignee = match ApprovalDelegation::find_delegate(pool, member, date).await? {
+                Some(delegate) => Assignee {
+                    user_id: delegate,
+                    delegated_from: Some(member),
+                },
+                None => Assignee {
+                    user_id: member,
+                    delegated_from: None,
+                },
+            };
+            if assignee.user_id == requester_id || is_away(pool, assignee.user_id, date).await? {
+                continue;
+            }
+            return Ok(Some(assignee));
+        }
+
+        Ok(None)
+    }
+}
+
+/// Whether the user is on an approved full-day absence on `date`.
+async fn is_away(pool: &MySqlPool, user_id: u32, date: NaiveDate) -> Result<bool, sqlx::Error> {
+    let count = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*)
+        FROM vacation_requests
+        WHERE user_id = ? AND status IN ('APPROVED', 'CANCELLATION_REQUESTED')
+          AND day_part = 'FULL' AND start_date <= ? AND end_date >= ?
+        "#,
+        user_id,
+        date,
+        date
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count > 0)
+}
+
+impl ApprovalDelegation {
+    pub async fn get_for_approver(
+        pool: &MySqlPool,
+        approver_id: u32,
+    ) -> Result<Vec<ApprovalDelegation>, sqlx::Error> {
+        sqlx::query_as!(
+            ApprovalDelegation,
+            r#"
+            SELECT d.id, d.approver_id, d.delegate_id, u.username as delegate_name,
+                   d.start_date, d.end_date
+            FROM approval_delegations d
+            JOIN users u ON u.id = d.delegate_id
+            WHERE d.approver_id = ?
+            ORDER BY d.start_date DESC
+            "#,
+            approver_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn create(
+        pool: &MySqlPool,
+        approver_id: u32,
+        delegation: &ApprovalDelegationRequest,
+    ) -> Result<u32, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO approval_delegations (approver_id, delegate_id, start_date, end_date)
+            VALUES (?, ?, ?, ?)
+            "#,
+            approver_id,
+            delegation.delegate_id,
+            delegation.start_date,
+            delegation.end_date
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_id() as u32)
+    }
+
+    /// Only the approver can delete their delegations.
+    pub async fn delete(
+        pool: &MySqlPool,
+        approver_id: u32,
+        delegation_id: u32,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"DELETE FROM approval_delegations WHERE id = ? AND approver_id = ?"#,
+            delegation_id,
+            approver_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The delegate standing in for the approver on `date`, the latest one when they overlap.
+    pub async fn find_delegate(
+        pool: &MySqlPool,
+        approver_id: u32,
+        date: NaiveDate,
+    ) -> Result<Option<u32>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT delegate_id
+            FROM approval_delegations
+            WHERE approver_id = ? AND start_date <= ? AND end_date >= ?
+            ORDER BY created_at DESC, id DESC
+            LIMIT 1
+            "#,
+            approver_id,
+            date,
+            date
+        )
+        .fetch_optional(pool)
+        .await
+    }
+}
+
+impl VacationRequestApproval {
+    /// The trail of a request, oldest first.
+    pub async fn get_for_request(
+        pool: &MySqlPool,
+        vacation_request_id: u32,
+    ) -> Result<Vec<VacationRequestApproval>, sqlx::Error> {
+        sqlx::query_as!(
+            VacationRequestApproval,
+            r#"
+            SELECT a.id, a.vacation_request_id, a.step_order, a.step_name, a.approver_role_id,
+                   a.assigned_to, assigned.username as "assigned_to_name?", a.delegated_from,
+                   a.status, a.acted_by, actor.username as "acted_by_name?", a.notes,
+                   a.due_date, a.reminded_at, a.created_at, a.acted_at
+            FROM vacation_request_approvals a
+            LEFT JOIN users assigned ON assigned.id = a.assigned_to
+            LEFT JOIN users actor ON actor.id = a.acted_by
+            WHERE a.vacation_request_id = ?
+            ORDER BY a.id
+            "#,
+            vacation_request_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// The step a request is waiting on, `None` when it has no chain or the chain is done.
+    pub async fn get_open(
+        pool: &MySqlPool,
+        vacation_request_id: u32,
+    ) -> Result<Option<VacationRequestApproval>, sqlx::Error> {
+        sqlx::query_as!(
+            VacationRequestApproval,
+            r#"
+            SELECT a.id, a.vacation_request_id, a.step_order, a.step_name, a.approver_role_id,
+                   a.assigned_to, assigned.username as "assigned_to_name?", a.delegated_from,
+                   a.status, a.acted_by, actor.username as "acted_by_name?", a.notes,
+                   a.due_date, a.reminded_at, a.created_at, a.acted_at
+            FROM vacation_request_approvals a
+            LEFT JOIN users assigned ON assigned.id = a.assigned_to
+            LEFT JOIN users actor ON actor.id = a.acted_by
+            WHERE a.vacation_request_id = ? AND a.status = 'PENDING'
+            ORDER BY a.id DESC
+            LIMIT 1
+            "#,
+            vacation_request_id
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Open steps of pending requests, for reminders and reassignment.
+    pub async fn get_all_open(
+        pool: &MySqlPool,
+    ) -> Result<Vec<VacationRequestApproval>, sqlx::Error> {
+        sqlx::query_as!(
+            VacationRequestApproval,
+            r#"
+            SELECT a.id, a.vacation_request_id, a.step_order, a.step_name, a.approver_role_id,
+                   a.assigned_to, assigned.username as "assigned_to_name?", a.delegated_from,
+                   a.status, a.acted_by, actor.username as "acted_by_name?", a.notes,
+                   a.due_date, a.reminded_at, a.created_at, a.acted_at
+            FROM vacation_request_approvals a
+            JOIN vacation_requests vr ON vr.id = a.vacation_request_id
+            LEFT JOIN users assigned ON assigned.id = a.assigned_to
+            LEFT JOIN users actor ON actor.id = a.acted_by
+            WHERE a.status = 'PENDING' AND vr.status = 'PENDING'
+            ORDER BY a.id
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Open steps of pending requests the user takes, as assignee or as delegate of the
+    /// assignee on `date`.
+    pub async fn get_pending_for_approver(
+        pool: &MySqlPool,
+        user_id: u32,
+        date: NaiveDate,
+    ) -> Result<Vec<PendingApproval>, sqlx::Error> {
+        sqlx::query_as!(
+            PendingApproval,
+            r#"
+            SELECT a.id as approval_id, a.vacation_request_id, a.step_order, a.step_name,
+                   a.delegated_from, a.due_date, vr.user_id, u.username, vr.absence_type_id,
+                   vr.start_date, vr.end_date, vr.day_part as "day_part: VacationDayPart",
+                   vr.minutes, vr.notes,
+                   vr.requested_at as "requested_at!"
+            FROM vacation_request_approvals a
+            JOIN vacation_requests vr ON vr.id = a.vacation_request_id
+            JOIN users u ON u.id = vr.user_id
+            WHERE a.status = 'PENDING' AND vr.status = 'PENDING'
+              AND (
+                  a.assigned_to = ?
+                  OR a.assigned_to IN (
+                      SELECT approver_id
+                      FROM approval_delegations
+                      WHERE delegate_id = ? AND start_date <= ? AND end_date >= ?
+                  )
+              )
+            ORDER BY a.due_date IS NULL, a.due_date, vr.requested_at
+            "#,
+            user_id,
+            user_id,
+            date,
+            date
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Opens a step of a request's chain. Returns the id of the new row.
+    pub async fn open(
+        pool: &MySqlPool,
+        vacation_request_id: u32,
+        step: &ApprovalStep,
+        assignee: Option<Assignee>,
+        due_date: Option<NaiveDate>,
+    ) -> Result<u32, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO vacation_request_approvals
+                (vacation_request_id, step_order, step_name, approver_role_id, assigned_to,
+                 delegated_from, due_date)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+            vacation_request_id,
+            step.step_order,
+            step.name,
+            step.approver_role_id,
+            assignee.map(|assignee| assignee.user_id),
+            assignee.and_then(|assignee| assignee.delegated_from),
+            due_date
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_id() as u32)
+    }
+
+    /// Records the decision on an open step. Returns false when it was decided already.
+    pub async fn decide(
+        executor: impl MySqlExecutor<'_>,
+        approval_id: u32,
+        status: &str,
+        acted_by: u32,
+        notes: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE vacation_request_approvals
+            SET status = ?, acted_by = ?, notes = ?, acted_at = CURRENT_TIMESTAMP
+            WHERE id = ? AND status = ?
+            "#,
+            status,
+            acted_by,
+            notes,
+            approval_id,
+            APPROVAL_PENDING
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Records the decision of an admin on a request without a chain.
+    pub async fn record_direct(
+        pool: &MySqlPool,
+        vacation_request_id: u32,
+        status: &str,
+        acted_by: u32,
+        notes: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO vacation_request_approvals
+                (vacation_request_id, step_order, assigned_to, status, acted_by, notes, acted_at)
+            VALUES (?, 0, ?, ?, ?, ?, CURRENT_TIMESTAMP)
+            "#,
+            vacation_request_id,
+            acted_by,
+            status,
+            acted_by,
+            notes
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Closes the step for its current approver and opens it again for `assignee`, keeping the
+    /// deadline. Returns the id of the new row, `None` when the step was decided meanwhile.
+    pub async fn reassign(
+        &self,
+        pool: &MySqlPool,
+        assignee: Assignee,
+    ) -> Result<Option<u32>, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let closed = sqlx::query!(
+            r#"
+            UPDATE vacation_request_approvals
+            SET status = ?, acted_at = CURRENT_TIMESTAMP
+            WHERE id = ? AND status = ?
+            "#,
+            APPROVAL_REASSIGNED,
+            self.id,
+            APPROVAL_PENDING
+        )
+        .execute(&mut *tx)
+        .await?;
+        if closed.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO vacation_request_approvals
+                (vacation_request_id, step_order, step_name, approver_role_id, assigned_to,
+                 delegated_from, due_date, reminded_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            self.vacation_request_id,
+            self.step_order,
+            self.step_name,
+            self.approver_role_id,
+            assignee.user_id,
+            assignee.delegated_from,
+            self.due_date,
+            self.reminded_at
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(result.last_insert_id() as u32))
+    }
+
+    pub async fn mark_reminded(pool: &MySqlPool, approval_id: u32) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE vacation_request_approvals SET reminded_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+            approval_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Whether the user can decide the step on `date`: its assignee, the assignee's delegate,
+    /// or, for steps nobody could take, a member of the approver role. Never the requester,
+    /// even when they are the delegate of the assignee.
+    pub async fn can_be_decided_by(
+        &self,
+        pool: &MySqlPool,
+        user_id: u32,
+        requester_id: u32,
+        date: NaiveDate,
+    ) -> Result<bool, sqlx::Error> {
+        if user_id == requester_id {
+            return Ok(false);
+        }
+
+        match self.assigned_to {
+            Some(assigned_to) if assigned_to == user_id => Ok(true),
+            Some(assigned_to) => Ok(ApprovalDelegation::find_delegate(pool, assigned_to, date)
+                .await?
+                == Some(user_id)),
+            None => {
+                let Some(role_id) = self.approver_role_id else {
+                    return Ok(false);
+                };
+                let count = sqlx::query_scalar!(
+                    r#"SELECT COUNT(*) FROM user_effective_roles WHERE user_id = ? AND role_id = ?"#,
+                    user_id,
+                    role_id
+                )
+                .fetch_one(pool)
+                .await?;
+                Ok(count > 0)
+            }
+        }
+    }
+}
@@ -0,0 +1,434 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{MySqlConnection, MySqlPool};
+
+use crate::{
+    models::{vacation_request::VacationRequest, work_schedule::WorkingTime},
+    utils::working_days::DayPart,
+};
+
+/// The vacation a user is entitled to in a year
+pub const LEDGER_ENTITLEMENT: &str = "ENTITLEMENT";
+/// What was left of the year before, until it expires
+pub const LEDGER_CARRY_OVER: &str = "CARRY_OVER";
+/// Added or taken away by an admin, with a reason
+pub const LEDGER_ADJUSTMENT: &str = "ADJUSTMENT";
+/// The carried-over vacation not taken by its expiry, as a negative amount
+pub const LEDGER_EXPIRY: &str = "EXPIRY";
+
+/// Years the ledger covers: entries are only made for them, and balances only asked of them.
+pub const LEDGER_YEARS: std::ops::RangeInclusive<i32> = 2000..=2100;
+
+/// A movement in a user's vacation balance of a year. Amounts are in the user's balance unit.
+#[derive(Debug, Serialize)]
+pub struct VacationLedgerEntry {
+    pub id: u32,
+    pub user_id: u32,
+    pub year: u16,
+    pub entry_type: String,
+    pub amount: i32,
+    pub reason: Option<String>,
+    pub expires_on: Option<NaiveDate>,
+    /// Cannot be taken before, the end of the waiting period of the hire year
+    pub available_from: Option<NaiveDate>,
+    /// `None` for entries made by the scheduler or the statutory rules
+    pub created_by: Option<u32>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// The ledger of a year summed up by entry type.
+#[derive(Debug, Default, Serialize)]
+pub struct VacationBalance {
+    pub year: i32,
+    pub entitlement: i64,
+    pub carried_over: i64,
+    pub carry_over_expires_on: Option<NaiveDate>,
+    pub adjustments: i64,
+    /// Carried-over vacation lost at its expiry, negative
+    pub expired: i64,
+    /// Everything the user can take in the year, before subtracting approved vacation
+    pub total: i64,
+}
+
+/// The entries of a year and what they add up to.
+#[derive(Debug, Serialize)]
+pub struct VacationLedger {
+    pub balance: VacationBalance,
+    pub entries: Vec<VacationLedgerEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VacationLedgerQuery {
+    /// Defaults to the current year
+    pub year: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CarryOverSettings {
+    /// `MM-DD` of the next year
+    pub expiry: String,
+    /// Unlimited when `None`
+    pub max: Option<u16>,
+}
+
+/// The entries the statutory rules give a user in a year, in their balance unit.
+#[derive(Debug)]
+pub struct StatutoryEntries<'a> {
+    pub year: i32,
+    pub amount: i32,
+    pub available_from: Option<NaiveDate>,
+    pub reason: &'a str,
+    /// Days of the hire year taken in this one, booked as a carry-over
+    pub deferred: Option<DeferredEntry>,
+}
+
+#[derive(Debug)]
+pub struct DeferredEntry {
+    pub amount: i32,
+    pub available_from: NaiveDate,
+    pub expires_on: NaiveDate,
+}
+
+/// A year whose balance does not cover a vacation.
+#[derive(Debug)]
+pub struct BalanceShortfall {
+    pub year: i32,
+    pub available: i64,
+    /// Half days and hours make these fractional
+    pub requested: f64,
+    pub approved: f64,
+    /// When more of the balance can be taken, if some of it is still in its waiting period
+    pub available_from: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VacationEntitlementRequest {
+    pub year: i32,
+    pub amount: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VacationAdjustmentRequest {
+    pub year: i32,
+    pub amount: i32,
+    pub reason: String,
+}
+
+impl VacationEntitlementRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if !LEDGER_YEARS.contains(&self.year) {
+            return Err(format!("Ano inválido: {}", self.year));
+        }
+        if self.amount < 0 {
+            return Err("O direito a férias não pode ser negativo.".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl VacationAdjustmentRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if !LEDGER_YEARS.contains(&self.year) {
+            return Err(format!("Ano inválido: {}", self.year));
+        }
+        if self.amount == 0 {
+            return Err("O ajuste não pode ser zero.".to_string());
+        }
+        let reason = self.reason.trim();
+        if reason.is_empty() || reason.chars().count() > 255 {
+            return Err("Indique o motivo do ajuste (até 255 caracteres).".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl VacationBalance {
+    pub fn from_entries(year: i32, entries: &[VacationLedgerEntry]) -> VacationBalance {
+        let mut balance = VacationBalance {
+            year,
+            ..Default::default()
+        };
+
+        for entry in entries {
+            let amount = entry.amount as i64;
+            match entry.entry_type.as_str() {
+                LEDGER_ENTITLEMENT => balance.entitlement += amount,
+                LEDGER_CARRY_OVER => {
+                    balance.carried_over += amount;
+                    balance.carry_over_expires_on = entry.expires_on;
+                }
+                LEDGER_EXPIRY => balance.expired += amount,
+                _ => balance.adjustments += amount,
+            }
+            balance.total += amount;
+        }
+
+        balance
+    }
+}
+
+impl VacationLedgerEntry {
+    pub async fn get_for_user(
+        pool: &MySqlPool,
+        user_id: u32,
+        year: i32,
+    ) -> Result<Vec<VacationLedgerEntry>, sqlx::Error> {
+        sqlx::query_as!(
+            VacationLedgerEntry,
+            r#"
+            SELECT id, user_id, year, entry_type, amount, reason, expires_on, available_from,
+                   created_by, created_at
+            FROM vacation_ledger_entries
+            WHERE user_id = ? AND year = ?
+            ORDER BY created_at, id
+            "#,
+            user_id,
+            year
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn ledger(
+        pool: &MySqlPool,
+        user_id: u32,
+        year: i32,
+    ) -> Result<VacationLedger, sqlx::Error> {
+        let entries = Self::get_for_user(pool, user_id, year).await?;
+        Ok(VacationLedger {
+            balance: VacationBalance::from_entries(year, &entries),
+            entries,
+        })
+    }
+
+    pub async fn balance(
+        pool: &MySqlPool,
+        user_id: u32,
+        year: i32,
+    ) -> Result<VacationBalance, sqlx::Error> {
+        let entries = Self::get_for_user(pool, user_id, year).await?;
+        Ok(VacationBalance::from_entries(year, &entries))
+    }
+
+    /// Sets the entitlement of a year, replacing the amount of the existing entry.
+    pub async fn set_entitlement(
+        pool: &MySqlPool,
+        user_id: u32,
+        year: i32,
+        amount: i32,
+        created_by: Option<u32>,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let existing = sqlx::query_scalar!(
+            r#"
+            SELECT id FROM vacation_ledger_entries
+            WHERE user_id = ? AND year = ? AND entry_type = ?
+            FOR UPDATE
+            "#,
+            user_id,
+            year,
+            LEDGER_ENTITLEMENT
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        match existing {
+            Some(id) => {
+                sqlx::query!(
+                    r#"UPDATE vacation_ledger_entries SET amount = ?, created_by = ? WHERE id = ?"#,
+                    amount,
+                    created_by,
+                    id
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO vacation_ledger_entries (user_id, year, entry_type, amount, created_by)
+                    VALUES (?, ?, ?, ?, ?)
+                    "#,
+                    user_id,
+                    year,
+                    LEDGER_ENTITLEMENT,
+                    amount,
+                    created_by
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await
+    }
+
+    /// Books the statutory entitlement of a year, and the days of the hire year moved into it.
+    /// An entitlement set by an admin is kept unless `replace_override`, returning false.
+    pub async fn set_statutory_entitlement(
+        conn: &mut MySqlConnection,
+        user_id: u32,
+        entries: &StatutoryEntries<'_>,
+        replace_override: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let existing = sqlx::query!(
+            r#"
+            SELECT id, created_by FROM vacation_ledger_entries
+            WHERE user_id = ? AND year = ? AND entry_type = ?
+            FOR UPDATE
+            "#,
+            user_id,
+            entries.year,
+            LEDGER_ENTITLEMENT
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        match existing {
+            Some(entry) if entry.created_by.is_some() && !replace_override => return Ok(false),
+            Some(entry) => {
+                sqlx::query!(
+                    r#"
+                    UPDATE vacation_ledger_entries
+                    SET amount = ?, reason = ?, available_from = ?, created_by = NULL
+                    WHERE id = ?
+                    "#,
+                    entries.amount,
+                    entries.reason,
+                    entries.available_from,
+                    entry.id
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+            None => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO vacation_ledger_entries
+                        (user_id, year, entry_type, amount, reason, available_from)
+                    VALUES (?, ?, ?, ?, ?, ?)
+                    "#,
+                    user_id,
+                    entries.year,
+                    LEDGER_ENTITLEMENT,
+                    entries.amount,
+                    entries.reason,
+                    entries.available_from
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+        }
+
+        // The days of the hire year are the only carry-over with a waiting period
+        sqlx::query!(
+            r#"
+            DELETE FROM vacation_ledger_entries
+            WHERE user_id = ? AND year = ? AND entry_type = ? AND available_from IS NOT NULL
+              AND created_by IS NULL
+            "#,
+            user_id,
+            entries.year,
+            LEDGER_CARRY_OVER
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        if let Some(deferred) = &entries.deferred {
+            sqlx::query!(
+                r#"
+                INSERT INTO vacation_ledger_entries
+                    (user_id, year, entry_type, amount, reason, expires_on, available_from)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+                user_id,
+                entries.year,
+                LEDGER_CARRY_OVER,
+                deferred.amount,
+                format!("Férias do ano de admissão ({})", entries.year - 1),
+                deferred.expires_on,
+                deferred.available_from
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        Ok(true)
+    }
+
+    pub async fn add_adjustment(
+        pool: &MySqlPool,
+        user_id: u32,
+        adjustment: &VacationAdjustmentRequest,
+        created_by: u32,
+    ) -> Result<u32, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO vacation_ledger_entries (user_id, year, entry_type, amount, reason, created_by)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+            user_id,
+            adjustment.year,
+            LEDGER_ADJUSTMENT,
+            adjustment.amount,
+            adjustment.reason.trim(),
+            created_by
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_id() as u32)
+    }
+
+    /// The first year [start, end] falls in whose balance, after the vacation already approved
+    /// in it, is too small for the part of the vacation in that year. `None` when it fits.
+    pub async fn find_shortfall(
+        pool: &MySqlPool,
+        user_id: u32,
+        start: NaiveDate,
+        end: NaiveDate,
+        part: DayPart,
+        working_time: &WorkingTime,
+    ) -> Result<Option<BalanceShortfall>, sqlx::Error> {
+        for year in start.year()..=end.year() {
+            let (Some(year_start), Some(year_end)) = (
+                NaiveDate::from_ymd_opt(year, 1, 1),
+                NaiveDate::from_ymd_opt(year, 12, 31),
+            ) else {
+                continue;
+            };
+
+            let requested = working_time.count_part(start.max(year_start), end.min(year_end), part);
+            if requested <= 0.0 {
+                continue;
+            }
+
+            // Entries still in their waiting period cannot be used yet
+            let slice_start = start.max(year_start);
+            let entries = Self::get_for_user(pool, user_id, year).await?;
+            let available = entries
+                .iter()
+                .filter(|entry| entry.available_from.is_none_or(|from| from <= slice_start))
+                .map(|entry| entry.amount as i64)
+                .sum::<i64>();
+            let approved =
+                VacationRequest::count_approved_vacation_days_for_year(pool, user_id, year).await?;
+            if approved + requested > available as f64 {
+                return Ok(Some(BalanceShortfall {
+                    year,
+                    available,
+                    requested,
+                    approved,
+                    available_from: entries
+                        .iter()
+                        .filter_map(|entry| entry.available_from)
+                        .filter(|from| *from > slice_start)
+                        .min(),
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+}
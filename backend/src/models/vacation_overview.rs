@@ -0,0 +1,337 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+
+use crate::{
+    models::{
+        absence_type::AbsenceType,
+        vacation_ledger::VacationLedgerEntry,
+        vacation_request::{VacationDayPart, VacationRequestStatus},
+        work_schedule::{WorkingTime, balance_unit_str},
+    },
+    utils::vacation_overview::{OverviewDay, Period, daily_matrix},
+};
+
+/// Filters of the admin overview of absences. The period stays within one year, whose
+/// balances the overview shows.
+#[derive(Debug, Deserialize)]
+pub struct VacationOverviewQuery {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub role_id: Option<u32>,
+    pub location: Option<i8>,
+    /// Every request but the rejected and cancelled ones when `None`
+    pub status: Option<VacationRequestStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VacationOverviewExportQuery {
+    pub format: ExportFormat,
+}
+
+/// A user the overview can show, with the roles that decide which admins see them.
+#[derive(Debug)]
+pub struct OverviewUser {
+    pub id: u32,
+    pub username: String,
+    pub location: Option<i8>,
+    pub role_ids: Vec<u32>,
+}
+
+/// A request shown in the overview.
+#[derive(Debug, Serialize)]
+pub struct OverviewPeriod {
+    pub request_id: u32,
+    pub absence_type_id: u32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub day_part: VacationDayPart,
+    pub minutes: Option<u16>,
+    pub status: VacationRequestStatus,
+}
+
+/// The legend of a user: how much vacation they took of the year, and their absences in the
+/// period. Amounts are in the user's balance unit.
+#[derive(Debug, Serialize)]
+pub struct UserVacationSummary {
+    pub user_id: u32,
+    pub username: String,
+    pub location: Option<i8>,
+    pub balance_unit: &'static str,
+    /// Everything the user can take in the year
+    pub total: i64,
+    /// Approved vacation in the year
+    pub taken: f64,
+    /// Vacation in the year waiting for approval
+    pub pending: f64,
+    pub remaining: f64,
+    /// Working time the periods below take inside the period, of every absence type
+    pub absent: f64,
+    pub periods: Vec<OverviewPeriod>,
+}
+
+/// Who is absent on each day of a period, across roles, with a summary of each user.
+#[derive(Debug, Serialize)]
+pub struct VacationOverview {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    /// Names the `absence_type_id` of the periods
+    pub absence_types: Vec<AbsenceType>,
+    pub users: Vec<UserVacationSummary>,
+    pub days: Vec<OverviewDay>,
+}
+
+/// A request of the year, whichever its status, for the balances and the periods.
+struct OverviewRequest {
+    id: u32,
+    user_id: u32,
+    absence_type_id: u32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    day_part: VacationDayPart,
+    minutes: Option<u16>,
+    status: String,
+    counts_against_vacation: bool,
+}
+
+impl VacationOverviewQuery {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.start_date > self.end_date {
+            return Err("A data de início não pode ser posterior à data de fim.".to_string());
+        }
+        if self.start_date.year() != self.end_date.year() {
+            return Err("O período tem de estar dentro de um ano.".to_string());
+        }
+        Ok(())
+    }
+
+    fn shows(&self, status: VacationRequestStatus) -> bool {
+        match self.status {
+            Some(shown) => shown == status,
+            None => !matches!(
+                status,
+                VacationRequestStatus::Rejected | VacationRequestStatus::Cancelled
+            ),
+        }
+    }
+}
+
+impl OverviewUser {
+    /// Users of the role and location, every one when they are `None`, by name.
+    async fn find(
+        pool: &MySqlPool,
+        role_id: Option<u32>,
+        location: Option<i8>,
+    ) -> Result<Vec<OverviewUser>, sqlx::Error> {
+        let users = sqlx::query!(
+            r#"
+            SELECT u.id, u.username, u.location
+            FROM users u
+            WHERE (? IS NULL OR u.location = ?)
+              AND (
+                  ? IS NULL
+                  OR EXISTS (
+                      SELECT 1 FROM user_effective_roles ur
+                      WHERE ur.user_id = u.id AND ur.role_id = ?
+                  )
+              )
+            ORDER BY u.username, u.id
+            "#,
+            location,
+            location,
+            role_id,
+            role_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut role_ids: HashMap<u32, Vec<u32>> = HashMap::new();
+        for row in sqlx::query!(r#"SELECT DISTINCT user_id, role_id FROM user_effective_roles"#)
+            .fetch_all(pool)
+            .await?
+        {
+            role_ids.entry(row.user_id).or_default().push(row.role_id);
+        }
+
+        Ok(users
+            .into_iter()
+            .map(|user| OverviewUser {
+                role_ids: role_ids.remove(&user.id).unwrap_or_default(),
+                id: user.id,
+                username: user.username,
+                location: user.location,
+            })
+            .collect())
+    }
+}
+
+impl OverviewRequest {
+    async fn in_range(
+        pool: &MySqlPool,
+        start: NaiveDate,
+        end: NaiveDate,
+        role_id: Option<u32>,
+        location: Option<i8>,
+    ) -> Result<Vec<OverviewRequest>, sqlx::Error> {
+        sqlx::query_as!(
+            OverviewRequest,
+            r#"
+            SELECT vr.id, vr.user_id, vr.absence_type_id, vr.start_date, vr.end_date,
+                   vr.day_part as "day_part: VacationDayPart", vr.minutes, vr.status,
+                   t.counts_against_vacation as "counts_against_vacation: bool"
+            FROM vacation_requests vr
+            JOIN absence_types t ON t.id = vr.absence_type_id
+            JOIN users u ON u.id = vr.user_id
+            WHERE vr.start_date <= ? AND vr.end_date >= ?
+              AND (? IS NULL OR u.location = ?)
+              AND (
+                  ? IS NULL
+                  OR EXISTS (
+                      SELECT 1 FROM user_effective_roles ur
+                      WHERE ur.user_id = u.id AND ur.role_id = ?
+                  )
+              )
+            ORDER BY vr.start_date, vr.id
+            "#,
+            end,
+            start,
+            location,
+            location,
+            role_id,
+            role_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+impl VacationOverview {
+    /// The overview of the users of the query that `can_see` lets through.
+    pub async fn build(
+        pool: &MySqlPool,
+        query: &VacationOverviewQuery,
+        can_see: impl Fn(&OverviewUser) -> bool,
+    ) -> Result<VacationOverview, sqlx::Error> {
+        let year = query.start_date.year();
+        let year_start = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+        let year_end = NaiveDate::from_ymd_opt(year, 12, 31).unwrap();
+
+        let users: Vec<OverviewUser> = OverviewUser::find(pool, query.role_id, query.location)
+            .await?
+            .into_iter()
+            .filter(|user| can_see(user))
+            .collect();
+        let user_ids: HashSet<u32> = users.iter().map(|user| user.id).collect();
+
+        let mut requests: HashMap<u32, Vec<OverviewRequest>> = HashMap::new();
+        for request in
+            OverviewRequest::in_range(pool, year_start, year_end, query.role_id, query.location)
+                .await?
+        {
+            if user_ids.contains(&request.user_id) {
+                requests.entry(request.user_id).or_default().push(request);
+            }
+        }
+
+        let mut summaries = Vec::with_capacity(users.len());
+        let mut periods = Vec::new();
+        let mut working_times = HashMap::new();
+        for user in users {
+            let working_time = WorkingTime::load(pool, user.id, year_start, year_end).await?;
+            let balance = VacationLedgerEntry::balance(pool, user.id, year).await?;
+
+            let mut taken = 0.0;
+            let mut pending = 0.0;
+            let mut absent = 0.0;
+            let mut user_periods = Vec::new();
+            for request in requests.remove(&user.id).unwrap_or_default() {
+                let status = VacationRequestStatus::from_db(&request.status);
+                let part = request.day_part.part(request.minutes);
+
+                // Counted like the balance users see of their own vacation
+                if request.counts_against_vacation {
+                    let amount = working_time.count_part(
+                        request.start_date.max(year_start),
+                        request.end_date.min(year_end),
+                        part,
+                    );
+                    match status {
+                        VacationRequestStatus::Approved
+                        | VacationRequestStatus::CancellationRequested => taken += amount,
+                        VacationRequestStatus::Pending => pending += amount,
+                        _ => {}
+                    }
+                }
+
+                if !query.shows(status)
+                    || request.end_date < query.start_date
+                    || request.start_date > query.end_date
+                {
+                    continue;
+                }
+                absent += working_time.count_part(
+                    request.start_date.max(query.start_date),
+                    request.end_date.min(query.end_date),
+                    part,
+                );
+                periods.push(Period {
+                    user_id: user.id,
+                    request_id: request.id,
+                    start_date: request.start_date,
+                    end_date: request.end_date,
+                });
+                user_periods.push(OverviewPeriod {
+                    request_id: request.id,
+                    absence_type_id: request.absence_type_id,
+                    start_date: request.start_date,
+                    end_date: request.end_date,
+                    day_part: request.day_part,
+                    minutes: request.minutes,
+                    status,
+                });
+            }
+
+            summaries.push(UserVacationSummary {
+                user_id: user.id,
+                username: user.username,
+                location: user.location,
+                balance_unit: balance_unit_str(working_time.unit),
+                total: balance.total,
+                taken,
+                pending,
+                remaining: balance.total as f64 - taken - pending,
+                absent,
+                periods: user_periods,
+            });
+            working_times.insert(user.id, working_time);
+        }
+
+        let days = daily_matrix(
+            query.start_date,
+            query.end_date,
+            &periods,
+            |user_id, date| {
+                working_times
+                    .get(&user_id)
+                    .is_some_and(|working_time| working_time.works_on(date))
+            },
+        );
+
+        Ok(VacationOverview {
+            start_date: query.start_date,
+            end_date: query.end_date,
+            absence_types: AbsenceType::get_all(pool).await?,
+            users: summaries,
+            days,
+        })
+    }
+}
@@ -1,7 +1,56 @@
-use chrono::{DateTime, NaiveDate, Utc, Datelike};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, MySqlPool, Row};
 
+use crate::{
+    models::{
+        absence_type::{ABSENCE_TYPE_VACATION, AbsenceDocument, AbsenceType},
+        vacation_approval::{APPROVAL_APPROVED, APPROVAL_REJECTED, VacationRequestApproval},
+        vacation_ledger::VacationLedgerEntry,
+        work_schedule::WorkingTime,
+    },
+    utils::working_days::DayPart,
+};
+
+/// The `day_part` of a request.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(
+    type_name = "ENUM('FULL', 'MORNING', 'AFTERNOON', 'HOURS')",
+    rename_all = "UPPERCASE"
+)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum VacationDayPart {
+    #[default]
+    Full,
+    Morning,
+    Afternoon,
+    /// A number of minutes, see `minutes`
+    Hours,
+}
+
+impl VacationDayPart {
+    /// The part of each day a request takes, with the `minutes` of HOURS requests.
+    pub fn part(self, minutes: Option<u16>) -> DayPart {
+        match self {
+            VacationDayPart::Full => DayPart::Full,
+            VacationDayPart::Morning => DayPart::Morning,
+            VacationDayPart::Afternoon => DayPart::Afternoon,
+            VacationDayPart::Hours => DayPart::Minutes(minutes.unwrap_or(0)),
+        }
+    }
+}
+
+impl From<DayPart> for VacationDayPart {
+    fn from(part: DayPart) -> Self {
+        match part {
+            DayPart::Full => VacationDayPart::Full,
+            DayPart::Morning => VacationDayPart::Morning,
+            DayPart::Afternoon => VacationDayPart::Afternoon,
+            DayPart::Minutes(_) => VacationDayPart::Hours,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, sqlx::Type)]
 #[sqlx(
     type_name = "ENUM('PENDING', 'APPROVED', 'REJECTED', 'CANCELLATION_REQUESTED', 'CANCELLED')",
@@ -15,13 +64,36 @@ pub enum VacationRequestStatus {
     Cancelled,
 }
 
+impl VacationRequestStatus {
+    /// The status stored as `status` in `vacation_requests`.
+    pub fn from_db(status: &str) -> VacationRequestStatus {
+        match status {
+            "PENDING" => VacationRequestStatus::Pending,
+            "APPROVED" => VacationRequestStatus::Approved,
+            "REJECTED" => VacationRequestStatus::Rejected,
+            "CANCELLATION_REQUESTED" => VacationRequestStatus::CancellationRequested,
+            "CANCELLED" => VacationRequestStatus::Cancelled,
+            _ => {
+                log::warn!(
+                    "Unknown vacation status string '{}', defaulting to Pending",
+                    status
+                );
+                VacationRequestStatus::Pending // Default or handle error
+            }
+        }
+    }
+}
+
 // Intermediate struct for fetching from DB, with status as String
 #[derive(Debug, FromRow, Clone)]
 struct VacationRequestDbRow {
     pub id: u32,
     pub user_id: u32,
+    pub absence_type_id: u32,
     pub start_date: NaiveDate,
     pub end_date: NaiveDate,
+    pub day_part: VacationDayPart,
+    pub minutes: Option<u16>,
     pub status: String, // Fetched as String
     pub notes: Option<String>,
     pub requested_at: DateTime<Utc>,
@@ -34,8 +106,13 @@ struct VacationRequestDbRow {
 pub struct VacationRequest {
     pub id: u32,
     pub user_id: u32,
+    /// See [`AbsenceType`], vacation unless said otherwise
+    pub absence_type_id: u32,
     pub start_date: NaiveDate,
     pub end_date: NaiveDate,
+    pub day_part: VacationDayPart,
+    /// Time taken by HOURS requests
+    pub minutes: Option<u16>,
     pub status: VacationRequestStatus, // Proper enum
     pub notes: Option<String>,
     pub requested_at: DateTime<Utc>,
@@ -45,22 +122,15 @@ pub struct VacationRequest {
 
 impl From<VacationRequestDbRow> for VacationRequest {
     fn from(db_row: VacationRequestDbRow) -> Self {
-        let status_enum = match db_row.status.as_str() {
-            "PENDING" => VacationRequestStatus::Pending,
-            "APPROVED" => VacationRequestStatus::Approved,
-            "REJECTED" => VacationRequestStatus::Rejected,
-            "CANCELLATION_REQUESTED" => VacationRequestStatus::CancellationRequested,
-            "CANCELLED" => VacationRequestStatus::Cancelled,
-            _ => {
-                log::warn!("Unknown vacation status string '{}', defaulting to Pending", db_row.status);
-                VacationRequestStatus::Pending // Default or handle error
-            }
-        };
+        let status_enum = VacationRequestStatus::from_db(&db_row.status);
         VacationRequest {
             id: db_row.id,
             user_id: db_row.user_id,
+            absence_type_id: db_row.absence_type_id,
             start_date: db_row.start_date,
             end_date: db_row.end_date,
+            day_part: db_row.day_part,
+            minutes: db_row.minutes,
             status: status_enum,
             notes: db_row.notes,
             requested_at: db_row.requested_at,
@@ -73,16 +143,56 @@ impl From<VacationRequestDbRow> for VacationRequest {
 // DTO for creating a new vacation request by a user
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateVacationRequest {
+    #[serde(default = "default_absence_type")]
+    pub absence_type_id: u32,
     pub start_date: NaiveDate,
     pub end_date: NaiveDate,
+    #[serde(default)]
+    pub day_part: VacationDayPart,
+    /// Required for HOURS requests
+    pub minutes: Option<u16>,
     pub notes: Option<String>,
 }
 
+fn default_absence_type() -> u32 {
+    ABSENCE_TYPE_VACATION
+}
+
+impl CreateVacationRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.day_part != VacationDayPart::Full && self.start_date != self.end_date {
+            return Err("Um pedido de parte do dia é só para um dia.".to_string());
+        }
+        match self.minutes {
+            Some(minutes) if self.day_part == VacationDayPart::Hours => {
+                if minutes == 0 || minutes >= 24 * 60 {
+                    return Err("O número de horas tem de estar entre 0 e 24.".to_string());
+                }
+            }
+            None if self.day_part == VacationDayPart::Hours => {
+                return Err("Indique o número de horas do pedido.".to_string());
+            }
+            Some(_) => {
+                return Err("Só os pedidos de horas têm número de horas.".to_string());
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    pub fn part(&self) -> DayPart {
+        self.day_part.part(self.minutes)
+    }
+}
+
 // DTO for an admin to action a vacation request
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ActionVacationRequest {
     pub status: VacationRequestStatus, // Will be either APPROVED or REJECTED
     pub admin_notes: Option<String>,   // Admin's notes for the action
+    /// Approves the request even when it breaks a coverage rule
+    #[serde(default)]
+    pub ignore_coverage: bool,
 }
 
 // Intermediate struct for fetching from DB, with status as String
@@ -90,10 +200,13 @@ pub struct ActionVacationRequest {
 struct VacationRequestWithUserDbRow {
     pub id: u32,
     pub user_id: u32,
+    pub absence_type_id: u32,
     pub username: String,
     pub email: String,
     pub start_date: NaiveDate,
     pub end_date: NaiveDate,
+    pub day_part: VacationDayPart,
+    pub minutes: Option<u16>,
     pub status: String, // Fetched as String
     pub notes: Option<String>,
     pub requested_at: DateTime<Utc>,
@@ -106,10 +219,13 @@ struct VacationRequestWithUserDbRow {
 pub struct VacationRequestWithUser {
     pub id: u32,
     pub user_id: u32,
+    pub absence_type_id: u32,
     pub username: String,
     pub email: String,
     pub start_date: NaiveDate,
     pub end_date: NaiveDate,
+    pub day_part: VacationDayPart,
+    pub minutes: Option<u16>,
     pub status: VacationRequestStatus, // Proper enum
     pub notes: Option<String>,
     pub requested_at: DateTime<Utc>,
@@ -133,10 +249,13 @@ impl From<VacationRequestWithUserDbRow> for VacationRequestWithUser {
         VacationRequestWithUser {
             id: db_row.id,
             user_id: db_row.user_id,
+            absence_type_id: db_row.absence_type_id,
             username: db_row.username,
             email: db_row.email,
             start_date: db_row.start_date,
             end_date: db_row.end_date,
+            day_part: db_row.day_part,
+            minutes: db_row.minutes,
             status: status_enum,
             notes: db_row.notes,
             requested_at: db_row.requested_at,
@@ -147,6 +266,10 @@ impl From<VacationRequestWithUserDbRow> for VacationRequestWithUser {
 }
 
 impl VacationRequest {
+    pub fn part(&self) -> DayPart {
+        self.day_part.part(self.minutes)
+    }
+
     pub async fn create(
         pool: &MySqlPool,
         user_id: u32,
@@ -154,12 +277,16 @@ impl VacationRequest {
     ) -> Result<u32, sqlx::Error> {
         let result = sqlx::query!(
             r#"
-            INSERT INTO vacation_requests (user_id, start_date, end_date, status, notes)
-            VALUES (?, ?, ?, ?, ?)
+            INSERT INTO vacation_requests
+                (user_id, absence_type_id, start_date, end_date, day_part, minutes, status, notes)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
             "#,
             user_id,
+            request_data.absence_type_id,
             request_data.start_date,
             request_data.end_date,
+            request_data.day_part as VacationDayPart,
+            request_data.minutes,
             VacationRequestStatus::Pending as VacationRequestStatus, // New requests are always PENDING
             request_data.notes
         )
@@ -168,6 +295,25 @@ impl VacationRequest {
         Ok(result.last_insert_id() as u32)
     }
 
+    /// Approves a pending request of a type that needs no approval. Returns false when it is
+    /// not pending any more.
+    pub async fn approve_automatically(
+        pool: &MySqlPool,
+        request_id: u32,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE vacation_requests SET status = 'APPROVED', actioned_at = CURRENT_TIMESTAMP
+            WHERE id = ? AND status = 'PENDING'
+            "#,
+            request_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     pub async fn get_by_id(
         pool: &MySqlPool,
         request_id: u32,
@@ -176,7 +322,8 @@ impl VacationRequest {
             VacationRequestDbRow, // Fetch as DbRow first
             r#"
             SELECT
-                id, user_id, start_date, end_date,
+                id, user_id, absence_type_id, start_date, end_date,
+                day_part as "day_part: VacationDayPart", minutes,
                 status,
                 notes,
                 requested_at AS "requested_at!",
@@ -201,7 +348,8 @@ impl VacationRequest {
             VacationRequestDbRow, // Fetch as DbRow first
             r#"
             SELECT
-                id, user_id, start_date, end_date,
+                id, user_id, absence_type_id, start_date, end_date,
+                day_part as "day_part: VacationDayPart", minutes,
                 status,
                 notes,
                 requested_at AS "requested_at!",
@@ -227,8 +375,8 @@ impl VacationRequest {
     ) -> Result<Vec<VacationRequestWithUser>, sqlx::Error> {
         let base_query = r#"
             SELECT
-                vr.id, vr.user_id, u.username, u.email,
-                vr.start_date, vr.end_date,
+                vr.id, vr.user_id, vr.absence_type_id, u.username, u.email,
+                vr.start_date, vr.end_date, vr.day_part, vr.minutes,
                 vr.status,
                 vr.notes,
                 vr.requested_at AS 'requested_at',
@@ -254,14 +402,17 @@ impl VacationRequest {
         };
         
         let rows = sqlx::query(&query_str)
-            .map(|row: sqlx::mysql::MySqlRow| {
-                VacationRequestWithUser {
+            .try_map(|row: sqlx::mysql::MySqlRow| {
+                Ok(VacationRequestWithUser {
                     id: row.get("id"),
                     user_id: row.get("user_id"),
+                    absence_type_id: row.get("absence_type_id"),
                     username: row.get("username"),
                     email: row.get("email"),
                     start_date: row.get("start_date"),
                     end_date: row.get("end_date"),
+                    day_part: row.try_get("day_part")?,
+                    minutes: row.get("minutes"),
                     status: match row.get::<String, _>("status").as_str() {
                         "PENDING" => VacationRequestStatus::Pending,
                         "APPROVED" => VacationRequestStatus::Approved,
@@ -274,7 +425,7 @@ impl VacationRequest {
                     requested_at: row.get("requested_at"),
                     approved_by: row.get("approved_by"),
                     actioned_at: row.get("actioned_at"),
-                }
+                })
             })
             .fetch_all(pool)
             .await?;
@@ -286,12 +437,16 @@ impl VacationRequest {
     /// Returns Ok(true) if action was successful.
     /// Returns Ok(false) if the request was not in PENDING state (already actioned).
     /// Returns Err for database errors or if not enough vacation days for approval.
+    ///
+    /// `approval_step_id` is the open step of an approval chain the action decides. It is
+    /// decided in the same transaction, and nothing changes when it was decided already.
     pub async fn action_request_with_days_deduction(
         pool: &MySqlPool,
         request_id: u32,
         admin_id: u32,
         new_status: VacationRequestStatus,
         admin_notes: Option<String>,
+        approval_step_id: Option<u32>,
     ) -> Result<bool, sqlx::Error> {
         if new_status == VacationRequestStatus::Pending {
             return Err(sqlx::Error::Protocol(
@@ -305,7 +460,8 @@ impl VacationRequest {
         // Fetch status as String first, then convert
         let request_details_raw = sqlx::query!(
             r#"
-            SELECT user_id, start_date, end_date, status
+            SELECT user_id, absence_type_id, start_date, end_date,
+                   day_part as "day_part: VacationDayPart", minutes, status
             FROM vacation_requests
             WHERE id = ?
             "#,
@@ -314,14 +470,16 @@ impl VacationRequest {
         .fetch_optional(&mut *tx)
         .await?;
 
-        let (user_id_for_deduction, request_start_date, request_end_date, current_db_status_str) =
-            match request_details_raw {
-                Some(req) => (req.user_id, req.start_date, req.end_date, req.status),
-                None => {
-                    tx.rollback().await?;
-                    return Err(sqlx::Error::RowNotFound); // Request not found
-                }
-            };
+        let Some(request_details) = request_details_raw else {
+            tx.rollback().await?;
+            return Err(sqlx::Error::RowNotFound); // Request not found
+        };
+        let user_id_for_deduction = request_details.user_id;
+        let absence_type_id = request_details.absence_type_id;
+        let request_start_date = request_details.start_date;
+        let request_end_date = request_details.end_date;
+        let request_part = request_details.day_part.part(request_details.minutes);
+        let current_db_status_str = request_details.status;
         
         // Convert status string to enum
         let current_db_status: VacationRequestStatus = match current_db_status_str.as_str() {
@@ -361,48 +519,53 @@ impl VacationRequest {
         };
 
         if current_db_status == VacationRequestStatus::Pending && new_status == VacationRequestStatus::Approved {
-            let duration_days = crate::utils::working_days::count_working_days(
-                request_start_date,
-                request_end_date,
-                request_start_date.year(),
-            );
-            if duration_days <= 0 {
+            let Some(absence_type) = AbsenceType::get_by_id(pool, absence_type_id).await? else {
                 tx.rollback().await?;
-                return Err(sqlx::Error::Protocol("Invalid request duration.".into()));
+                return Err(sqlx::Error::RowNotFound);
+            };
+            if absence_type.requires_document
+                && !AbsenceDocument::exists_for_request(pool, request_id).await?
+            {
+                tx.rollback().await?;
+                return Err(sqlx::Error::Protocol("Supporting document missing.".into()));
             }
 
-            // Fetch user's total allocated vacation days
-            let user_vacation_days = sqlx::query_scalar!(
-                "SELECT vacation_days_current_year FROM users WHERE id = ?",
-                user_id_for_deduction
-            )
-            .fetch_one(&mut *tx)
-            .await?
-            .unwrap_or(0); // Default to 0 if NULL
-
-            // Calculate already approved days
-            let current_year = chrono::Utc::now().year();
-            let approved_days = VacationRequest::count_approved_vacation_days_for_year(
+            let working_time = WorkingTime::load(
                 pool,
                 user_id_for_deduction,
-                current_year,
+                request_start_date,
+                request_end_date,
             )
             .await?;
-
-            // Calculate the days that would be used after this approval
-            let total_used_days = approved_days + duration_days;
-            
-            // Check if enough vacation days are available
-            if (user_vacation_days as i64) < total_used_days {
+            let duration_days =
+                working_time.count_part(request_start_date, request_end_date, request_part);
+            if duration_days <= 0.0 {
                 tx.rollback().await?;
-                return Err(sqlx::Error::Protocol(
-                    "Not enough vacation days available.".into(),
-                ));
+                return Err(sqlx::Error::Protocol("Invalid request duration.".into()));
             }
 
-            // We no longer deduct days from vacation_days_current_year
-            // The remaining days are calculated on-demand by counting approved days in get_my_remaining_vacation_days
-            // This avoids modifying the total allocation which should remain constant
+            // Check if enough vacation days are available in the ledger of every year the
+            // request falls in, for absences taken from it
+            if absence_type.counts_against_vacation {
+                let shortfall = VacationLedgerEntry::find_shortfall(
+                    pool,
+                    user_id_for_deduction,
+                    request_start_date,
+                    request_end_date,
+                    request_part,
+                    &working_time,
+                )
+                .await?;
+                if shortfall.is_some() {
+                    tx.rollback().await?;
+                    return Err(sqlx::Error::Protocol(
+                        "Not enough vacation days available.".into(),
+                    ));
+                }
+            }
+
+            // Nothing is deducted from the ledger: approved vacation is subtracted from it
+            // whenever a balance is calculated
         }
 
         let new_status_str = match new_status {
@@ -419,7 +582,7 @@ impl VacationRequest {
         let update_result = sqlx::query(&update_sql)
             .bind(new_status_str)
             .bind(admin_id)
-            .bind(admin_notes)
+            .bind(admin_notes.as_deref())
             .bind(request_id)
             .execute(&mut *tx)
             .await?;
@@ -431,6 +594,31 @@ impl VacationRequest {
             return Ok(false); 
         }
 
+        let decision = match new_status {
+            VacationRequestStatus::Approved => Some(APPROVAL_APPROVED),
+            VacationRequestStatus::Rejected => Some(APPROVAL_REJECTED),
+            _ => None,
+        };
+        if let Some(step_id) = approval_step_id
+            && let Some(decision) = decision
+            && !VacationRequestApproval::decide(
+                &mut *tx,
+                step_id,
+                decision,
+                admin_id,
+                admin_notes.as_deref(),
+            )
+            .await?
+        {
+            tx.rollback().await?;
+            log::warn!(
+                "Approval step {} of vacation request {} was already decided.",
+                step_id,
+                request_id
+            );
+            return Ok(false);
+        }
+
         tx.commit().await?;
         Ok(true) // Successfully actioned
     }
@@ -500,7 +688,7 @@ impl VacationRequest {
         pool: &MySqlPool,
         user_ids: &[u32],
         year: i32,
-    ) -> Result<Vec<(NaiveDate, NaiveDate, String)>, sqlx::Error> {
+    ) -> Result<Vec<(NaiveDate, NaiveDate, String, DayPart)>, sqlx::Error> {
         if user_ids.is_empty() {
             return Ok(Vec::new());
         }
@@ -516,7 +704,7 @@ impl VacationRequest {
             .join(",");
         let query_str = format!(
             r#"
-            SELECT start_date, end_date, status
+            SELECT start_date, end_date, day_part, minutes, status
             FROM vacation_requests
             WHERE user_id IN ({})
               AND status IN ('APPROVED', 'PENDING', 'CANCELLATION_REQUESTED')
@@ -549,9 +737,11 @@ impl VacationRequest {
                 let start_date: NaiveDate = sqlx::Row::get(&row, "start_date");
                 let end_date: NaiveDate = sqlx::Row::get(&row, "end_date");
                 let status: String = sqlx::Row::get(&row, "status");
-                (start_date, end_date, status)
+                let day_part: VacationDayPart = sqlx::Row::try_get(&row, "day_part")?;
+                let minutes: Option<u16> = sqlx::Row::get(&row, "minutes");
+                Ok((start_date, end_date, status, day_part.part(minutes)))
             })
-            .collect();
+            .collect::<Result<_, sqlx::Error>>()?;
 
         Ok(rows)
     }
@@ -561,42 +751,57 @@ impl VacationRequest {
         pool: &MySqlPool,
         user_id: u32,
         year: i32,
-    ) -> Result<i64, sqlx::Error> {
+    ) -> Result<f64, sqlx::Error> {
         // Define the start and end of the year for filtering
         let year_start = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
         let year_end = NaiveDate::from_ymd_opt(year, 12, 31).unwrap();
 
+        Self::count_approved_vacation_days_between(pool, user_id, year_start, year_end).await
+    }
+
+    /// Approved vacation days of a user in [start, end], of the absence types taken from the
+    /// vacation ledger; the part of a request outside the range is not counted.
+    pub async fn count_approved_vacation_days_between(
+        pool: &MySqlPool,
+        user_id: u32,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<f64, sqlx::Error> {
         let approved_requests = sqlx::query!(
             r#"
-            SELECT start_date, end_date
-            FROM vacation_requests
-            WHERE user_id = ?
-              AND status IN ('APPROVED', 'CANCELLATION_REQUESTED')
-              AND start_date <= ? 
-              AND end_date >= ?   
+            SELECT vr.start_date, vr.end_date, vr.day_part as "day_part: VacationDayPart",
+                   vr.minutes
+            FROM vacation_requests vr
+            JOIN absence_types t ON t.id = vr.absence_type_id
+            WHERE vr.user_id = ?
+              AND t.counts_against_vacation
+              AND vr.status IN ('APPROVED', 'CANCELLATION_REQUESTED')
+              AND vr.start_date <= ?
+              AND vr.end_date >= ?
             "#,
             user_id,
-            year_end,   
-            year_start, 
+            end,
+            start,
         )
         .fetch_all(pool)
         .await?;
 
-        let mut total_days_in_year = 0;
+        let working_time = WorkingTime::load(pool, user_id, start, end).await?;
+        let mut total_days = 0.0;
 
         for req in approved_requests {
-            // Clamp the request dates to the given year
-            let effective_start_date = std::cmp::max(req.start_date, year_start);
-            let effective_end_date = std::cmp::min(req.end_date, year_end);
+            // Clamp the request dates to the range
+            let effective_start_date = std::cmp::max(req.start_date, start);
+            let effective_end_date = std::cmp::min(req.end_date, end);
 
             if effective_start_date <= effective_end_date {
-                total_days_in_year += crate::utils::working_days::count_working_days(
+                total_days += working_time.count_part(
                     effective_start_date,
                     effective_end_date,
-                    year,
+                    req.day_part.part(req.minutes),
                 );
             }
         }
-        Ok(total_days_in_year)
+        Ok(total_days)
     }
 }
\ No newline at end of file
@@ -1,8 +1,223 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+
 use crate::impl_enum_conversions;
 
+/// Users whose vacation days are set by hand every year, from `vacation_days_current_year`
+pub const ENTITLEMENT_RULE_FIXED: &str = "FIXED";
+/// Users whose vacation days follow the Código do Trabalho, from their hire date and contracts
+pub const ENTITLEMENT_RULE_STATUTORY: &str = "STATUTORY";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Type {
     Addendum,
     Worker,
 }
 
 impl_enum_conversions!(Type, Addendum => 0, Worker => 1);
+
+/// How long a worker contract lasts (sem termo, a termo certo, a termo incerto).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Term {
+    Permanent,
+    Fixed,
+    Uncertain,
+}
+
+impl_enum_conversions!(Term, Permanent => 0, Fixed => 1, Uncertain => 2);
+
+impl Type {
+    pub const ALL: [Type; 2] = [Type::Addendum, Type::Worker];
+
+    pub fn from_value(value: i8) -> Option<Type> {
+        Type::ALL.into_iter().find(|kind| *kind as i8 == value)
+    }
+}
+
+impl Term {
+    pub const ALL: [Term; 3] = [Term::Permanent, Term::Fixed, Term::Uncertain];
+
+    pub fn from_value(value: i8) -> Option<Term> {
+        Term::ALL.into_iter().find(|term| *term as i8 == value)
+    }
+}
+
+/// A worker contract, or an addendum changing its term, from `start_date` on. The one that
+/// starts last says when the employment ends.
+#[derive(Debug, Serialize)]
+pub struct WorkContract {
+    pub id: u32,
+    pub user_id: u32,
+    pub contract_type: Type,
+    pub term: Term,
+    pub start_date: NaiveDate,
+    /// `None` for permanent contracts, and uncertain ones whose end is not known yet
+    pub end_date: Option<NaiveDate>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WorkContractRequest {
+    pub contract_type: i8,
+    pub term: i8,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+    pub notes: Option<String>,
+}
+
+impl WorkContractRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if Type::from_value(self.contract_type).is_none() {
+            return Err("Tipo de contrato inválido.".to_string());
+        }
+        match Term::from_value(self.term) {
+            None => return Err("Termo do contrato inválido.".to_string()),
+            Some(Term::Permanent) if self.end_date.is_some() => {
+                return Err("Um contrato sem termo não tem data de fim.".to_string());
+            }
+            Some(Term::Fixed) if self.end_date.is_none() => {
+                return Err("Um contrato a termo certo tem de ter data de fim.".to_string());
+            }
+            _ => {}
+        }
+        if self.end_date.is_some_and(|end| end < self.start_date) {
+            return Err("A data de fim não pode ser anterior à de início.".to_string());
+        }
+        if self
+            .notes
+            .as_ref()
+            .is_some_and(|notes| notes.chars().count() > 255)
+        {
+            return Err("As notas não podem ter mais de 255 caracteres.".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl WorkContract {
+    pub async fn get_for_user(
+        pool: &MySqlPool,
+        user_id: u32,
+    ) -> Result<Vec<WorkContract>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, user_id, contract_type, term, start_date, end_date, notes
+            FROM work_contracts
+            WHERE user_id = ?
+            ORDER BY start_date, id
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| WorkContract {
+                id: row.id,
+                user_id: row.user_id,
+                contract_type: row.contract_type.into(),
+                term: row.term.into(),
+                start_date: row.start_date,
+                end_date: row.end_date,
+                notes: row.notes,
+            })
+            .collect())
+    }
+
+    pub async fn create(
+        pool: &MySqlPool,
+        user_id: u32,
+        contract: &WorkContractRequest,
+    ) -> Result<u32, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO work_contracts (user_id, contract_type, term, start_date, end_date, notes)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+            user_id,
+            contract.contract_type,
+            contract.term,
+            contract.start_date,
+            contract.end_date,
+            contract.notes
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_id() as u32)
+    }
+
+    /// Returns the user of the contract, `None` when it does not exist.
+    pub async fn update(
+        pool: &MySqlPool,
+        contract_id: u32,
+        contract: &WorkContractRequest,
+    ) -> Result<Option<u32>, sqlx::Error> {
+        let Some(user_id) = Self::user_id(pool, contract_id).await? else {
+            return Ok(None);
+        };
+
+        sqlx::query!(
+            r#"
+            UPDATE work_contracts
+            SET contract_type = ?, term = ?, start_date = ?, end_date = ?, notes = ?
+            WHERE id = ?
+            "#,
+            contract.contract_type,
+            contract.term,
+            contract.start_date,
+            contract.end_date,
+            contract.notes,
+            contract_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(Some(user_id))
+    }
+
+    /// Returns the user of the contract, `None` when it does not exist.
+    pub async fn delete(pool: &MySqlPool, contract_id: u32) -> Result<Option<u32>, sqlx::Error> {
+        let Some(user_id) = Self::user_id(pool, contract_id).await? else {
+            return Ok(None);
+        };
+
+        sqlx::query!(r#"DELETE FROM work_contracts WHERE id = ?"#, contract_id)
+            .execute(pool)
+            .await?;
+
+        Ok(Some(user_id))
+    }
+
+    async fn user_id(pool: &MySqlPool, contract_id: u32) -> Result<Option<u32>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT user_id FROM work_contracts WHERE id = ?"#,
+            contract_id
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// When the user's employment ends: the end of the contract or addendum that starts last.
+    /// `None` while it is open-ended, or the user has no contracts.
+    pub async fn employment_end(
+        pool: &MySqlPool,
+        user_id: u32,
+    ) -> Result<Option<NaiveDate>, sqlx::Error> {
+        let end_date = sqlx::query_scalar!(
+            r#"
+            SELECT end_date FROM work_contracts
+            WHERE user_id = ?
+            ORDER BY start_date DESC, id DESC
+            LIMIT 1
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(end_date.flatten())
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashSet;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::{MySqlPool, types::Json};
+
+use crate::{
+    models::holiday::Holiday,
+    utils::working_days::{
+        BalanceUnit, DayPart, WorkPattern, WorkTimeline, count_day_parts, count_working_days,
+    },
+};
+
+pub const BALANCE_UNIT_DAYS: &str = "DAYS";
+pub const BALANCE_UNIT_HOURS: &str = "HOURS";
+
+/// Longest cycle a schedule can have, eight weeks.
+const MAX_CYCLE_DAYS: usize = 56;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// Minutes worked on each day of a cycle that repeats, a week or a rotation of shifts.
+#[derive(Debug, Serialize)]
+pub struct WorkSchedule {
+    pub id: u32,
+    pub name: String,
+    pub description: Option<String>,
+    /// 0 for days off
+    pub day_minutes: Vec<u16>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WorkScheduleRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub day_minutes: Vec<u16>,
+}
+
+/// A schedule a user works from `valid_from` until their next one.
+#[derive(Debug, Serialize)]
+pub struct UserWorkSchedule {
+    pub id: u32,
+    pub user_id: u32,
+    pub schedule_id: u32,
+    pub schedule_name: String,
+    pub valid_from: NaiveDate,
+    pub cycle_start: NaiveDate,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserWorkScheduleRequest {
+    pub schedule_id: u32,
+    pub valid_from: NaiveDate,
+    /// Defaults to `valid_from`
+    pub cycle_start: Option<NaiveDate>,
+}
+
+impl WorkScheduleRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("O nome é obrigatório.".to_string());
+        }
+        if self.day_minutes.is_empty() || self.day_minutes.len() > MAX_CYCLE_DAYS {
+            return Err(format!(
+                "O ciclo tem de ter entre 1 e {} dias.",
+                MAX_CYCLE_DAYS
+            ));
+        }
+        if self
+            .day_minutes
+            .iter()
+            .any(|minutes| *minutes > MINUTES_PER_DAY)
+        {
+            return Err("Um dia não pode ter mais de 24 horas.".to_string());
+        }
+        if self.day_minutes.iter().all(|minutes| *minutes == 0) {
+            return Err("O horário tem de ter pelo menos um dia de trabalho.".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub fn balance_unit_from_str(unit: &str) -> BalanceUnit {
+    match unit {
+        BALANCE_UNIT_HOURS => BalanceUnit::Hours,
+        _ => BalanceUnit::Days,
+    }
+}
+
+pub fn balance_unit_str(unit: BalanceUnit) -> &'static str {
+    match unit {
+        BalanceUnit::Days => BALANCE_UNIT_DAYS,
+        BalanceUnit::Hours => BALANCE_UNIT_HOURS,
+    }
+}
+
+impl WorkSchedule {
+    pub async fn get_all(pool: &MySqlPool) -> Result<Vec<WorkSchedule>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, name, description, day_minutes as "day_minutes: Json<Vec<u16>>"
+            FROM work_schedules
+            ORDER BY name
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| WorkSchedule {
+                id: row.id,
+                name: row.name,
+                description: row.description,
+                day_minutes: row.day_minutes.0,
+            })
+            .collect())
+    }
+
+    pub async fn create(
+        pool: &MySqlPool,
+        schedule: &WorkScheduleRequest,
+    ) -> Result<u32, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"INSERT INTO work_schedules (name, description, day_minutes) VALUES (?, ?, ?)"#,
+            schedule.name.trim(),
+            schedule.description,
+            Json(&schedule.day_minutes) as _
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_id() as u32)
+    }
+
+    /// Returns false when the schedule does not exist. The change applies to every count made
+    /// from now on, past ones included.
+    pub async fn update(
+        pool: &MySqlPool,
+        schedule_id: u32,
+        schedule: &WorkScheduleRequest,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE work_schedules SET name = ?, description = ?, day_minutes = ?
+            WHERE id = ?
+            "#,
+            schedule.name.trim(),
+            schedule.description,
+            Json(&schedule.day_minutes) as _,
+            schedule_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Whether any user has had the schedule, which keeps it from being deleted.
+    pub async fn is_assigned(pool: &MySqlPool, schedule_id: u32) -> Result<bool, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) FROM user_work_schedules WHERE schedule_id = ?"#,
+            schedule_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count > 0)
+    }
+
+    pub async fn delete(pool: &MySqlPool, schedule_id: u32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(r#"DELETE FROM work_schedules WHERE id = ?"#, schedule_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+impl UserWorkSchedule {
+    pub async fn get_for_user(
+        pool: &MySqlPool,
+        user_id: u32,
+    ) -> Result<Vec<UserWorkSchedule>, sqlx::Error> {
+        sqlx::query_as!(
+            UserWorkSchedule,
+            r#"
+            SELECT uws.id, uws.user_id, uws.schedule_id, ws.name as schedule_name,
+                   uws.valid_from, uws.cycle_start
+            FROM user_work_schedules uws
+            JOIN work_schedules ws ON ws.id = uws.schedule_id
+            WHERE uws.user_id = ?
+            ORDER BY uws.valid_from
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Assigns a schedule from a date on, replacing the one assigned from that same date.
+    pub async fn assign(
+        pool: &MySqlPool,
+        user_id: u32,
+        assignment: &UserWorkScheduleRequest,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO user_work_schedules (user_id, schedule_id, valid_from, cycle_start)
+            VALUES (?, ?, ?, ?)
+            ON DUPLICATE KEY UPDATE schedule_id = VALUES(schedule_id),
+                                    cycle_start = VALUES(cycle_start)
+            "#,
+            user_id,
+            assignment.schedule_id,
+            assignment.valid_from,
+            assignment.cycle_start.unwrap_or(assignment.valid_from)
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(pool: &MySqlPool, user_id: u32, id: u32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"DELETE FROM user_work_schedules WHERE id = ? AND user_id = ?"#,
+            id,
+            user_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Every schedule the user has had, for counting working time on any date.
+    pub async fn timeline(pool: &MySqlPool, user_id: u32) -> Result<WorkTimeline, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT uws.valid_from, uws.cycle_start,
+                   ws.day_minutes as "day_minutes: Json<Vec<u16>>"
+            FROM user_work_schedules uws
+            JOIN work_schedules ws ON ws.id = uws.schedule_id
+            WHERE uws.user_id = ?
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(WorkTimeline::new(
+            rows.into_iter()
+                .map(|row| {
+                    (
+                        row.valid_from,
+                        WorkPattern {
+                            cycle_start: row.cycle_start,
+                            day_minutes: row.day_minutes.0,
+                        },
+                    )
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// Everything a user's vacation is counted against between two dates: their schedules, the
+/// holidays of their location and the unit of their balance.
+pub struct WorkingTime {
+    pub unit: BalanceUnit,
+    timeline: WorkTimeline,
+    holidays: HashSet<NaiveDate>,
+}
+
+impl WorkingTime {
+    pub async fn load(
+        pool: &MySqlPool,
+        user_id: u32,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<WorkingTime, sqlx::Error> {
+        let user = sqlx::query!(
+            r#"SELECT location, vacation_balance_unit FROM users WHERE id = ?"#,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(WorkingTime {
+            unit: balance_unit_from_str(&user.vacation_balance_unit),
+            timeline: UserWorkSchedule::timeline(pool, user_id).await?,
+            holidays: Holiday::dates_between(pool, start, end, user.location).await?,
+        })
+    }
+
+    /// Scheduled working time in [start, end], which must lie within the loaded range.
+    pub fn count(&self, start: NaiveDate, end: NaiveDate) -> i64 {
+        count_working_days(start, end, &self.holidays, &self.timeline, self.unit)
+    }
+
+    /// Like [`WorkingTime::count`], for requests taking only part of each day.
+    pub fn count_part(&self, start: NaiveDate, end: NaiveDate, part: DayPart) -> f64 {
+        count_day_parts(start, end, &self.holidays, &self.timeline, self.unit, part)
+    }
+
+    /// Whether the user is scheduled to work on `date` and it is not a holiday.
+    pub fn works_on(&self, date: NaiveDate) -> bool {
+        self.timeline.minutes_on(date) > 0 && !self.holidays.contains(&date)
+    }
+}
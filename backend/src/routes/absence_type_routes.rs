@@ -0,0 +1,26 @@
+use actix_web::{middleware::from_fn, web};
+use crate::{auth::require_admin, handlers::absence_type_handlers};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.route(
+        "/absence-types",
+        web::get().to(absence_type_handlers::get_active_absence_types),
+    );
+    cfg.service(
+        web::scope("/admin/absence-types")
+            .wrap(from_fn(require_admin))
+            .route("", web::get().to(absence_type_handlers::get_absence_types))
+            .route(
+                "",
+                web::post().to(absence_type_handlers::create_absence_type),
+            )
+            .route(
+                "/{absence_type_id}",
+                web::put().to(absence_type_handlers::update_absence_type),
+            )
+            .route(
+                "/{absence_type_id}",
+                web::delete().to(absence_type_handlers::delete_absence_type),
+            ),
+    );
+}
@@ -12,6 +12,26 @@ pub fn init(cfg: &mut web::ServiceConfig) {
                 "/request/{request_id}/action",
                 web::put().to(admin_vacation_handlers::action_vacation_request_admin)
             )
+            .route( // Route for the documents supporting a request, like a sick note
+                "/request/{request_id}/documents",
+                web::get().to(admin_vacation_handlers::get_request_documents)
+            )
+            .route( // Route for downloading one of those documents
+                "/request/{request_id}/documents/{document_id}",
+                web::get().to(admin_vacation_handlers::download_request_document)
+            )
+            .route( // Route for the approval trail of a request
+                "/request/{request_id}/approvals",
+                web::get().to(admin_vacation_handlers::get_request_approvals)
+            )
+            .route( // Route for the absences of every role, day by day
+                "/overview",
+                web::get().to(admin_vacation_handlers::get_vacation_overview)
+            )
+            .route( // Route for the overview as a CSV or XLSX file
+                "/overview/export",
+                web::get().to(admin_vacation_handlers::export_vacation_overview)
+            )
         // Future routes could be added here
     );
 }
\ No newline at end of file
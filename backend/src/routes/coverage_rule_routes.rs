@@ -0,0 +1,25 @@
+use actix_web::{middleware::from_fn, web};
+use crate::{auth::require_admin, handlers::coverage_rule_handlers};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/coverage-rules")
+            .wrap(from_fn(require_admin))
+            .route(
+                "",
+                web::get().to(coverage_rule_handlers::get_coverage_rules),
+            )
+            .route(
+                "",
+                web::post().to(coverage_rule_handlers::create_coverage_rule),
+            )
+            .route(
+                "/{rule_id}",
+                web::put().to(coverage_rule_handlers::update_coverage_rule),
+            )
+            .route(
+                "/{rule_id}",
+                web::delete().to(coverage_rule_handlers::delete_coverage_rule),
+            ),
+    );
+}
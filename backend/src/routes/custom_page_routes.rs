@@ -35,6 +35,14 @@ pub fn init(cfg: &mut web::ServiceConfig) {
                 "/{page_id}/permissions",
                 web::put().to(custom_page_handlers::update_page_permissions),
             )
+            .route(
+                "/{page_id}/row-rules",
+                web::get().to(custom_page_handlers::get_page_row_rules),
+            )
+            .route(
+                "/{page_id}/row-rules",
+                web::put().to(custom_page_handlers::update_page_row_rules),
+            )
             .route(
                 "/{id}",
                 web::get().to(custom_page_handlers::get_custom_page),
@@ -11,6 +11,14 @@ pub fn init(cfg: &mut web::ServiceConfig) {
                 "/{field_id}",
                 web::delete().to(field_handlers::delete_field),
             )
+            .route(
+                "/{field_id}/permissions",
+                web::get().to(field_handlers::get_field_permissions),
+            )
+            .route(
+                "/{field_id}/permissions",
+                web::put().to(field_handlers::update_field_permissions),
+            )
             .route(
                 "/validations",
                 web::get().to(field_handlers::get_validations),
@@ -0,0 +1,36 @@
+use actix_web::{middleware::from_fn, web};
+use crate::{auth::require_admin, handlers::holiday_handlers};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/holidays")
+            .wrap(from_fn(require_admin))
+            .route("", web::get().to(holiday_handlers::get_holidays))
+            .route("", web::post().to(holiday_handlers::create_holiday))
+            .route(
+                "/generate",
+                web::post().to(holiday_handlers::generate_holidays),
+            )
+            .route("/rules", web::get().to(holiday_handlers::get_holiday_rules))
+            .route(
+                "/rules",
+                web::post().to(holiday_handlers::create_holiday_rule),
+            )
+            .route(
+                "/rules/{rule_id}",
+                web::put().to(holiday_handlers::update_holiday_rule),
+            )
+            .route(
+                "/rules/{rule_id}",
+                web::delete().to(holiday_handlers::delete_holiday_rule),
+            )
+            .route(
+                "/{holiday_id}",
+                web::put().to(holiday_handlers::update_holiday),
+            )
+            .route(
+                "/{holiday_id}",
+                web::delete().to(holiday_handlers::delete_holiday),
+            ),
+    );
+}
@@ -0,0 +1,16 @@
+use actix_web::{middleware::from_fn, web};
+use crate::{auth::require_admin, handlers::job_handlers};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/jobs")
+            .wrap(from_fn(require_admin))
+            .route("", web::get().to(job_handlers::get_jobs))
+            .route(
+                "/{name}/enabled",
+                web::put().to(job_handlers::set_job_enabled),
+            )
+            .route("/{name}/runs", web::get().to(job_handlers::get_job_runs))
+            .route("/{name}/run", web::post().to(job_handlers::trigger_job)),
+    );
+}
@@ -1,27 +1,45 @@
-use actix_session::Session;
+use std::path::{Component, Path};
+
 use actix_web::{Error, HttpRequest, error::ErrorUnauthorized, get, web};
 
-use crate::auth::validate_session;
+use crate::auth::AuthenticatedUser;
 
+pub mod absence_type_routes;
 pub mod admin_vacation_routes;
 pub mod calendar_routes;
+pub mod coverage_rule_routes;
 pub mod custom_page_routes;
 pub mod field_routes;
+pub mod holiday_routes;
+pub mod job_routes;
 pub mod notification_routes;
 pub mod record_routes;
 pub mod role_routes;
+pub mod security_routes;
 pub mod user_routes;
+pub mod vacation_approval_routes;
+pub mod vacation_ledger_routes;
 pub mod vacation_routes;
+pub mod work_contract_routes;
+pub mod work_schedule_routes;
 
-#[get("/{filename:.*}")]
-async fn serve_files(req: HttpRequest, session: Session) -> Result<actix_files::NamedFile, Error> {
-    if validate_session(&session).is_err() {
-        return Err(ErrorUnauthorized("Não autorizado"));
-    }
+/// Directory under `media` whose files anyone can fetch: the page icons.
+pub const PUBLIC_MEDIA_DIR: &str = "page_icons";
 
+#[get("/{filename:.*}")]
+async fn serve_files(
+    req: HttpRequest,
+    _user: AuthenticatedUser,
+) -> Result<actix_files::NamedFile, Error> {
     let path = req.match_info().query("filename");
 
-    if !path.starts_with("media") {
+    let public = Path::new(path)
+        .strip_prefix("media")
+        .is_ok_and(|path| path.starts_with(PUBLIC_MEDIA_DIR));
+    let escapes = Path::new(path)
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)));
+    if !public || escapes {
         return Err(ErrorUnauthorized("Não autorizado"));
     }
 
@@ -39,6 +57,15 @@ pub fn init(cfg: &mut web::ServiceConfig) {
     notification_routes::init(cfg);
     vacation_routes::init(cfg); // Added vacation routes
     admin_vacation_routes::init(cfg);
+    job_routes::init(cfg);
+    security_routes::init(cfg);
+    holiday_routes::init(cfg);
+    work_schedule_routes::init(cfg);
+    vacation_ledger_routes::init(cfg);
+    work_contract_routes::init(cfg);
+    absence_type_routes::init(cfg);
+    coverage_rule_routes::init(cfg);
+    vacation_approval_routes::init(cfg);
 
     cfg.service(serve_files);
 }
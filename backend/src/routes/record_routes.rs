@@ -5,6 +5,11 @@ use crate::handlers::{record_handlers, acknowledgment_handlers}; // Add acknowle
 pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/records")
+            // Must come before "/{record_id}"
+            .route(
+                "/expiring",
+                web::get().to(record_handlers::get_expiring_records),
+            )
             // Standard Record Routes
             .route("/{record_id}", web::get().to(record_handlers::get_record))
             .route(
@@ -19,6 +24,10 @@ pub fn init(cfg: &mut web::ServiceConfig) {
                 "/{record_id}/files",
                 web::post().to(record_handlers::upload_record_files),
             )
+            .route(
+                "/{record_id}/files/{file_id}",
+                web::get().to(record_handlers::download_record_file),
+            )
             .route(
                 "/{record_id}/files/{file_id}",
                 web::delete().to(record_handlers::delete_record_file),
@@ -0,0 +1,28 @@
+use actix_web::{middleware::from_fn, web};
+use crate::{auth::require_admin, handlers::security_handlers};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/security")
+            .wrap(from_fn(require_admin))
+            .route("/events", web::get().to(security_handlers::get_security_events))
+            .route("/access", web::get().to(security_handlers::get_access_summary))
+            .route(
+                "/users/{user_id}/unlock",
+                web::post().to(security_handlers::unlock_user),
+            )
+            .route("/ips/{ip}/unlock", web::post().to(security_handlers::unlock_ip))
+            .route(
+                "/auth-providers",
+                web::get().to(security_handlers::get_auth_providers),
+            )
+            .route(
+                "/group-mappings",
+                web::get().to(security_handlers::get_group_mappings),
+            )
+            .route(
+                "/group-mappings",
+                web::put().to(security_handlers::replace_group_mappings),
+            ),
+    );
+}
@@ -1,12 +1,43 @@
 use actix_web::web;
 
-use crate::handlers::{user_handlers, vacation_handlers}; // Import vacation_handlers
+use crate::handlers::{
+    account_handlers, oidc_handlers, two_factor_handlers, user_handlers, vacation_handlers,
+}; // Import vacation_handlers
 
 pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/users")
             .route("/register", web::post().to(user_handlers::register))
+            .route(
+                "/registration",
+                web::get().to(account_handlers::get_registration_status),
+            )
+            .route(
+                "/password/forgot",
+                web::post().to(account_handlers::forgot_password),
+            )
+            .route(
+                "/password/token/{token}",
+                web::get().to(account_handlers::get_password_token),
+            )
+            .route(
+                "/password/reset",
+                web::post().to(account_handlers::set_password_with_token),
+            )
             .route("/login", web::post().to(user_handlers::login))
+            .route(
+                "/login/2fa",
+                web::post().to(two_factor_handlers::login_second_factor),
+            )
+            .route(
+                "/login/options",
+                web::get().to(oidc_handlers::get_login_options),
+            )
+            .route("/login/oidc", web::get().to(oidc_handlers::oidc_login))
+            .route(
+                "/login/oidc/callback",
+                web::get().to(oidc_handlers::oidc_callback),
+            )
             .route("/protected", web::get().to(user_handlers::protected))
             .route("/check", web::post().to(user_handlers::check))
             .route("/logout", web::post().to(user_handlers::logout))
@@ -26,7 +57,43 @@ pub fn init(cfg: &mut web::ServiceConfig) {
                 "/me/password",
                 web::put().to(user_handlers::change_user_password),
             )
+            .route(
+                "/me/2fa",
+                web::get().to(two_factor_handlers::get_two_factor_status),
+            )
+            .route(
+                "/me/2fa/setup",
+                web::post().to(two_factor_handlers::setup_two_factor),
+            )
+            .route(
+                "/me/2fa/confirm",
+                web::post().to(two_factor_handlers::confirm_two_factor),
+            )
+            .route(
+                "/me/2fa/disable",
+                web::post().to(two_factor_handlers::disable_two_factor),
+            )
+            .route(
+                "/me/2fa/recovery-codes",
+                web::post().to(two_factor_handlers::regenerate_recovery_codes),
+            )
             // Admin routes for specific user modification
+            .route(
+                "/admin/registration",
+                web::put().to(account_handlers::set_registration_status),
+            )
+            .route(
+                "/admin/invite",
+                web::post().to(account_handlers::invite_user),
+            )
+            .route(
+                "/admin/{user_id}/invite",
+                web::post().to(account_handlers::resend_invitation),
+            )
+            .route(
+                "/admin/{user_id}/password-reset",
+                web::post().to(account_handlers::admin_send_password_reset),
+            )
             .route(
                 "/admin/{user_id}/details",
                 web::put().to(user_handlers::admin_update_user_details),
@@ -35,6 +102,10 @@ pub fn init(cfg: &mut web::ServiceConfig) {
                 "/admin/{user_id}/password",
                 web::put().to(user_handlers::admin_set_user_password),
             )
+            .route(
+                "/admin/{user_id}/2fa",
+                web::delete().to(two_factor_handlers::admin_reset_two_factor),
+            )
             .route(
                 "/admin/{user_id}",
                 web::delete().to(user_handlers::admin_delete_user),
@@ -43,6 +114,11 @@ pub fn init(cfg: &mut web::ServiceConfig) {
             .route(
                 "/me/vacation-days",
                 web::get().to(vacation_handlers::get_my_remaining_vacation_days),
+            )
+            // The entries behind the balance of a year
+            .route(
+                "/me/vacation-ledger",
+                web::get().to(vacation_handlers::get_my_vacation_ledger),
             ),
     );
 }
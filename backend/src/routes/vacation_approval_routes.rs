@@ -0,0 +1,40 @@
+use actix_web::{middleware::from_fn, web};
+use crate::{auth::require_admin, handlers::vacation_approval_handlers};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/vacation-approvals")
+            .route(
+                "/pending",
+                web::get().to(vacation_approval_handlers::get_my_pending_approvals),
+            )
+            .route(
+                "/delegations",
+                web::get().to(vacation_approval_handlers::get_my_delegations),
+            )
+            .route(
+                "/delegations",
+                web::post().to(vacation_approval_handlers::create_delegation),
+            )
+            .route(
+                "/delegations/{delegation_id}",
+                web::delete().to(vacation_approval_handlers::delete_delegation),
+            )
+            .route(
+                "/{request_id}/action",
+                web::put().to(vacation_approval_handlers::action_approval),
+            ),
+    );
+    cfg.service(
+        web::scope("/admin/approval-chains")
+            .wrap(from_fn(require_admin))
+            .route(
+                "/{role_id}",
+                web::get().to(vacation_approval_handlers::get_approval_chain),
+            )
+            .route(
+                "/{role_id}",
+                web::put().to(vacation_approval_handlers::update_approval_chain),
+            ),
+    );
+}
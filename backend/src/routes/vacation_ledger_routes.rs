@@ -0,0 +1,37 @@
+use actix_web::{middleware::from_fn, web};
+use crate::{auth::require_admin, handlers::vacation_ledger_handlers};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/vacation-ledger")
+            .wrap(from_fn(require_admin))
+            .route(
+                "/carry-over-settings",
+                web::get().to(vacation_ledger_handlers::get_carry_over_settings),
+            )
+            .route(
+                "/carry-over-settings",
+                web::put().to(vacation_ledger_handlers::set_carry_over_settings),
+            )
+            .route(
+                "/{user_id}",
+                web::get().to(vacation_ledger_handlers::get_user_vacation_ledger),
+            )
+            .route(
+                "/{user_id}/entitlement",
+                web::put().to(vacation_ledger_handlers::set_user_vacation_entitlement),
+            )
+            .route(
+                "/{user_id}/statutory-entitlement",
+                web::get().to(vacation_ledger_handlers::get_statutory_entitlement),
+            )
+            .route(
+                "/{user_id}/statutory-entitlement",
+                web::post().to(vacation_ledger_handlers::apply_statutory_entitlement),
+            )
+            .route(
+                "/{user_id}/adjustments",
+                web::post().to(vacation_ledger_handlers::add_user_vacation_adjustment),
+            ),
+    );
+}
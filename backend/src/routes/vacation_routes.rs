@@ -1,5 +1,5 @@
 // src/routes/vacation_routes.rs
-use crate::handlers::vacation_handlers;
+use crate::handlers::{vacation_approval_handlers, vacation_handlers};
 use actix_web::web;
 
 pub fn init(cfg: &mut web::ServiceConfig) {
@@ -19,6 +19,22 @@ pub fn init(cfg: &mut web::ServiceConfig) {
                 "/shared-calendar",
                 web::get().to(vacation_handlers::get_shared_calendar_vacations),
             )
+            .route(
+                "/{request_id}/documents",
+                web::get().to(vacation_handlers::get_absence_documents),
+            )
+            .route(
+                "/{request_id}/documents",
+                web::post().to(vacation_handlers::upload_absence_documents),
+            )
+            .route(
+                "/{request_id}/documents/{document_id}",
+                web::get().to(vacation_handlers::download_absence_document),
+            )
+            .route(
+                "/{request_id}/approvals",
+                web::get().to(vacation_approval_handlers::get_request_approvals),
+            )
             .route(
                 "/{request_id}/request-cancellation",
                 web::post().to(vacation_handlers::request_vacation_cancellation),
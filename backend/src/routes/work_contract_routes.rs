@@ -0,0 +1,25 @@
+use actix_web::{middleware::from_fn, web};
+use crate::{auth::require_admin, handlers::work_contract_handlers};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/work-contracts")
+            .wrap(from_fn(require_admin))
+            .route(
+                "/users/{user_id}",
+                web::get().to(work_contract_handlers::get_user_work_contracts),
+            )
+            .route(
+                "/users/{user_id}",
+                web::post().to(work_contract_handlers::create_work_contract),
+            )
+            .route(
+                "/{contract_id}",
+                web::put().to(work_contract_handlers::update_work_contract),
+            )
+            .route(
+                "/{contract_id}",
+                web::delete().to(work_contract_handlers::delete_work_contract),
+            ),
+    );
+}
@@ -0,0 +1,37 @@
+use actix_web::{middleware::from_fn, web};
+use crate::{auth::require_admin, handlers::work_schedule_handlers};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/work-schedules")
+            .wrap(from_fn(require_admin))
+            .route(
+                "",
+                web::get().to(work_schedule_handlers::get_work_schedules),
+            )
+            .route(
+                "",
+                web::post().to(work_schedule_handlers::create_work_schedule),
+            )
+            .route(
+                "/users/{user_id}",
+                web::get().to(work_schedule_handlers::get_user_work_schedules),
+            )
+            .route(
+                "/users/{user_id}",
+                web::post().to(work_schedule_handlers::assign_user_work_schedule),
+            )
+            .route(
+                "/users/{user_id}/{assignment_id}",
+                web::delete().to(work_schedule_handlers::delete_user_work_schedule),
+            )
+            .route(
+                "/{schedule_id}",
+                web::put().to(work_schedule_handlers::update_work_schedule),
+            )
+            .route(
+                "/{schedule_id}",
+                web::delete().to(work_schedule_handlers::delete_work_schedule),
+            ),
+    );
+}
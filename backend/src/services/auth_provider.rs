@@ -0,0 +1,322 @@
+//! Ways of checking the password of a login.
+//!
+//! `AUTH_PROVIDERS` lists the providers to try, in order, separated by commas (default
+//! `local`). `local` checks the password stored in `users`; `ldap` binds to a directory, see
+//! [`crate::services::ldap_provider`]. Accounts a directory vouches for are created or updated
+//! on login by [`provision_external_user`], and their roles follow the directory groups mapped
+//! in `directory_group_roles`.
+
+use std::fmt;
+
+use futures::future::BoxFuture;
+use sqlx::{MySql, MySqlPool, Transaction};
+
+use crate::{
+    models::directory_group::DirectoryGroupRole,
+    services::{
+        ldap_provider::LdapProvider,
+        password_service::{rehash_if_needed, verify_password},
+    },
+    utils::{directory::group_names, hashing_utils::hash, secure_token::generate_token},
+};
+
+/// `users.auth_source` of accounts with a password of their own.
+pub const LOCAL_PROVIDER: &str = "local";
+
+#[derive(Debug)]
+pub enum AuthError {
+    Database(sqlx::Error),
+    /// The directory could not be reached or gave an unexpected answer
+    Directory(String),
+}
+
+impl From<sqlx::Error> for AuthError {
+    fn from(e: sqlx::Error) -> Self {
+        AuthError::Database(e)
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Database(e) => write!(f, "database error: {}", e),
+            AuthError::Directory(e) => write!(f, "directory error: {}", e),
+        }
+    }
+}
+
+pub trait AuthProvider: Send + Sync {
+    /// Stored in `users.auth_source` and `user_roles.granted_by`, at most 16 characters.
+    fn name(&self) -> &'static str;
+
+    /// Id of the user the login and password belong to, `None` when this provider does not
+    /// accept them.
+    fn authenticate<'a>(
+        &'a self,
+        pool: &'a MySqlPool,
+        login: &'a str,
+        password: &'a str,
+    ) -> BoxFuture<'a, Result<Option<u32>, AuthError>>;
+}
+
+pub struct LocalProvider;
+
+impl AuthProvider for LocalProvider {
+    fn name(&self) -> &'static str {
+        LOCAL_PROVIDER
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        pool: &'a MySqlPool,
+        login: &'a str,
+        password: &'a str,
+    ) -> BoxFuture<'a, Result<Option<u32>, AuthError>> {
+        Box::pin(async move {
+            let user = sqlx::query!(
+                r#"SELECT id, password FROM users WHERE email = ? AND auth_source = ?"#,
+                login,
+                LOCAL_PROVIDER
+            )
+            .fetch_optional(pool)
+            .await?;
+
+            let Some(user) = user else {
+                return Ok(None);
+            };
+            if !verify_password(password, &user.password).await? {
+                return Ok(None);
+            }
+
+            rehash_if_needed(pool, user.id, password, &user.password).await;
+
+            Ok(Some(user.id))
+        })
+    }
+}
+
+pub struct AuthProviders {
+    providers: Vec<Box<dyn AuthProvider>>,
+}
+
+impl AuthProviders {
+    pub fn from_env() -> Result<AuthProviders, String> {
+        let names = std::env::var("AUTH_PROVIDERS").unwrap_or_else(|_| LOCAL_PROVIDER.to_string());
+
+        let mut providers: Vec<Box<dyn AuthProvider>> = Vec::new();
+        for name in names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            match name {
+                LOCAL_PROVIDER => providers.push(Box::new(LocalProvider)),
+                "ldap" => providers.push(Box::new(LdapProvider::from_env()?)),
+                _ => return Err(format!("Fornecedor de autenticação desconhecido: {}", name)),
+            }
+        }
+
+        if providers.is_empty() {
+            return Err("AUTH_PROVIDERS não tem nenhum fornecedor".to_string());
+        }
+
+        Ok(AuthProviders { providers })
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.providers
+            .iter()
+            .map(|provider| provider.name())
+            .collect()
+    }
+
+    /// Tries every provider in order, the first one to accept the credentials wins. An error is
+    /// only returned when no provider accepted them and one of them failed, so a directory
+    /// that is down does not count as a wrong password.
+    pub async fn authenticate(
+        &self,
+        pool: &MySqlPool,
+        login: &str,
+        password: &str,
+    ) -> Result<Option<u32>, AuthError> {
+        let mut error = None;
+
+        for provider in &self.providers {
+            match provider.authenticate(pool, login, password).await {
+                Ok(Some(user_id)) => return Ok(Some(user_id)),
+                Ok(None) => {}
+                Err(e) => {
+                    log::error!("Authentication provider {} failed: {}", provider.name(), e);
+                    error = Some(e);
+                }
+            }
+        }
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A user as a directory describes them.
+#[derive(Debug)]
+pub struct ExternalIdentity {
+    /// Stable identifier of the user in the directory
+    pub external_id: String,
+    pub username: String,
+    pub email: String,
+    /// Groups as the directory names them, see [`group_names`]
+    pub groups: Vec<String>,
+}
+
+/// Creates or updates the account of a user the directory vouched for and returns its id.
+///
+/// The account is found by its id in the directory, then by email, which links an existing
+/// local account to the directory: from then on its password is checked there. New accounts
+/// get a random password they never use. Roles granted by the provider are replaced by the
+/// ones mapped to the user's groups, roles assigned by an admin are left alone.
+pub async fn provision_external_user(
+    pool: &MySqlPool,
+    provider: &str,
+    identity: &ExternalIdentity,
+) -> Result<u32, sqlx::Error> {
+    let role_ids = mapped_role_ids(pool, provider, &identity.groups).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let linked = sqlx::query_scalar!(
+        r#"SELECT id FROM users WHERE auth_source = ? AND external_id = ?"#,
+        provider,
+        identity.external_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let mut created = false;
+    let user_id = match linked {
+        Some(user_id) => {
+            sqlx::query!(
+                r#"UPDATE users SET username = ?, email = ? WHERE id = ?"#,
+                identity.username,
+                identity.email,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?;
+            user_id
+        }
+        None => {
+            let existing = sqlx::query_scalar!(
+                r#"SELECT id FROM users WHERE LOWER(email) = LOWER(?)"#,
+                identity.email
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            match existing {
+                Some(user_id) => {
+                    sqlx::query!(
+                        r#"UPDATE users SET auth_source = ?, external_id = ? WHERE id = ?"#,
+                        provider,
+                        identity.external_id,
+                        user_id
+                    )
+                    .execute(&mut *tx)
+                    .await?;
+                    log::info!(
+                        "Linked user {} to {} entry {}",
+                        user_id,
+                        provider,
+                        identity.external_id
+                    );
+                    user_id
+                }
+                None => {
+                    let unusable_password = hash(&generate_token());
+                    let result = sqlx::query!(
+                        r#"
+                        INSERT INTO users (username, email, password, auth_source, external_id)
+                        VALUES (?, ?, ?, ?, ?)
+                        "#,
+                        identity.username,
+                        identity.email,
+                        unusable_password.as_bytes(),
+                        provider,
+                        identity.external_id
+                    )
+                    .execute(&mut *tx)
+                    .await?;
+                    created = true;
+                    log::info!(
+                        "Created user {} for {} entry {}",
+                        result.last_insert_id(),
+                        provider,
+                        identity.external_id
+                    );
+                    result.last_insert_id() as u32
+                }
+            }
+        }
+    };
+
+    sync_granted_roles(&mut tx, user_id, provider, &role_ids, created).await?;
+
+    tx.commit().await?;
+
+    Ok(user_id)
+}
+
+/// Roles mapped to any of the groups a provider says the user is in.
+pub(crate) async fn mapped_role_ids(
+    pool: &MySqlPool,
+    provider: &str,
+    groups: &[String],
+) -> Result<Vec<u32>, sqlx::Error> {
+    let groups: Vec<String> = groups.iter().flat_map(|group| group_names(group)).collect();
+    DirectoryGroupRole::role_ids_for_groups(pool, provider, &groups).await
+}
+
+/// Replaces the roles a provider granted a user with `role_ids`. A new account the provider
+/// maps to no role gets the default one, like accounts that register.
+pub(crate) async fn sync_granted_roles(
+    tx: &mut Transaction<'_, MySql>,
+    user_id: u32,
+    provider: &str,
+    role_ids: &[u32],
+    created: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM user_roles WHERE user_id = ? AND granted_by = ?"#,
+        user_id,
+        provider
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    for role_id in role_ids {
+        // A role an admin already assigned stays theirs
+        sqlx::query!(
+            r#"INSERT IGNORE INTO user_roles (user_id, role_id, granted_by) VALUES (?, ?, ?)"#,
+            user_id,
+            role_id,
+            provider
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    if created && role_ids.is_empty() {
+        sqlx::query!(
+            r#"
+            INSERT INTO user_roles (user_id, role_id)
+            SELECT ?, id FROM roles WHERE name = 'Colaborador' LIMIT 1
+            "#,
+            user_id
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok(())
+}
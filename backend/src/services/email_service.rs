@@ -0,0 +1,96 @@
+//! Outgoing email, sent over SMTP.
+//!
+//! Configured through environment variables: `SMTP_HOST`, `SMTP_PORT` (default 587, STARTTLS),
+//! `SMTP_USERNAME`, `SMTP_PASSWORD` and `SMTP_FROM`. `APP_BASE_URL` is the address of the
+//! frontend, used to build the links in the messages. Without `SMTP_HOST` nothing is sent and
+//! the messages are written to the log instead, which is only meant for development.
+
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, header::ContentType},
+    transport::smtp::authentication::Credentials,
+};
+
+const DEFAULT_SMTP_PORT: u16 = 587;
+const DEFAULT_BASE_URL: &str = "http://localhost:4321";
+
+pub struct Mailer {
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    from: Mailbox,
+    base_url: String,
+}
+
+impl Mailer {
+    pub fn from_env() -> Result<Mailer, String> {
+        let from = std::env::var("SMTP_FROM")
+            .unwrap_or_else(|_| "Gestão Documental <no-reply@localhost>".to_string())
+            .parse::<Mailbox>()
+            .map_err(|e| format!("SMTP_FROM inválido: {}", e))?;
+
+        let base_url = std::env::var("APP_BASE_URL")
+            .unwrap_or_else(|_| DEFAULT_BASE_URL.to_string())
+            .trim_end_matches('/')
+            .to_string();
+
+        let Ok(host) = std::env::var("SMTP_HOST") else {
+            log::warn!("SMTP_HOST is not set, emails will only be logged");
+            return Ok(Mailer {
+                transport: None,
+                from,
+                base_url,
+            });
+        };
+
+        let port = match std::env::var("SMTP_PORT") {
+            Ok(port) => port
+                .parse()
+                .map_err(|e| format!("SMTP_PORT inválido: {}", e))?,
+            Err(_) => DEFAULT_SMTP_PORT,
+        };
+
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+            .map_err(|e| format!("Servidor SMTP inválido: {}", e))?
+            .port(port);
+        if let (Ok(username), Ok(password)) = (
+            std::env::var("SMTP_USERNAME"),
+            std::env::var("SMTP_PASSWORD"),
+        ) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Mailer {
+            transport: Some(builder.build()),
+            from,
+            base_url,
+        })
+    }
+
+    /// Absolute frontend link to `path`.
+    pub fn link(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> Result<(), String> {
+        let Some(transport) = &self.transport else {
+            log::info!("Email to {} ({}):\n{}", to, subject, body);
+            return Ok(());
+        };
+
+        let to = to
+            .parse::<Mailbox>()
+            .map_err(|e| format!("Endereço inválido {}: {}", to, e))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .map_err(|e| e.to_string())?;
+
+        transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
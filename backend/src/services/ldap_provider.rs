@@ -0,0 +1,160 @@
+//! Logins checked by binding to an LDAP directory or Active Directory.
+//!
+//! Configured through environment variables: `LDAP_URL` (`ldap://` or `ldaps://`),
+//! `LDAP_STARTTLS` (false), `LDAP_BIND_DN` and `LDAP_BIND_PASSWORD` of the account used to
+//! find users, `LDAP_BASE_DN`, `LDAP_USER_FILTER` (`(&(objectClass=person)(mail={login}))`,
+//! `{login}` is what the user typed), and the attributes holding the name (`LDAP_NAME_ATTR`,
+//! `cn`), the email (`LDAP_MAIL_ATTR`, `mail`) and the groups (`LDAP_GROUP_ATTR`,
+//! `memberOf`). The entry's DN identifies the user, so moving it in the directory makes a new
+//! link by email on the next login.
+
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use ldap3::{LdapConnAsync, LdapConnSettings, LdapError, Scope, SearchEntry};
+use sqlx::MySqlPool;
+
+use crate::{
+    services::auth_provider::{AuthError, AuthProvider, ExternalIdentity, provision_external_user},
+    utils::directory::user_filter,
+};
+
+pub const LDAP_PROVIDER: &str = "ldap";
+
+const DEFAULT_USER_FILTER: &str = "(&(objectClass=person)(mail={login}))";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Result code of a bind with a wrong password
+const INVALID_CREDENTIALS: u32 = 49;
+
+pub struct LdapProvider {
+    url: String,
+    starttls: bool,
+    bind_dn: String,
+    bind_password: String,
+    base_dn: String,
+    user_filter: String,
+    name_attr: String,
+    mail_attr: String,
+    group_attr: String,
+}
+
+fn required_env(name: &str) -> Result<String, String> {
+    std::env::var(name).map_err(|_| format!("{} é obrigatório com o fornecedor ldap", name))
+}
+
+fn env_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+impl LdapProvider {
+    pub fn from_env() -> Result<LdapProvider, String> {
+        let user_filter = env_or("LDAP_USER_FILTER", DEFAULT_USER_FILTER);
+        if !user_filter.contains("{login}") {
+            return Err("LDAP_USER_FILTER tem de conter {login}".to_string());
+        }
+
+        Ok(LdapProvider {
+            url: required_env("LDAP_URL")?,
+            starttls: env_or("LDAP_STARTTLS", "false") == "true",
+            bind_dn: required_env("LDAP_BIND_DN")?,
+            bind_password: required_env("LDAP_BIND_PASSWORD")?,
+            base_dn: required_env("LDAP_BASE_DN")?,
+            user_filter,
+            name_attr: env_or("LDAP_NAME_ATTR", "cn"),
+            mail_attr: env_or("LDAP_MAIL_ATTR", "mail"),
+            group_attr: env_or("LDAP_GROUP_ATTR", "memberOf"),
+        })
+    }
+
+    /// The directory entry of the login if the password is right.
+    async fn find_identity(
+        &self,
+        login: &str,
+        password: &str,
+    ) -> Result<Option<ExternalIdentity>, LdapError> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(CONNECT_TIMEOUT)
+            .set_starttls(self.starttls);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.url).await?;
+        ldap3::drive!(conn);
+
+        ldap.simple_bind(&self.bind_dn, &self.bind_password)
+            .await?
+            .success()?;
+
+        let filter = user_filter(&self.user_filter, login);
+        let attrs = [
+            self.name_attr.as_str(),
+            self.mail_attr.as_str(),
+            self.group_attr.as_str(),
+        ];
+        let (entries, _) = ldap
+            .search(&self.base_dn, Scope::Subtree, &filter, attrs)
+            .await?
+            .success()?;
+
+        // No entry, or a filter so loose that the login is ambiguous
+        let mut entries = entries.into_iter();
+        let (Some(entry), None) = (entries.next(), entries.next()) else {
+            let _ = ldap.unbind().await;
+            return Ok(None);
+        };
+        let mut entry = SearchEntry::construct(entry);
+
+        let bind = ldap.simple_bind(&entry.dn, password).await?;
+        let _ = ldap.unbind().await;
+        if bind.rc == INVALID_CREDENTIALS {
+            return Ok(None);
+        }
+        bind.success()?;
+
+        let mut first = |attr: &str| {
+            entry
+                .attrs
+                .remove(attr)
+                .and_then(|values| values.into_iter().next())
+        };
+        let email = first(&self.mail_attr).unwrap_or_else(|| login.trim().to_string());
+        let username = first(&self.name_attr).unwrap_or_else(|| email.clone());
+        let groups = entry.attrs.remove(&self.group_attr).unwrap_or_default();
+
+        Ok(Some(ExternalIdentity {
+            external_id: entry.dn,
+            username,
+            email,
+            groups,
+        }))
+    }
+}
+
+impl AuthProvider for LdapProvider {
+    fn name(&self) -> &'static str {
+        LDAP_PROVIDER
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        pool: &'a MySqlPool,
+        login: &'a str,
+        password: &'a str,
+    ) -> BoxFuture<'a, Result<Option<u32>, AuthError>> {
+        Box::pin(async move {
+            // A bind without a password is anonymous and always succeeds
+            if password.is_empty() || login.trim().is_empty() {
+                return Ok(None);
+            }
+
+            let identity = self
+                .find_identity(login, password)
+                .await
+                .map_err(|e| AuthError::Directory(e.to_string()))?;
+
+            match identity {
+                Some(identity) => Ok(Some(
+                    provision_external_user(pool, LDAP_PROVIDER, &identity).await?,
+                )),
+                None => Ok(None),
+            }
+        })
+    }
+}
@@ -1 +1,11 @@
+pub mod auth_provider;
+pub mod email_service;
+pub mod ldap_provider;
 pub mod notification_service;
+pub mod oidc_service;
+pub mod password_service;
+pub mod permission_service;
+pub mod scheduler;
+pub mod vacation_approval_service;
+pub mod vacation_ledger_service;
+pub mod vacation_report_service;
@@ -0,0 +1,304 @@
+//! Single sign-on through an OpenID Connect identity provider, with the authorization code
+//! flow and PKCE.
+//!
+//! Configured through environment variables, and only enabled when `OIDC_ISSUER` is set:
+//! `OIDC_CLIENT_ID`, `OIDC_CLIENT_SECRET` (left out for public clients), `OIDC_REDIRECT_URL`,
+//! the address of `/users/login/oidc/callback` as the browser reaches it, `OIDC_SCOPES`
+//! (`openid email profile`) and `OIDC_ROLE_CLAIM` (`groups`), the claim whose values are mapped
+//! to roles in `directory_group_roles` under the provider `oidc`. The endpoints are discovered
+//! from the issuer, so any provider works, including a local mock one over plain http.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::MySqlPool;
+use tokio::sync::OnceCell;
+
+use crate::{
+    services::auth_provider::{ExternalIdentity, mapped_role_ids, sync_granted_roles},
+    utils::{
+        hashing_utils::hash,
+        oidc::{claim_values, decode_id_token, pkce_challenge, validate_id_token},
+        secure_token::generate_token,
+    },
+};
+
+pub const OIDC_PROVIDER: &str = "oidc";
+
+const DEFAULT_SCOPES: &str = "openid email profile";
+const DEFAULT_ROLE_CLAIM: &str = "groups";
+
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+pub struct OidcClient {
+    issuer: String,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_url: String,
+    scopes: String,
+    role_claim: String,
+    http: reqwest::Client,
+    /// Fetched on the first login, so the server starts while the provider is down
+    metadata: OnceCell<ProviderMetadata>,
+}
+
+/// What the login started by [`OidcClient::authorization_url`] has to remember until the
+/// callback, kept in the session.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingOidcLogin {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub started_at: i64,
+}
+
+fn required_env(name: &str) -> Result<String, String> {
+    std::env::var(name).map_err(|_| format!("{} é obrigatório com OIDC_ISSUER", name))
+}
+
+impl OidcClient {
+    /// `None` when single sign-on is not configured.
+    pub fn from_env() -> Result<Option<OidcClient>, String> {
+        let Ok(issuer) = std::env::var("OIDC_ISSUER") else {
+            return Ok(None);
+        };
+
+        let http = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .map_err(|e| format!("Erro ao criar o cliente HTTP: {}", e))?;
+
+        Ok(Some(OidcClient {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id: required_env("OIDC_CLIENT_ID")?,
+            client_secret: std::env::var("OIDC_CLIENT_SECRET").ok(),
+            redirect_url: required_env("OIDC_REDIRECT_URL")?,
+            scopes: std::env::var("OIDC_SCOPES").unwrap_or_else(|_| DEFAULT_SCOPES.to_string()),
+            role_claim: std::env::var("OIDC_ROLE_CLAIM")
+                .unwrap_or_else(|_| DEFAULT_ROLE_CLAIM.to_string()),
+            http,
+            metadata: OnceCell::new(),
+        }))
+    }
+
+    async fn metadata(&self) -> Result<&ProviderMetadata, String> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.issuer);
+                let metadata: ProviderMetadata = self
+                    .http
+                    .get(&url)
+                    .send()
+                    .await
+                    .and_then(|resp| resp.error_for_status())
+                    .map_err(|e| format!("Erro ao obter {}: {}", url, e))?
+                    .json()
+                    .await
+                    .map_err(|e| format!("Resposta inválida de {}: {}", url, e))?;
+
+                // The issuer in the tokens must be the configured one, exactly
+                if metadata.issuer.trim_end_matches('/') != self.issuer {
+                    return Err(format!("Emissor inesperado: {}", metadata.issuer));
+                }
+
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// Starts a login: the address of the provider's login page and what the callback needs.
+    pub async fn authorization_url(&self) -> Result<(String, PendingOidcLogin), String> {
+        let metadata = self.metadata().await?;
+
+        let pending = PendingOidcLogin {
+            state: generate_token(),
+            nonce: generate_token(),
+            code_verifier: generate_token(),
+            started_at: chrono::Utc::now().timestamp(),
+        };
+
+        let url = reqwest::Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.client_id),
+                ("redirect_uri", &self.redirect_url),
+                ("scope", &self.scopes),
+                ("state", &pending.state),
+                ("nonce", &pending.nonce),
+                ("code_challenge", &pkce_challenge(&pending.code_verifier)),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| format!("authorization_endpoint inválido: {}", e))?;
+
+        Ok((url.into(), pending))
+    }
+
+    /// Trades the code the provider sent to the callback for the user's checked ID token
+    /// claims.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        pending: &PendingOidcLogin,
+    ) -> Result<Value, String> {
+        let metadata = self.metadata().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.redirect_url),
+            ("client_id", &self.client_id),
+            ("code_verifier", &pending.code_verifier),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret));
+        }
+
+        let tokens: TokenResponse = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|e| format!("Erro ao trocar o código: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Resposta inválida do token endpoint: {}", e))?;
+
+        let claims = decode_id_token(&tokens.id_token)?;
+        validate_id_token(
+            &claims,
+            &metadata.issuer,
+            &self.client_id,
+            &pending.nonce,
+            chrono::Utc::now().timestamp(),
+        )?;
+
+        Ok(claims)
+    }
+
+    /// The user the claims describe. The email is what links existing accounts, so it is
+    /// refused unless the provider says it is verified; a missing `email_verified` claim counts
+    /// as not verified.
+    pub fn identity(&self, claims: &Value) -> Result<ExternalIdentity, String> {
+        let email = claims["email"]
+            .as_str()
+            .map(str::trim)
+            .filter(|email| !email.is_empty())
+            .ok_or("ID token sem email")?;
+        if claims["email_verified"].as_bool() != Some(true) {
+            return Err(format!("Email {} não verificado", email));
+        }
+
+        let username = ["name", "preferred_username"]
+            .iter()
+            .find_map(|claim| claims[*claim].as_str())
+            .unwrap_or(email);
+
+        Ok(ExternalIdentity {
+            external_id: claims["sub"].as_str().unwrap_or_default().to_string(),
+            username: username.to_string(),
+            email: email.to_string(),
+            groups: claim_values(claims, &self.role_claim),
+        })
+    }
+}
+
+/// Id of the user an identity provider account belongs to, linking it by email to an existing
+/// account on its first login or creating a new account without a password. Roles follow the
+/// mapped claim values as with directory groups.
+pub async fn provision_oidc_user(
+    pool: &MySqlPool,
+    identity: &ExternalIdentity,
+) -> Result<u32, sqlx::Error> {
+    let role_ids = mapped_role_ids(pool, OIDC_PROVIDER, &identity.groups).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let linked = sqlx::query_scalar!(
+        r#"SELECT user_id FROM user_identities WHERE provider = ? AND subject = ?"#,
+        OIDC_PROVIDER,
+        identity.external_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let mut created = false;
+    let user_id = match linked {
+        Some(user_id) => user_id,
+        None => {
+            let existing = sqlx::query_scalar!(
+                r#"SELECT id FROM users WHERE LOWER(email) = LOWER(?)"#,
+                identity.email
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            let user_id = match existing {
+                Some(user_id) => user_id,
+                None => {
+                    let unusable_password = hash(&generate_token());
+                    let result = sqlx::query!(
+                        r#"
+                        INSERT INTO users (username, email, password, auth_source)
+                        VALUES (?, ?, ?, ?)
+                        "#,
+                        identity.username,
+                        identity.email,
+                        unusable_password.as_bytes(),
+                        OIDC_PROVIDER
+                    )
+                    .execute(&mut *tx)
+                    .await?;
+                    created = true;
+                    result.last_insert_id() as u32
+                }
+            };
+
+            sqlx::query!(
+                r#"INSERT INTO user_identities (provider, subject, user_id) VALUES (?, ?, ?)"#,
+                OIDC_PROVIDER,
+                identity.external_id,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?;
+            log::info!(
+                "Linked user {} to {} subject {}",
+                user_id,
+                OIDC_PROVIDER,
+                identity.external_id
+            );
+
+            user_id
+        }
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE user_identities SET last_login_at = CURRENT_TIMESTAMP
+        WHERE provider = ? AND subject = ?
+        "#,
+        OIDC_PROVIDER,
+        identity.external_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sync_granted_roles(&mut tx, user_id, OIDC_PROVIDER, &role_ids, created).await?;
+
+    tx.commit().await?;
+
+    Ok(user_id)
+}
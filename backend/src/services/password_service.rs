@@ -0,0 +1,115 @@
+//! Checking and storing new passwords. Every place that sets a password goes through
+//! [`check_new_password`] and [`store_password`], so the policy and the history apply to all of
+//! them alike.
+//!
+//! Argon2 takes a noticeable time and memory by design, so hashing and verifying run on the
+//! blocking thread pool instead of the worker serving other requests.
+
+use actix_web::{HttpResponse, web};
+use sqlx::{MySql, MySqlPool, Transaction};
+
+use crate::{
+    models::password_history::PasswordHistory,
+    utils::{
+        hashing_utils::{hash, needs_rehash, verify},
+        password_policy::PasswordPolicy,
+    },
+};
+
+/// Runs a hash or a verification on the blocking thread pool.
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, sqlx::Error> {
+    web::block(f).await.map_err(|e| {
+        log::error!("Error running password hashing: {}", e);
+        sqlx::Error::WorkerCrashed
+    })
+}
+
+/// Whether `password` matches a stored hash.
+pub async fn verify_password(password: &str, stored: &[u8]) -> Result<bool, sqlx::Error> {
+    let password = password.to_string();
+    let stored = stored.to_vec();
+    run_blocking(move || verify(&password, &stored)).await
+}
+
+/// Rules a new password breaks, including being one of the user's last passwords. `user_id` is
+/// `None` for accounts that do not exist yet.
+pub async fn check_new_password(
+    pool: &MySqlPool,
+    policy: &PasswordPolicy,
+    user_id: Option<u32>,
+    password: &str,
+    identifiers: &[&str],
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut violations = policy.violations(password, identifiers);
+
+    if let (Some(user_id), true) = (user_id, policy.history_size > 0) {
+        let previous = PasswordHistory::recent_hashes(pool, user_id, policy.history_size).await?;
+        let password = password.to_string();
+        let reused =
+            run_blocking(move || previous.iter().any(|stored| verify(&password, stored))).await?;
+        if reused {
+            violations.push(format!(
+                "A palavra-passe não pode ser igual a nenhuma das últimas {}.",
+                policy.history_size
+            ));
+        }
+    }
+
+    Ok(violations)
+}
+
+/// 400 listing what is wrong with a password.
+pub fn password_rejected(violations: Vec<String>) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "error": "A palavra-passe não cumpre a política de palavras-passe.",
+        "violations": violations,
+    }))
+}
+
+/// Hashes and stores a user's new password, remembering it in their history.
+pub async fn store_password(
+    tx: &mut Transaction<'_, MySql>,
+    policy: &PasswordPolicy,
+    user_id: u32,
+    password: &str,
+) -> Result<(), sqlx::Error> {
+    let password = password.to_string();
+    let password_hash = run_blocking(move || hash(&password)).await?;
+
+    sqlx::query!(
+        r#"UPDATE users SET password = ? WHERE id = ?"#,
+        password_hash.as_bytes(),
+        user_id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    PasswordHistory::record(tx, user_id, password_hash.as_bytes(), policy.history_size).await
+}
+
+/// Replaces a hash made with old parameters after a successful login, while the password is
+/// at hand. Failures are only logged, the login goes on with the old hash.
+pub async fn rehash_if_needed(pool: &MySqlPool, user_id: u32, password: &str, stored: &[u8]) {
+    if !needs_rehash(stored) {
+        return;
+    }
+
+    let password = password.to_string();
+    let password_hash = match run_blocking(move || hash(&password)).await {
+        Ok(password_hash) => password_hash,
+        Err(_) => return,
+    };
+    match sqlx::query!(
+        r#"UPDATE users SET password = ? WHERE id = ?"#,
+        password_hash.as_bytes(),
+        user_id
+    )
+    .execute(pool)
+    .await
+    {
+        Ok(_) => log::info!("Upgraded password hash of user {}", user_id),
+        Err(e) => log::error!("Error upgrading password hash of user {}: {}", user_id, e),
+    }
+}
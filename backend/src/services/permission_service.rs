@@ -0,0 +1,99 @@
+//! Page permission resolution for the current request.
+//!
+//! Handlers take a [`Permissions`] argument instead of a `Session` and ask it for the
+//! permissions of a page. They are resolved with a single query the first time a page is asked
+//! for and cached in the request extensions, so further checks on the same page during the
+//! request are free.
+
+use std::{
+    cell::RefCell,
+    future::{Ready, ready},
+    rc::Rc,
+};
+
+use actix_session::SessionExt;
+use actix_web::{
+    FromRequest, HttpMessage, HttpRequest, HttpResponse, dev::Payload, error::InternalError, web,
+};
+use ahash::HashMap;
+use sqlx::MySqlPool;
+
+use crate::{
+    State,
+    auth::validate_session,
+    models::custom_page::{CustomPage, UserPagePermissions},
+};
+
+type PermissionCache = Rc<RefCell<HashMap<u32, UserPagePermissions>>>;
+
+/// Permissions of the logged in user. Extracting it fails with 401 without a valid session.
+pub struct Permissions {
+    user_id: i32,
+    pool: MySqlPool,
+    cache: PermissionCache,
+}
+
+impl FromRequest for Permissions {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let user_id = match validate_session(&req.get_session()) {
+            Ok(id) => id,
+            Err(resp) => return ready(Err(InternalError::from_response("", resp).into())),
+        };
+
+        let Some(state) = req.app_data::<web::Data<State>>() else {
+            log::error!("Application state missing while resolving permissions");
+            return ready(Err(InternalError::from_response(
+                "",
+                HttpResponse::InternalServerError().finish(),
+            )
+            .into()));
+        };
+
+        let cache = req
+            .extensions_mut()
+            .get_or_insert_with(PermissionCache::default)
+            .clone();
+
+        ready(Ok(Permissions {
+            user_id,
+            pool: state.db.pool.clone(),
+            cache,
+        }))
+    }
+}
+
+impl Permissions {
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
+
+    /// Effective permissions of the user on a page, admin override included.
+    pub async fn for_page(&self, page_id: u32) -> Result<UserPagePermissions, sqlx::Error> {
+        if let Some(permissions) = self.cache.borrow().get(&page_id) {
+            return Ok(permissions.clone());
+        }
+
+        let permissions =
+            CustomPage::get_user_permissions_for_page(&self.pool, self.user_id, page_id).await?;
+        self.cache.borrow_mut().insert(page_id, permissions.clone());
+
+        Ok(permissions)
+    }
+
+    /// Same as [`Permissions::for_page`], with database errors logged and turned into a 500
+    /// response so handlers can return it directly.
+    pub async fn page(&self, page_id: u32) -> Result<UserPagePermissions, HttpResponse> {
+        self.for_page(page_id).await.map_err(|e| {
+            log::error!(
+                "Error resolving permissions of user {} on page {}: {}",
+                self.user_id,
+                page_id,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        })
+    }
+}
@@ -0,0 +1,258 @@
+//! Background job scheduler.
+//!
+//! Jobs are declared in [`JOBS`] with a cron expression (UTC). Their state lives in the
+//! `scheduled_jobs` table and every run is recorded in `scheduled_job_runs`. Each backend
+//! instance polls the table, and a job only runs on the instance that manages to take its
+//! lock, so running several replicas never fires a job twice.
+
+use std::{future::Future, pin::Pin, sync::LazyLock};
+
+use actix_web::rt::spawn;
+use chrono::{Datelike, Utc};
+use sqlx::MySqlPool;
+use tokio::time::{Duration, interval};
+
+use crate::{
+    models::{
+        holiday::Holiday,
+        scheduled_job::{JOB_STATUS_FAILED, JOB_STATUS_SUCCESS, ScheduledJob, ScheduledJobRun},
+    },
+    services::{
+        notification_service::check_expiring_date_ranges,
+        vacation_approval_service::reassign_and_remind,
+        vacation_ledger_service::{expire_carry_overs, start_year},
+    },
+    utils::cron::CronSchedule,
+};
+
+/// How often each instance checks for due jobs.
+const POLL_INTERVAL_SECS: u64 = 30;
+
+pub type JobFuture = Pin<Box<dyn Future<Output = Result<String, String>> + Send>>;
+
+pub struct Job {
+    pub name: &'static str,
+    pub cron: &'static str,
+    /// Upper bound for a run; the lock is released after this even if the instance died.
+    pub lock_secs: u32,
+    /// Returns a short summary of the run, or the error that made it fail.
+    pub run: fn(MySqlPool) -> JobFuture,
+}
+
+pub static JOBS: &[Job] = &[
+    Job {
+        name: "expiring_date_notifications",
+        cron: "0 * * * *",
+        lock_secs: 30 * 60,
+        run: run_expiring_date_notifications,
+    },
+    Job {
+        name: "holiday_generation",
+        cron: "0 3 1 12 *",
+        lock_secs: 10 * 60,
+        run: run_holiday_generation,
+    },
+    Job {
+        name: "vacation_year_rollover",
+        cron: "0 1 1 1 *",
+        lock_secs: 30 * 60,
+        run: run_vacation_year_rollover,
+    },
+    Job {
+        name: "vacation_carry_over_expiry",
+        cron: "0 2 * * *",
+        lock_secs: 30 * 60,
+        run: run_vacation_carry_over_expiry,
+    },
+    Job {
+        name: "vacation_approval_reminders",
+        cron: "0 7 * * *",
+        lock_secs: 30 * 60,
+        run: run_vacation_approval_reminders,
+    },
+];
+
+/// Identifies this process in job locks and run history.
+static INSTANCE_ID: LazyLock<String> = LazyLock::new(|| uuid::Uuid::new_v4().to_string());
+
+fn run_expiring_date_notifications(pool: MySqlPool) -> JobFuture {
+    Box::pin(async move {
+        check_expiring_date_ranges(&pool)
+            .await
+            .map(|count| format!("{} notificações criadas", count))
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Generates the holidays of the current and the next year, so they are in place before
+/// anyone plans vacations across the turn of the year.
+fn run_holiday_generation(pool: MySqlPool) -> JobFuture {
+    Box::pin(async move {
+        let year = Utc::now().year();
+        let mut created = 0;
+        for year in [year, year + 1] {
+            created += Holiday::generate_year(&pool, year, false)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(format!("{} feriados criados", created))
+    })
+}
+
+/// Starts the current year in the vacation ledger. Users already started are skipped, so a
+/// late or repeated run is harmless.
+fn run_vacation_year_rollover(pool: MySqlPool) -> JobFuture {
+    Box::pin(async move {
+        start_year(&pool, Utc::now().year())
+            .await
+            .map(|count| format!("{} utilizadores", count))
+            .map_err(|e| e.to_string())
+    })
+}
+
+fn run_vacation_carry_over_expiry(pool: MySqlPool) -> JobFuture {
+    Box::pin(async move {
+        expire_carry_overs(&pool, Utc::now().date_naive())
+            .await
+            .map(|count| format!("{} transições expiradas", count))
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Hands approval steps over from approvers who are away and reminds the ones past their
+/// deadline.
+fn run_vacation_approval_reminders(pool: MySqlPool) -> JobFuture {
+    Box::pin(async move {
+        reassign_and_remind(&pool, Utc::now().date_naive())
+            .await
+            .map(|(reassigned, reminded)| {
+                format!("{} passos reatribuídos, {} lembretes", reassigned, reminded)
+            })
+            .map_err(|e| e.to_string())
+    })
+}
+
+pub fn find_job(name: &str) -> Option<&'static Job> {
+    JOBS.iter().find(|job| job.name == name)
+}
+
+/// Registers every job and starts polling for due jobs.
+pub async fn start(pool: MySqlPool) {
+    let now = Utc::now();
+    let mut jobs: Vec<&'static Job> = Vec::with_capacity(JOBS.len());
+
+    for job in JOBS {
+        let schedule = match CronSchedule::parse(job.cron) {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                log::error!("Invalid cron expression for job {}: {}", job.name, e);
+                continue;
+            }
+        };
+
+        match ScheduledJob::register(&pool, job.name, job.cron, schedule.next_after(now)).await {
+            Ok(()) => jobs.push(job),
+            Err(e) => log::error!("Failed to register job {}: {}", job.name, e),
+        }
+    }
+
+    spawn(async move {
+        let mut timer = interval(Duration::from_secs(POLL_INTERVAL_SECS));
+        loop {
+            timer.tick().await;
+            for &job in &jobs {
+                let pool = pool.clone();
+                spawn(async move {
+                    if let Err(e) = run_if_due(pool, job).await {
+                        log::error!("Failed to run scheduled job {}: {}", job.name, e);
+                    }
+                });
+            }
+        }
+    });
+}
+
+async fn run_if_due(pool: MySqlPool, job: &'static Job) -> Result<(), sqlx::Error> {
+    if !ScheduledJob::try_lock(&pool, job.name, &INSTANCE_ID, job.lock_secs, false).await? {
+        return Ok(());
+    }
+
+    let run_id = start_run(&pool, job, None).await?;
+    execute(pool, job, run_id, true).await
+}
+
+/// Starts a job right away on behalf of an admin, regardless of its schedule.
+///
+/// Returns the id of the new run, or `None` if the job is already running somewhere.
+pub async fn trigger(
+    pool: &MySqlPool,
+    job: &'static Job,
+    user_id: u32,
+) -> Result<Option<u32>, sqlx::Error> {
+    if !ScheduledJob::try_lock(pool, job.name, &INSTANCE_ID, job.lock_secs, true).await? {
+        return Ok(None);
+    }
+
+    let run_id = start_run(pool, job, Some(user_id)).await?;
+
+    let pool = pool.clone();
+    spawn(async move {
+        if let Err(e) = execute(pool, job, run_id, false).await {
+            log::error!("Failed to run job {} triggered manually: {}", job.name, e);
+        }
+    });
+
+    Ok(Some(run_id))
+}
+
+/// Records the start of a run, releasing the lock again if that fails. Runs left as running
+/// by an instance that died are closed first, as the lock they held has expired by now.
+async fn start_run(
+    pool: &MySqlPool,
+    job: &'static Job,
+    triggered_by: Option<u32>,
+) -> Result<u32, sqlx::Error> {
+    match ScheduledJobRun::fail_stale(pool, job.name, job.lock_secs).await {
+        Ok(0) => {}
+        Ok(count) => log::warn!("Marked {} stale runs of job {} as failed", count, job.name),
+        Err(e) => log::error!("Failed to close stale runs of job {}: {}", job.name, e),
+    }
+
+    match ScheduledJobRun::start(pool, job.name, &INSTANCE_ID, triggered_by).await {
+        Ok(id) => Ok(id),
+        Err(e) => {
+            ScheduledJob::finish(pool, job.name, &INSTANCE_ID, JOB_STATUS_FAILED, None).await?;
+            Err(e)
+        }
+    }
+}
+
+/// Runs a job whose lock is already held and records the outcome. Scheduled runs also move
+/// `next_run_at` forward; manual runs leave the schedule as it was.
+async fn execute(
+    pool: MySqlPool,
+    job: &'static Job,
+    run_id: u32,
+    reschedule: bool,
+) -> Result<(), sqlx::Error> {
+    log::info!("Running job {} (run #{})", job.name, run_id);
+
+    let (status, message) = match (job.run)(pool.clone()).await {
+        Ok(summary) => (JOB_STATUS_SUCCESS, summary),
+        Err(e) => {
+            log::error!("Job {} failed: {}", job.name, e);
+            (JOB_STATUS_FAILED, e)
+        }
+    };
+
+    let next_run_at = if reschedule {
+        CronSchedule::parse(job.cron)
+            .ok()
+            .and_then(|schedule| schedule.next_after(Utc::now()))
+    } else {
+        None
+    };
+
+    ScheduledJobRun::finish(&pool, run_id, status, Some(&message)).await?;
+    ScheduledJob::finish(&pool, job.name, &INSTANCE_ID, status, next_run_at).await
+}
@@ -0,0 +1,169 @@
+//! Approval chains of vacation requests: each step is given to a member of its approver role,
+//! or to the delegate of one who is away, and the next step opens once it is approved. The
+//! scheduler reminds approvers of steps past their deadline and hands steps over when their
+//! approver goes away or comes back. See [`crate::models::vacation_approval`].
+
+use chrono::{Duration, NaiveDate};
+use sqlx::MySqlPool;
+
+use crate::models::{
+    absence_type::AbsenceType,
+    notification::{NOTIFICATION_TYPE_VACATION_APPROVAL_PENDING, Notification},
+    vacation_approval::{ApprovalStep, Assignee, VacationRequestApproval},
+    vacation_request::VacationRequest,
+};
+
+/// Opens the first step of the requester's chain and tells its approver. Returns false when
+/// the requester has no chain, and any admin approves.
+pub async fn start_chain(
+    pool: &MySqlPool,
+    request_id: u32,
+    requester_id: u32,
+    today: NaiveDate,
+) -> Result<bool, sqlx::Error> {
+    let chain = ApprovalStep::get_chain_for_user(pool, requester_id).await?;
+    let Some(first) = chain.first() else {
+        return Ok(false);
+    };
+
+    open_step(pool, request_id, requester_id, first, today).await?;
+    Ok(true)
+}
+
+/// The step after `step_order` in the requester's chain, `None` after the last one.
+pub async fn next_step(
+    pool: &MySqlPool,
+    requester_id: u32,
+    step_order: u8,
+) -> Result<Option<ApprovalStep>, sqlx::Error> {
+    let chain = ApprovalStep::get_chain_for_user(pool, requester_id).await?;
+    Ok(chain.into_iter().find(|step| step.step_order > step_order))
+}
+
+/// Gives a step of the request to whoever takes it on `today`, and tells them.
+pub async fn open_step(
+    pool: &MySqlPool,
+    request_id: u32,
+    requester_id: u32,
+    step: &ApprovalStep,
+    today: NaiveDate,
+) -> Result<(), sqlx::Error> {
+    let assignee =
+        ApprovalStep::find_assignee(pool, step.approver_role_id, requester_id, today).await?;
+    let due_date = step
+        .deadline_days
+        .map(|days| today + Duration::days(days as i64));
+
+    VacationRequestApproval::open(pool, request_id, step, assignee, due_date).await?;
+
+    match assignee {
+        Some(assignee) => notify_approver(pool, request_id, assignee.user_id, false).await,
+        None => {
+            log::warn!(
+                "No approver available for step {} of vacation request {}",
+                step.step_order,
+                request_id
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Tells an approver a request waits on them, or reminds them it still does.
+async fn notify_approver(
+    pool: &MySqlPool,
+    request_id: u32,
+    approver_id: u32,
+    reminder: bool,
+) -> Result<(), sqlx::Error> {
+    let Some(request) = VacationRequest::get_by_id(pool, request_id).await? else {
+        return Ok(());
+    };
+    let user_name = sqlx::query_scalar!(
+        r#"SELECT username FROM users WHERE id = ?"#,
+        request.user_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .unwrap_or_else(|| "Um utilizador".to_string());
+    let label = AbsenceType::get_by_id(pool, request.absence_type_id)
+        .await?
+        .map(|absence_type| absence_type.label())
+        .unwrap_or_else(|| "ausência".to_string());
+
+    let start_date_fmt = request.start_date.format("%d/%m/%Y");
+    let end_date_fmt = request.end_date.format("%d/%m/%Y");
+    let message = if reminder {
+        format!(
+            "O pedido de {} de {} ({} a {}) continua a aguardar a sua aprovação.",
+            label, user_name, start_date_fmt, end_date_fmt
+        )
+    } else {
+        format!(
+            "{} solicitou {} ({} a {}). O pedido aguarda a sua aprovação.",
+            user_name, label, start_date_fmt, end_date_fmt
+        )
+    };
+
+    Notification::create(
+        pool,
+        approver_id,
+        None,
+        Some(request_id),
+        None,
+        None,
+        NOTIFICATION_TYPE_VACATION_APPROVAL_PENDING,
+        &message,
+        Some(request.end_date),
+    )
+    .await
+}
+
+/// Hands open steps over to whoever should take them on `today`, when their approver went
+/// away, delegated or came back, and reminds approvers of steps past their deadline, once.
+/// Returns how many steps were reassigned and how many reminders were sent.
+pub async fn reassign_and_remind(
+    pool: &MySqlPool,
+    today: NaiveDate,
+) -> Result<(u64, u64), sqlx::Error> {
+    let mut reassigned = 0;
+    let mut reminded = 0;
+
+    for approval in VacationRequestApproval::get_all_open(pool).await? {
+        let Some(request) = VacationRequest::get_by_id(pool, approval.vacation_request_id).await?
+        else {
+            continue;
+        };
+
+        let mut open_id = approval.id;
+        let mut assigned_to = approval.assigned_to;
+        if let Some(approver_role_id) = approval.approver_role_id {
+            let current = approval.assigned_to.map(|user_id| Assignee {
+                user_id,
+                delegated_from: approval.delegated_from,
+            });
+            let assignee =
+                ApprovalStep::find_assignee(pool, approver_role_id, request.user_id, today).await?;
+            // Without anyone available, the step stays where it is
+            if let Some(assignee) = assignee.filter(|assignee| Some(*assignee) != current)
+                && let Some(reopened_id) = approval.reassign(pool, assignee).await?
+            {
+                reassigned += 1;
+                open_id = reopened_id;
+                assigned_to = Some(assignee.user_id);
+                notify_approver(pool, request.id, assignee.user_id, false).await?;
+            }
+        }
+
+        let overdue = approval.due_date.is_some_and(|due_date| due_date <= today);
+        if overdue && approval.reminded_at.is_none() {
+            if let Some(user_id) = assigned_to {
+                notify_approver(pool, request.id, user_id, true).await?;
+                reminded += 1;
+            }
+            VacationRequestApproval::mark_reminded(pool, open_id).await?;
+        }
+    }
+
+    Ok((reassigned, reminded))
+}
@@ -0,0 +1,285 @@
+//! Yearly movements of the vacation ledger, run by the scheduler: each new year starts with the
+//! users' entitlement and what they had left, and carried-over vacation not taken by its
+//! expiry is booked as lost. Users under the statutory rules get the entitlement the
+//! Código do Trabalho gives them, see [`crate::utils::vacation_entitlement`].
+
+use chrono::NaiveDate;
+use sqlx::{MySqlConnection, MySqlPool};
+
+use crate::{
+    models::{
+        app_setting::{AppSetting, SETTING_CARRY_OVER_EXPIRY, SETTING_CARRY_OVER_MAX},
+        vacation_ledger::{
+            DeferredEntry, LEDGER_CARRY_OVER, LEDGER_ENTITLEMENT, LEDGER_EXPIRY, StatutoryEntries,
+            VacationLedgerEntry,
+        },
+        vacation_request::VacationRequest,
+        work_contract::{ENTITLEMENT_RULE_STATUTORY, WorkContract},
+        work_schedule::BALANCE_UNIT_HOURS,
+    },
+    utils::{
+        vacation_balance::{
+            DEFAULT_CARRY_OVER_EXPIRY, carry_over_amount, carry_over_expiry, expired_carry_over,
+            parse_expiry,
+        },
+        vacation_entitlement::{Entitlement, EntitlementRule, statutory_entitlement},
+        working_days::STANDARD_DAY_MINUTES,
+    },
+};
+
+/// The carry-over expiry day (month, day) and the most that can be carried over, if limited.
+pub async fn carry_over_settings(
+    pool: &MySqlPool,
+) -> Result<((u32, u32), Option<i64>), sqlx::Error> {
+    let expiry = AppSetting::get(pool, SETTING_CARRY_OVER_EXPIRY)
+        .await?
+        .and_then(|value| parse_expiry(&value))
+        .or_else(|| parse_expiry(DEFAULT_CARRY_OVER_EXPIRY))
+        .unwrap_or((4, 30));
+    let max = AppSetting::get(pool, SETTING_CARRY_OVER_MAX)
+        .await?
+        .and_then(|value| value.trim().parse().ok());
+
+    Ok((expiry, max))
+}
+
+/// The entitlement the statutory rules give a user in `year`. `None` for users with fixed
+/// vacation days, or with no hire date.
+pub async fn user_statutory_entitlement(
+    pool: &MySqlPool,
+    user_id: u32,
+    year: i32,
+) -> Result<Option<Entitlement>, sqlx::Error> {
+    let user = sqlx::query!(
+        r#"SELECT hire_date, vacation_entitlement_rule FROM users WHERE id = ?"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let Some(hire_date) = user
+        .hire_date
+        .filter(|_| user.vacation_entitlement_rule == ENTITLEMENT_RULE_STATUTORY)
+    else {
+        return Ok(None);
+    };
+    let contract_end = WorkContract::employment_end(pool, user_id).await?;
+
+    Ok(Some(statutory_entitlement(hire_date, contract_end, year)))
+}
+
+/// Books the statutory entitlement of `year` in a user's ledger, after their hire date or
+/// contracts changed. One set by an admin is kept unless `replace_override`. Returns what the
+/// rules give, `None` for users they do not apply to.
+pub async fn apply_statutory_entitlement(
+    pool: &MySqlPool,
+    user_id: u32,
+    year: i32,
+    replace_override: bool,
+) -> Result<Option<Entitlement>, sqlx::Error> {
+    let Some(entitlement) = user_statutory_entitlement(pool, user_id, year).await? else {
+        return Ok(None);
+    };
+    let balance_unit = sqlx::query_scalar!(
+        r#"SELECT vacation_balance_unit FROM users WHERE id = ?"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    book_statutory_entitlement(
+        &mut tx,
+        user_id,
+        &entitlement,
+        &balance_unit,
+        replace_override,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Some(entitlement))
+}
+
+async fn book_statutory_entitlement(
+    conn: &mut MySqlConnection,
+    user_id: u32,
+    entitlement: &Entitlement,
+    balance_unit: &str,
+    replace_override: bool,
+) -> Result<bool, sqlx::Error> {
+    // The rules count working days, users counting hours get standard days of them
+    let per_day = if balance_unit == BALANCE_UNIT_HOURS {
+        STANDARD_DAY_MINUTES as i64 / 60
+    } else {
+        1
+    };
+
+    let entries = StatutoryEntries {
+        year: entitlement.year,
+        amount: (entitlement.days * per_day) as i32,
+        available_from: entitlement.available_from,
+        reason: match entitlement.rule {
+            EntitlementRule::NotEmployed => "Sem contrato no ano",
+            EntitlementRule::ShortContract => "Contrato inferior a 6 meses",
+            EntitlementRule::FirstYear => "Ano de admissão",
+            EntitlementRule::Yearly => "Direito anual",
+        },
+        deferred: entitlement.deferred.map(|deferred| DeferredEntry {
+            amount: (deferred.days * per_day) as i32,
+            available_from: deferred.available_from,
+            expires_on: deferred.expires_on,
+        }),
+    };
+
+    VacationLedgerEntry::set_statutory_entitlement(conn, user_id, &entries, replace_override).await
+}
+
+/// Starts `year` in the ledger of every user who has no entitlement for it yet: the yearly
+/// entitlement, and what was left of the year before as a carry-over. Returns how many users
+/// were started.
+pub async fn start_year(pool: &MySqlPool, year: i32) -> Result<u64, sqlx::Error> {
+    let (expiry, max) = carry_over_settings(pool).await?;
+    let expires_on = carry_over_expiry(year, expiry);
+
+    let users = sqlx::query!(
+        r#"
+        SELECT u.id, u.vacation_days_current_year, u.vacation_balance_unit
+        FROM users u
+        WHERE NOT EXISTS (
+            SELECT 1 FROM vacation_ledger_entries e
+            WHERE e.user_id = u.id AND e.year = ? AND e.entry_type = ?
+        )
+        "#,
+        year,
+        LEDGER_ENTITLEMENT
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut started = 0;
+    for user in users {
+        let previous = VacationLedgerEntry::balance(pool, user.id, year - 1).await?;
+        let taken =
+            VacationRequest::count_approved_vacation_days_for_year(pool, user.id, year - 1).await?;
+        // Only whole days (or hours) are carried over, what is left of a half day is not
+        let carried_over = carry_over_amount((previous.total as f64 - taken).floor() as i64, max);
+
+        let statutory = user_statutory_entitlement(pool, user.id, year).await?;
+
+        let mut tx = pool.begin().await?;
+
+        match statutory {
+            Some(entitlement) => {
+                book_statutory_entitlement(
+                    &mut tx,
+                    user.id,
+                    &entitlement,
+                    &user.vacation_balance_unit,
+                    false,
+                )
+                .await?;
+            }
+            None => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO vacation_ledger_entries (user_id, year, entry_type, amount, reason)
+                    VALUES (?, ?, ?, ?, ?)
+                    "#,
+                    user.id,
+                    year,
+                    LEDGER_ENTITLEMENT,
+                    user.vacation_days_current_year.unwrap_or(0),
+                    "Direito anual"
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        if carried_over > 0 {
+            sqlx::query!(
+                r#"
+                INSERT INTO vacation_ledger_entries
+                    (user_id, year, entry_type, amount, reason, expires_on)
+                VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+                user.id,
+                year,
+                LEDGER_CARRY_OVER,
+                carried_over,
+                format!("Transitado de {}", year - 1),
+                expires_on
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        started += 1;
+    }
+
+    log::info!("Started vacation year {} for {} users", year, started);
+
+    Ok(started)
+}
+
+/// Books what is left of every carry-over that expired before `today` as lost, once. Vacation
+/// taken until the expiry uses the carried-over days first. Returns how many carry-overs
+/// expired.
+pub async fn expire_carry_overs(pool: &MySqlPool, today: NaiveDate) -> Result<u64, sqlx::Error> {
+    let carry_overs = sqlx::query!(
+        r#"
+        SELECT c.user_id, c.year, c.amount, c.expires_on as "expires_on!", c.available_from
+        FROM vacation_ledger_entries c
+        WHERE c.entry_type = ? AND c.expires_on < ?
+          AND NOT EXISTS (
+            SELECT 1 FROM vacation_ledger_entries x
+            WHERE x.user_id = c.user_id AND x.year = c.year AND x.entry_type = ?
+          )
+        "#,
+        LEDGER_CARRY_OVER,
+        today,
+        LEDGER_EXPIRY
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut expired_count = 0;
+    for carry_over in carry_overs {
+        let Some(year_start) = NaiveDate::from_ymd_opt(carry_over.year as i32, 1, 1) else {
+            continue;
+        };
+        // Days of the hire year are only used from the end of their waiting period
+        let used = VacationRequest::count_approved_vacation_days_between(
+            pool,
+            carry_over.user_id,
+            carry_over.available_from.unwrap_or(year_start),
+            carry_over.expires_on,
+        )
+        .await?;
+        // A half day taken uses a whole carried-over day, in the user's favour
+        let expired = expired_carry_over(carry_over.amount as i64, used.ceil() as i64);
+
+        // Booked even when nothing is lost, so the carry-over is not looked at again
+        sqlx::query!(
+            r#"
+            INSERT INTO vacation_ledger_entries (user_id, year, entry_type, amount, reason)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+            carry_over.user_id,
+            carry_over.year,
+            LEDGER_EXPIRY,
+            -expired,
+            format!(
+                "Dias transitados não gozados até {}",
+                carry_over.expires_on.format("%d/%m/%Y")
+            )
+        )
+        .execute(pool)
+        .await?;
+        expired_count += 1;
+    }
+
+    Ok(expired_count)
+}
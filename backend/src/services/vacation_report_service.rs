@@ -0,0 +1,264 @@
+//! Exports of the admin overview of absences, see [`crate::models::vacation_overview`]. The
+//! map has a row per user with the code of their absence type on each day they are away,
+//! followed by their vacation totals; the spreadsheet adds the periods and the codes.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+
+use crate::{
+    models::{
+        location::Location,
+        vacation_overview::VacationOverview,
+        vacation_request::{VacationDayPart, VacationRequestStatus},
+        work_schedule::BALANCE_UNIT_HOURS,
+    },
+    utils::vacation_overview::csv_line,
+};
+
+enum Cell {
+    Text(String),
+    Number(f64),
+}
+
+impl Cell {
+    fn text(&self) -> String {
+        match self {
+            Cell::Text(text) => text.clone(),
+            Cell::Number(number) => number.to_string(),
+        }
+    }
+}
+
+/// The map as CSV, with a byte order mark so spreadsheets read it as UTF-8.
+pub fn overview_csv(overview: &VacationOverview) -> String {
+    let mut csv = String::from('\u{feff}');
+    csv.push_str(&csv_line(&map_header(overview)));
+    for row in map_rows(overview) {
+        let fields: Vec<String> = row.iter().map(Cell::text).collect();
+        csv.push_str(&csv_line(&fields));
+    }
+    csv
+}
+
+/// The map, the periods and the absence type codes, a sheet each.
+pub fn overview_xlsx(overview: &VacationOverview) -> Result<Vec<u8>, XlsxError> {
+    let mut workbook = Workbook::new();
+    let bold = Format::new().set_bold();
+
+    let sheet = workbook.add_worksheet().set_name("Mapa")?;
+    write_rows(sheet, &map_header(overview), map_rows(overview), &bold)?;
+    // Names stay in view while scrolling through the days
+    sheet.set_freeze_panes(1, 3)?;
+
+    let sheet = workbook.add_worksheet().set_name("Períodos")?;
+    let header = [
+        "ID",
+        "Utilizador",
+        "Pedido",
+        "Tipo",
+        "Início",
+        "Fim",
+        "Parte do dia",
+        "Estado",
+    ]
+    .map(String::from);
+    write_rows(sheet, &header, period_rows(overview), &bold)?;
+    sheet.set_freeze_panes(1, 0)?;
+
+    let sheet = workbook.add_worksheet().set_name("Legenda")?;
+    let header = ["Código", "Tipo"].map(String::from);
+    let rows = overview
+        .absence_types
+        .iter()
+        .map(|absence_type| {
+            vec![
+                Cell::Text(absence_type.code.clone()),
+                Cell::Text(absence_type.name.clone()),
+            ]
+        })
+        .collect();
+    write_rows(sheet, &header, rows, &bold)?;
+
+    workbook.save_to_buffer()
+}
+
+fn write_rows(
+    sheet: &mut Worksheet,
+    header: &[String],
+    rows: Vec<Vec<Cell>>,
+    bold: &Format,
+) -> Result<(), XlsxError> {
+    for (col, title) in header.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, title, bold)?;
+    }
+    for (row, cells) in rows.iter().enumerate() {
+        let row = row as u32 + 1;
+        for (col, cell) in cells.iter().enumerate() {
+            match cell {
+                Cell::Text(text) => sheet.write_string(row, col as u16, text)?,
+                Cell::Number(number) => sheet.write_number(row, col as u16, *number)?,
+            };
+        }
+    }
+    Ok(())
+}
+
+fn map_header(overview: &VacationOverview) -> Vec<String> {
+    let mut header: Vec<String> = ["ID", "Utilizador", "Local"].map(String::from).into();
+    header.extend(
+        overview
+            .days
+            .iter()
+            .map(|day| day.date.format("%d/%m").to_string()),
+    );
+    header.extend(
+        [
+            "Unidade",
+            "Total",
+            "Gozados",
+            "Pendentes",
+            "Restantes",
+            "Ausente no período",
+        ]
+        .map(String::from),
+    );
+    header
+}
+
+fn map_rows(overview: &VacationOverview) -> Vec<Vec<Cell>> {
+    let codes = request_codes(overview);
+    let mut day_codes: HashMap<(u32, NaiveDate), Vec<&str>> = HashMap::new();
+    for day in &overview.days {
+        for absence in &day.absences {
+            if let Some(code) = codes.get(&absence.request_id) {
+                day_codes
+                    .entry((absence.user_id, day.date))
+                    .or_default()
+                    .push(code);
+            }
+        }
+    }
+
+    overview
+        .users
+        .iter()
+        .map(|user| {
+            let mut row = vec![
+                Cell::Number(user.user_id as f64),
+                Cell::Text(user.username.clone()),
+                Cell::Text(location_name(user.location)),
+            ];
+            row.extend(overview.days.iter().map(|day| {
+                Cell::Text(
+                    day_codes
+                        .get(&(user.user_id, day.date))
+                        .map(|codes| codes.join("+"))
+                        .unwrap_or_default(),
+                )
+            }));
+            let unit = if user.balance_unit == BALANCE_UNIT_HOURS {
+                "horas"
+            } else {
+                "dias"
+            };
+            row.extend([
+                Cell::Text(unit.to_string()),
+                Cell::Number(user.total as f64),
+                Cell::Number(user.taken),
+                Cell::Number(user.pending),
+                Cell::Number(user.remaining),
+                Cell::Number(user.absent),
+            ]);
+            row
+        })
+        .collect()
+}
+
+fn period_rows(overview: &VacationOverview) -> Vec<Vec<Cell>> {
+    let names: HashMap<u32, &str> = overview
+        .absence_types
+        .iter()
+        .map(|absence_type| (absence_type.id, absence_type.name.as_str()))
+        .collect();
+
+    overview
+        .users
+        .iter()
+        .flat_map(|user| {
+            user.periods.iter().map(|period| {
+                vec![
+                    Cell::Number(user.user_id as f64),
+                    Cell::Text(user.username.clone()),
+                    Cell::Number(period.request_id as f64),
+                    Cell::Text(
+                        names
+                            .get(&period.absence_type_id)
+                            .copied()
+                            .unwrap_or_default()
+                            .to_string(),
+                    ),
+                    Cell::Text(period.start_date.format("%d/%m/%Y").to_string()),
+                    Cell::Text(period.end_date.format("%d/%m/%Y").to_string()),
+                    Cell::Text(day_part_label(period.day_part, period.minutes)),
+                    Cell::Text(status_label(period.status).to_string()),
+                ]
+            })
+        })
+        .collect()
+}
+
+/// The code of each request's absence type, marked `½` for half days and `h` for hours.
+fn request_codes(overview: &VacationOverview) -> HashMap<u32, String> {
+    let codes: HashMap<u32, &str> = overview
+        .absence_types
+        .iter()
+        .map(|absence_type| (absence_type.id, absence_type.code.as_str()))
+        .collect();
+
+    overview
+        .users
+        .iter()
+        .flat_map(|user| &user.periods)
+        .map(|period| {
+            let code = codes.get(&period.absence_type_id).copied().unwrap_or("?");
+            let mark = match period.day_part {
+                VacationDayPart::Full => "",
+                VacationDayPart::Hours => "h",
+                VacationDayPart::Morning | VacationDayPart::Afternoon => "½",
+            };
+            (period.request_id, format!("{}{}", code, mark))
+        })
+        .collect()
+}
+
+fn location_name(location: Option<i8>) -> String {
+    location
+        .and_then(Location::from_value)
+        .map(|location| location.name())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn day_part_label(day_part: VacationDayPart, minutes: Option<u16>) -> String {
+    match day_part {
+        VacationDayPart::Full => "Dia inteiro".to_string(),
+        VacationDayPart::Morning => "Manhã".to_string(),
+        VacationDayPart::Afternoon => "Tarde".to_string(),
+        VacationDayPart::Hours => {
+            let minutes = minutes.unwrap_or(0);
+            format!("{}h{:02}", minutes / 60, minutes % 60)
+        }
+    }
+}
+
+fn status_label(status: VacationRequestStatus) -> &'static str {
+    match status {
+        VacationRequestStatus::Pending => "Pendente",
+        VacationRequestStatus::Approved => "Aprovado",
+        VacationRequestStatus::Rejected => "Rejeitado",
+        VacationRequestStatus::CancellationRequested => "Cancelamento pedido",
+        VacationRequestStatus::Cancelled => "Cancelado",
+    }
+}
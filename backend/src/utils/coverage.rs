@@ -0,0 +1,157 @@
+//! Coverage rules: how many members of a role can be absent on the same day, or how many must
+//! be present. See [`crate::models::coverage_rule`] for the rules and who they apply to.
+//!
+//! A request is checked against the absences already approved. Absences on the same day only
+//! count together when they take the same part of it, see [`DayPart::overlaps`].
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::utils::working_days::DayPart;
+
+/// What a rule asks of a group on every day it applies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Requirement {
+    MaxAbsent(u16),
+    MinPresent(u16),
+}
+
+/// An absence of a member of the group, approved or requested.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Absence {
+    pub user_id: u32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub part: DayPart,
+}
+
+impl Absence {
+    fn takes(&self, date: NaiveDate, part: DayPart) -> bool {
+        self.start_date <= date && date <= self.end_date && self.part.overlaps(part)
+    }
+}
+
+/// How a group would stand on a day if a request were approved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct DayCoverage {
+    pub date: NaiveDate,
+    pub members: usize,
+    /// The requester included
+    pub absent: usize,
+}
+
+/// The days among `dates` on which `request`, with the `others` absences of the group's
+/// `members`, breaks the requirement. Each member is absent once a day, however many of
+/// their absences fall on it, and the requester's own are not counted twice.
+pub fn uncovered_days(
+    requirement: Requirement,
+    members: usize,
+    request: &Absence,
+    others: &[Absence],
+    dates: impl IntoIterator<Item = NaiveDate>,
+) -> Vec<DayCoverage> {
+    let mut uncovered = Vec::new();
+
+    for date in dates {
+        if !request.takes(date, request.part) {
+            continue;
+        }
+
+        let mut absent_users: Vec<u32> = others
+            .iter()
+            .filter(|absence| absence.user_id != request.user_id)
+            .filter(|absence| absence.takes(date, request.part))
+            .map(|absence| absence.user_id)
+            .collect();
+        absent_users.sort_unstable();
+        absent_users.dedup();
+        let absent = absent_users.len() + 1;
+
+        let broken = match requirement {
+            Requirement::MaxAbsent(max) => absent > max as usize,
+            Requirement::MinPresent(min) => members.saturating_sub(absent) < min as usize,
+        };
+        if broken {
+            uncovered.push(DayCoverage {
+                date,
+                members,
+                absent,
+            });
+        }
+    }
+
+    uncovered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn absence(user_id: u32, start: NaiveDate, end: NaiveDate, part: DayPart) -> Absence {
+        Absence {
+            user_id,
+            start_date: start,
+            end_date: end,
+            part,
+        }
+    }
+
+    #[test]
+    fn test_max_absent() {
+        // Monday 2 to Friday 6 June 2025
+        let request = absence(1, date(2025, 6, 2), date(2025, 6, 6), DayPart::Full);
+        let others = [
+            absence(2, date(2025, 6, 4), date(2025, 6, 10), DayPart::Full),
+            absence(3, date(2025, 6, 5), date(2025, 6, 5), DayPart::Full),
+            // Counted once on the 5th
+            absence(3, date(2025, 6, 5), date(2025, 6, 5), DayPart::Full),
+        ];
+        let dates = (2..=6).map(|day| date(2025, 6, day));
+
+        let uncovered = uncovered_days(Requirement::MaxAbsent(2), 5, &request, &others, dates);
+        assert_eq!(
+            uncovered,
+            vec![DayCoverage {
+                date: date(2025, 6, 5),
+                members: 5,
+                absent: 3,
+            }]
+        );
+
+        // Days not checked, like weekends, are left out
+        let uncovered = uncovered_days(
+            Requirement::MaxAbsent(1),
+            5,
+            &request,
+            &others,
+            [date(2025, 6, 2), date(2025, 6, 4)],
+        );
+        assert_eq!(uncovered.len(), 1);
+        assert_eq!(uncovered[0].date, date(2025, 6, 4));
+    }
+
+    #[test]
+    fn test_min_present() {
+        let monday = date(2025, 6, 2);
+        let request = absence(1, monday, monday, DayPart::Morning);
+        let afternoon = [absence(2, monday, monday, DayPart::Afternoon)];
+        let morning = [absence(2, monday, monday, DayPart::Morning)];
+        let one_stays = Requirement::MinPresent(1);
+
+        // Two nurses, one must stay: a morning and an afternoon leave one in each half
+        assert!(uncovered_days(one_stays, 2, &request, &afternoon, [monday]).is_empty());
+        assert_eq!(
+            uncovered_days(one_stays, 2, &request, &morning, [monday]).len(),
+            1
+        );
+        // A single member cannot leave
+        assert_eq!(
+            uncovered_days(one_stays, 1, &request, &[], [monday]).len(),
+            1
+        );
+    }
+}
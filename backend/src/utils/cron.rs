@@ -0,0 +1,235 @@
+//! Minimal 5-field cron expressions (`minute hour day-of-month month day-of-week`).
+//!
+//! Each field accepts `*`, single values, lists (`1,15`), ranges (`1-5`) and steps
+//! (`*/15`, `0-30/10`). Day of week goes from 0 (Sunday) to 6, 7 is also accepted as Sunday.
+//! Expressions are evaluated in UTC.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Timelike, Utc};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u32,
+    days_of_month: u32,
+    months: u16,
+    days_of_week: u8,
+    // When both day fields are restricted, cron matches if either one matches
+    day_of_month_any: bool,
+    day_of_week_any: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0u64;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| format!("Passo inválido '{}'", step))?;
+                if step == 0 {
+                    return Err("O passo não pode ser 0".to_string());
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            let start: u32 = start
+                .parse()
+                .map_err(|_| format!("Valor inválido '{}'", start))?;
+            let end: u32 = end
+                .parse()
+                .map_err(|_| format!("Valor inválido '{}'", end))?;
+            (start, end)
+        } else {
+            let value: u32 = range
+                .parse()
+                .map_err(|_| format!("Valor inválido '{}'", range))?;
+            // "5/10" means "from 5 to the end, every 10"
+            if step > 1 {
+                (value, max)
+            } else {
+                (value, value)
+            }
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!(
+                "Intervalo '{}' fora dos limites {}-{}",
+                range, min, max
+            ));
+        }
+
+        let mut value = start;
+        while value <= end {
+            mask |= 1 << value;
+            value += step;
+        }
+    }
+
+    Ok(mask)
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(format!(
+                "A expressão cron '{}' deve ter 5 campos",
+                expression
+            ));
+        };
+
+        let mut days_of_week = parse_field(day_of_week, 0, 7)?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+
+        Ok(CronSchedule {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)? as u32,
+            days_of_month: parse_field(day_of_month, 1, 31)? as u32,
+            months: parse_field(month, 1, 12)? as u16,
+            days_of_week: days_of_week as u8,
+            day_of_month_any: day_of_month == "*",
+            day_of_week_any: day_of_week == "*",
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let dom = self.days_of_month & (1 << date.day()) != 0;
+        let dow = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+
+        match (self.day_of_month_any, self.day_of_week_any) {
+            (true, true) => true,
+            (true, false) => dow,
+            (false, true) => dom,
+            (false, false) => dom || dow,
+        }
+    }
+
+    /// Returns the first time strictly after `after` that matches the schedule.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.naive_utc().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let mut date = start.date();
+        let mut first_day = true;
+
+        // Any valid expression matches at least once within 5 years (covers 29 February)
+        let limit = date + Duration::days(366 * 5);
+
+        while date <= limit {
+            if self.months & (1 << date.month()) != 0 && self.matches_day(date) {
+                let from = if first_day {
+                    start.time()
+                } else {
+                    NaiveTime::MIN
+                };
+
+                for hour in from.hour()..24 {
+                    if self.hours & (1 << hour) == 0 {
+                        continue;
+                    }
+                    let first_minute = if hour == from.hour() {
+                        from.minute()
+                    } else {
+                        0
+                    };
+                    for minute in first_minute..60 {
+                        if self.minutes & (1 << minute) != 0 {
+                            return date.and_hms_opt(hour, minute, 0).map(|dt| dt.and_utc());
+                        }
+                    }
+                }
+            }
+
+            date = date.succ_opt()?;
+            first_day = false;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_hourly() {
+        let cron = CronSchedule::parse("0 * * * *").unwrap();
+        assert_eq!(
+            cron.next_after(utc(2025, 5, 1, 10, 0)),
+            Some(utc(2025, 5, 1, 11, 0))
+        );
+        assert_eq!(
+            cron.next_after(utc(2025, 12, 31, 23, 30)),
+            Some(utc(2026, 1, 1, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_steps_and_lists() {
+        let cron = CronSchedule::parse("*/15 8-9 * * *").unwrap();
+        assert_eq!(
+            cron.next_after(utc(2025, 5, 1, 8, 20)),
+            Some(utc(2025, 5, 1, 8, 30))
+        );
+        assert_eq!(
+            cron.next_after(utc(2025, 5, 1, 9, 45)),
+            Some(utc(2025, 5, 2, 8, 0))
+        );
+
+        let cron = CronSchedule::parse("0 6,18 * * *").unwrap();
+        assert_eq!(
+            cron.next_after(utc(2025, 5, 1, 7, 0)),
+            Some(utc(2025, 5, 1, 18, 0))
+        );
+    }
+
+    #[test]
+    fn test_day_fields() {
+        // 1 January at 00:05
+        let cron = CronSchedule::parse("5 0 1 1 *").unwrap();
+        assert_eq!(
+            cron.next_after(utc(2025, 3, 1, 0, 0)),
+            Some(utc(2026, 1, 1, 0, 5))
+        );
+
+        // Mondays at 07:00 (2025-05-05 is a Monday)
+        let cron = CronSchedule::parse("0 7 * * 1").unwrap();
+        assert_eq!(
+            cron.next_after(utc(2025, 5, 1, 0, 0)),
+            Some(utc(2025, 5, 5, 7, 0))
+        );
+
+        // 7 is Sunday as well (2025-05-04 is a Sunday)
+        let cron = CronSchedule::parse("0 0 * * 7").unwrap();
+        assert_eq!(
+            cron.next_after(utc(2025, 5, 1, 0, 0)),
+            Some(utc(2025, 5, 4, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("a * * * *").is_err());
+        assert!(
+            CronSchedule::parse("0 0 31 2 *")
+                .unwrap()
+                .next_after(utc(2025, 1, 1, 0, 0))
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Helpers for directory (LDAP / Active Directory) logins that do not need a connection.
+
+use ldap3::ldap_escape;
+
+/// Fills the `{login}` placeholder of a search filter, escaping what the user typed so it
+/// cannot change the filter.
+pub fn user_filter(template: &str, login: &str) -> String {
+    template.replace("{login}", &ldap_escape(login.trim()))
+}
+
+/// Names a group can be mapped by, lowercase: the value as the directory gives it, usually a
+/// full DN like `cn=Enfermagem,ou=Grupos,dc=clinica,dc=pt`, and its first `cn`.
+pub fn group_names(group: &str) -> Vec<String> {
+    let group = group.trim().to_lowercase();
+    let cn = group
+        .split(',')
+        .next()
+        .and_then(|rdn| rdn.trim().strip_prefix("cn="))
+        .map(|cn| cn.trim().to_string());
+
+    match cn {
+        Some(cn) if cn != group => vec![group, cn],
+        _ => vec![group],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_filter_escapes_login() {
+        let template = "(&(objectClass=person)(mail={login}))";
+        assert_eq!(
+            user_filter(template, " ana@clinica.pt "),
+            "(&(objectClass=person)(mail=ana@clinica.pt))"
+        );
+        assert_eq!(
+            user_filter(template, "*)(uid=*"),
+            "(&(objectClass=person)(mail=\\2a\\29\\28uid=\\2a))"
+        );
+    }
+
+    #[test]
+    fn test_group_names() {
+        assert_eq!(
+            group_names("CN=Enfermagem,OU=Grupos,DC=clinica,DC=pt"),
+            vec!["cn=enfermagem,ou=grupos,dc=clinica,dc=pt", "enfermagem"]
+        );
+        assert_eq!(group_names("Enfermagem"), vec!["enfermagem"]);
+    }
+}
@@ -1,38 +1,99 @@
-use argon2_kdf::{Hash, Hasher};
-
-#[inline(always)]
-pub fn hash(password: &str) -> [u8; 48] {
-    let hash = Hasher::new()
-        .algorithm(argon2_kdf::Algorithm::Argon2id)
-        .salt_length(16)
-        .iterations(4)
-        .memory_cost_kib(65536)
-        .hash_length(32)
-        .threads(4)
+//! Password hashing with Argon2id.
+//!
+//! Hashes are stored as PHC strings (`$argon2id$v=19$m=...,t=...,p=...$salt$hash`), which carry
+//! their own parameters, so [`HASH_PARAMS`] can change without breaking existing passwords:
+//! [`needs_rehash`] tells the login to store a new hash once the password is known. Accounts
+//! created before PHC strings hold 48 raw bytes, a 16 byte salt and a 32 byte hash made with
+//! the original parameters, and keep working until their next login.
+
+use std::str::FromStr;
+
+use argon2_kdf::{Algorithm, Hash, Hasher};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashParams {
+    pub memory_cost_kib: u32,
+    pub iterations: u32,
+    pub threads: u32,
+}
+
+/// Parameters of new hashes.
+pub const HASH_PARAMS: HashParams = HashParams {
+    memory_cost_kib: 65536,
+    iterations: 4,
+    threads: 4,
+};
+
+/// Parameters of the raw 48 byte hashes.
+const LEGACY_PARAMS: HashParams = HashParams {
+    memory_cost_kib: 65536,
+    iterations: 4,
+    threads: 4,
+};
+const LEGACY_LEN: usize = 48;
+const SALT_LEN: u32 = 16;
+const HASH_LEN: u32 = 32;
+
+fn hash_with(password: &str, params: HashParams) -> Hash<'static> {
+    Hasher::new()
+        .algorithm(Algorithm::Argon2id)
+        .salt_length(SALT_LEN)
+        .iterations(params.iterations)
+        .memory_cost_kib(params.memory_cost_kib)
+        .hash_length(HASH_LEN)
+        .threads(params.threads)
         .hash(password.as_bytes())
-        .unwrap();
-    let mut combined_bytes = [0u8; 48]; // 16 bytes for the salt and 32 bytes for the hash
+        .unwrap()
+}
 
-    combined_bytes[..16].copy_from_slice(hash.salt_bytes());
-    combined_bytes[16..].copy_from_slice(hash.as_bytes());
+/// PHC string of a new password, stored as is in `users.password`.
+pub fn hash(password: &str) -> String {
+    hash_with(password, HASH_PARAMS).to_string()
+}
 
-    combined_bytes
+fn parse_phc(stored: &[u8]) -> Option<Hash<'static>> {
+    std::str::from_utf8(stored)
+        .ok()
+        .filter(|s| s.starts_with("$argon2"))
+        .and_then(|s| Hash::from_str(s).ok())
 }
 
-#[inline(always)]
-pub fn verify(password: &str, combined_bytes: &[u8]) -> bool {
+pub fn verify(password: &str, stored: &[u8]) -> bool {
+    if let Some(hash) = parse_phc(stored) {
+        return hash.verify(password.as_bytes());
+    }
+
+    if stored.len() != LEGACY_LEN {
+        return false;
+    }
     let hash = Hash::from_parts(
-        &combined_bytes[16..],
-        &combined_bytes[..16],
-        argon2_kdf::Algorithm::Argon2id,
-        65536,
-        4,
-        4,
+        &stored[16..],
+        &stored[..16],
+        Algorithm::Argon2id,
+        LEGACY_PARAMS.memory_cost_kib,
+        LEGACY_PARAMS.iterations,
+        LEGACY_PARAMS.threads,
     );
 
     hash.verify(password.as_bytes())
 }
 
+/// Whether a stored hash was made differently from how new hashes are, and should be replaced
+/// the next time the password is known.
+pub fn needs_rehash(stored: &[u8]) -> bool {
+    let Some(hash) = parse_phc(stored) else {
+        return true;
+    };
+
+    !matches!(hash.algorithm(), Algorithm::Argon2id)
+        || hash.as_bytes().len() != HASH_LEN as usize
+        || HashParams {
+            memory_cost_kib: hash.memory_cost_kib(),
+            iterations: hash.iterations(),
+            threads: hash.threads(),
+        } != HASH_PARAMS
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -41,10 +102,11 @@ mod tests {
     fn test_hash_password_and_verify() {
         let password = "supersecretpassword";
         let hashed = hash(password);
-        assert!(verify(password, &hashed));
+        assert!(hashed.starts_with("$argon2id$v=19$m=65536,t=4,p=4$"));
+        assert!(verify(password, hashed.as_bytes()));
 
         let wrong_password = "wrongpassword";
-        assert!(!verify(wrong_password, &hashed));
+        assert!(!verify(wrong_password, hashed.as_bytes()));
     }
 
     #[test]
@@ -60,4 +122,32 @@ mod tests {
         ];
         assert!(!verify(password, &combined_bytes));
     }
+
+    #[test]
+    fn test_legacy_hash_still_verifies() {
+        let password = "supersecretpassword";
+        let legacy = hash_with(password, LEGACY_PARAMS);
+        let mut stored = legacy.salt_bytes().to_vec();
+        stored.extend_from_slice(legacy.as_bytes());
+
+        assert_eq!(stored.len(), LEGACY_LEN);
+        assert!(verify(password, &stored));
+        assert!(!verify("wrongpassword", &stored));
+        assert!(needs_rehash(&stored));
+    }
+
+    #[test]
+    fn test_needs_rehash() {
+        assert!(!needs_rehash(hash("password").as_bytes()));
+
+        let weaker = HashParams {
+            iterations: 2,
+            ..HASH_PARAMS
+        };
+        let old = hash_with("password", weaker).to_string();
+        assert!(verify("password", old.as_bytes()));
+        assert!(needs_rehash(old.as_bytes()));
+
+        assert!(!verify("password", b"garbage"));
+    }
 }
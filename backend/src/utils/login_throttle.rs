@@ -0,0 +1,94 @@
+//! Limits on failed logins, per account and per client IP.
+//!
+//! A key can fail `max_failures` times; every failure after that locks it for `base_lock`,
+//! doubled for each further failure up to `max_lock`. Failures older than `reset_after` are
+//! forgotten, and a successful login clears the account counter.
+
+use chrono::Duration;
+
+pub const SCOPE_ACCOUNT: &str = "ACCOUNT";
+pub const SCOPE_IP: &str = "IP";
+/// Password reset emails asked for an account, counted like failures so a flood of requests
+/// does not flood the inbox
+pub const SCOPE_PASSWORD_RESET: &str = "RESET";
+
+#[derive(Debug, Clone, Copy)]
+pub struct ThrottlePolicy {
+    pub max_failures: u32,
+    pub base_lock: Duration,
+    pub max_lock: Duration,
+    pub reset_after: Duration,
+}
+
+pub const ACCOUNT_POLICY: ThrottlePolicy = ThrottlePolicy {
+    max_failures: 5,
+    base_lock: Duration::minutes(1),
+    max_lock: Duration::hours(1),
+    reset_after: Duration::hours(24),
+};
+
+/// Looser than the account limit since several people can share an office IP.
+pub const IP_POLICY: ThrottlePolicy = ThrottlePolicy {
+    max_failures: 20,
+    base_lock: Duration::minutes(1),
+    max_lock: Duration::hours(1),
+    reset_after: Duration::hours(1),
+};
+
+pub const PASSWORD_RESET_POLICY: ThrottlePolicy = ThrottlePolicy {
+    max_failures: 3,
+    base_lock: Duration::minutes(15),
+    max_lock: Duration::hours(1),
+    reset_after: Duration::hours(1),
+};
+
+impl ThrottlePolicy {
+    /// How long a key is locked after its `failed_attempts`-th consecutive failure.
+    pub fn lock_duration(&self, failed_attempts: u32) -> Option<Duration> {
+        if failed_attempts <= self.max_failures {
+            return None;
+        }
+
+        // Past 2^20 minutes every sensible cap is reached anyway
+        let doublings = (failed_attempts - self.max_failures - 1).min(20);
+        let lock = self.base_lock * (1 << doublings);
+
+        Some(lock.min(self.max_lock))
+    }
+}
+
+/// Normalizes an email into its account throttle key.
+pub fn account_key(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_lock_under_limit() {
+        for attempts in 0..=ACCOUNT_POLICY.max_failures {
+            assert_eq!(ACCOUNT_POLICY.lock_duration(attempts), None);
+        }
+    }
+
+    #[test]
+    fn test_progressive_lock() {
+        assert_eq!(ACCOUNT_POLICY.lock_duration(6), Some(Duration::minutes(1)));
+        assert_eq!(ACCOUNT_POLICY.lock_duration(7), Some(Duration::minutes(2)));
+        assert_eq!(ACCOUNT_POLICY.lock_duration(8), Some(Duration::minutes(4)));
+        assert_eq!(ACCOUNT_POLICY.lock_duration(11), Some(Duration::minutes(32)));
+    }
+
+    #[test]
+    fn test_lock_is_capped() {
+        assert_eq!(ACCOUNT_POLICY.lock_duration(12), Some(Duration::hours(1)));
+        assert_eq!(ACCOUNT_POLICY.lock_duration(u32::MAX), Some(Duration::hours(1)));
+    }
+
+    #[test]
+    fn test_account_key() {
+        assert_eq!(account_key("  Maria.Silva@Example.PT "), "maria.silva@example.pt");
+    }
+}
@@ -1,5 +1,18 @@
 pub mod forms;
 pub mod working_days;
 pub mod hashing_utils;
+pub mod password_policy;
 pub mod json_utils;
 pub mod memory_file;
+pub mod cron;
+pub mod directory;
+pub mod record_dates;
+pub mod role_hierarchy;
+pub mod login_throttle;
+pub mod secure_token;
+pub mod totp;
+pub mod oidc;
+pub mod vacation_balance;
+pub mod vacation_entitlement;
+pub mod coverage;
+pub mod vacation_overview;
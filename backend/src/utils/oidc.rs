@@ -0,0 +1,152 @@
+//! Parts of an OpenID Connect login that do not need the network: the PKCE challenge and the
+//! checks on the ID token.
+//!
+//! The ID token comes straight from the token endpoint over TLS, so as allowed by OpenID
+//! Connect Core 3.1.3.7 its signature is not checked, only its claims.
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Tolerated difference between our clock and the identity provider's
+const CLOCK_SKEW_SECS: i64 = 60;
+
+/// `S256` code challenge of a PKCE code verifier (RFC 7636).
+pub fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// Claims of an ID token, without checking them.
+pub fn decode_id_token(id_token: &str) -> Result<Value, String> {
+    let mut parts = id_token.split('.');
+    let (Some(_header), Some(payload), Some(_signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err("ID token mal formado".to_string());
+    };
+
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|e| format!("ID token mal formado: {}", e))?;
+    serde_json::from_slice(&payload).map_err(|e| format!("ID token mal formado: {}", e))
+}
+
+/// Checks that the token was issued by `issuer` for `client_id`, in answer to the login that
+/// sent `nonce`, and has not expired. `now` is a Unix timestamp.
+pub fn validate_id_token(
+    claims: &Value,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+    now: i64,
+) -> Result<(), String> {
+    if claims["iss"].as_str() != Some(issuer) {
+        return Err(format!("Emissor inesperado: {}", claims["iss"]));
+    }
+
+    let audience_ok = match &claims["aud"] {
+        Value::String(aud) => aud == client_id,
+        Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(client_id)),
+        _ => false,
+    };
+    if !audience_ok {
+        return Err(format!("Audiência inesperada: {}", claims["aud"]));
+    }
+
+    match claims["exp"].as_i64() {
+        Some(exp) if exp + CLOCK_SKEW_SECS > now => {}
+        _ => return Err("ID token expirado".to_string()),
+    }
+
+    if claims["nonce"].as_str() != Some(nonce) {
+        return Err("Nonce inválido".to_string());
+    }
+
+    if claims["sub"].as_str().is_none_or(str::is_empty) {
+        return Err("ID token sem sub".to_string());
+    }
+
+    Ok(())
+}
+
+/// Values of a claim, which may be a string or a list of strings. Dots reach into nested
+/// objects, as in Keycloak's `realm_access.roles`.
+pub fn claim_values(claims: &Value, name: &str) -> Vec<String> {
+    let claim = name
+        .split('.')
+        .try_fold(claims, |value, key| value.get(key));
+
+    match claim {
+        Some(Value::String(value)) => vec![value.clone()],
+        Some(Value::Array(values)) => values
+            .iter()
+            .filter_map(|value| value.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn claims() -> Value {
+        json!({
+            "iss": "https://idp.clinica.pt",
+            "aud": ["gestao-documental", "outra-app"],
+            "exp": 1_700_000_600,
+            "nonce": "abc",
+            "sub": "1234",
+            "realm_access": { "roles": ["enfermagem", "admin"] },
+            "department": "Lisboa",
+        })
+    }
+
+    #[test]
+    fn test_pkce_challenge() {
+        // Example from RFC 7636, appendix B
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn test_decode_id_token() {
+        let payload = URL_SAFE_NO_PAD.encode(claims().to_string());
+        let token = format!("eyJhbGciOiJSUzI1NiJ9.{}.c2lnbmF0dXJl", payload);
+        assert_eq!(decode_id_token(&token).unwrap(), claims());
+        assert!(decode_id_token("not-a-token").is_err());
+    }
+
+    #[test]
+    fn test_validate_id_token() {
+        let claims = claims();
+        let now = 1_700_000_000;
+        let issuer = "https://idp.clinica.pt";
+        assert!(validate_id_token(&claims, issuer, "gestao-documental", "abc", now).is_ok());
+
+        assert!(
+            validate_id_token(&claims, "https://outro.pt", "gestao-documental", "abc", now)
+                .is_err()
+        );
+        assert!(validate_id_token(&claims, issuer, "outro-cliente", "abc", now).is_err());
+        assert!(validate_id_token(&claims, issuer, "gestao-documental", "xyz", now).is_err());
+        assert!(
+            validate_id_token(&claims, issuer, "gestao-documental", "abc", now + 3600).is_err()
+        );
+    }
+
+    #[test]
+    fn test_claim_values() {
+        let claims = claims();
+        assert_eq!(
+            claim_values(&claims, "realm_access.roles"),
+            vec!["enfermagem", "admin"]
+        );
+        assert_eq!(claim_values(&claims, "department"), vec!["Lisboa"]);
+        assert!(claim_values(&claims, "groups").is_empty());
+        assert!(claim_values(&claims, "exp").is_empty());
+    }
+}
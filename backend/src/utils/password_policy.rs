@@ -0,0 +1,241 @@
+//! Rules new passwords must follow.
+//!
+//! Configured through environment variables, each with a default: `PASSWORD_MIN_LENGTH` (10),
+//! `PASSWORD_REQUIRE_UPPERCASE`, `PASSWORD_REQUIRE_LOWERCASE`, `PASSWORD_REQUIRE_DIGIT` (all
+//! true), `PASSWORD_REQUIRE_SYMBOL` (false), `PASSWORD_HISTORY` (5 previous passwords that
+//! cannot be reused) and `PASSWORD_BREACHED_LIST`, a file of known breached passwords.
+//!
+//! The breached list has one entry per line, either the password itself or its SHA-1 in hex as
+//! in the Have I Been Pwned downloads (`HASH:count`). It is held in memory, so it is meant to be
+//! the most common breached passwords, such as the first lines of the downloads sorted by count,
+//! not the whole corpus: lists longer than [`MAX_BREACHED_ENTRIES`] are refused.
+
+use std::io::BufRead;
+
+use sha1::{Digest, Sha1};
+
+/// Most entries the breached list can have, about 20 MB in memory.
+pub const MAX_BREACHED_ENTRIES: usize = 1_000_000;
+
+type Sha1Digest = [u8; 20];
+
+#[derive(Debug, Clone, Default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// How many previous passwords of a user cannot be used again
+    pub history_size: u32,
+    /// SHA-1 of every breached password, sorted
+    breached: Vec<Sha1Digest>,
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> Result<T, String> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| format!("{} inválido: {}", name, value)),
+        Err(_) => Ok(default),
+    }
+}
+
+fn sha1(value: &str) -> Sha1Digest {
+    Sha1::digest(value.as_bytes()).into()
+}
+
+/// The digest written as 40 hex digits, if `value` is one.
+fn parse_sha1_hex(value: &str) -> Option<Sha1Digest> {
+    if value.len() != 40 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut digest = [0; 20];
+    for (byte, pair) in digest.iter_mut().zip(value.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+/// Parses the breached list, see the module docs. Fails on lists longer than `max_entries`.
+pub fn parse_breached_list(
+    reader: impl BufRead,
+    max_entries: usize,
+) -> Result<Vec<Sha1Digest>, String> {
+    let mut breached = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            continue;
+        }
+        if breached.len() == max_entries {
+            return Err(format!("a lista tem mais de {} entradas", max_entries));
+        }
+
+        let candidate = line.split(':').next().unwrap_or(line);
+        breached.push(parse_sha1_hex(candidate).unwrap_or_else(|| sha1(line)));
+    }
+
+    breached.sort_unstable();
+    breached.dedup();
+    Ok(breached)
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> Result<PasswordPolicy, String> {
+        let breached = match std::env::var("PASSWORD_BREACHED_LIST") {
+            Ok(path) => {
+                let file = std::fs::File::open(&path)
+                    .map_err(|e| format!("Erro ao ler {}: {}", path, e))?;
+                let breached =
+                    parse_breached_list(std::io::BufReader::new(file), MAX_BREACHED_ENTRIES)
+                        .map_err(|e| format!("Erro ao ler {}: {}", path, e))?;
+                log::info!("Loaded {} breached passwords from {}", breached.len(), path);
+                breached
+            }
+            Err(_) => Vec::new(),
+        };
+
+        Ok(PasswordPolicy {
+            min_length: env_or("PASSWORD_MIN_LENGTH", 10)?,
+            require_uppercase: env_or("PASSWORD_REQUIRE_UPPERCASE", true)?,
+            require_lowercase: env_or("PASSWORD_REQUIRE_LOWERCASE", true)?,
+            require_digit: env_or("PASSWORD_REQUIRE_DIGIT", true)?,
+            require_symbol: env_or("PASSWORD_REQUIRE_SYMBOL", false)?,
+            history_size: env_or("PASSWORD_HISTORY", 5)?,
+            breached,
+        })
+    }
+
+    /// `breached` must be sorted, as [`parse_breached_list`] returns it.
+    pub fn with_breached(mut self, breached: Vec<Sha1Digest>) -> Self {
+        self.breached = breached;
+        self
+    }
+
+    /// Every rule the password breaks, as messages for the user. `identifiers` are the username
+    /// and email, which cannot be part of the password.
+    pub fn violations(&self, password: &str, identifiers: &[&str]) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        if password.chars().count() < self.min_length {
+            violations.push(format!(
+                "A palavra-passe deve ter pelo menos {} caracteres.",
+                self.min_length
+            ));
+        }
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            violations.push("A palavra-passe deve ter uma letra maiúscula.".to_string());
+        }
+        if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+            violations.push("A palavra-passe deve ter uma letra minúscula.".to_string());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            violations.push("A palavra-passe deve ter um algarismo.".to_string());
+        }
+        if self.require_symbol && password.chars().all(char::is_alphanumeric) {
+            violations.push("A palavra-passe deve ter um símbolo.".to_string());
+        }
+
+        let lowercase = password.to_lowercase();
+        let contains_identifier = identifiers
+            .iter()
+            // For an email only the part before the @ is meaningful
+            .map(|id| id.split('@').next().unwrap_or(id).trim().to_lowercase())
+            .any(|id| id.chars().count() >= 3 && lowercase.contains(&id));
+        if contains_identifier {
+            violations.push(
+                "A palavra-passe não pode conter o nome de utilizador ou o email.".to_string(),
+            );
+        }
+
+        if self.breached.binary_search(&sha1(password)).is_ok() {
+            violations.push(
+                "Esta palavra-passe aparece em fugas de dados conhecidas, escolha outra."
+                    .to_string(),
+            );
+        }
+
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 10,
+            require_uppercase: true,
+            require_lowercase: true,
+            require_digit: true,
+            require_symbol: false,
+            history_size: 5,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_valid_password() {
+        assert!(policy().violations("Correct7Horse", &["ana"]).is_empty());
+    }
+
+    #[test]
+    fn test_every_violation_is_reported() {
+        let violations = PasswordPolicy {
+            require_symbol: true,
+            ..policy()
+        }
+        .violations("abc", &[]);
+        assert_eq!(violations.len(), 4);
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let policy = policy();
+        assert_eq!(
+            policy
+                .violations("Maria.Silva2024", &["msilva", "maria.silva@example.pt"])
+                .len(),
+            1
+        );
+        // Identifiers too short to matter are ignored
+        assert!(policy.violations("Correct7Horse", &["co"]).is_empty());
+    }
+
+    #[test]
+    fn test_breached_list() {
+        // "Password1234" in plain text, and SHA-1 of "Qwerty123456" as in HIBP downloads
+        let breached = parse_breached_list(
+            "Password1234\r\n\n15540B124CFAA055E2E267DCFB4A3D983F7A2422:42\n".as_bytes(),
+            MAX_BREACHED_ENTRIES,
+        )
+        .unwrap();
+        assert_eq!(breached.len(), 2);
+
+        let policy = policy().with_breached(breached);
+        assert_eq!(policy.violations("Password1234", &[]).len(), 1);
+        assert_eq!(policy.violations("Qwerty123456", &[]).len(), 1);
+        assert!(policy.violations("Password12345", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_breached_list_limit() {
+        let list = "Password1234\nQwerty123456\n".as_bytes();
+        assert_eq!(parse_breached_list(list, 2).unwrap().len(), 2);
+        assert!(parse_breached_list(list, 1).is_err());
+        // The lowercase hex of the downloads' other formats is read as well
+        assert_eq!(
+            parse_breached_list(
+                "15540b124cfaa055e2e267dcfb4a3d983f7a2422\nQwerty123456".as_bytes(),
+                10
+            )
+            .unwrap()
+            .len(),
+            1
+        );
+    }
+}
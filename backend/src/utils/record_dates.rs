@@ -0,0 +1,78 @@
+//! Parsing of DATE and DATE_RANGE values stored in `page_records.data`.
+
+use chrono::NaiveDate;
+
+/// Parses a single date written either as `YYYY-MM-DD` (frontend) or `DD/MM/YYYY` (legacy).
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    let value = value.trim();
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%d/%m/%Y"))
+        .ok()
+}
+
+/// Returns the `(start, end)` bounds of a DATE or DATE_RANGE value.
+///
+/// Accepted shapes:
+/// - `"YYYY-MM-DD"` or `"DD/MM/YYYY"` for DATE fields (start == end)
+/// - `{"start": "YYYY-MM-DD", "end": "YYYY-MM-DD"}` for DATE_RANGE fields
+/// - `"DD/MM/YYYY - DD/MM/YYYY"` for DATE_RANGE fields saved by older versions
+pub fn extract_date_bounds(value: &serde_json::Value) -> Option<(NaiveDate, NaiveDate)> {
+    match value {
+        serde_json::Value::String(s) => {
+            if let Some((start, end)) = s.split_once(" - ") {
+                Some((parse_date(start)?, parse_date(end)?))
+            } else {
+                let date = parse_date(s)?;
+                Some((date, date))
+            }
+        }
+        serde_json::Value::Object(obj) => {
+            let start = obj.get("start").and_then(|v| v.as_str()).and_then(parse_date)?;
+            let end = obj.get("end").and_then(|v| v.as_str()).and_then(parse_date)?;
+            Some((start, end))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn d(y: i32, m: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, day).unwrap()
+    }
+
+    #[test]
+    fn test_extract_single_date() {
+        assert_eq!(
+            extract_date_bounds(&json!("2025-03-14")),
+            Some((d(2025, 3, 14), d(2025, 3, 14)))
+        );
+        assert_eq!(
+            extract_date_bounds(&json!("14/03/2025")),
+            Some((d(2025, 3, 14), d(2025, 3, 14)))
+        );
+    }
+
+    #[test]
+    fn test_extract_date_range() {
+        assert_eq!(
+            extract_date_bounds(&json!({"start": "2025-01-01", "end": "2025-12-31"})),
+            Some((d(2025, 1, 1), d(2025, 12, 31)))
+        );
+        assert_eq!(
+            extract_date_bounds(&json!("01/01/2025 - 31/12/2025")),
+            Some((d(2025, 1, 1), d(2025, 12, 31)))
+        );
+    }
+
+    #[test]
+    fn test_extract_invalid_values() {
+        assert_eq!(extract_date_bounds(&json!(null)), None);
+        assert_eq!(extract_date_bounds(&json!("")), None);
+        assert_eq!(extract_date_bounds(&json!("not a date")), None);
+        assert_eq!(extract_date_bounds(&json!({"start": "2025-01-01"})), None);
+    }
+}
@@ -0,0 +1,94 @@
+//! Checks on the parent links between roles.
+
+use ahash::{HashMap, HashSet};
+
+/// Whether making `parent_id` the parent of `role_id` would close a loop, i.e. `role_id` is
+/// `parent_id` itself or one of its ancestors.
+///
+/// `parents` maps every role to its current parent. The walk stops after visiting every role
+/// once, so a loop already present in the data cannot hang it.
+pub fn creates_cycle(parents: &HashMap<u32, Option<u32>>, role_id: u32, parent_id: u32) -> bool {
+    let mut current = Some(parent_id);
+
+    for _ in 0..=parents.len() {
+        match current {
+            Some(id) if id == role_id => return true,
+            Some(id) => current = parents.get(&id).copied().flatten(),
+            None => return false,
+        }
+    }
+
+    true
+}
+
+/// Whether `role_id` or one of its ancestors is in `roles`. Like [`creates_cycle`], the walk
+/// visits every role at most once.
+pub fn has_ancestor_in(
+    parents: &HashMap<u32, Option<u32>>,
+    role_id: u32,
+    roles: &HashSet<u32>,
+) -> bool {
+    let mut current = Some(role_id);
+
+    for _ in 0..=parents.len() {
+        match current {
+            Some(id) if roles.contains(&id) => return true,
+            Some(id) => current = parents.get(&id).copied().flatten(),
+            None => return false,
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(links: &[(u32, Option<u32>)]) -> HashMap<u32, Option<u32>> {
+        links.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_valid_parents() {
+        // 3 -> 2 -> 1
+        let parents = tree(&[(1, None), (2, Some(1)), (3, Some(2)), (4, None)]);
+        assert!(!creates_cycle(&parents, 4, 3));
+        assert!(!creates_cycle(&parents, 3, 1));
+        assert!(!creates_cycle(&parents, 1, 4));
+    }
+
+    #[test]
+    fn test_cycles() {
+        let parents = tree(&[(1, None), (2, Some(1)), (3, Some(2))]);
+        assert!(creates_cycle(&parents, 1, 1));
+        assert!(creates_cycle(&parents, 1, 3));
+        assert!(creates_cycle(&parents, 2, 3));
+
+        // Existing loop not involving the role being changed
+        let parents = tree(&[(1, Some(2)), (2, Some(1)), (3, None)]);
+        assert!(creates_cycle(&parents, 3, 1));
+    }
+
+    #[test]
+    fn test_has_ancestor_in() {
+        // 3 -> 2 -> 1, 5 -> 4
+        let parents = tree(&[
+            (1, None),
+            (2, Some(1)),
+            (3, Some(2)),
+            (4, None),
+            (5, Some(4)),
+        ]);
+        let admins: HashSet<u32> = [1].into_iter().collect();
+        assert!(has_ancestor_in(&parents, 1, &admins));
+        assert!(has_ancestor_in(&parents, 3, &admins));
+        assert!(!has_ancestor_in(&parents, 5, &admins));
+        assert!(!has_ancestor_in(&parents, 6, &admins));
+
+        // A loop in the data ends the walk
+        let parents = tree(&[(1, Some(2)), (2, Some(1)), (3, None)]);
+        let roles: HashSet<u32> = [3].into_iter().collect();
+        assert!(!has_ancestor_in(&parents, 1, &roles));
+    }
+}
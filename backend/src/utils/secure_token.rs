@@ -0,0 +1,40 @@
+//! Single-use tokens sent by email, like invitation and password reset links. The token is 32
+//! random bytes in hex; only its SHA-256 is stored, so a leaked table cannot be used to reset
+//! anyone's password.
+
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+const TOKEN_BYTES: usize = 32;
+
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::rng().fill(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Stored form of a token.
+pub fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.trim().as_bytes()).to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_token() {
+        let token = generate_token();
+        assert_eq!(token.len(), TOKEN_BYTES * 2);
+        assert!(token.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_ne!(token, generate_token());
+    }
+
+    #[test]
+    fn test_hash_token() {
+        let token = generate_token();
+        assert_eq!(hash_token(&token).len(), 32);
+        assert_eq!(hash_token(&token), hash_token(&format!(" {}\n", token)));
+        assert_ne!(hash_token(&token), hash_token(&generate_token()));
+    }
+}
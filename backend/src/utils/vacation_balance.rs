@@ -0,0 +1,73 @@
+//! Carry-over rules of the vacation ledger, see [`crate::models::vacation_ledger`].
+//!
+//! Vacation left at the end of a year is carried over to the next one, up to an optional limit,
+//! and the part of it not taken by the expiry date (30 April by default) is lost.
+
+use chrono::NaiveDate;
+
+/// Day carried-over vacation expires on, when the setting is missing or invalid.
+pub const DEFAULT_CARRY_OVER_EXPIRY: &str = "04-30";
+
+/// Parses an expiry setting written as `MM-DD`.
+pub fn parse_expiry(value: &str) -> Option<(u32, u32)> {
+    let (month, day) = value.trim().split_once('-')?;
+    let (month, day) = (month.parse().ok()?, day.parse().ok()?);
+    // A leap year, so 29 February is accepted
+    NaiveDate::from_ymd_opt(2024, month, day)?;
+    Some((month, day))
+}
+
+/// When vacation carried over into `year` expires. 29 February falls back to the 28th in
+/// common years.
+pub fn carry_over_expiry(year: i32, (month, day): (u32, u32)) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(year, month, day).or_else(|| NaiveDate::from_ymd_opt(year, month, 28))
+}
+
+/// What is carried into the next year out of `remaining`: nothing when it is negative, and no
+/// more than `max` when there is a limit.
+pub fn carry_over_amount(remaining: i64, max: Option<i64>) -> i64 {
+    let amount = remaining.max(0);
+    match max {
+        Some(max) => amount.min(max.max(0)),
+        None => amount,
+    }
+}
+
+/// The part of the carried-over vacation lost at its expiry. Vacation taken until then uses
+/// the carried-over days first.
+pub fn expired_carry_over(carried_over: i64, used_until_expiry: i64) -> i64 {
+    (carried_over - used_until_expiry.max(0)).max(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_expiry() {
+        assert_eq!(parse_expiry("04-30"), Some((4, 30)));
+        assert_eq!(parse_expiry(" 2-29 "), Some((2, 29)));
+        assert_eq!(parse_expiry("04-31"), None);
+        assert_eq!(parse_expiry("30/04"), None);
+        assert_eq!(
+            carry_over_expiry(2025, (4, 30)),
+            NaiveDate::from_ymd_opt(2025, 4, 30)
+        );
+        assert_eq!(
+            carry_over_expiry(2025, (2, 29)),
+            NaiveDate::from_ymd_opt(2025, 2, 28)
+        );
+    }
+
+    #[test]
+    fn test_carry_over() {
+        assert_eq!(carry_over_amount(7, None), 7);
+        assert_eq!(carry_over_amount(7, Some(5)), 5);
+        assert_eq!(carry_over_amount(-3, None), 0);
+        assert_eq!(carry_over_amount(7, Some(0)), 0);
+
+        assert_eq!(expired_carry_over(5, 2), 3);
+        assert_eq!(expired_carry_over(5, 8), 0);
+        assert_eq!(expired_carry_over(0, 0), 0);
+    }
+}
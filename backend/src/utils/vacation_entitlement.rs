@@ -0,0 +1,222 @@
+//! Vacation entitlement under the Código do Trabalho (articles 237 to 239).
+//!
+//! Every year 22 working days vest on 1 January. In the year a worker is hired they get 2
+//! working days per complete month of contract, up to 20, which can only be taken once the
+//! contract has run for 6 months. When those 6 months end in the next year, the days are taken
+//! in it until 30 June, without the year going over 30 days of vacation. Contracts shorter than
+//! 6 months give 2 days per complete month, taken before they end.
+
+use chrono::{Datelike, Months, NaiveDate};
+use serde::Serialize;
+
+pub const YEARLY_DAYS: i64 = 22;
+pub const DAYS_PER_MONTH: i64 = 2;
+pub const FIRST_YEAR_MAX_DAYS: i64 = 20;
+pub const WAITING_MONTHS: u32 = 6;
+/// Most vacation taken in a year when the days of the hire year move into it
+pub const MAX_DAYS_IN_YEAR: i64 = 30;
+
+/// Which rule gave the entitlement of a year.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EntitlementRule {
+    /// Before the hire date or after the contract ended
+    NotEmployed,
+    /// Contract of less than 6 months, counted in the year it ends
+    ShortContract,
+    /// Pro rata of the hire year
+    FirstYear,
+    Yearly,
+}
+
+/// First-year days that could not be taken in the hire year, taken in the next one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct DeferredDays {
+    pub days: i64,
+    pub available_from: NaiveDate,
+    pub expires_on: NaiveDate,
+}
+
+/// The vacation days a worker is entitled to in a year.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Entitlement {
+    pub year: i32,
+    pub rule: EntitlementRule,
+    pub days: i64,
+    /// The days cannot be taken before this date, the end of the waiting period
+    pub available_from: Option<NaiveDate>,
+    pub deferred: Option<DeferredDays>,
+}
+
+/// Complete months from `start` to `end`, both included.
+pub fn full_months(start: NaiveDate, end: NaiveDate) -> i64 {
+    let Some(after_end) = end.succ_opt() else {
+        return 0;
+    };
+    if after_end <= start {
+        return 0;
+    }
+
+    let mut months = (after_end.year() - start.year()) as i64 * 12 + after_end.month() as i64
+        - start.month() as i64;
+    // The last month is complete only once the day of the month is reached again
+    if after_end.day() < start.day() {
+        months -= 1;
+    }
+    months.max(0)
+}
+
+/// The statutory entitlement of `year` for a worker hired on `hire_date`, whose contract ends
+/// on `contract_end` (`None` for open-ended contracts).
+pub fn statutory_entitlement(
+    hire_date: NaiveDate,
+    contract_end: Option<NaiveDate>,
+    year: i32,
+) -> Entitlement {
+    let mut entitlement = Entitlement {
+        year,
+        rule: EntitlementRule::NotEmployed,
+        days: 0,
+        available_from: None,
+        deferred: None,
+    };
+
+    let (Some(year_start), Some(year_end)) = (
+        NaiveDate::from_ymd_opt(year, 1, 1),
+        NaiveDate::from_ymd_opt(year, 12, 31),
+    ) else {
+        return entitlement;
+    };
+    if hire_date > year_end || contract_end.is_some_and(|end| end < year_start) {
+        return entitlement;
+    }
+
+    if let Some(end) = contract_end
+        && full_months(hire_date, end) < WAITING_MONTHS as i64
+    {
+        entitlement.rule = EntitlementRule::ShortContract;
+        if end.year() == year {
+            entitlement.days = DAYS_PER_MONTH * full_months(hire_date, end);
+        }
+        return entitlement;
+    }
+
+    let Some(waiting_end) = hire_date.checked_add_months(Months::new(WAITING_MONTHS)) else {
+        return entitlement;
+    };
+    let hire_year_end = NaiveDate::from_ymd_opt(hire_date.year(), 12, 31).unwrap_or(year_end);
+    let first_year_end = contract_end.map_or(hire_year_end, |end| end.min(hire_year_end));
+    let first_year_days =
+        (DAYS_PER_MONTH * full_months(hire_date, first_year_end)).min(FIRST_YEAR_MAX_DAYS);
+
+    if year == hire_date.year() {
+        entitlement.rule = EntitlementRule::FirstYear;
+        // Days whose waiting period ends next year are taken in it instead
+        if waiting_end <= year_end {
+            entitlement.days = first_year_days;
+            entitlement.available_from = Some(waiting_end);
+        }
+        return entitlement;
+    }
+
+    entitlement.rule = EntitlementRule::Yearly;
+    entitlement.days = YEARLY_DAYS;
+    if year == hire_date.year() + 1 && waiting_end > hire_year_end {
+        let days = first_year_days.min(MAX_DAYS_IN_YEAR - YEARLY_DAYS);
+        if let Some(expires_on) = NaiveDate::from_ymd_opt(year, 6, 30)
+            && days > 0
+        {
+            entitlement.deferred = Some(DeferredDays {
+                days,
+                available_from: waiting_end,
+                expires_on,
+            });
+        }
+    }
+
+    entitlement
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_full_months() {
+        assert_eq!(full_months(date(2025, 1, 1), date(2025, 12, 31)), 12);
+        assert_eq!(full_months(date(2025, 3, 15), date(2025, 12, 31)), 9);
+        assert_eq!(full_months(date(2025, 3, 15), date(2025, 4, 14)), 1);
+        assert_eq!(full_months(date(2025, 3, 15), date(2025, 4, 13)), 0);
+        assert_eq!(full_months(date(2025, 1, 31), date(2025, 2, 27)), 0);
+        assert_eq!(full_months(date(2025, 6, 1), date(2025, 5, 31)), 0);
+    }
+
+    #[test]
+    fn test_first_year() {
+        // Hired in March: 9 complete months, capped at 20, from September on
+        let entitlement = statutory_entitlement(date(2025, 3, 15), None, 2025);
+        assert_eq!(entitlement.rule, EntitlementRule::FirstYear);
+        assert_eq!(entitlement.days, 18);
+        assert_eq!(entitlement.available_from, Some(date(2025, 9, 15)));
+
+        let entitlement = statutory_entitlement(date(2025, 1, 2), None, 2025);
+        assert_eq!(entitlement.days, FIRST_YEAR_MAX_DAYS);
+
+        let entitlement = statutory_entitlement(date(2025, 3, 15), None, 2026);
+        assert_eq!(entitlement.rule, EntitlementRule::Yearly);
+        assert_eq!(entitlement.days, YEARLY_DAYS);
+        assert_eq!(entitlement.deferred, None);
+
+        assert_eq!(
+            statutory_entitlement(date(2025, 3, 15), None, 2024).rule,
+            EntitlementRule::NotEmployed
+        );
+    }
+
+    #[test]
+    fn test_waiting_period_ending_next_year() {
+        // Hired in October: the 6 days of 2025 move to 2026, from April until June
+        let entitlement = statutory_entitlement(date(2025, 10, 1), None, 2025);
+        assert_eq!(entitlement.days, 0);
+
+        let entitlement = statutory_entitlement(date(2025, 10, 1), None, 2026);
+        assert_eq!(entitlement.days, YEARLY_DAYS);
+        assert_eq!(
+            entitlement.deferred,
+            Some(DeferredDays {
+                days: 6,
+                available_from: date(2026, 4, 1),
+                expires_on: date(2026, 6, 30),
+            })
+        );
+
+        // No more than 30 days in 2026
+        let entitlement = statutory_entitlement(date(2025, 7, 1), None, 2026);
+        assert_eq!(entitlement.deferred.map(|deferred| deferred.days), Some(8));
+    }
+
+    #[test]
+    fn test_short_contract() {
+        // Four months, counted in the year the contract ends
+        let entitlement = statutory_entitlement(date(2025, 11, 1), Some(date(2026, 2, 28)), 2025);
+        assert_eq!(entitlement.rule, EntitlementRule::ShortContract);
+        assert_eq!(entitlement.days, 0);
+
+        let entitlement = statutory_entitlement(date(2025, 11, 1), Some(date(2026, 2, 28)), 2026);
+        assert_eq!(entitlement.days, 8);
+        assert_eq!(entitlement.available_from, None);
+
+        // Six months is no longer short, and it ended before 2027
+        let entitlement = statutory_entitlement(date(2025, 1, 1), Some(date(2025, 6, 30)), 2025);
+        assert_eq!(entitlement.rule, EntitlementRule::FirstYear);
+        assert_eq!(entitlement.days, 12);
+        assert_eq!(
+            statutory_entitlement(date(2025, 1, 1), Some(date(2025, 6, 30)), 2026).rule,
+            EntitlementRule::NotEmployed
+        );
+    }
+}
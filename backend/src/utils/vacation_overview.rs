@@ -0,0 +1,157 @@
+//! The day by day map of who is absent, for the admin overview of every role. See
+//! [`crate::models::vacation_overview`] for the requests it is made from.
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+/// A request shown in the overview, over the days it was asked for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Period {
+    pub user_id: u32,
+    pub request_id: u32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+/// Someone absent on a day, and the request they are absent by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct DayAbsence {
+    pub user_id: u32,
+    pub request_id: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct OverviewDay {
+    pub date: NaiveDate,
+    /// By user, then request
+    pub absences: Vec<DayAbsence>,
+}
+
+/// Every day from `start` to `end` with who is absent on it. A user is only absent on days
+/// `works_on` says they work, so weekends and holidays inside a request stay empty.
+pub fn daily_matrix(
+    start: NaiveDate,
+    end: NaiveDate,
+    periods: &[Period],
+    works_on: impl Fn(u32, NaiveDate) -> bool,
+) -> Vec<OverviewDay> {
+    start
+        .iter_days()
+        .take_while(|date| *date <= end)
+        .map(|date| {
+            let mut absences: Vec<DayAbsence> = periods
+                .iter()
+                .filter(|period| period.start_date <= date && date <= period.end_date)
+                .filter(|period| works_on(period.user_id, date))
+                .map(|period| DayAbsence {
+                    user_id: period.user_id,
+                    request_id: period.request_id,
+                })
+                .collect();
+            absences.sort_unstable_by_key(|absence| (absence.user_id, absence.request_id));
+            OverviewDay { date, absences }
+        })
+        .collect()
+}
+
+/// A CSV line of `fields`, quoting the ones with separators, quotes or line breaks. Text a
+/// spreadsheet would take for a formula, starting with `=`, `+`, `-` or `@`, is quoted behind
+/// an apostrophe; numbers are left as they are.
+pub fn csv_line<S: AsRef<str>>(fields: &[S]) -> String {
+    let mut line = fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.starts_with(['=', '+', '-', '@']) && field.parse::<f64>().is_err() {
+                format!("\"'{}\"", field.replace('"', "\"\""))
+            } else if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push_str("\r\n");
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, Weekday};
+
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn period(user_id: u32, request_id: u32, start: NaiveDate, end: NaiveDate) -> Period {
+        Period {
+            user_id,
+            request_id,
+            start_date: start,
+            end_date: end,
+        }
+    }
+
+    #[test]
+    fn test_daily_matrix() {
+        // Friday 6 to Monday 9 June 2025
+        let periods = [
+            period(2, 10, date(2025, 6, 5), date(2025, 6, 9)),
+            period(1, 11, date(2025, 6, 9), date(2025, 6, 20)),
+            // A morning and an afternoon of the same user on the same day
+            period(1, 13, date(2025, 6, 6), date(2025, 6, 6)),
+            period(1, 12, date(2025, 6, 6), date(2025, 6, 6)),
+        ];
+        let weekdays =
+            |_: u32, date: NaiveDate| !matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+
+        let days = daily_matrix(date(2025, 6, 6), date(2025, 6, 9), &periods, weekdays);
+        assert_eq!(days.len(), 4);
+        assert_eq!(
+            days[0].absences,
+            vec![
+                DayAbsence {
+                    user_id: 1,
+                    request_id: 12
+                },
+                DayAbsence {
+                    user_id: 1,
+                    request_id: 13
+                },
+                DayAbsence {
+                    user_id: 2,
+                    request_id: 10
+                },
+            ]
+        );
+        assert!(days[1].absences.is_empty());
+        assert!(days[2].absences.is_empty());
+        assert_eq!(days[3].date, date(2025, 6, 9));
+        assert_eq!(
+            days[3]
+                .absences
+                .iter()
+                .map(|absence| absence.user_id)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        assert!(daily_matrix(date(2025, 6, 9), date(2025, 6, 8), &periods, weekdays).is_empty());
+    }
+
+    #[test]
+    fn test_csv_line() {
+        assert_eq!(csv_line(&["1", "Ana", ""]), "1,Ana,\r\n");
+        assert_eq!(
+            csv_line(&["Silva, Ana", "o \"Zé\"", "a\nb"]),
+            "\"Silva, Ana\",\"o \"\"Zé\"\"\",\"a\nb\"\r\n"
+        );
+        assert_eq!(
+            csv_line(&["=HYPERLINK(\"x\")", "+A1", "@SUM(A1)", "-2+3", "-1.5", "2"]),
+            "\"'=HYPERLINK(\"\"x\"\")\",\"'+A1\",\"'@SUM(A1)\",\"'-2+3\",-1.5,2\r\n"
+        );
+    }
+}
@@ -1,23 +1,111 @@
-//! Working days calculation: excludes weekends (Sábado, Domingo) and holidays.
+//! Working days calculation: counts the days a user is scheduled to work, excluding holidays.
+//!
+//! Holidays come from the database, see [`crate::models::holiday::Holiday::dates_between`], and
+//! so do work schedules, see [`crate::models::work_schedule`]. Users without a schedule work
+//! Monday to Friday.
 
-use chrono::{Datelike, NaiveDate, Weekday};
+use chrono::{NaiveDate, Weekday};
 use std::collections::HashSet;
 
-/// Fixed Portuguese national holidays (day, month).
-const FIXED_HOLIDAYS: &[(u32, u32)] = &[
-    (1, 1),   // Ano Novo
-    (25, 4),  // Dia da Liberdade
-    (1, 5),   // Dia do Trabalhador
-    (10, 6),  // Dia de Portugal
-    (15, 8),  // Assunção
-    (5, 10),  // Implantação da República
-    (1, 11),  // Todos os Santos
-    (1, 12),  // Restauração
-    (8, 12),  // Imaculada Conceição
-    (25, 12), // Natal
-];
-
-fn easter_sunday(year: i32) -> Option<NaiveDate> {
+/// Minutes of a full working day.
+pub const STANDARD_DAY_MINUTES: u16 = 8 * 60;
+
+/// Working minutes on each day of a cycle that repeats, such as a week or a rotation of
+/// shifts. 0 is a day off.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkPattern {
+    /// A date the first day of the cycle falls on
+    pub cycle_start: NaiveDate,
+    pub day_minutes: Vec<u16>,
+}
+
+impl WorkPattern {
+    /// Monday to Friday, full days.
+    pub fn standard() -> WorkPattern {
+        WorkPattern {
+            cycle_start: NaiveDate::from_isoywd_opt(2024, 1, Weekday::Mon).unwrap(),
+            day_minutes: vec![
+                STANDARD_DAY_MINUTES,
+                STANDARD_DAY_MINUTES,
+                STANDARD_DAY_MINUTES,
+                STANDARD_DAY_MINUTES,
+                STANDARD_DAY_MINUTES,
+                0,
+                0,
+            ],
+        }
+    }
+
+    pub fn minutes_on(&self, date: NaiveDate) -> u16 {
+        if self.day_minutes.is_empty() {
+            return 0;
+        }
+        let day = (date - self.cycle_start)
+            .num_days()
+            .rem_euclid(self.day_minutes.len() as i64);
+        self.day_minutes[day as usize]
+    }
+}
+
+/// What vacation balances are counted in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BalanceUnit {
+    /// Every scheduled day counts as one, however long it is
+    Days,
+    /// The scheduled time, rounded up to whole hours
+    Hours,
+}
+
+/// How much of each working day in its range a request takes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DayPart {
+    Full,
+    Morning,
+    Afternoon,
+    /// A number of minutes, no more than the day's scheduled time
+    Minutes(u16),
+}
+
+impl DayPart {
+    /// Whether two requests on the same day take the same time. Mornings and afternoons leave
+    /// each other free, and so do hours and half days; hours are not placed in the day, so two
+    /// requests for hours always clash.
+    pub fn overlaps(self, other: DayPart) -> bool {
+        matches!(
+            (self, other),
+            (DayPart::Full, _)
+                | (_, DayPart::Full)
+                | (DayPart::Morning, DayPart::Morning)
+                | (DayPart::Afternoon, DayPart::Afternoon)
+                | (DayPart::Minutes(_), DayPart::Minutes(_))
+        )
+    }
+}
+
+/// The patterns a user worked over time, each from its date until the next one. Before the
+/// first, and without any, the standard week applies.
+#[derive(Debug, Clone, Default)]
+pub struct WorkTimeline {
+    periods: Vec<(NaiveDate, WorkPattern)>,
+}
+
+impl WorkTimeline {
+    pub fn new(mut periods: Vec<(NaiveDate, WorkPattern)>) -> WorkTimeline {
+        periods.sort_by_key(|(from, _)| *from);
+        WorkTimeline { periods }
+    }
+
+    pub fn minutes_on(&self, date: NaiveDate) -> u16 {
+        match self.periods.iter().rev().find(|(from, _)| *from <= date) {
+            Some((_, pattern)) => pattern.minutes_on(date),
+            None => WorkPattern::standard().minutes_on(date),
+        }
+    }
+}
+
+/// Easter Sunday of the given year, by the Anonymous Gregorian algorithm. Movable holidays
+/// are a number of days from it.
+pub fn easter_sunday(year: i32) -> Option<NaiveDate> {
     let a = year % 19;
     let b = year / 100;
     let c = year % 100;
@@ -35,43 +123,68 @@ fn easter_sunday(year: i32) -> Option<NaiveDate> {
     NaiveDate::from_ymd_opt(year, month, day)
 }
 
-/// Returns all holiday dates (fixed + movable) for the given year.
-pub fn get_holiday_dates_for_year(year: i32) -> HashSet<NaiveDate> {
-    let mut dates = HashSet::new();
+/// Counts the working time in the range [start, end], in `unit`.
+/// Range is INCLUSIVE: both start and end dates are counted.
+/// Excludes the days off of the schedule and the given holidays.
+pub fn count_working_days(
+    start: NaiveDate,
+    end: NaiveDate,
+    holidays: &HashSet<NaiveDate>,
+    timeline: &WorkTimeline,
+    unit: BalanceUnit,
+) -> i64 {
+    let mut days = 0i64;
+    let mut minutes = 0i64;
+    let mut current = start;
 
-    for &(day, month) in FIXED_HOLIDAYS {
-        if let Some(d) = NaiveDate::from_ymd_opt(year, month, day) {
-            dates.insert(d);
+    while current <= end {
+        let scheduled = timeline.minutes_on(current);
+        if scheduled > 0 && !holidays.contains(&current) {
+            days += 1;
+            minutes += scheduled as i64;
+        }
+        if let Some(next) = current.succ_opt() {
+            current = next;
+        } else {
+            break;
         }
     }
 
-    if let Some(easter) = easter_sunday(year) {
-        dates.insert(easter - chrono::Duration::days(47)); // Carnaval
-        dates.insert(easter - chrono::Duration::days(2));  // Sexta-feira Santa
-        dates.insert(easter);                               // Páscoa
-        dates.insert(easter + chrono::Duration::days(60)); // Corpo de Deus
+    match unit {
+        BalanceUnit::Days => days,
+        BalanceUnit::Hours => (minutes + 59) / 60,
     }
-
-    dates
 }
 
-/// Counts working days (dias úteis) in the range [start, end].
-/// Range is INCLUSIVE: both start and end dates are counted.
-/// Excludes weekends (Saturday, Sunday) and holidays.
-pub fn count_working_days(start: NaiveDate, end: NaiveDate, _year: i32) -> i64 {
-    let mut holidays = HashSet::new();
-    for y in start.year()..=end.year() {
-        holidays.extend(get_holiday_dates_for_year(y));
+/// Counts the working time `part` takes on each day of [start, end], in `unit`. Half days are
+/// half of the scheduled time, and minutes a share of it in days. Unlike
+/// [`count_working_days`], partial days are not rounded to whole hours.
+pub fn count_day_parts(
+    start: NaiveDate,
+    end: NaiveDate,
+    holidays: &HashSet<NaiveDate>,
+    timeline: &WorkTimeline,
+    unit: BalanceUnit,
+    part: DayPart,
+) -> f64 {
+    if part == DayPart::Full {
+        return count_working_days(start, end, holidays, timeline, unit) as f64;
     }
-    let mut count = 0i64;
+
+    let mut total = 0.0;
     let mut current = start;
 
     while current <= end {
-        let wd = current.weekday();
-        let is_weekend = wd == Weekday::Sat || wd == Weekday::Sun;
-        let is_holiday = holidays.contains(&current);
-        if !is_weekend && !is_holiday {
-            count += 1;
+        let scheduled = timeline.minutes_on(current);
+        if scheduled > 0 && !holidays.contains(&current) {
+            let minutes = match part {
+                DayPart::Minutes(minutes) => minutes.min(scheduled),
+                _ => scheduled / 2,
+            };
+            total += match unit {
+                BalanceUnit::Days => minutes as f64 / scheduled as f64,
+                BalanceUnit::Hours => minutes as f64 / 60.0,
+            };
         }
         if let Some(next) = current.succ_opt() {
             current = next;
@@ -80,5 +193,184 @@ pub fn count_working_days(start: NaiveDate, end: NaiveDate, _year: i32) -> i64 {
         }
     }
 
-    count
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_easter_sunday() {
+        assert_eq!(easter_sunday(2024), Some(date(2024, 3, 31)));
+        assert_eq!(easter_sunday(2025), Some(date(2025, 4, 20)));
+        assert_eq!(easter_sunday(2026), Some(date(2026, 4, 5)));
+    }
+
+    #[test]
+    fn test_count_working_days() {
+        // Monday 2 to Sunday 15 of June 2025, with Dia de Portugal on Tuesday 10
+        let holidays = HashSet::from([date(2025, 6, 10)]);
+        let standard = WorkTimeline::default();
+        assert_eq!(
+            count_working_days(
+                date(2025, 6, 2),
+                date(2025, 6, 15),
+                &holidays,
+                &standard,
+                BalanceUnit::Days
+            ),
+            9
+        );
+        assert_eq!(
+            count_working_days(
+                date(2025, 6, 2),
+                date(2025, 6, 15),
+                &HashSet::new(),
+                &standard,
+                BalanceUnit::Days
+            ),
+            10
+        );
+        assert_eq!(
+            count_working_days(
+                date(2025, 6, 7),
+                date(2025, 6, 8),
+                &holidays,
+                &standard,
+                BalanceUnit::Days
+            ),
+            0
+        );
+        assert_eq!(
+            count_working_days(
+                date(2025, 6, 2),
+                date(2025, 6, 15),
+                &holidays,
+                &standard,
+                BalanceUnit::Hours
+            ),
+            72
+        );
+    }
+
+    #[test]
+    fn test_rotating_schedule() {
+        // Two days on, two days off, from Friday 6 June 2025
+        let rotation = WorkPattern {
+            cycle_start: date(2025, 6, 6),
+            day_minutes: vec![480, 480, 0, 0],
+        };
+        assert_eq!(rotation.minutes_on(date(2025, 6, 6)), 480);
+        assert_eq!(rotation.minutes_on(date(2025, 6, 8)), 0);
+        assert_eq!(rotation.minutes_on(date(2025, 6, 10)), 480);
+        assert_eq!(rotation.minutes_on(date(2025, 6, 5)), 0);
+
+        let timeline = WorkTimeline::new(vec![(date(2025, 6, 6), rotation)]);
+        // Mon 2 to Thu 5 on the standard week, then Fri 6, Sat 7, Tue 10 and Wed 11, Sat 14
+        // and Sun 15
+        assert_eq!(
+            count_working_days(
+                date(2025, 6, 2),
+                date(2025, 6, 15),
+                &HashSet::new(),
+                &timeline,
+                BalanceUnit::Days
+            ),
+            10
+        );
+        assert_eq!(
+            count_working_days(
+                date(2025, 6, 2),
+                date(2025, 6, 15),
+                &HashSet::from([date(2025, 6, 10)]),
+                &timeline,
+                BalanceUnit::Days
+            ),
+            9
+        );
+
+        let part_time = WorkTimeline::new(vec![(
+            date(2025, 1, 1),
+            WorkPattern {
+                cycle_start: date(2024, 12, 30),
+                day_minutes: vec![300, 0, 300, 0, 300, 210, 0],
+            },
+        )]);
+        assert_eq!(
+            count_working_days(
+                date(2025, 6, 2),
+                date(2025, 6, 8),
+                &HashSet::new(),
+                &part_time,
+                BalanceUnit::Days
+            ),
+            4
+        );
+        // 18.5 hours
+        assert_eq!(
+            count_working_days(
+                date(2025, 6, 2),
+                date(2025, 6, 8),
+                &HashSet::new(),
+                &part_time,
+                BalanceUnit::Hours
+            ),
+            19
+        );
+    }
+
+    #[test]
+    fn test_day_parts() {
+        let standard = WorkTimeline::default();
+        let count = |start, end, unit, part| {
+            count_day_parts(start, end, &HashSet::new(), &standard, unit, part)
+        };
+        // Monday 2 June 2025
+        let monday = date(2025, 6, 2);
+        assert_eq!(
+            count(monday, monday, BalanceUnit::Days, DayPart::Morning),
+            0.5
+        );
+        assert_eq!(
+            count(monday, monday, BalanceUnit::Hours, DayPart::Afternoon),
+            4.0
+        );
+        assert_eq!(
+            count(monday, monday, BalanceUnit::Days, DayPart::Minutes(120)),
+            0.25
+        );
+        assert_eq!(
+            count(monday, monday, BalanceUnit::Hours, DayPart::Minutes(90)),
+            1.5
+        );
+        // No more than the day, and nothing on days off
+        assert_eq!(
+            count(monday, monday, BalanceUnit::Hours, DayPart::Minutes(600)),
+            8.0
+        );
+        assert_eq!(
+            count(
+                date(2025, 6, 7),
+                date(2025, 6, 7),
+                BalanceUnit::Days,
+                DayPart::Morning
+            ),
+            0.0
+        );
+        assert_eq!(
+            count(monday, date(2025, 6, 8), BalanceUnit::Days, DayPart::Full),
+            5.0
+        );
+
+        assert!(DayPart::Full.overlaps(DayPart::Afternoon));
+        assert!(DayPart::Morning.overlaps(DayPart::Morning));
+        assert!(!DayPart::Morning.overlaps(DayPart::Afternoon));
+        assert!(!DayPart::Minutes(60).overlaps(DayPart::Morning));
+        assert!(DayPart::Minutes(60).overlaps(DayPart::Minutes(30)));
+    }
 }